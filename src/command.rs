@@ -1,13 +1,635 @@
-use crate::frame::Frame;
+mod table;
+
+use crate::{frame::Frame, geo, reply::ErrorReply};
 use bytes::Bytes;
-use std::slice::Iter;
+use std::{borrow::Cow, cmp::Ordering, fmt, ops::Bound, slice, time::Duration, vec::IntoIter};
+
+pub use table::{introspect, lookup, Spec, CATEGORIES, COMMANDS};
 
 #[derive(Debug)]
 pub enum Command {
     Ping,
+    Acl(AclCommand),
+    Append(Bytes, Bytes),
+    /// `AUTH [username] password`.
+    Auth(Option<Bytes>, Bytes),
+    BgRewriteAof,
+    BgSave,
+    /// `BITFIELD key [GET type offset | [OVERFLOW WRAP | SAT | FAIL] SET type offset value |
+    /// [OVERFLOW WRAP | SAT | FAIL] INCRBY type offset increment ...]`.
+    BitField(Bytes, Vec<BitFieldOp>),
+    /// `BITCOUNT key [start end [BYTE | BIT]]`.
+    BitCount(Bytes, Option<BitRange>),
+    /// `BITOP operation destkey key [key ...]`.
+    BitOp(BitOp, Bytes, Vec<Bytes>),
+    /// `BITPOS key bit [start [end [BYTE | BIT]]]`.
+    BitPos(Bytes, bool, Option<BitRange>),
+    BLMove(Bytes, Bytes, End, End, Option<Duration>),
+    BPop(Vec<Bytes>, End, Option<Duration>),
+    Client(ClientCommand),
+    Introspect(CommandCommand),
+    Config(ConfigCommand),
+    Debug(DebugCommand),
+    Del(Vec<Bytes>),
+    Discard,
     Echo(Bytes),
+    Exec,
+    Exists(Vec<Bytes>),
+    /// `EXPIRE`, `PEXPIRE`, `EXPIREAT` or `PEXPIREAT`.
+    Expire(Bytes, Expiry, ExpireOptions),
+    /// `EXPIRETIME` or, if the flag is set, `PEXPIRETIME`.
+    ExpireTime(Bytes, bool),
+    /// `GEODIST key member1 member2 [M | KM | FT | MI]`, with the unit in meters.
+    GeoDist(Bytes, Bytes, Bytes, f64),
+    GeoPos(Bytes, Vec<Bytes>),
+    GeoSearch(Bytes, GeoSearch),
     Get(Bytes),
-    Set([Bytes; 2]),
+    GetDel(Bytes),
+    /// `GETEX key [EX seconds | PX milliseconds | EXAT timestamp | PXAT timestamp | PERSIST]`,
+    /// where `Some(None)` is `PERSIST`.
+    GetEx(Bytes, Option<Option<Expiry>>),
+    GetBit(Bytes, u64),
+    GetRange(Bytes, i64, i64),
+    HDel(Bytes, Vec<Bytes>),
+    /// `HELLO [protover [AUTH username password] [SETNAME name]]`, which switches the connection to
+    /// the protocol version given, if any, and replies with the server's details.
+    Hello(Option<i64>, Option<(Bytes, Bytes)>, Option<Bytes>),
+    HExists(Bytes, Bytes),
+    HGet(Bytes, Bytes),
+    HGetAll(Bytes),
+    HIncrBy(Bytes, Bytes, i64),
+    HLen(Bytes),
+    HScan(Bytes, u64, ScanOptions),
+    HSet(Bytes, Vec<(Bytes, Bytes)>),
+    IncrBy(Bytes, i64),
+    /// `INFO [section ...]`, where no sections means the default ones.
+    Info(Vec<Bytes>),
+    Keys(Bytes),
+    Latency(LatencyCommand),
+    /// `LCS key1 key2 [LEN] [IDX] [MINMATCHLEN len] [WITHMATCHLEN]`.
+    Lcs(Bytes, Bytes, LcsOptions),
+    /// `LINSERT key BEFORE|AFTER pivot element`, where `before` is whether `BEFORE` was given.
+    LInsert(Bytes, bool, Bytes, Bytes),
+    LLen(Bytes),
+    LMove(Bytes, Bytes, End, End),
+    LPos(Bytes, Bytes, LPosOptions),
+    LRange(Bytes, i64, i64),
+    LRem(Bytes, i64, Bytes),
+    LSet(Bytes, i64, Bytes),
+    LTrim(Bytes, i64, i64),
+    Memory(MemoryCommand),
+    MGet(Vec<Bytes>),
+    MSet(Vec<(Bytes, Bytes)>),
+    MSetNx(Vec<(Bytes, Bytes)>),
+    Monitor,
+    Multi,
+    Object(ObjectCommand, Bytes),
+    Persist(Bytes),
+    Pop(Bytes, End, Option<usize>),
+    Pttl(Bytes),
+    /// `PUBLISH channel message`.
+    Publish(Bytes, Bytes),
+    PubSub(PubSubCommand),
+    /// `PSYNC replicationid offset`, sent by replicas to start following this server. Replicas are
+    /// always sent the whole dataset, so the arguments are only validated.
+    PSync,
+    Push(Bytes, End, Vec<Bytes>),
+    /// `QUIT`, after which the connection is closed once replied to.
+    Quit,
+    RandomKey,
+    Role,
+    /// `REPLCONF option value [option value ...]`, exchanged by masters and their replicas.
+    ReplConf(Vec<ReplConfOption>),
+    /// `REPLICAOF host port`, or `REPLICAOF NO ONE` (`None`) to stop replicating.
+    ReplicaOf(Option<(String, u16)>),
+    /// `RESET`, which returns the connection to the state it was in when it connected.
+    Reset,
+    SAdd(Bytes, Vec<Bytes>),
+    Save,
+    Scan(u64, ScanOptions),
+    SCard(Bytes),
+    /// `SINTER`, `SUNION` or `SDIFF`.
+    SCombine(SetOp, Vec<Bytes>),
+    /// `SINTERSTORE`, `SUNIONSTORE` or `SDIFFSTORE`, with the destination first.
+    SCombineStore(Bytes, SetOp, Vec<Bytes>),
+    SIsMember(Bytes, Bytes),
+    SMembers(Bytes),
+    SRem(Bytes, Vec<Bytes>),
+    SScan(Bytes, u64, ScanOptions),
+    Set(Bytes, Bytes, SetOptions),
+    SetBit(Bytes, u64, bool),
+    SetRange(Bytes, usize, Bytes),
+    /// `SHUTDOWN [NOSAVE|SAVE]`, where the flag is whether to save the dataset regardless of the
+    /// `save` points.
+    Shutdown(Option<bool>),
+    SlowLog(SlowLogCommand),
+    Strlen(Bytes),
+    /// `SUBSCRIBE channel [channel ...]` or, if the flag is set, `PSUBSCRIBE pattern [pattern ...]`.
+    Subscribe(Vec<Bytes>, bool),
+    Touch(Vec<Bytes>),
+    Ttl(Bytes),
+    Type(Bytes),
+    Unlink(Vec<Bytes>),
+    /// `UNSUBSCRIBE [channel ...]` or, if the flag is set, `PUNSUBSCRIBE [pattern ...]`, where none
+    /// means every one the client subscribes to.
+    Unsubscribe(Vec<Bytes>, bool),
+    Unwatch,
+    /// `WAIT numreplicas timeout`, where a timeout of `None` waits forever.
+    Wait(usize, Option<Duration>),
+    Watch(Vec<Bytes>),
+    /// `XACK key group id [id ...]`.
+    XAck(Bytes, Bytes, Vec<StreamId>),
+    XAdd(Bytes, XAddId, Vec<(Bytes, Bytes)>),
+    /// `XCLAIM key group consumer min-idle-time id [id ...]`, with its options.
+    XClaim(Bytes, Bytes, Bytes, Vec<StreamId>, XClaimOptions),
+    XGroup(XGroupCommand),
+    XLen(Bytes),
+    /// `XPENDING key group`, or with a range, `XPENDING key group [IDLE min-idle-time] start end
+    /// count [consumer]`.
+    XPending(Bytes, Bytes, Option<XPendingRange>),
+    /// `XRANGE` or, if the flag is set, `XREVRANGE`, with an optional count.
+    XRange(Bytes, Bound<StreamId>, Bound<StreamId>, Option<usize>, bool),
+    /// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]`, where an ID of
+    /// `None` is `$`, i.e. the last ID in the stream.
+    XRead(Vec<Bytes>, Vec<Option<StreamId>>, XReadOptions),
+    /// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key
+    /// [key ...] id [id ...]`, where an ID of `None` is `>`, i.e. entries never delivered to the
+    /// group.
+    XReadGroup(
+        Bytes,
+        Bytes,
+        Vec<Bytes>,
+        Vec<Option<StreamId>>,
+        XReadOptions,
+    ),
+    ZAdd(Bytes, Vec<(f64, Bytes)>, ZAddOptions),
+    ZCard(Bytes),
+    /// `ZCOUNT` or `ZLEXCOUNT`.
+    ZCount(Bytes, ZRangeBy),
+    ZIncrBy(Bytes, f64, Bytes),
+    /// `ZRANGE`, `ZRANGEBYSCORE` or `ZRANGEBYLEX`.
+    ZRange(Bytes, ZRangeBy, ZRangeOptions),
+    /// `ZRANK` or, if the flag is set, `ZREVRANK`.
+    ZRank(Bytes, Bytes, bool),
+    ZRem(Bytes, Vec<Bytes>),
+    ZScan(Bytes, u64, ScanOptions),
+    ZScore(Bytes, Bytes),
+}
+
+/// When a key should expire, in milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub enum Expiry {
+    /// Relative to the time the command is applied.
+    In(i64),
+    /// A unix timestamp.
+    At(i64),
+}
+
+/// An end of a list.
+#[derive(Debug, Clone, Copy)]
+pub enum End {
+    Left,
+    Right,
+}
+
+/// A subcommand of `ACL`, which manages the users clients authenticate as.
+#[derive(Debug)]
+pub enum AclCommand {
+    /// `CAT [category]`, which lists every category, or the commands in one.
+    Cat(Option<Bytes>),
+    GetUser(Bytes),
+    List,
+    /// `SETUSER username [rule ...]`, which creates the user if it doesn't exist.
+    SetUser(Bytes, Vec<Bytes>),
+    WhoAmI,
+}
+
+/// A subcommand of `OBJECT`, which inspects the value stored at a key.
+#[derive(Debug)]
+pub enum ObjectCommand {
+    Encoding,
+    Freq,
+    IdleTime,
+    RefCount,
+}
+
+/// A subcommand of `MEMORY`.
+#[derive(Debug)]
+pub enum MemoryCommand {
+    /// `USAGE key [SAMPLES count]`, with how many elements of a collection are sampled, or none to
+    /// sample every one.
+    Usage(Bytes, Option<usize>),
+    Stats,
+    Doctor,
+}
+
+/// A subcommand of `CLIENT`.
+#[derive(Debug)]
+pub enum ClientCommand {
+    Id,
+    GetName,
+    /// `SETNAME name`, where an empty name clears it.
+    SetName(Bytes),
+    List,
+    /// `KILL ip:port`, which replies with an error if no client connected from the address.
+    KillAddr(Bytes),
+    /// `KILL [ID id] [ADDR ip:port] [TYPE type] [SKIPME yes|no]`, which kills every client matching
+    /// each filter given and replies with how many there were.
+    Kill(ClientFilter),
+}
+
+/// The clients killed by `CLIENT KILL`.
+#[derive(Debug, Default)]
+pub struct ClientFilter {
+    pub id: Option<u64>,
+    pub addr: Option<Bytes>,
+    pub kind: Option<ClientType>,
+    /// Whether the client sending the command is never killed.
+    pub skip_me: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientType {
+    Normal,
+    Replica,
+    PubSub,
+}
+
+/// `COMMAND`, or one of its subcommands.
+#[derive(Debug)]
+pub enum CommandCommand {
+    /// `COMMAND` without a subcommand, which describes every command.
+    All,
+    Count,
+    /// `INFO [name ...]`
+    Info(Vec<Bytes>),
+    /// `DOCS [name ...]`
+    Docs(Vec<Bytes>),
+}
+
+/// A subcommand of `SLOWLOG`.
+#[derive(Debug)]
+pub enum SlowLogCommand {
+    /// `GET [count]`, where no count gets every entry.
+    Get(Option<usize>),
+    Len,
+    Reset,
+}
+
+/// A subcommand of `LATENCY`.
+#[derive(Debug)]
+pub enum LatencyCommand {
+    Latest,
+    History(Bytes),
+    /// `RESET [event ...]`, where no events resets every one.
+    Reset(Vec<Bytes>),
+    Doctor,
+}
+
+/// A subcommand of `DEBUG`, which exposes internals for testing.
+#[derive(Debug)]
+pub enum DebugCommand {
+    /// `OBJECT key`, which describes how the value at a key is stored.
+    Object(Bytes),
+    /// `SLEEP seconds`, which blocks the whole server like Redis.
+    Sleep(Duration),
+    /// `RELOAD`, which saves the dataset and loads it back.
+    Reload,
+    /// `SET-ACTIVE-EXPIRE 0|1`, which stops or resumes removing expired keys in the background.
+    SetActiveExpire(bool),
+    ChangeReplId,
+}
+
+/// A subcommand of `CONFIG`.
+#[derive(Debug)]
+pub enum ConfigCommand {
+    /// `GET pattern [pattern ...]`.
+    Get(Vec<Bytes>),
+    /// `SET parameter value [parameter value ...]`, which sets every parameter or none of them.
+    Set(Vec<(Bytes, Bytes)>),
+    Rewrite,
+}
+
+/// A subcommand of `PUBSUB`.
+#[derive(Debug)]
+pub enum PubSubCommand {
+    /// `CHANNELS [pattern]`.
+    Channels(Option<Bytes>),
+    NumPat,
+    NumSub(Vec<Bytes>),
+}
+
+/// An option of `REPLCONF`.
+#[derive(Debug)]
+pub enum ReplConfOption {
+    /// `ACK offset`, sent by replicas to acknowledge having applied every command up to `offset`.
+    Ack(u64),
+    /// `CAPA capability`, which is ignored, as replicas are always sent plain RDB snapshots.
+    Capa,
+    /// `GETACK *`, sent by masters to ask their replicas for an `ACK`.
+    GetAck,
+    /// `LISTENING-PORT port`, the port a replica accepts connections on.
+    ListeningPort(u16),
+}
+
+/// An operation combining sets.
+#[derive(Debug, Clone, Copy)]
+pub enum SetOp {
+    /// The members of the first set that are in every other set.
+    Inter,
+    /// The members of any set.
+    Union,
+    /// The members of the first set that are in no other set.
+    Diff,
+}
+
+/// The ID of a stream entry, made of a unix time in milliseconds and a sequence number that
+/// distinguishes entries added in the same millisecond.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID given to `XADD`.
+#[derive(Debug, Clone, Copy)]
+pub enum XAddId {
+    /// `*`, generating the whole ID.
+    Auto,
+    /// `ms-*`, generating only the sequence number.
+    Seq(u64),
+    Explicit(StreamId),
+}
+
+/// The optional arguments of `XREAD` and `XREADGROUP`.
+#[derive(Debug, Default)]
+pub struct XReadOptions {
+    pub count: Option<usize>,
+    /// How long to block for, where `Some(None)` waits forever.
+    pub block: Option<Option<Duration>>,
+    /// Whether delivered entries should be considered acknowledged (`XREADGROUP` only).
+    pub no_ack: bool,
+}
+
+/// A subcommand of `XGROUP`, where IDs of `None` are `$`, i.e. the last ID in the stream.
+#[derive(Debug)]
+pub enum XGroupCommand {
+    /// `CREATE key group id [MKSTREAM]`.
+    Create(Bytes, Bytes, Option<StreamId>, bool),
+    CreateConsumer(Bytes, Bytes, Bytes),
+    DelConsumer(Bytes, Bytes, Bytes),
+    Destroy(Bytes, Bytes),
+    SetId(Bytes, Bytes, Option<StreamId>),
+}
+
+/// The range of pending entries `XPENDING` should return in detail.
+#[derive(Debug)]
+pub struct XPendingRange {
+    /// The fewest milliseconds since an entry was last delivered.
+    pub min_idle: i64,
+    pub start: Bound<StreamId>,
+    pub end: Bound<StreamId>,
+    pub count: usize,
+    pub consumer: Option<Bytes>,
+}
+
+/// The arguments of `XCLAIM` other than the key, group, consumer and IDs.
+#[derive(Debug, Default)]
+pub struct XClaimOptions {
+    pub min_idle: i64,
+    /// How many milliseconds ago the claimed entries should be considered delivered (`IDLE`).
+    pub idle: Option<i64>,
+    /// When the claimed entries should be considered delivered, as a unix time in milliseconds
+    /// (`TIME`).
+    pub time: Option<i64>,
+    pub retry_count: Option<u64>,
+    pub force: bool,
+    pub just_id: bool,
+}
+
+/// The optional arguments of `ZADD`.
+#[derive(Debug, Default)]
+pub struct ZAddOptions {
+    /// `Some(true)` to only update existing members (XX), `Some(false)` to only add new ones (NX).
+    pub exists: Option<bool>,
+    /// `Some(Ordering::Greater)` to only update scores upwards (GT), or `Some(Ordering::Less)` to
+    /// only update them downwards (LT).
+    pub comparison: Option<Ordering>,
+    /// Whether to reply with the number of members added or updated (CH), rather than just added.
+    pub changed: bool,
+}
+
+/// Which members of a sorted set a range selects.
+#[derive(Debug)]
+pub enum ZRangeBy {
+    /// Members by index, counting back from the end if negative.
+    Index(i64, i64),
+    /// Members with a score between a minimum and a maximum.
+    Score(Bound<f64>, Bound<f64>),
+    /// Members between a minimum and a maximum, comparing members rather than scores. Like Redis,
+    /// this assumes that every member has the same score.
+    Lex(LexBound, LexBound),
+}
+
+/// A bound of a lexicographical range of sorted set members.
+#[derive(Debug)]
+pub enum LexBound {
+    /// `-`, which sorts before every member.
+    Min,
+    /// `+`, which sorts after every member.
+    Max,
+    Included(Bytes),
+    Excluded(Bytes),
+}
+
+/// The optional arguments of `ZRANGE`.
+#[derive(Debug, Default)]
+pub struct ZRangeOptions {
+    /// Whether to order the members from the highest score down.
+    pub rev: bool,
+    pub with_scores: bool,
+    /// How many of the selected members to skip and then return, where a negative count returns
+    /// all of the rest.
+    pub limit: Option<(i64, i64)>,
+}
+
+/// The arguments of `GEOSEARCH`, which replies with the members of a sorted set indexed by
+/// `GEOADD` that are within a shape.
+#[derive(Debug)]
+pub struct GeoSearch {
+    pub from: GeoFrom,
+    pub shape: GeoShape,
+    /// The meters per unit of the shape's dimensions and of the distances replied with.
+    pub unit: f64,
+    /// Whether to sort the members by decreasing distance (`Some(true)`, i.e. `DESC`) or increasing
+    /// distance (`Some(false)`, i.e. `ASC`), rather than leave them unsorted.
+    pub descending: Option<bool>,
+    /// The most members to reply with, and whether any members may be (`ANY`) rather than the
+    /// nearest ones.
+    pub count: Option<(usize, bool)>,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+}
+
+/// The center of a `GEOSEARCH`.
+#[derive(Debug)]
+pub enum GeoFrom {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+/// The shape of a `GEOSEARCH`, in its unit.
+#[derive(Debug)]
+pub enum GeoShape {
+    Radius(f64),
+    /// A box of a width and a height.
+    Box(f64, f64),
+}
+
+/// The optional arguments of `LCS`.
+#[derive(Debug, Default)]
+pub struct LcsOptions {
+    /// Whether to reply with the length of the longest common subsequence (`LEN`), rather than
+    /// the subsequence itself.
+    pub len: bool,
+    /// Whether to reply with the positions of the subsequence's runs in both strings (`IDX`).
+    pub idx: bool,
+    /// The length of the shortest runs replied with.
+    pub min_match_len: usize,
+    /// Whether to reply with the length of each run too.
+    pub with_match_len: bool,
+}
+
+/// The optional arguments of `LPOS`.
+#[derive(Debug)]
+pub struct LPosOptions {
+    /// Which match to start from, counting from the tail of the list if negative.
+    pub rank: i64,
+    /// How many matches to return, where `Some(0)` means all of them. Without a count only the
+    /// first match is returned, and not as an array.
+    pub count: Option<usize>,
+    /// The most elements to compare, where `0` means the whole list.
+    pub max_len: usize,
+}
+
+impl Default for LPosOptions {
+    fn default() -> Self {
+        LPosOptions {
+            rank: 1,
+            count: None,
+            max_len: 0,
+        }
+    }
+}
+
+/// The optional arguments of `SCAN`, `HSCAN`, `SSCAN` and `ZSCAN`.
+#[derive(Debug)]
+pub struct ScanOptions {
+    /// A glob-style pattern that returned keys or members must match.
+    pub pattern: Option<Bytes>,
+    /// Roughly how many keys or members to visit.
+    pub count: usize,
+    /// The type that returned keys must hold, e.g. `zset`. Only `SCAN` accepts it.
+    pub kind: Option<Bytes>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            pattern: None,
+            count: 10,
+            kind: None,
+        }
+    }
+}
+
+/// The optional arguments of `EXPIRE` and its variants, which only set the expiry depending on the
+/// key's current one.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExpireOptions {
+    /// Only set the expiry if the key has one (`Some(true)`, i.e. `XX`) or if it doesn't
+    /// (`Some(false)`, i.e. `NX`).
+    pub exists: Option<bool>,
+    /// Only set the expiry if it is later (`Some(Greater)`, i.e. `GT`) or sooner (`Some(Less)`,
+    /// i.e. `LT`) than the current one, where no expiry is later than any.
+    pub compare: Option<Ordering>,
+}
+
+/// A range of a string's bits given to `BITCOUNT` or `BITPOS`, counting from the end if negative.
+#[derive(Debug, Clone, Copy)]
+pub struct BitRange {
+    pub start: i64,
+    /// The last position of the range, or the end of the string if none.
+    pub end: Option<i64>,
+    /// Whether the positions are of bits (`BIT`) rather than bytes (`BYTE`, the default).
+    pub bits: bool,
+}
+
+/// An operation of `BITOP`, combining strings bit by bit.
+#[derive(Debug, Clone, Copy)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    /// Inverts a single string.
+    Not,
+}
+
+/// A subcommand of `BITFIELD`, on the integer of a given type at a given bit offset.
+#[derive(Debug, Clone, Copy)]
+pub enum BitFieldOp {
+    Get(BitFieldType, u64),
+    /// Sets the integer, replying with its previous value.
+    Set(BitFieldType, u64, i64, Overflow),
+    /// Increments the integer, replying with its new value.
+    IncrBy(BitFieldType, u64, i64, Overflow),
+}
+
+/// The type of an integer of `BITFIELD`, e.g. `i16` or `u8`.
+#[derive(Debug, Clone, Copy)]
+pub struct BitFieldType {
+    pub signed: bool,
+    /// The number of bits, from 1 to 64 if signed, or to 63 if not.
+    pub bits: u32,
+}
+
+/// What `BITFIELD` does when a value doesn't fit in its type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Keeps the value's least significant bits.
+    Wrap,
+    /// Keeps the type's smallest or largest value.
+    Sat,
+    /// Leaves the integer unchanged, replying with nil.
+    Fail,
+}
+
+/// The optional arguments of `SET`.
+#[derive(Debug, Default)]
+pub struct SetOptions {
+    pub expiry: Option<Expiry>,
+    /// Keep the key's current expiry instead of clearing it (`KEEPTTL`).
+    pub keep_ttl: bool,
+    /// Only set the key if it already exists (`Some(true)`, i.e. `XX`) or if it doesn't
+    /// (`Some(false)`, i.e. `NX`).
+    pub exists: Option<bool>,
+    /// Reply with the key's previous value instead of `OK` (`GET`).
+    pub get: bool,
 }
 
 #[derive(Debug)]
@@ -15,7 +637,169 @@ pub enum Error {
     NotAnArray,
     MissingArgument,
     WrongType,
-    UnknownCommand,
+    /// An unknown command, with its arguments.
+    UnknownCommand(Bytes, Vec<Bytes>),
+    WrongArity(&'static str),
+    Syntax,
+    NotAnInteger,
+    InvalidExpireTime(&'static str),
+    DecrementOverflow,
+    OffsetOutOfRange,
+    BitOffsetOutOfRange,
+    BitOutOfRange,
+    /// A bit searched for by `BITPOS` that is neither 0 nor 1.
+    InvalidBitPosBit,
+    BitOpNotArity,
+    InvalidBitFieldType,
+    InvalidOverflow,
+    InvalidLonLat(f64, f64),
+    UnsupportedUnit,
+    GeoSearchFrom,
+    GeoSearchBy,
+    NegativeRadius,
+    NegativeBox,
+    GeoCountNotPositive,
+    AnyWithoutCount,
+    LcsLenAndIdx,
+    NotPositive,
+    InvalidTimeout,
+    NegativeTimeout,
+    ZeroRank,
+    NegativeCount,
+    NegativeMaxLen,
+    NotAFloat,
+    IncompatibleOptions(&'static str),
+    MinMaxNotAFloat,
+    InvalidLexRange,
+    InvalidStreamId,
+    UnbalancedStreams,
+    /// An ID that `XREADGROUP` doesn't accept, such as `$`.
+    InvalidGroupId,
+    UnknownSubcommand(Bytes, &'static str),
+    UnknownClientType(Bytes),
+    InvalidSlowLogCount,
+    InvalidCursor,
+    InvalidPort,
+    UnknownReplConfOption(Bytes),
+    UnsupportedOption(Bytes),
+    InvalidProtocolVersion,
+    /// An option of `HELLO` that is unknown or missing its arguments.
+    HelloOption(Bytes),
+}
+
+impl From<Error> for ErrorReply {
+    fn from(value: Error) -> Self {
+        let message: Cow<'static, str> = match value {
+            Error::NotAnArray => "Protocol error: expected an array of bulk strings".into(),
+            Error::MissingArgument => "Protocol error: empty command".into(),
+            Error::WrongType => "Protocol error: expected a bulk string argument".into(),
+            Error::UnknownCommand(name, args) => {
+                let mut message = format!(
+                    "unknown command '{}', with args beginning with: ",
+                    String::from_utf8_lossy(&name)
+                );
+                for arg in args {
+                    message.push_str(&format!("'{}' ", String::from_utf8_lossy(&arg)));
+                }
+                message.into()
+            }
+            Error::WrongArity(command) => {
+                format!("wrong number of arguments for '{}' command", command).into()
+            }
+            Error::Syntax => "syntax error".into(),
+            Error::NotAnInteger => "value is not an integer or out of range".into(),
+            Error::InvalidExpireTime(command) => {
+                format!("invalid expire time in '{}' command", command).into()
+            }
+            Error::DecrementOverflow => "decrement would overflow".into(),
+            Error::OffsetOutOfRange => "offset is out of range".into(),
+            Error::BitOffsetOutOfRange => "bit offset is not an integer or out of range".into(),
+            Error::BitOutOfRange => "bit is not an integer or out of range".into(),
+            Error::InvalidBitPosBit => "The bit argument must be 1 or 0.".into(),
+            Error::BitOpNotArity => "BITOP NOT must be called with a single source key.".into(),
+            Error::InvalidBitFieldType => "Invalid bitfield type. Use something like i16 u8. Note \
+                                           that u64 is not supported but i64 is."
+                .into(),
+            Error::InvalidOverflow => "Invalid OVERFLOW type specified".into(),
+            Error::InvalidLonLat(lon, lat) => {
+                format!("invalid longitude,latitude pair {:.6},{:.6}", lon, lat).into()
+            }
+            Error::UnsupportedUnit => "unsupported unit provided. please use M, KM, FT, MI".into(),
+            Error::GeoSearchFrom => {
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH".into()
+            }
+            Error::GeoSearchBy => {
+                "exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".into()
+            }
+            Error::NegativeRadius => "radius cannot be negative".into(),
+            Error::NegativeBox => "height or width cannot be negative".into(),
+            Error::GeoCountNotPositive => "COUNT must be > 0".into(),
+            Error::AnyWithoutCount => "the ANY argument requires COUNT argument".into(),
+            Error::LcsLenAndIdx => {
+                "If you want both the length and indexes, please just use IDX.".into()
+            }
+            Error::NotPositive => "value is out of range, must be positive".into(),
+            Error::InvalidTimeout => "timeout is not a float or out of range".into(),
+            Error::NegativeTimeout => "timeout is negative".into(),
+            Error::ZeroRank => "RANK can't be zero: use 1 to start from the first match, 2 from \
+                                the second ... or use negative to start from the end of the list"
+                .into(),
+            Error::NegativeCount => "COUNT can't be negative".into(),
+            Error::NegativeMaxLen => "MAXLEN can't be negative".into(),
+            Error::NotAFloat => "value is not a valid float".into(),
+            Error::IncompatibleOptions(options) => {
+                format!("{options} options at the same time are not compatible").into()
+            }
+            Error::MinMaxNotAFloat => "min or max is not a float".into(),
+            Error::InvalidLexRange => "min or max not valid string range item".into(),
+            Error::InvalidStreamId => {
+                "Invalid stream ID specified as stream command argument".into()
+            }
+            Error::InvalidGroupId => "The $ ID is meaningless in the context of XREADGROUP: \
+                                      you want to read the history of this consumer by \
+                                      specifying a proper ID, or use the > ID to get new messages. \
+                                      The $ ID would just return an empty result set."
+                .into(),
+            Error::UnknownSubcommand(subcommand, command) => format!(
+                "unknown subcommand or wrong number of arguments for '{}'. Try {} HELP.",
+                String::from_utf8_lossy(&subcommand),
+                command
+            )
+            .into(),
+            Error::UnknownClientType(kind) => {
+                format!("Unknown client type '{}'", String::from_utf8_lossy(&kind)).into()
+            }
+            Error::InvalidSlowLogCount => "count should be greater than or equal to -1".into(),
+            Error::InvalidCursor => "invalid cursor".into(),
+            Error::InvalidPort => "Invalid master port".into(),
+            Error::UnknownReplConfOption(option) => format!(
+                "Unrecognized REPLCONF option: {}",
+                String::from_utf8_lossy(&option)
+            )
+            .into(),
+            Error::UnsupportedOption(option) => {
+                format!("Unsupported option {}", String::from_utf8_lossy(&option)).into()
+            }
+            Error::InvalidProtocolVersion => {
+                "Protocol version is not an integer or out of range".into()
+            }
+            Error::HelloOption(option) => format!(
+                "Syntax error in HELLO option '{}'",
+                String::from_utf8_lossy(&option)
+            )
+            .into(),
+            Error::UnbalancedStreams => "Unbalanced 'xread' list of streams: for each stream \
+                                         key an ID or '$' must be specified."
+                .into(),
+        };
+        ErrorReply::err(message)
+    }
+}
+
+impl From<Error> for Frame {
+    fn from(value: Error) -> Self {
+        ErrorReply::from(value).into()
+    }
 }
 
 impl TryFrom<Frame> for Command {
@@ -24,35 +808,2161 @@ impl TryFrom<Frame> for Command {
         let Frame::Array(Some(arr)) = value else {
             return Err(Error::NotAnArray);
         };
-        let mut args = arr.iter();
+        let len = arr.len();
+        let mut args = arr.into_iter();
+
+        let name = next_bytes(&mut args)?;
+        let Some(spec) = table::lookup(&name) else {
+            let args = args.filter_map(Frame::into_bytes).collect();
+            return Err(Error::UnknownCommand(name, args));
+        };
+        if !spec.accepts(len) {
+            return Err(Error::WrongArity(spec.name));
+        }
+        let command = (spec.parse)(&mut args)?;
+        match args.next() {
+            Some(_) => Err(Error::Syntax),
+            None => Ok(command),
+        }
+    }
+}
+
+/// Parses `ACL CAT [category]`, `ACL GETUSER username`, `ACL LIST`, `ACL SETUSER username
+/// [rule ...]` or `ACL WHOAMI`.
+fn parse_acl(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let acl = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"cat", 0) => AclCommand::Cat(None),
+        (b"cat", 1) => AclCommand::Cat(Some(next_bytes(args)?)),
+        (b"getuser", 1) => AclCommand::GetUser(next_bytes(args)?),
+        (b"list", 0) => AclCommand::List,
+        (b"setuser", 1..) => AclCommand::SetUser(next_bytes(args)?, rest_bytes(args)?),
+        (b"whoami", 0) => AclCommand::WhoAmI,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "ACL")),
+    };
+    Ok(Command::Acl(acl))
+}
+
+/// Parses `AUTH password` or `AUTH username password`.
+fn parse_auth(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let first = next_bytes(args)?;
+    match args.len() {
+        0 => Ok(Command::Auth(None, first)),
+        1 => Ok(Command::Auth(Some(first), next_bytes(args)?)),
+        _ => Err(Error::Syntax),
+    }
+}
+
+/// Parses `CLIENT ID`, `CLIENT GETNAME`, `CLIENT SETNAME name`, `CLIENT LIST` or `CLIENT KILL`.
+fn parse_client(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let client = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"id", 0) => ClientCommand::Id,
+        (b"getname", 0) => ClientCommand::GetName,
+        (b"setname", 1) => ClientCommand::SetName(next_bytes(args)?),
+        (b"list", 0) => ClientCommand::List,
+        (b"kill", 1) => ClientCommand::KillAddr(next_bytes(args)?),
+        (b"kill", n) if n >= 2 && n % 2 == 0 => {
+            let mut filter = ClientFilter {
+                skip_me: true,
+                ..ClientFilter::default()
+            };
+            for (option, value) in rest_pairs(args)? {
+                match option.to_ascii_lowercase().as_slice() {
+                    b"id" => {
+                        let id = parse_integer(&value).and_then(|id| u64::try_from(id).ok());
+                        filter.id = Some(id.ok_or(Error::NotAnInteger)?);
+                    }
+                    b"addr" => filter.addr = Some(value),
+                    b"type" => {
+                        filter.kind = Some(match value.to_ascii_lowercase().as_slice() {
+                            b"normal" => ClientType::Normal,
+                            b"replica" | b"slave" => ClientType::Replica,
+                            b"pubsub" => ClientType::PubSub,
+                            _ => return Err(Error::UnknownClientType(value)),
+                        })
+                    }
+                    b"skipme" => {
+                        filter.skip_me = match value.to_ascii_lowercase().as_slice() {
+                            b"yes" => true,
+                            b"no" => false,
+                            _ => return Err(Error::Syntax),
+                        }
+                    }
+                    _ => return Err(Error::Syntax),
+                }
+            }
+            ClientCommand::Kill(filter)
+        }
+        _ => return Err(Error::UnknownSubcommand(subcommand, "CLIENT")),
+    };
+    Ok(Command::Client(client))
+}
+
+/// Parses `HELLO [protover [AUTH username password] [SETNAME name]]`. Unsupported versions are
+/// replied to once applied, as their error isn't a generic one.
+fn parse_hello(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let Some(protover) = args.next() else {
+        return Ok(Command::Hello(None, None, None));
+    };
+    let protover = protover.into_bytes().ok_or(Error::WrongType)?;
+    let protover = parse_integer(&protover).ok_or(Error::InvalidProtocolVersion)?;
+    let (mut auth, mut name) = (None, None);
+    while let Some(option) = args.next() {
+        let option = option.into_bytes().ok_or(Error::WrongType)?;
+        match option.to_ascii_lowercase().as_slice() {
+            b"auth" if args.len() >= 2 => auth = Some((next_bytes(args)?, next_bytes(args)?)),
+            b"setname" if args.len() >= 1 => name = Some(next_bytes(args)?),
+            _ => return Err(Error::HelloOption(option)),
+        }
+    }
+    Ok(Command::Hello(Some(protover), auth, name))
+}
+
+/// Parses `COMMAND`, `COMMAND COUNT`, `COMMAND INFO [name ...]` or `COMMAND DOCS [name ...]`.
+fn parse_command(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    if args.len() == 0 {
+        return Ok(Command::Introspect(CommandCommand::All));
+    }
+    let subcommand = next_bytes(args)?;
+    let command = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"count", 0) => CommandCommand::Count,
+        (b"info", _) => CommandCommand::Info(rest_bytes(args)?),
+        (b"docs", _) => CommandCommand::Docs(rest_bytes(args)?),
+        _ => return Err(Error::UnknownSubcommand(subcommand, "COMMAND")),
+    };
+    Ok(Command::Introspect(command))
+}
+
+/// Parses `SHUTDOWN [NOSAVE|SAVE]`.
+fn parse_shutdown(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let save = match args.next() {
+        None => None,
+        Some(frame) => match frame
+            .into_bytes()
+            .ok_or(Error::WrongType)?
+            .to_ascii_lowercase()
+        {
+            flag if flag == b"nosave" => Some(false),
+            flag if flag == b"save" => Some(true),
+            _ => return Err(Error::Syntax),
+        },
+    };
+    Ok(Command::Shutdown(save))
+}
+
+/// Parses `SLOWLOG GET [count]`, `SLOWLOG LEN` or `SLOWLOG RESET`.
+fn parse_slowlog(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let slowlog = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"get", 0) => SlowLogCommand::Get(Some(10)),
+        (b"get", 1) => match next_integer(args)? {
+            -1 => SlowLogCommand::Get(None),
+            count => SlowLogCommand::Get(Some(
+                usize::try_from(count).map_err(|_| Error::InvalidSlowLogCount)?,
+            )),
+        },
+        (b"len", 0) => SlowLogCommand::Len,
+        (b"reset", 0) => SlowLogCommand::Reset,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "SLOWLOG")),
+    };
+    Ok(Command::SlowLog(slowlog))
+}
+
+/// Parses `LATENCY LATEST`, `LATENCY HISTORY event`, `LATENCY RESET [event ...]` or
+/// `LATENCY DOCTOR`.
+fn parse_latency(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let latency = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"latest", 0) => LatencyCommand::Latest,
+        (b"history", 1) => LatencyCommand::History(next_bytes(args)?),
+        (b"reset", _) => LatencyCommand::Reset(rest_bytes(args)?),
+        (b"doctor", 0) => LatencyCommand::Doctor,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "LATENCY")),
+    };
+    Ok(Command::Latency(latency))
+}
+
+/// Parses `DEBUG OBJECT key`, `DEBUG SLEEP seconds`, `DEBUG RELOAD`, `DEBUG SET-ACTIVE-EXPIRE 0|1`
+/// or `DEBUG CHANGE-REPL-ID`.
+fn parse_debug(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let debug = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"object", 1) => DebugCommand::Object(next_bytes(args)?),
+        (b"sleep", 1) => {
+            let seconds = parse_float(&next_bytes(args)?).ok_or(Error::NotAFloat)?;
+            DebugCommand::Sleep(Duration::from_secs_f64(seconds.clamp(0.0, 1e9)))
+        }
+        (b"reload", 0) => DebugCommand::Reload,
+        (b"set-active-expire", 1) => DebugCommand::SetActiveExpire(next_integer(args)? != 0),
+        (b"change-repl-id", 0) => DebugCommand::ChangeReplId,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "DEBUG")),
+    };
+    Ok(Command::Debug(debug))
+}
+
+/// Parses `CONFIG GET pattern [pattern ...]`, `CONFIG SET parameter value [parameter value ...]` or
+/// `CONFIG REWRITE`.
+fn parse_config(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let config = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"get", 1..) => ConfigCommand::Get(rest_bytes(args)?),
+        (b"set", n) if n >= 2 && n % 2 == 0 => ConfigCommand::Set(rest_pairs(args)?),
+        (b"rewrite", 0) => ConfigCommand::Rewrite,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "CONFIG")),
+    };
+    Ok(Command::Config(config))
+}
+
+/// Parses `OBJECT ENCODING key`, `OBJECT FREQ key`, `OBJECT IDLETIME key` or `OBJECT REFCOUNT
+/// key`.
+fn parse_object(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let object = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"encoding", 1) => ObjectCommand::Encoding,
+        (b"freq", 1) => ObjectCommand::Freq,
+        (b"idletime", 1) => ObjectCommand::IdleTime,
+        (b"refcount", 1) => ObjectCommand::RefCount,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "OBJECT")),
+    };
+    Ok(Command::Object(object, next_bytes(args)?))
+}
+
+/// Parses `MEMORY USAGE key [SAMPLES count]`, `MEMORY STATS` or `MEMORY DOCTOR`.
+fn parse_memory_command(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let memory = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"usage", 1) => MemoryCommand::Usage(next_bytes(args)?, Some(5)),
+        (b"usage", 3) => {
+            let key = next_bytes(args)?;
+            if !next_bytes(args)?.eq_ignore_ascii_case(b"samples") {
+                return Err(Error::Syntax);
+            }
+            // like Redis, 0 samples every element
+            match usize::try_from(next_integer(args)?).map_err(|_| Error::Syntax)? {
+                0 => MemoryCommand::Usage(key, None),
+                samples => MemoryCommand::Usage(key, Some(samples)),
+            }
+        }
+        (b"stats", 0) => MemoryCommand::Stats,
+        (b"doctor", 0) => MemoryCommand::Doctor,
+        _ => return Err(Error::UnknownSubcommand(subcommand, "MEMORY")),
+    };
+    Ok(Command::Memory(memory))
+}
+
+/// Parses `PSYNC replicationid offset`, whose arguments are ignored as only full resyncs are
+/// supported.
+fn parse_psync(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    next_bytes(args)?;
+    next_integer(args)?;
+    Ok(Command::PSync)
+}
+
+/// Parses `PUBSUB CHANNELS [pattern]`, `PUBSUB NUMPAT` or `PUBSUB NUMSUB [channel ...]`.
+fn parse_pubsub(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let pubsub = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"channels", 0) => PubSubCommand::Channels(None),
+        (b"channels", 1) => PubSubCommand::Channels(Some(next_bytes(args)?)),
+        (b"numpat", 0) => PubSubCommand::NumPat,
+        (b"numsub", _) => PubSubCommand::NumSub(rest_bytes(args)?),
+        _ => return Err(Error::UnknownSubcommand(subcommand, "PUBSUB")),
+    };
+    Ok(Command::PubSub(pubsub))
+}
+
+/// Parses `REPLCONF option value [option value ...]`.
+fn parse_replconf(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    if !args.len().is_multiple_of(2) {
+        return Err(Error::Syntax);
+    }
+    let mut options = Vec::with_capacity(args.len() / 2);
+    for (option, value) in rest_pairs(args)? {
+        options.push(match option.to_ascii_lowercase().as_slice() {
+            b"ack" => ReplConfOption::Ack(
+                parse_integer(&value)
+                    .and_then(|o| u64::try_from(o).ok())
+                    .ok_or(Error::NotAnInteger)?,
+            ),
+            b"capa" => ReplConfOption::Capa,
+            b"getack" => ReplConfOption::GetAck,
+            b"listening-port" => ReplConfOption::ListeningPort(
+                parse_integer(&value)
+                    .and_then(|p| u16::try_from(p).ok())
+                    .ok_or(Error::NotAnInteger)?,
+            ),
+            _ => return Err(Error::UnknownReplConfOption(option)),
+        });
+    }
+    Ok(Command::ReplConf(options))
+}
+
+/// Parses `REPLICAOF host port` or `REPLICAOF NO ONE`.
+fn parse_replicaof(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let host = next_bytes(args)?;
+    let port = next_bytes(args)?;
+    if host.eq_ignore_ascii_case(b"no") && port.eq_ignore_ascii_case(b"one") {
+        return Ok(Command::ReplicaOf(None));
+    }
+    let host = String::from_utf8(host.to_vec()).map_err(|_| Error::Syntax)?;
+    let port = parse_integer(&port).and_then(|p| u16::try_from(p).ok());
+    Ok(Command::ReplicaOf(Some((
+        host,
+        port.ok_or(Error::InvalidPort)?,
+    ))))
+}
+
+/// Parses `WAIT numreplicas timeout`.
+fn parse_wait(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    // like Redis, a negative number of replicas is satisfied by none
+    let replicas = usize::try_from(next_integer(args)?).unwrap_or(0);
+    let timeout = match next_integer(args)? {
+        ..=-1 => return Err(Error::NegativeTimeout),
+        0 => None,
+        ms => Some(Duration::from_millis(ms as u64)),
+    };
+    Ok(Command::Wait(replicas, timeout))
+}
+
+/// Parses `SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT timestamp |
+/// PXAT timestamp | KEEPTTL]`.
+fn parse_set(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let value = next_bytes(args)?;
+    let mut options = SetOptions::default();
+    while let Ok(option) = next_bytes(args) {
+        let has_ttl_option = options.expiry.is_some() || options.keep_ttl;
+        let expiry = match option.to_ascii_lowercase().as_slice() {
+            b"nx" if options.exists.is_none() => {
+                options.exists = Some(false);
+                continue;
+            }
+            b"xx" if options.exists.is_none() => {
+                options.exists = Some(true);
+                continue;
+            }
+            b"get" => {
+                options.get = true;
+                continue;
+            }
+            b"keepttl" if !has_ttl_option => {
+                options.keep_ttl = true;
+                continue;
+            }
+            b"ex" if !has_ttl_option => Expiry::In(next_seconds(args, "set")?),
+            b"px" if !has_ttl_option => Expiry::In(next_integer(args)?),
+            b"exat" if !has_ttl_option => Expiry::At(next_seconds(args, "set")?),
+            b"pxat" if !has_ttl_option => Expiry::At(next_integer(args)?),
+            _ => return Err(Error::Syntax),
+        };
+        let (Expiry::In(ms) | Expiry::At(ms)) = expiry;
+        if ms <= 0 {
+            return Err(Error::InvalidExpireTime("set"));
+        }
+        options.expiry = Some(expiry);
+    }
+    Ok(Command::Set(key, value, options))
+}
+
+/// Parses `EXPIRE key seconds [NX | XX | GT | LT]`, or its variants named `name`: in milliseconds
+/// if `millis`, and at a unix timestamp rather than relative to now if `at`.
+fn parse_expire(
+    args: &mut IntoIter<Frame>,
+    name: &'static str,
+    millis: bool,
+    at: bool,
+) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let ms = match millis {
+        true => next_integer(args)?,
+        false => next_seconds(args, name)?,
+    };
+    let expiry = if at { Expiry::At(ms) } else { Expiry::In(ms) };
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    while let Ok(option) = next_bytes(args) {
+        match option.to_ascii_lowercase().as_slice() {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"gt" => gt = true,
+            b"lt" => lt = true,
+            _ => return Err(Error::UnsupportedOption(option)),
+        }
+    }
+    if nx && (xx || gt || lt) {
+        return Err(Error::IncompatibleOptions("NX and XX, GT or LT"));
+    }
+    if gt && lt {
+        return Err(Error::IncompatibleOptions("GT and LT"));
+    }
+    let options = ExpireOptions {
+        exists: (nx || xx).then_some(xx),
+        compare: (gt || lt).then_some(if gt {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        }),
+    };
+    Ok(Command::Expire(key, expiry, options))
+}
+
+/// Parses `SETEX key seconds value` or, if `millis`, `PSETEX key milliseconds value`, which are
+/// `SET` with `EX` or `PX`.
+fn parse_setex(args: &mut IntoIter<Frame>, millis: bool) -> Result<Command, Error> {
+    let name = if millis { "psetex" } else { "setex" };
+    let key = next_bytes(args)?;
+    let ms = match millis {
+        true => next_integer(args)?,
+        false => next_seconds(args, name)?,
+    };
+    if ms <= 0 {
+        return Err(Error::InvalidExpireTime(name));
+    }
+    let options = SetOptions {
+        expiry: Some(Expiry::In(ms)),
+        ..SetOptions::default()
+    };
+    Ok(Command::Set(key, next_bytes(args)?, options))
+}
+
+/// Parses `GETEX key [EX seconds | PX milliseconds | EXAT timestamp | PXAT timestamp | PERSIST]`.
+fn parse_getex(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let Ok(option) = next_bytes(args) else {
+        return Ok(Command::GetEx(key, None));
+    };
+    let expiry = match option.to_ascii_lowercase().as_slice() {
+        b"persist" => None,
+        b"ex" => Some(Expiry::In(next_seconds(args, "getex")?)),
+        b"px" => Some(Expiry::In(next_integer(args)?)),
+        b"exat" => Some(Expiry::At(next_seconds(args, "getex")?)),
+        b"pxat" => Some(Expiry::At(next_integer(args)?)),
+        _ => return Err(Error::Syntax),
+    };
+    if args.len() != 0 {
+        return Err(Error::Syntax);
+    }
+    if let Some(Expiry::In(ms) | Expiry::At(ms)) = expiry {
+        if ms <= 0 {
+            return Err(Error::InvalidExpireTime("getex"));
+        }
+    }
+    Ok(Command::GetEx(key, Some(expiry)))
+}
+
+/// Parses `SETRANGE key offset value`.
+fn parse_setrange(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::SetRange(
+        next_bytes(args)?,
+        next_integer(args)?
+            .try_into()
+            .map_err(|_| Error::OffsetOutOfRange)?,
+        next_bytes(args)?,
+    ))
+}
+
+/// Parses `SETBIT key offset value`.
+fn parse_setbit(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let offset = next_bit_offset(args)?;
+    let bit = match next_bytes(args)?.as_ref() {
+        b"0" => false,
+        b"1" => true,
+        _ => return Err(Error::BitOutOfRange),
+    };
+    Ok(Command::SetBit(key, offset, bit))
+}
+
+/// Parses `BITCOUNT key [start end [BYTE | BIT]]`.
+fn parse_bitcount(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    if args.len() == 0 {
+        return Ok(Command::BitCount(key, None));
+    }
+    let start = next_integer(args)?;
+    let end = next_integer(args).map_err(|e| match e {
+        Error::MissingArgument => Error::Syntax,
+        e => e,
+    })?;
+    let range = BitRange {
+        start,
+        end: Some(end),
+        bits: next_bit_unit(args)?,
+    };
+    Ok(Command::BitCount(key, Some(range)))
+}
+
+/// Parses `BITPOS key bit [start [end [BYTE | BIT]]]`.
+fn parse_bitpos(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let bit = match next_integer(args)? {
+        0 => false,
+        1 => true,
+        _ => return Err(Error::InvalidBitPosBit),
+    };
+    if args.len() == 0 {
+        return Ok(Command::BitPos(key, bit, None));
+    }
+    let start = next_integer(args)?;
+    let end = match args.len() {
+        0 => None,
+        _ => Some(next_integer(args)?),
+    };
+    let range = BitRange {
+        start,
+        end,
+        bits: next_bit_unit(args)?,
+    };
+    Ok(Command::BitPos(key, bit, Some(range)))
+}
+
+/// Parses `BITFIELD key [GET type offset | SET type offset value | INCRBY type offset increment |
+/// OVERFLOW WRAP | SAT | FAIL ...]`, where `OVERFLOW` applies to the subcommands following it.
+fn parse_bitfield(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    while let Some(op) = args.next() {
+        let op = op.into_bytes().ok_or(Error::WrongType)?;
+        let op = op.to_ascii_lowercase();
+        if op == b"overflow" {
+            overflow = match next_bytes(args)?.to_ascii_lowercase().as_slice() {
+                b"wrap" => Overflow::Wrap,
+                b"sat" => Overflow::Sat,
+                b"fail" => Overflow::Fail,
+                _ => return Err(Error::InvalidOverflow),
+            };
+            continue;
+        }
+        let arity = match op.as_slice() {
+            b"get" => 2,
+            b"set" | b"incrby" => 3,
+            _ => return Err(Error::Syntax),
+        };
+        if args.len() < arity {
+            return Err(Error::Syntax);
+        }
+        let ty = next_bitfield_type(args)?;
+        // `#n` is the offset of the nth integer of the type
+        let offset = next_bytes(args)?;
+        let offset = match offset.strip_prefix(b"#") {
+            Some(index) => parse_integer(index).and_then(|i| i.checked_mul(ty.bits as i64)),
+            None => parse_integer(&offset),
+        };
+        let offset = offset
+            .and_then(|o| u64::try_from(o).ok())
+            .filter(|&o| o + (ty.bits as u64) <= 4 * 1024 * 1024 * 1024)
+            .ok_or(Error::BitOffsetOutOfRange)?;
+        ops.push(match op.as_slice() {
+            b"get" => BitFieldOp::Get(ty, offset),
+            b"set" => BitFieldOp::Set(ty, offset, next_integer(args)?, overflow),
+            _ => BitFieldOp::IncrBy(ty, offset, next_integer(args)?, overflow),
+        });
+    }
+    Ok(Command::BitField(key, ops))
+}
+
+/// Advances the iterator and parses the next value as a type of `BITFIELD`, e.g. `i16` or `u8`.
+fn next_bitfield_type(it: &mut IntoIter<Frame>) -> Result<BitFieldType, Error> {
+    let ty = next_bytes(it)?;
+    let (signed, max) = match ty.first().map(u8::to_ascii_lowercase) {
+        Some(b'i') => (true, 64),
+        Some(b'u') => (false, 63),
+        _ => return Err(Error::InvalidBitFieldType),
+    };
+    match parse_integer(&ty[1..]) {
+        Some(bits @ 1..) if bits <= max => Ok(BitFieldType {
+            signed,
+            bits: bits as u32,
+        }),
+        _ => Err(Error::InvalidBitFieldType),
+    }
+}
+
+/// Parses `BITOP operation destkey key [key ...]`.
+fn parse_bitop(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let op = match next_bytes(args)?.to_ascii_lowercase().as_slice() {
+        b"and" => BitOp::And,
+        b"or" => BitOp::Or,
+        b"xor" => BitOp::Xor,
+        b"not" => BitOp::Not,
+        _ => return Err(Error::Syntax),
+    };
+    let destination = next_bytes(args)?;
+    let keys = rest_bytes(args)?;
+    if matches!(op, BitOp::Not) && keys.len() != 1 {
+        return Err(Error::BitOpNotArity);
+    }
+    Ok(Command::BitOp(op, destination, keys))
+}
+
+/// Parses `DECRBY key decrement`.
+fn parse_decrby(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::IncrBy(
+        next_bytes(args)?,
+        next_integer(args)?
+            .checked_neg()
+            .ok_or(Error::DecrementOverflow)?,
+    ))
+}
+
+/// Parses the `key value [key value ...]` of `MSET` or `MSETNX`.
+fn parse_mset(
+    args: &mut IntoIter<Frame>,
+    command: &'static str,
+) -> Result<Vec<(Bytes, Bytes)>, Error> {
+    match args.len() % 2 {
+        0 => rest_pairs(args),
+        _ => Err(Error::WrongArity(command)),
+    }
+}
+
+/// Parses `ZADD key [NX | XX] [GT | LT] [CH] score member [score member ...]`.
+fn parse_zadd(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let (mut nx, mut xx, mut gt, mut lt, mut changed) = (false, false, false, false, false);
+    let mut pairs = vec![];
+    while let Ok(arg) = next_bytes(args) {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"gt" => gt = true,
+            b"lt" => lt = true,
+            b"ch" => changed = true,
+            _ => {
+                pairs.push((arg, next_bytes(args).map_err(|_| Error::Syntax)?));
+                pairs.extend(rest_pairs(args).map_err(|_| Error::Syntax)?);
+                break;
+            }
+        }
+    }
+    if nx && xx {
+        return Err(Error::IncompatibleOptions("XX and NX"));
+    }
+    if u8::from(nx) + u8::from(gt) + u8::from(lt) > 1 {
+        return Err(Error::IncompatibleOptions("GT, LT, and/or NX"));
+    }
+    let members = pairs
+        .into_iter()
+        .map(|(score, member)| Some((parse_float(&score)?, member)))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::NotAFloat)?;
+    if members.is_empty() {
+        return Err(Error::MissingArgument);
+    }
+    let options = ZAddOptions {
+        exists: (nx || xx).then_some(xx),
+        comparison: (gt || lt).then_some(if gt {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        }),
+        changed,
+    };
+    Ok(Command::ZAdd(key, members, options))
+}
+
+/// Parses `GEOADD key [NX | XX] [CH] longitude latitude member [...]`, which is `ZADD` with the
+/// members' geohashes as their scores.
+fn parse_geoadd(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let args = rest_bytes(args)?;
+    let (mut nx, mut xx, mut changed) = (false, false, false);
+    let mut options = 0;
+    for option in &args {
+        match option.to_ascii_lowercase().as_slice() {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"ch" => changed = true,
+            _ => break,
+        }
+        options += 1;
+    }
+    if nx && xx {
+        return Err(Error::IncompatibleOptions("XX and NX"));
+    }
+    let points = &args[options..];
+    if points.is_empty() || !points.len().is_multiple_of(3) {
+        return Err(Error::Syntax);
+    }
+    let mut members = Vec::new();
+    for point in points.chunks(3) {
+        let (lon, lat) = parse_lon_lat(&point[0], &point[1])?;
+        members.push((geo::encode(lon, lat) as f64, point[2].clone()));
+    }
+    let options = ZAddOptions {
+        exists: (nx || xx).then_some(xx),
+        comparison: None,
+        changed,
+    };
+    Ok(Command::ZAdd(key, members, options))
+}
+
+/// Parses `GEODIST key member1 member2 [M | KM | FT | MI]`.
+fn parse_geodist(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let (key, member1, member2) = (next_bytes(args)?, next_bytes(args)?, next_bytes(args)?);
+    let unit = match args.len() {
+        0 => 1.0,
+        1 => next_unit(args)?,
+        _ => return Err(Error::Syntax),
+    };
+    Ok(Command::GeoDist(key, member1, member2, unit))
+}
+
+/// Parses `GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude BYRADIUS radius unit |
+/// BYBOX width height unit [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]`.
+fn parse_geosearch(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let (mut from, mut shape, mut unit) = (None, None, 1.0);
+    let mut search = GeoSearch {
+        from: GeoFrom::LonLat(0.0, 0.0),
+        shape: GeoShape::Radius(0.0),
+        unit,
+        descending: None,
+        count: None,
+        with_coord: false,
+        with_dist: false,
+        with_hash: false,
+    };
+    let mut any = false;
+    while let Ok(arg) = next_bytes(args) {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"frommember" if from.is_none() => from = Some(GeoFrom::Member(next_bytes(args)?)),
+            b"fromlonlat" if from.is_none() => {
+                let (lon, lat) = parse_lon_lat(&next_bytes(args)?, &next_bytes(args)?)?;
+                from = Some(GeoFrom::LonLat(lon, lat));
+            }
+            b"frommember" | b"fromlonlat" => return Err(Error::GeoSearchFrom),
+            b"byradius" if shape.is_none() => {
+                let radius = parse_float(&next_bytes(args)?).ok_or(Error::NotAFloat)?;
+                if radius < 0.0 {
+                    return Err(Error::NegativeRadius);
+                }
+                shape = Some(GeoShape::Radius(radius));
+                unit = next_unit(args)?;
+            }
+            b"bybox" if shape.is_none() => {
+                let width = parse_float(&next_bytes(args)?).ok_or(Error::NotAFloat)?;
+                let height = parse_float(&next_bytes(args)?).ok_or(Error::NotAFloat)?;
+                if width < 0.0 || height < 0.0 {
+                    return Err(Error::NegativeBox);
+                }
+                shape = Some(GeoShape::Box(width, height));
+                unit = next_unit(args)?;
+            }
+            b"byradius" | b"bybox" => return Err(Error::GeoSearchBy),
+            b"asc" => search.descending = Some(false),
+            b"desc" => search.descending = Some(true),
+            b"count" => {
+                let count = next_integer(args)?;
+                if count <= 0 {
+                    return Err(Error::GeoCountNotPositive);
+                }
+                search.count = Some((count as usize, false));
+            }
+            b"any" => any = true,
+            b"withcoord" => search.with_coord = true,
+            b"withdist" => search.with_dist = true,
+            b"withhash" => search.with_hash = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    search.from = from.ok_or(Error::GeoSearchFrom)?;
+    search.shape = shape.ok_or(Error::GeoSearchBy)?;
+    search.unit = unit;
+    if any {
+        let count = search.count.as_mut().ok_or(Error::AnyWithoutCount)?;
+        count.1 = true;
+    }
+    Ok(Command::GeoSearch(key, search))
+}
+
+/// Parses a longitude and a latitude that can be indexed by `GEOADD`.
+fn parse_lon_lat(lon: &[u8], lat: &[u8]) -> Result<(f64, f64), Error> {
+    let lon = parse_float(lon).ok_or(Error::NotAFloat)?;
+    let lat = parse_float(lat).ok_or(Error::NotAFloat)?;
+    match geo::is_valid(lon, lat) {
+        true => Ok((lon, lat)),
+        false => Err(Error::InvalidLonLat(lon, lat)),
+    }
+}
+
+/// Advances the iterator and parses the next value as a unit of distance, returning how many
+/// meters it is.
+fn next_unit(it: &mut IntoIter<Frame>) -> Result<f64, Error> {
+    match next_bytes(it)?.to_ascii_lowercase().as_slice() {
+        b"m" => Ok(1.0),
+        b"km" => Ok(1000.0),
+        b"ft" => Ok(0.3048),
+        b"mi" => Ok(1609.34),
+        _ => Err(Error::UnsupportedUnit),
+    }
+}
+
+/// What a `ZRANGE` command's `start` and `stop` arguments refer to.
+#[derive(PartialEq)]
+enum By {
+    Index,
+    Score,
+    Lex,
+}
+
+/// Parses `ZCOUNT key min max` or `ZLEXCOUNT key min max`.
+fn parse_zcount(args: &mut IntoIter<Frame>, by: By) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let (min, max) = (next_bytes(args)?, next_bytes(args)?);
+    let range = match by {
+        By::Lex => ZRangeBy::Lex(parse_lex_bound(&min)?, parse_lex_bound(&max)?),
+        _ => ZRangeBy::Score(parse_score_bound(&min)?, parse_score_bound(&max)?),
+    };
+    Ok(Command::ZCount(key, range))
+}
+
+/// Parses `ZINCRBY key increment member`.
+fn parse_zincrby(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::ZIncrBy(
+        next_bytes(args)?,
+        parse_float(&next_bytes(args)?).ok_or(Error::NotAFloat)?,
+        next_bytes(args)?,
+    ))
+}
+
+/// Parses `ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]`, or,
+/// given `By::Score` or `By::Lex`, `ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]`
+/// or `ZRANGEBYLEX key min max [LIMIT offset count]`.
+fn parse_zrange(args: &mut IntoIter<Frame>, mut by: By) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let (start, stop) = (next_bytes(args)?, next_bytes(args)?);
+    let zrange = by == By::Index;
+    let mut options = ZRangeOptions::default();
+    while let Ok(option) = next_bytes(args) {
+        match option.to_ascii_lowercase().as_slice() {
+            b"byscore" if by == By::Index => by = By::Score,
+            b"bylex" if by == By::Index => by = By::Lex,
+            b"rev" if zrange => options.rev = true,
+            b"limit" => options.limit = Some((next_integer(args)?, next_integer(args)?)),
+            b"withscores" => options.with_scores = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    // with REV, score and lex ranges are given from the maximum down to the minimum
+    let (min, max) = if options.rev && by != By::Index {
+        (stop, start)
+    } else {
+        (start, stop)
+    };
+    let by = match by {
+        By::Index if options.limit.is_some() => return Err(Error::Syntax),
+        By::Lex if options.with_scores => return Err(Error::Syntax),
+        By::Index => ZRangeBy::Index(
+            parse_integer(&min).ok_or(Error::NotAnInteger)?,
+            parse_integer(&max).ok_or(Error::NotAnInteger)?,
+        ),
+        By::Score => ZRangeBy::Score(parse_score_bound(&min)?, parse_score_bound(&max)?),
+        By::Lex => ZRangeBy::Lex(parse_lex_bound(&min)?, parse_lex_bound(&max)?),
+    };
+    Ok(Command::ZRange(key, by, options))
+}
+
+/// Parses a bound of a score range, which is exclusive if prefixed with `(`.
+fn parse_score_bound(bytes: &Bytes) -> Result<Bound<f64>, Error> {
+    match bytes.strip_prefix(b"(") {
+        Some(score) => parse_float(score).map(Bound::Excluded),
+        None => parse_float(bytes).map(Bound::Included),
+    }
+    .ok_or(Error::MinMaxNotAFloat)
+}
+
+/// Parses a bound of a lexicographical range, i.e. `-`, `+`, or a member prefixed with `[` if
+/// inclusive or `(` if exclusive.
+fn parse_lex_bound(bytes: &Bytes) -> Result<LexBound, Error> {
+    match bytes.first() {
+        Some(b'-') if bytes.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bytes.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Included(bytes.slice(1..))),
+        Some(b'(') => Ok(LexBound::Excluded(bytes.slice(1..))),
+        _ => Err(Error::InvalidLexRange),
+    }
+}
+
+/// Parses the ID given to `XADD`, i.e. `*`, `ms-*`, `ms-seq` or `ms`.
+fn parse_xadd_id(bytes: &Bytes) -> Result<XAddId, Error> {
+    if bytes.as_ref() == b"*" {
+        return Ok(XAddId::Auto);
+    }
+    match bytes.strip_suffix(b"-*") {
+        Some(ms) => parse_u64(ms).map(XAddId::Seq),
+        None => parse_stream_id(bytes, 0).map(XAddId::Explicit),
+    }
+}
+
+/// Parses `XADD key id field value [field value ...]`.
+fn parse_xadd(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    if !args.len().is_multiple_of(2) {
+        return Err(Error::WrongArity("xadd"));
+    }
+    Ok(Command::XAdd(
+        next_bytes(args)?,
+        parse_xadd_id(&next_bytes(args)?)?,
+        rest_pairs(args)?,
+    ))
+}
+
+/// Parses `XACK key group id [id ...]`.
+fn parse_xack(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::XAck(
+        next_bytes(args)?,
+        next_bytes(args)?,
+        rest_bytes(args)?
+            .iter()
+            .map(|id| parse_stream_id(id, 0))
+            .collect::<Result<_, _>>()?,
+    ))
+}
+
+/// Parses `XRANGE key start end [COUNT count]`, or `XREVRANGE key end start [COUNT count]` if `rev`
+/// is set.
+fn parse_xrange(args: &mut IntoIter<Frame>, rev: bool) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let (mut start, mut end) = (next_bytes(args)?, next_bytes(args)?);
+    if rev {
+        std::mem::swap(&mut start, &mut end);
+    }
+    let (start, end) = (parse_range_start(&start)?, parse_range_end(&end)?);
+    let count = match next_bytes(args) {
+        Ok(option) if option.eq_ignore_ascii_case(b"count") => {
+            // like Redis, a negative count returns nothing
+            Some(next_integer(args)?.try_into().unwrap_or(0))
+        }
+        Ok(_) => return Err(Error::Syntax),
+        Err(_) => None,
+    };
+    Ok(Command::XRange(key, start, end, count, rev))
+}
+
+/// Parses `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]`, or if
+/// `group` is set, `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK]
+/// STREAMS key [key ...] id [id ...]`.
+fn parse_xread(args: &mut IntoIter<Frame>, group: bool) -> Result<Command, Error> {
+    let group = if !group {
+        None
+    } else if next_bytes(args)?.eq_ignore_ascii_case(b"group") {
+        Some((next_bytes(args)?, next_bytes(args)?))
+    } else {
+        return Err(Error::Syntax);
+    };
+    let mut options = XReadOptions::default();
+    loop {
+        match next_bytes(args)?.to_ascii_lowercase().as_slice() {
+            b"count" => options.count = Some(next_integer(args)?.try_into().unwrap_or(0)),
+            b"block" => {
+                let ms = next_integer(args)?;
+                let ms: u64 = ms.try_into().map_err(|_| Error::NegativeTimeout)?;
+                options.block = Some((ms > 0).then(|| Duration::from_millis(ms)));
+            }
+            b"noack" if group.is_some() => options.no_ack = true,
+            b"streams" => break,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    let mut keys = rest_bytes(args)?;
+    if keys.is_empty() || keys.len() % 2 == 1 {
+        return Err(Error::UnbalancedStreams);
+    }
+    let ids = keys
+        .split_off(keys.len() / 2)
+        .iter()
+        .map(|id| match (id.as_ref(), &group) {
+            (b"$", None) | (b">", Some(_)) => Ok(None),
+            (b"$", Some(_)) => Err(Error::InvalidGroupId),
+            _ => parse_stream_id(id, 0).map(Some),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(match group {
+        Some((group, consumer)) => Command::XReadGroup(group, consumer, keys, ids, options),
+        None => Command::XRead(keys, ids, options),
+    })
+}
+
+/// Parses the subcommands of `XGROUP`.
+fn parse_xgroup(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let subcommand = next_bytes(args)?;
+    let id = |id: Bytes| match id.as_ref() {
+        b"$" => Ok(None),
+        _ => parse_stream_id(&id, 0).map(Some),
+    };
+    let xgroup = match (subcommand.to_ascii_lowercase().as_slice(), args.len()) {
+        (b"create", 3 | 4) => XGroupCommand::Create(
+            next_bytes(args)?,
+            next_bytes(args)?,
+            id(next_bytes(args)?)?,
+            match next_bytes(args) {
+                Ok(option) if option.eq_ignore_ascii_case(b"mkstream") => true,
+                Ok(_) => return Err(Error::Syntax),
+                Err(_) => false,
+            },
+        ),
+        (b"createconsumer", 3) => {
+            XGroupCommand::CreateConsumer(next_bytes(args)?, next_bytes(args)?, next_bytes(args)?)
+        }
+        (b"delconsumer", 3) => {
+            XGroupCommand::DelConsumer(next_bytes(args)?, next_bytes(args)?, next_bytes(args)?)
+        }
+        (b"destroy", 2) => XGroupCommand::Destroy(next_bytes(args)?, next_bytes(args)?),
+        (b"setid", 3) => {
+            XGroupCommand::SetId(next_bytes(args)?, next_bytes(args)?, id(next_bytes(args)?)?)
+        }
+        _ => return Err(Error::UnknownSubcommand(subcommand, "XGROUP")),
+    };
+    Ok(Command::XGroup(xgroup))
+}
+
+/// Parses `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`.
+fn parse_xpending(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let group = next_bytes(args)?;
+    if args.len() == 0 {
+        return Ok(Command::XPending(key, group, None));
+    }
+    let mut start = next_bytes(args)?;
+    let mut min_idle = 0;
+    if start.eq_ignore_ascii_case(b"idle") {
+        min_idle = next_integer(args)?;
+        start = next_bytes(args)?;
+    }
+    let range = XPendingRange {
+        min_idle,
+        start: parse_range_start(&start)?,
+        end: parse_range_end(&next_bytes(args)?)?,
+        count: next_integer(args)?.try_into().unwrap_or(0),
+        consumer: next_bytes(args).ok(),
+    };
+    Ok(Command::XPending(key, group, Some(range)))
+}
+
+/// Parses `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-ms]
+/// [RETRYCOUNT count] [FORCE] [JUSTID]`.
+fn parse_xclaim(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let group = next_bytes(args)?;
+    let consumer = next_bytes(args)?;
+    let mut options = XClaimOptions {
+        min_idle: next_integer(args)?.max(0),
+        ..Default::default()
+    };
+    let mut ids = vec![];
+    while let Ok(arg) = next_bytes(args) {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"idle" => options.idle = Some(next_integer(args)?),
+            b"time" => options.time = Some(next_integer(args)?),
+            b"retrycount" => {
+                let count = next_integer(args)?;
+                options.retry_count = Some(count.try_into().map_err(|_| Error::NotAnInteger)?);
+            }
+            b"force" => options.force = true,
+            b"justid" => options.just_id = true,
+            // IDs come before options, so anything else after an option is invalid
+            _ if options.idle.is_none()
+                && options.time.is_none()
+                && options.retry_count.is_none()
+                && !options.force
+                && !options.just_id =>
+            {
+                ids.push(parse_stream_id(&arg, 0)?)
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok(Command::XClaim(key, group, consumer, ids, options))
+}
+
+/// Parses the start of a range of stream IDs, i.e. `-`, or an ID that is exclusive if prefixed with
+/// `(`.
+fn parse_range_start(bytes: &Bytes) -> Result<Bound<StreamId>, Error> {
+    match bytes.as_ref() {
+        b"-" => Ok(Bound::Included(StreamId::MIN)),
+        _ => match bytes.strip_prefix(b"(") {
+            Some(id) => parse_stream_id(id, 0).map(Bound::Excluded),
+            None => parse_stream_id(bytes, 0).map(Bound::Included),
+        },
+    }
+}
+
+/// Parses the end of a range of stream IDs, i.e. `+`, or an ID that is exclusive if prefixed with
+/// `(`. Without a sequence number, the ID includes every entry in its millisecond.
+fn parse_range_end(bytes: &Bytes) -> Result<Bound<StreamId>, Error> {
+    match bytes.as_ref() {
+        b"+" => Ok(Bound::Included(StreamId::MAX)),
+        _ => match bytes.strip_prefix(b"(") {
+            Some(id) => parse_stream_id(id, u64::MAX).map(Bound::Excluded),
+            None => parse_stream_id(bytes, u64::MAX).map(Bound::Included),
+        },
+    }
+}
+
+/// Parses `ms-seq`, or `ms` with the sequence number defaulting to `seq`.
+fn parse_stream_id(bytes: &[u8], seq: u64) -> Result<StreamId, Error> {
+    let mut parts = bytes.splitn(2, |b| *b == b'-');
+    let ms = parse_u64(parts.next().unwrap_or_default())?;
+    let seq = match parts.next() {
+        Some(seq) => parse_u64(seq)?,
+        None => seq,
+    };
+    Ok(StreamId { ms, seq })
+}
 
-        let command: Bytes = next_bytes(&mut args)?;
+/// Parses a part of a stream ID.
+fn parse_u64(bytes: &[u8]) -> Result<u64, Error> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .filter(|s| !s.starts_with('+'))
+        .and_then(|s| s.parse().ok())
+        .ok_or(Error::InvalidStreamId)
+}
+
+/// Parses `LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]`.
+fn parse_lpos(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let element = next_bytes(args)?;
+    let mut options = LPosOptions::default();
+    while let Ok(option) = next_bytes(args) {
+        match option.to_ascii_lowercase().as_slice() {
+            b"rank" => match next_integer(args)? {
+                0 => return Err(Error::ZeroRank),
+                rank => options.rank = rank,
+            },
+            b"count" => {
+                let count = next_integer(args)?;
+                options.count = Some(count.try_into().map_err(|_| Error::NegativeCount)?);
+            }
+            b"maxlen" => {
+                let max_len = next_integer(args)?;
+                options.max_len = max_len.try_into().map_err(|_| Error::NegativeMaxLen)?;
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok(Command::LPos(key, element, options))
+}
+
+/// Parses the `cursor [MATCH pattern] [COUNT count] [TYPE type]` arguments of the `SCAN` family,
+/// where only `SCAN` itself accepts `TYPE`.
+fn parse_scan(args: &mut IntoIter<Frame>, kind: bool) -> Result<(u64, ScanOptions), Error> {
+    let cursor = next_bytes(args)?;
+    let cursor = str::from_utf8(&cursor)
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .ok_or(Error::InvalidCursor)?;
+    let mut options = ScanOptions::default();
+    while let Ok(option) = next_bytes(args) {
+        match option.to_ascii_lowercase().as_slice() {
+            b"match" => options.pattern = Some(next_bytes(args)?),
+            b"count" => match next_integer(args)? {
+                count if count < 1 => return Err(Error::Syntax),
+                count => options.count = count.try_into().unwrap_or(usize::MAX),
+            },
+            b"type" if kind => options.kind = Some(next_bytes(args)?.to_ascii_lowercase().into()),
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok((cursor, options))
+}
+
+/// Parses `HSCAN`, `SSCAN` or `ZSCAN`, i.e. a key followed by the arguments of `SCAN`.
+fn parse_key_scan(
+    args: &mut IntoIter<Frame>,
+    command: fn(Bytes, u64, ScanOptions) -> Command,
+) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let (cursor, options) = parse_scan(args, false)?;
+    Ok(command(key, cursor, options))
+}
+
+/// Parses `LPOP key [count]` or `RPOP key [count]`.
+fn parse_pop(args: &mut IntoIter<Frame>, end: End) -> Result<Command, Error> {
+    let key = next_bytes(args)?;
+    let count = match args.len() {
+        0 => None,
+        _ => Some(
+            next_integer(args)?
+                .try_into()
+                .map_err(|_| Error::NotPositive)?,
+        ),
+    };
+    Ok(Command::Pop(key, end, count))
+}
+
+/// Parses `BLPOP key [key ...] timeout` or `BRPOP key [key ...] timeout`.
+fn parse_bpop(args: &mut IntoIter<Frame>, end: End) -> Result<Command, Error> {
+    let mut keys = rest_bytes(args)?;
+    let timeout = keys.pop().ok_or(Error::MissingArgument)?;
+    Ok(Command::BPop(keys, end, parse_timeout(&timeout)?))
+}
+
+/// Parses `LMOVE source destination LEFT|RIGHT LEFT|RIGHT`.
+fn parse_lmove(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::LMove(
+        next_bytes(args)?,
+        next_bytes(args)?,
+        next_end(args)?,
+        next_end(args)?,
+    ))
+}
+
+/// Parses `BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout`.
+fn parse_blmove(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::BLMove(
+        next_bytes(args)?,
+        next_bytes(args)?,
+        next_end(args)?,
+        next_end(args)?,
+        next_timeout(args)?,
+    ))
+}
+
+/// Parses `LCS key1 key2 [LEN] [IDX] [MINMATCHLEN len] [WITHMATCHLEN]`.
+fn parse_lcs(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    let (key1, key2) = (next_bytes(args)?, next_bytes(args)?);
+    let mut options = LcsOptions::default();
+    while let Ok(option) = next_bytes(args) {
+        match option.to_ascii_lowercase().as_slice() {
+            b"len" => options.len = true,
+            b"idx" => options.idx = true,
+            // like Redis, negative lengths are no minimum
+            b"minmatchlen" => options.min_match_len = next_integer(args)?.max(0) as usize,
+            b"withmatchlen" => options.with_match_len = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    if options.len && options.idx {
+        return Err(Error::LcsLenAndIdx);
+    }
+    Ok(Command::Lcs(key1, key2, options))
+}
+
+/// Parses `LINSERT key BEFORE|AFTER pivot element`.
+fn parse_linsert(args: &mut IntoIter<Frame>) -> Result<Command, Error> {
+    Ok(Command::LInsert(
+        next_bytes(args)?,
+        match next_bytes(args)?.to_ascii_lowercase().as_slice() {
+            b"before" => true,
+            b"after" => false,
+            _ => return Err(Error::Syntax),
+        },
+        next_bytes(args)?,
+        next_bytes(args)?,
+    ))
+}
 
-        return match arr.len() {
-            1 if command.eq_ignore_ascii_case(b"ping") => Ok(Command::Ping),
-            2 if command.eq_ignore_ascii_case(b"echo") => Ok(Command::Echo(next_bytes(&mut args)?)),
-            2 if command.eq_ignore_ascii_case(b"get") => Ok(Command::Get(next_bytes(&mut args)?)),
-            3 if command.eq_ignore_ascii_case(b"set") => Ok(Command::Set([
-                next_bytes(&mut args)?,
-                next_bytes(&mut args)?,
-            ])),
-            _ => Err(Error::UnknownCommand),
+impl Command {
+    /// Returns the keys a blocking command waits on for data, and for how long it may wait (`None`
+    /// meaning forever), or `None` if the command never blocks.
+    pub fn blocking(&self) -> Option<(&[Bytes], Option<Duration>)> {
+        match self {
+            Command::BLMove(source, _, _, _, timeout) => Some((slice::from_ref(source), *timeout)),
+            Command::BPop(keys, _, timeout) => Some((keys, *timeout)),
+            Command::XRead(keys, _, options) | Command::XReadGroup(_, _, keys, _, options) => {
+                options.block.map(|timeout| (keys.as_slice(), timeout))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the command in the table, which for commands parsed from several names
+    /// is one of them, as they share their flags, e.g. `incrby` for `INCR` and `DECR` too.
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Ping => "ping",
+            Command::Acl(_) => "acl",
+            Command::Append(..) => "append",
+            Command::Auth(..) => "auth",
+            Command::BgRewriteAof => "bgrewriteaof",
+            Command::BgSave => "bgsave",
+            Command::BitField(..) => "bitfield",
+            Command::BitCount(..) => "bitcount",
+            Command::BitOp(..) => "bitop",
+            Command::BitPos(..) => "bitpos",
+            Command::BLMove(..) => "blmove",
+            Command::BPop(_, End::Left, _) => "blpop",
+            Command::BPop(_, End::Right, _) => "brpop",
+            Command::Client(_) => "client",
+            Command::Introspect(_) => "command",
+            Command::Config(_) => "config",
+            Command::Debug(_) => "debug",
+            Command::Del(_) => "del",
+            Command::Discard => "discard",
+            Command::Echo(_) => "echo",
+            Command::Exec => "exec",
+            Command::Exists(_) => "exists",
+            Command::Expire(..) => "expire",
+            Command::ExpireTime(..) => "expiretime",
+            Command::GeoDist(..) => "geodist",
+            Command::GeoPos(..) => "geopos",
+            Command::GeoSearch(..) => "geosearch",
+            Command::Get(_) => "get",
+            Command::GetDel(_) => "getdel",
+            Command::GetEx(..) => "getex",
+            Command::GetBit(..) => "getbit",
+            Command::GetRange(..) => "getrange",
+            Command::HDel(..) => "hdel",
+            Command::Hello(..) => "hello",
+            Command::HExists(..) => "hexists",
+            Command::HGet(..) => "hget",
+            Command::HGetAll(_) => "hgetall",
+            Command::HIncrBy(..) => "hincrby",
+            Command::HLen(_) => "hlen",
+            Command::HScan(..) => "hscan",
+            Command::HSet(..) => "hset",
+            Command::IncrBy(..) => "incrby",
+            Command::Info(_) => "info",
+            Command::Keys(_) => "keys",
+            Command::Latency(_) => "latency",
+            Command::Lcs(..) => "lcs",
+            Command::LInsert(..) => "linsert",
+            Command::LLen(_) => "llen",
+            Command::LMove(..) => "lmove",
+            Command::LPos(..) => "lpos",
+            Command::LRange(..) => "lrange",
+            Command::LRem(..) => "lrem",
+            Command::LSet(..) => "lset",
+            Command::LTrim(..) => "ltrim",
+            Command::Memory(_) => "memory",
+            Command::MGet(_) => "mget",
+            Command::MSet(_) => "mset",
+            Command::MSetNx(_) => "msetnx",
+            Command::Monitor => "monitor",
+            Command::Multi => "multi",
+            Command::Object(..) => "object",
+            Command::Persist(_) => "persist",
+            Command::Pop(_, End::Left, _) => "lpop",
+            Command::Pop(_, End::Right, _) => "rpop",
+            Command::Pttl(_) => "pttl",
+            Command::Publish(..) => "publish",
+            Command::PubSub(_) => "pubsub",
+            Command::PSync => "psync",
+            Command::Push(_, End::Left, _) => "lpush",
+            Command::Push(_, End::Right, _) => "rpush",
+            Command::Quit => "quit",
+            Command::RandomKey => "randomkey",
+            Command::Role => "role",
+            Command::ReplConf(_) => "replconf",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::Reset => "reset",
+            Command::SAdd(..) => "sadd",
+            Command::Save => "save",
+            Command::Scan(..) => "scan",
+            Command::SCard(_) => "scard",
+            Command::SCombine(SetOp::Diff, _) => "sdiff",
+            Command::SCombine(SetOp::Inter, _) => "sinter",
+            Command::SCombine(SetOp::Union, _) => "sunion",
+            Command::SCombineStore(_, SetOp::Diff, _) => "sdiffstore",
+            Command::SCombineStore(_, SetOp::Inter, _) => "sinterstore",
+            Command::SCombineStore(_, SetOp::Union, _) => "sunionstore",
+            Command::SIsMember(..) => "sismember",
+            Command::SMembers(_) => "smembers",
+            Command::SRem(..) => "srem",
+            Command::SScan(..) => "sscan",
+            Command::Set(..) => "set",
+            Command::SetBit(..) => "setbit",
+            Command::SetRange(..) => "setrange",
+            Command::Shutdown(..) => "shutdown",
+            Command::SlowLog(_) => "slowlog",
+            Command::Strlen(_) => "strlen",
+            Command::Subscribe(_, false) => "subscribe",
+            Command::Subscribe(_, true) => "psubscribe",
+            Command::Touch(_) => "touch",
+            Command::Ttl(_) => "ttl",
+            Command::Type(_) => "type",
+            Command::Unlink(_) => "unlink",
+            Command::Unsubscribe(_, false) => "unsubscribe",
+            Command::Unsubscribe(_, true) => "punsubscribe",
+            Command::Unwatch => "unwatch",
+            Command::Wait(..) => "wait",
+            Command::Watch(_) => "watch",
+            Command::XAck(..) => "xack",
+            Command::XAdd(..) => "xadd",
+            Command::XClaim(..) => "xclaim",
+            Command::XGroup(_) => "xgroup",
+            Command::XLen(_) => "xlen",
+            Command::XPending(..) => "xpending",
+            Command::XRange(..) => "xrange",
+            Command::XRead(..) => "xread",
+            Command::XReadGroup(..) => "xreadgroup",
+            Command::ZAdd(..) => "zadd",
+            Command::ZCard(_) => "zcard",
+            Command::ZCount(..) => "zcount",
+            Command::ZIncrBy(..) => "zincrby",
+            Command::ZRange(..) => "zrange",
+            Command::ZRank(_, _, false) => "zrank",
+            Command::ZRank(_, _, true) => "zrevrank",
+            Command::ZRem(..) => "zrem",
+            Command::ZScan(..) => "zscan",
+            Command::ZScore(..) => "zscore",
+        }
+    }
+
+    /// Returns the spec of the command in the table.
+    fn spec(&self) -> &'static Spec {
+        lookup(self.name().as_bytes()).expect("every command is in the table")
+    }
+
+    /// Returns whether the command modifies the keystore, i.e. Redis's `write` flag.
+    pub fn is_write(&self) -> bool {
+        self.spec().flags.contains(&"write")
+    }
+
+    /// Returns whether the command may grow the dataset, and so is rejected once `maxmemory` is
+    /// reached and no key can be evicted, i.e. Redis's `denyoom` flag.
+    pub fn denies_oom(&self) -> bool {
+        self.spec().flags.contains(&"denyoom")
+    }
+
+    /// Returns the keys a write command may modify, or none for other commands.
+    pub fn written_keys(&self) -> Vec<Bytes> {
+        if !self.is_write() {
+            return Vec::new();
+        }
+        let Frame::Array(Some(args)) = self.to_frame() else {
+            unreachable!("write commands are arrays");
         };
+        let args: Vec<Bytes> = args.into_iter().filter_map(Frame::into_bytes).collect();
+        let spec = self.spec();
+        spec.written_keys(&args).into_iter().cloned().collect()
+    }
+
+    /// Returns the frame a write command is parsed from, e.g. to append it to the AOF.
+    ///
+    /// # Panics
+    ///
+    /// If the command is not a write command.
+    pub fn to_frame(&self) -> Frame {
+        fn end(end: &End) -> Bytes {
+            match end {
+                End::Left => "LEFT".into(),
+                End::Right => "RIGHT".into(),
+            }
+        }
+        fn seconds(timeout: &Option<Duration>) -> Bytes {
+            timeout.map_or(0.0, |t| t.as_secs_f64()).to_string().into()
+        }
+        fn ids(ids: &[StreamId]) -> impl Iterator<Item = Bytes> + '_ {
+            ids.iter().map(|id| id.to_string().into())
+        }
+        fn pairs(pairs: &[(Bytes, Bytes)]) -> impl Iterator<Item = Bytes> + '_ {
+            pairs.iter().flat_map(|(k, v)| [k.clone(), v.clone()])
+        }
+        let args: Vec<Bytes> = match self {
+            Command::Append(key, value) => vec!["APPEND".into(), key.clone(), value.clone()],
+            Command::BitField(key, ops) => {
+                let mut args = vec!["BITFIELD".into(), key.clone()];
+                let mut current = Overflow::Wrap;
+                for op in ops {
+                    let (name, ty, offset, value) = match *op {
+                        BitFieldOp::Get(ty, offset) => ("GET", ty, offset, None),
+                        BitFieldOp::Set(ty, offset, value, overflow) => {
+                            ("SET", ty, offset, Some((value, overflow)))
+                        }
+                        BitFieldOp::IncrBy(ty, offset, increment, overflow) => {
+                            ("INCRBY", ty, offset, Some((increment, overflow)))
+                        }
+                    };
+                    if let Some((_, overflow)) = value.filter(|(_, o)| *o != current) {
+                        let overflow = match overflow {
+                            Overflow::Wrap => "WRAP",
+                            Overflow::Sat => "SAT",
+                            Overflow::Fail => "FAIL",
+                        };
+                        args.extend(["OVERFLOW".into(), overflow.into()]);
+                    }
+                    let sign = if ty.signed { "i" } else { "u" };
+                    args.push(name.into());
+                    args.push(format!("{}{}", sign, ty.bits).into());
+                    args.push(offset.to_string().into());
+                    if let Some((value, overflow)) = value {
+                        args.push(value.to_string().into());
+                        current = overflow;
+                    }
+                }
+                args
+            }
+            Command::BitOp(op, destination, keys) => {
+                let op = match op {
+                    BitOp::And => "AND",
+                    BitOp::Or => "OR",
+                    BitOp::Xor => "XOR",
+                    BitOp::Not => "NOT",
+                };
+                ["BITOP".into(), op.into(), destination.clone()]
+                    .into_iter()
+                    .chain(keys.iter().cloned())
+                    .collect()
+            }
+            Command::BLMove(source, destination, from, to, timeout) => vec![
+                "BLMOVE".into(),
+                source.clone(),
+                destination.clone(),
+                end(from),
+                end(to),
+                seconds(timeout),
+            ],
+            Command::BPop(keys, side, timeout) => {
+                let name = match side {
+                    End::Left => "BLPOP",
+                    End::Right => "BRPOP",
+                };
+                let keys = keys.iter().cloned();
+                [name.into()]
+                    .into_iter()
+                    .chain(keys)
+                    .chain([seconds(timeout)])
+                    .collect()
+            }
+            Command::Del(keys) => [Bytes::from("DEL")]
+                .into_iter()
+                .chain(keys.iter().cloned())
+                .collect(),
+            Command::Expire(key, expiry, options) => {
+                let (name, ms) = match expiry {
+                    Expiry::In(ms) => ("PEXPIRE", ms),
+                    Expiry::At(ms) => ("PEXPIREAT", ms),
+                };
+                let mut args = vec![name.into(), key.clone(), ms.to_string().into()];
+                match options.exists {
+                    Some(true) => args.push("XX".into()),
+                    Some(false) => args.push("NX".into()),
+                    None => (),
+                }
+                match options.compare {
+                    Some(Ordering::Greater) => args.push("GT".into()),
+                    Some(Ordering::Less) => args.push("LT".into()),
+                    _ => (),
+                }
+                args
+            }
+            Command::GetDel(key) => vec!["GETDEL".into(), key.clone()],
+            Command::GetEx(key, expiry) => {
+                let mut args = vec!["GETEX".into(), key.clone()];
+                match expiry {
+                    Some(Some(Expiry::In(ms))) => args.extend(["PX".into(), ms.to_string().into()]),
+                    Some(Some(Expiry::At(ms))) => {
+                        args.extend(["PXAT".into(), ms.to_string().into()])
+                    }
+                    Some(None) => args.push("PERSIST".into()),
+                    None => (),
+                }
+                args
+            }
+            Command::HDel(key, fields) => [Bytes::from("HDEL"), key.clone()]
+                .into_iter()
+                .chain(fields.iter().cloned())
+                .collect(),
+            Command::HIncrBy(key, field, delta) => {
+                vec![
+                    "HINCRBY".into(),
+                    key.clone(),
+                    field.clone(),
+                    delta.to_string().into(),
+                ]
+            }
+            Command::HSet(key, fields) => [Bytes::from("HSET"), key.clone()]
+                .into_iter()
+                .chain(pairs(fields))
+                .collect(),
+            Command::IncrBy(key, delta) => {
+                vec!["INCRBY".into(), key.clone(), delta.to_string().into()]
+            }
+            Command::LInsert(key, before, pivot, element) => vec![
+                "LINSERT".into(),
+                key.clone(),
+                if *before { "BEFORE" } else { "AFTER" }.into(),
+                pivot.clone(),
+                element.clone(),
+            ],
+            Command::LMove(source, destination, from, to) => vec![
+                "LMOVE".into(),
+                source.clone(),
+                destination.clone(),
+                end(from),
+                end(to),
+            ],
+            Command::LRem(key, count, element) => {
+                vec![
+                    "LREM".into(),
+                    key.clone(),
+                    count.to_string().into(),
+                    element.clone(),
+                ]
+            }
+            Command::LSet(key, index, element) => {
+                vec![
+                    "LSET".into(),
+                    key.clone(),
+                    index.to_string().into(),
+                    element.clone(),
+                ]
+            }
+            Command::LTrim(key, start, stop) => vec![
+                "LTRIM".into(),
+                key.clone(),
+                start.to_string().into(),
+                stop.to_string().into(),
+            ],
+            Command::MSet(values) => [Bytes::from("MSET")]
+                .into_iter()
+                .chain(pairs(values))
+                .collect(),
+            Command::MSetNx(values) => [Bytes::from("MSETNX")]
+                .into_iter()
+                .chain(pairs(values))
+                .collect(),
+            Command::Persist(key) => vec!["PERSIST".into(), key.clone()],
+            Command::Pop(key, side, count) => {
+                let name = match side {
+                    End::Left => "LPOP",
+                    End::Right => "RPOP",
+                };
+                let count = count.map(|c| c.to_string().into());
+                [name.into(), key.clone()]
+                    .into_iter()
+                    .chain(count)
+                    .collect()
+            }
+            Command::Push(key, side, elements) => {
+                let name = match side {
+                    End::Left => "LPUSH",
+                    End::Right => "RPUSH",
+                };
+                [name.into(), key.clone()]
+                    .into_iter()
+                    .chain(elements.iter().cloned())
+                    .collect()
+            }
+            Command::SAdd(key, members) => [Bytes::from("SADD"), key.clone()]
+                .into_iter()
+                .chain(members.iter().cloned())
+                .collect(),
+            Command::SCombineStore(destination, op, keys) => {
+                let name = match op {
+                    SetOp::Inter => "SINTERSTORE",
+                    SetOp::Union => "SUNIONSTORE",
+                    SetOp::Diff => "SDIFFSTORE",
+                };
+                [name.into(), destination.clone()]
+                    .into_iter()
+                    .chain(keys.iter().cloned())
+                    .collect()
+            }
+            Command::SRem(key, members) => [Bytes::from("SREM"), key.clone()]
+                .into_iter()
+                .chain(members.iter().cloned())
+                .collect(),
+            Command::Set(key, value, options) => {
+                let mut args = vec!["SET".into(), key.clone(), value.clone()];
+                match options.exists {
+                    Some(true) => args.push("XX".into()),
+                    Some(false) => args.push("NX".into()),
+                    None => (),
+                }
+                if options.get {
+                    args.push("GET".into());
+                }
+                match options.expiry {
+                    Some(Expiry::In(ms)) => args.extend(["PX".into(), ms.to_string().into()]),
+                    Some(Expiry::At(ms)) => args.extend(["PXAT".into(), ms.to_string().into()]),
+                    None if options.keep_ttl => args.push("KEEPTTL".into()),
+                    None => (),
+                }
+                args
+            }
+            Command::SetBit(key, offset, bit) => vec![
+                "SETBIT".into(),
+                key.clone(),
+                offset.to_string().into(),
+                if *bit { "1" } else { "0" }.into(),
+            ],
+            Command::SetRange(key, offset, value) => {
+                vec![
+                    "SETRANGE".into(),
+                    key.clone(),
+                    offset.to_string().into(),
+                    value.clone(),
+                ]
+            }
+            Command::Unlink(keys) => [Bytes::from("UNLINK")]
+                .into_iter()
+                .chain(keys.iter().cloned())
+                .collect(),
+            Command::XAck(key, group, acked) => [Bytes::from("XACK"), key.clone(), group.clone()]
+                .into_iter()
+                .chain(ids(acked))
+                .collect(),
+            Command::XAdd(key, id, fields) => {
+                let id = match id {
+                    XAddId::Auto => "*".into(),
+                    XAddId::Seq(ms) => format!("{}-*", ms).into(),
+                    XAddId::Explicit(id) => id.to_string().into(),
+                };
+                [Bytes::from("XADD"), key.clone(), id]
+                    .into_iter()
+                    .chain(pairs(fields))
+                    .collect()
+            }
+            Command::XClaim(key, group, consumer, claimed, options) => {
+                let mut args: Vec<Bytes> = vec![
+                    "XCLAIM".into(),
+                    key.clone(),
+                    group.clone(),
+                    consumer.clone(),
+                    options.min_idle.to_string().into(),
+                ];
+                args.extend(ids(claimed));
+                if let Some(idle) = options.idle {
+                    args.extend(["IDLE".into(), idle.to_string().into()]);
+                }
+                if let Some(time) = options.time {
+                    args.extend(["TIME".into(), time.to_string().into()]);
+                }
+                if let Some(count) = options.retry_count {
+                    args.extend(["RETRYCOUNT".into(), count.to_string().into()]);
+                }
+                if options.force {
+                    args.push("FORCE".into());
+                }
+                if options.just_id {
+                    args.push("JUSTID".into());
+                }
+                args
+            }
+            Command::XGroup(subcommand) => {
+                let id = |id: &Option<StreamId>| id.map_or("$".into(), |id| id.to_string().into());
+                let mut args: Vec<Bytes> = vec!["XGROUP".into()];
+                match subcommand {
+                    XGroupCommand::Create(key, group, last, make_stream) => {
+                        args.extend(["CREATE".into(), key.clone(), group.clone(), id(last)]);
+                        if *make_stream {
+                            args.push("MKSTREAM".into());
+                        }
+                    }
+                    XGroupCommand::CreateConsumer(key, group, consumer) => args.extend([
+                        "CREATECONSUMER".into(),
+                        key.clone(),
+                        group.clone(),
+                        consumer.clone(),
+                    ]),
+                    XGroupCommand::DelConsumer(key, group, consumer) => args.extend([
+                        "DELCONSUMER".into(),
+                        key.clone(),
+                        group.clone(),
+                        consumer.clone(),
+                    ]),
+                    XGroupCommand::Destroy(key, group) => {
+                        args.extend(["DESTROY".into(), key.clone(), group.clone()])
+                    }
+                    XGroupCommand::SetId(key, group, last) => {
+                        args.extend(["SETID".into(), key.clone(), group.clone(), id(last)])
+                    }
+                }
+                args
+            }
+            Command::XReadGroup(group, consumer, keys, from, options) => {
+                let mut args = vec![
+                    "XREADGROUP".into(),
+                    "GROUP".into(),
+                    group.clone(),
+                    consumer.clone(),
+                ];
+                if let Some(count) = options.count {
+                    args.extend(["COUNT".into(), count.to_string().into()]);
+                }
+                if let Some(block) = options.block {
+                    let ms = block.map_or(0, |b| b.as_millis());
+                    args.extend(["BLOCK".into(), ms.to_string().into()]);
+                }
+                if options.no_ack {
+                    args.push("NOACK".into());
+                }
+                args.push("STREAMS".into());
+                args.extend(keys.iter().cloned());
+                args.extend(
+                    from.iter()
+                        .map(|id| id.map_or(">".into(), |id| id.to_string().into())),
+                );
+                args
+            }
+            Command::ZAdd(key, members, options) => {
+                let mut args = vec!["ZADD".into(), key.clone()];
+                match options.exists {
+                    Some(true) => args.push("XX".into()),
+                    Some(false) => args.push("NX".into()),
+                    None => (),
+                }
+                match options.comparison {
+                    Some(Ordering::Greater) => args.push("GT".into()),
+                    Some(Ordering::Less) => args.push("LT".into()),
+                    _ => (),
+                }
+                if options.changed {
+                    args.push("CH".into());
+                }
+                for (score, member) in members {
+                    args.extend([score.to_string().into(), member.clone()]);
+                }
+                args
+            }
+            Command::ZIncrBy(key, delta, member) => {
+                vec![
+                    "ZINCRBY".into(),
+                    key.clone(),
+                    delta.to_string().into(),
+                    member.clone(),
+                ]
+            }
+            Command::ZRem(key, members) => [Bytes::from("ZREM"), key.clone()]
+                .into_iter()
+                .chain(members.iter().cloned())
+                .collect(),
+            command => unreachable!("{:?} is not a write command", command),
+        };
+        Frame::Array(Some(
+            args.into_iter().map(|a| Frame::Bulk(Some(a))).collect(),
+        ))
     }
 }
 
 /// Advances the iterator and returns the next value.
 ///
 /// Returns `Err(Error::MissingArgument)` if the next item is unavailable.
-fn next<'a>(it: &'a mut Iter<'_, Frame>) -> Result<&'a Frame, Error> {
+fn next(it: &mut IntoIter<Frame>) -> Result<Frame, Error> {
     it.next().ok_or(Error::MissingArgument)
 }
 
-/// Advances the iterator and returns the `Bytes` contained in next value.
+/// Advances the iterator and moves out the `Bytes` contained in next value.
+///
+/// Returns:
+/// - `Err(Error::MissingArgument)` if the next item is unavailable
+/// - `Err(Error::WrongType)` if the next item does not contain `Bytes`
+fn next_bytes(it: &mut IntoIter<Frame>) -> Result<Bytes, Error> {
+    next(it)?.into_bytes().ok_or(Error::WrongType)
+}
+
+/// Moves out the `Bytes` contained in every remaining value.
+///
+/// Returns `Err(Error::WrongType)` if any remaining item does not contain `Bytes`.
+fn rest_bytes(it: &mut IntoIter<Frame>) -> Result<Vec<Bytes>, Error> {
+    it.map(|f| f.into_bytes().ok_or(Error::WrongType)).collect()
+}
+
+/// Moves out the `Bytes` contained in every remaining value, two at a time.
+///
+/// Returns:
+/// - `Err(Error::MissingArgument)` if an odd number of items remain
+/// - `Err(Error::WrongType)` if any remaining item does not contain `Bytes`
+fn rest_pairs(it: &mut IntoIter<Frame>) -> Result<Vec<(Bytes, Bytes)>, Error> {
+    let mut pairs = Vec::with_capacity(it.len() / 2);
+    while let Ok(first) = next_bytes(it) {
+        pairs.push((first, next_bytes(it)?));
+    }
+    Ok(pairs)
+}
+
+/// Advances the iterator and parses the next value as an integer.
 ///
 /// Returns:
 /// - `Err(Error::MissingArgument)` if the next item is unavailable
 /// - `Err(Error::WrongType)` if the next item does not contain `Bytes`
-fn next_bytes<'a>(it: &mut Iter<'_, Frame>) -> Result<Bytes, Error> {
-    next(it)?.get_bytes().ok_or(Error::WrongType)
+/// - `Err(Error::NotAnInteger)` if the next item is not a base 10 `i64`
+fn next_integer(it: &mut IntoIter<Frame>) -> Result<i64, Error> {
+    parse_integer(&next_bytes(it)?).ok_or(Error::NotAnInteger)
+}
+
+/// Advances the iterator and parses the next value as the offset of a bit in a string, which can't
+/// be longer than 512MB.
+fn next_bit_offset(it: &mut IntoIter<Frame>) -> Result<u64, Error> {
+    let offset = next_bytes(it)?;
+    parse_integer(&offset)
+        .and_then(|o| u64::try_from(o).ok())
+        .filter(|&o| o < 4 * 1024 * 1024 * 1024)
+        .ok_or(Error::BitOffsetOutOfRange)
+}
+
+/// Parses the unit of the range of `BITCOUNT` or `BITPOS`, if any is left, returning whether it is
+/// `BIT` rather than `BYTE`.
+fn next_bit_unit(it: &mut IntoIter<Frame>) -> Result<bool, Error> {
+    let Some(unit) = it.next() else {
+        return Ok(false);
+    };
+    let bits = match unit
+        .into_bytes()
+        .ok_or(Error::WrongType)?
+        .to_ascii_lowercase()
+        .as_slice()
+    {
+        b"byte" => false,
+        b"bit" => true,
+        _ => return Err(Error::Syntax),
+    };
+    match it.len() {
+        0 => Ok(bits),
+        _ => Err(Error::Syntax),
+    }
+}
+
+/// Advances the iterator and parses the next value as an end of a list, i.e. `LEFT` or `RIGHT`.
+fn next_end(it: &mut IntoIter<Frame>) -> Result<End, Error> {
+    match next_bytes(it)?.to_ascii_lowercase().as_slice() {
+        b"left" => Ok(End::Left),
+        b"right" => Ok(End::Right),
+        _ => Err(Error::Syntax),
+    }
+}
+
+/// Advances the iterator and parses the next value as a blocking timeout.
+fn next_timeout(it: &mut IntoIter<Frame>) -> Result<Option<Duration>, Error> {
+    parse_timeout(&next_bytes(it)?)
+}
+
+/// Parses a blocking timeout in (possibly fractional) seconds, where `0` means forever.
+fn parse_timeout(bytes: &[u8]) -> Result<Option<Duration>, Error> {
+    let seconds = parse_float(bytes)
+        .filter(|s| s.is_finite())
+        .ok_or(Error::InvalidTimeout)?;
+    if seconds < 0.0 {
+        Err(Error::NegativeTimeout)
+    } else if seconds == 0.0 {
+        Ok(None)
+    } else {
+        Duration::try_from_secs_f64(seconds)
+            .map(Some)
+            .map_err(|_| Error::InvalidTimeout)
+    }
+}
+
+/// Parses a float the way Redis does, accepting `inf` and `-inf` but not NaN.
+pub fn parse_float(bytes: &[u8]) -> Option<f64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|f: &f64| !f.is_nan())
+}
+
+/// Parses a base 10 `i64` the way Redis does, i.e. without the leading `+` Rust would accept.
+pub fn parse_integer(bytes: &[u8]) -> Option<i64> {
+    if bytes.first() == Some(&b'+') {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Parses a number of bytes the way Redis's configuration does, e.g. `64mb`, where `k`, `m` and `g`
+/// are powers of 1000, and `kb`, `mb` and `gb` powers of 1024.
+pub fn parse_memory(bytes: &[u8]) -> Option<u64> {
+    let value = std::str::from_utf8(bytes).ok()?.to_ascii_lowercase();
+    let units = [
+        ("kb", 1 << 10),
+        ("mb", 1 << 20),
+        ("gb", 1 << 30),
+        ("k", 1000),
+        ("m", 1000 * 1000),
+        ("g", 1000 * 1000 * 1000),
+        ("b", 1),
+    ];
+    let (digits, unit) = units
+        .into_iter()
+        .find_map(|(suffix, unit)| Some((value.strip_suffix(suffix)?, unit)))
+        .unwrap_or((&value, 1));
+    if digits.starts_with('+') {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Advances the iterator and parses the next value as a number of seconds, returning it in
+/// milliseconds.
+///
+/// Returns `Err(Error::InvalidExpireTime(command))` if the milliseconds would overflow.
+fn next_seconds(it: &mut IntoIter<Frame>, command: &'static str) -> Result<i64, Error> {
+    next_integer(it)?
+        .checked_mul(1000)
+        .ok_or(Error::InvalidExpireTime(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&'static str]) -> Result<Command, Error> {
+        let args = args.iter().map(|a| Frame::Bulk(Some(a.as_bytes().into())));
+        Command::try_from(Frame::Array(Some(args.collect())))
+    }
+
+    #[test]
+    fn write_commands_round_trip() {
+        let commands: &[&[&'static str]] = &[
+            &["BLMOVE", "a", "b", "LEFT", "RIGHT", "0.5"],
+            &["BRPOP", "a", "b", "0"],
+            &["HSET", "h", "f", "v", "g", "w"],
+            &["LINSERT", "l", "AFTER", "p", "e"],
+            &["LPOP", "l", "2"],
+            &["PEXPIREAT", "k", "1000"],
+            &["SDIFFSTORE", "d", "a", "b"],
+            &["SET", "k", "v", "NX", "GET", "PXAT", "1000"],
+            &["SET", "k", "v", "KEEPTTL"],
+            &["XADD", "s", "1-*", "f", "v"],
+            &[
+                "XCLAIM", "s", "g", "c", "10", "1-1", "2-0", "TIME", "5", "FORCE",
+            ],
+            &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"],
+            &[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "c",
+                "COUNT",
+                "2",
+                "BLOCK",
+                "0",
+                "STREAMS",
+                "s",
+                ">",
+            ],
+            &["ZADD", "z", "XX", "GT", "CH", "1.5", "a", "-inf", "b"],
+        ];
+        for args in commands {
+            let frame = parse(args).unwrap().to_frame();
+            let expected: Vec<_> = args
+                .iter()
+                .map(|a| Frame::Bulk(Some(a.as_bytes().into())))
+                .collect();
+            assert_eq!(Frame::Array(Some(expected)), frame);
+        }
+    }
+
+    #[test]
+    fn written_keys_follow_the_table() {
+        let commands: &[(&[&'static str], &[&str])] = &[
+            (&["BITOP", "AND", "d", "a", "b"], &["d"]),
+            (&["BLPOP", "a", "b", "0"], &["a", "b"]),
+            (&["GET", "k"], &[]),
+            (&["LMOVE", "a", "b", "LEFT", "RIGHT"], &["a", "b"]),
+            (&["MSET", "a", "1", "b", "2"], &["a", "b"]),
+            (&["SINTERSTORE", "d", "a", "b"], &["d"]),
+            (&["XGROUP", "DESTROY", "s", "g"], &["s"]),
+            (
+                &[
+                    "XREADGROUP",
+                    "GROUP",
+                    "g",
+                    "c",
+                    "STREAMS",
+                    "s",
+                    "t",
+                    ">",
+                    ">",
+                ],
+                &["s", "t"],
+            ),
+        ];
+        for (args, keys) in commands {
+            let command = parse(args).unwrap();
+            assert_eq!(!keys.is_empty(), command.is_write(), "{:?}", args);
+            assert_eq!(*keys, command.written_keys(), "{:?}", args);
+        }
+        assert!(parse(&["INCR", "k"]).unwrap().denies_oom());
+        assert!(!parse(&["EXPIRE", "k", "1"]).unwrap().denies_oom());
+    }
+
+    #[test]
+    fn set_with_expiry() {
+        assert!(matches!(
+            parse(&["SET", "k", "v", "px", "100"]),
+            Ok(Command::Set(
+                _,
+                _,
+                SetOptions {
+                    expiry: Some(Expiry::In(100)),
+                    ..
+                }
+            ))
+        ));
+        assert!(matches!(
+            parse(&["set", "k", "v", "EXAT", "2"]),
+            Ok(Command::Set(
+                _,
+                _,
+                SetOptions {
+                    expiry: Some(Expiry::At(2000)),
+                    ..
+                }
+            ))
+        ));
+    }
+
+    #[test]
+    fn set_with_invalid_expiry() {
+        assert!(matches!(
+            parse(&["SET", "k", "v", "EX", "0"]),
+            Err(Error::InvalidExpireTime("set"))
+        ));
+        assert!(matches!(
+            parse(&["SET", "k", "v", "EX", "1", "PX", "1"]),
+            Err(Error::Syntax)
+        ));
+        assert!(matches!(
+            parse(&["SET", "k", "v", "EX", "one"]),
+            Err(Error::NotAnInteger)
+        ));
+    }
+
+    #[test]
+    fn set_with_conditions() {
+        assert!(matches!(
+            parse(&["SET", "k", "v", "nx", "GET", "KEEPTTL"]),
+            Ok(Command::Set(
+                _,
+                _,
+                SetOptions {
+                    exists: Some(false),
+                    get: true,
+                    keep_ttl: true,
+                    expiry: None,
+                }
+            ))
+        ));
+        assert!(matches!(
+            parse(&["SET", "k", "v", "NX", "XX"]),
+            Err(Error::Syntax)
+        ));
+        assert!(matches!(
+            parse(&["SET", "k", "v", "KEEPTTL", "EX", "1"]),
+            Err(Error::Syntax)
+        ));
+    }
+
+    #[test]
+    fn expire_overflow() {
+        assert!(matches!(
+            parse(&["EXPIRE", "k", "9223372036854775807"]),
+            Err(Error::InvalidExpireTime("expire"))
+        ));
+    }
+
+    #[test]
+    fn arity_is_checked_against_the_command_table() {
+        assert!(matches!(
+            parse(&["GET", "a", "b"]),
+            Err(Error::WrongArity("get"))
+        ));
+        assert!(matches!(
+            parse(&["zadd", "z", "1"]),
+            Err(Error::WrongArity("zadd"))
+        ));
+        assert!(matches!(
+            parse(&["MSET", "a", "1", "b"]),
+            Err(Error::WrongArity("mset"))
+        ));
+        assert!(matches!(parse(&["Ping"]), Ok(Command::Ping)));
+        let error = Frame::from(parse(&["NOPE", "a"]).unwrap_err());
+        let expected = "ERR unknown command 'NOPE', with args beginning with: 'a' ";
+        assert_eq!(Frame::Error(expected.into()), error);
+    }
 }
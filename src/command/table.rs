@@ -0,0 +1,553 @@
+use std::vec::IntoIter;
+
+use bytes::Bytes;
+
+use super::{
+    next_bit_offset, next_bytes, next_integer, parse_acl, parse_auth, parse_bitcount,
+    parse_bitfield, parse_bitop, parse_bitpos, parse_blmove, parse_bpop, parse_client,
+    parse_command, parse_config, parse_debug, parse_decrby, parse_expire, parse_geoadd,
+    parse_geodist, parse_geosearch, parse_getex, parse_hello, parse_key_scan, parse_latency,
+    parse_lcs, parse_linsert, parse_lmove, parse_lpos, parse_memory_command, parse_mset,
+    parse_object, parse_pop, parse_psync, parse_pubsub, parse_replconf, parse_replicaof,
+    parse_scan, parse_set, parse_setbit, parse_setex, parse_setrange, parse_shutdown,
+    parse_slowlog, parse_wait, parse_xack, parse_xadd, parse_xclaim, parse_xgroup, parse_xpending,
+    parse_xrange, parse_xread, parse_zadd, parse_zcount, parse_zincrby, parse_zrange, rest_bytes,
+    rest_pairs, By, Command, CommandCommand, End, Error, SetOp,
+};
+use crate::frame::Frame;
+
+/// Parses the arguments following a command's name, which were already checked against its arity.
+type Parse = fn(&mut IntoIter<Frame>) -> Result<Command, Error>;
+
+/// A supported command: what `COMMAND` reports about it, and how its arguments are parsed.
+pub struct Spec {
+    pub name: &'static str,
+    /// The number of arguments including the name, or its negation if that's only the minimum.
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    /// The positions of the first and last keys, and the step between them, where a negative last
+    /// key counts from the end; commands without keys at fixed positions have `(0, 0, 0)`.
+    pub keys: (i64, i64, i64),
+    /// The group the command is documented in, e.g. `string` or `sorted-set`.
+    pub group: &'static str,
+    pub parse: Parse,
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    keys: (i64, i64, i64),
+    group: &'static str,
+    parse: Parse,
+) -> Spec {
+    Spec {
+        name,
+        arity,
+        flags,
+        keys,
+        group,
+        parse,
+    }
+}
+
+const NONE: (i64, i64, i64) = (0, 0, 0);
+const KEY: (i64, i64, i64) = (1, 1, 1);
+const KEYS: (i64, i64, i64) = (1, -1, 1);
+const TWO_KEYS: (i64, i64, i64) = (1, 2, 1);
+
+/// The ACL categories of commands, which users are given permission to run commands by.
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+];
+
+/// Every supported command, sorted by name, with their flags and key positions following Redis.
+#[rustfmt::skip]
+pub const COMMANDS: &[Spec] = &[
+    // subcommands can't be permitted separately, so like `ACL SETUSER`, every subcommand is admin
+    spec("acl", -2, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        parse_acl),
+    spec("append", 3, &["write", "denyoom", "fast"], KEY, "string",
+        |args| Ok(Command::Append(next_bytes(args)?, next_bytes(args)?))),
+    spec("auth", -2, &["noscript", "loading", "stale", "fast", "no_auth"], NONE, "connection",
+        parse_auth),
+    spec("bgrewriteaof", 1, &["admin", "noscript"], NONE, "server",
+        |_| Ok(Command::BgRewriteAof)),
+    spec("bgsave", 1, &["admin", "noscript"], NONE, "server",
+        |_| Ok(Command::BgSave)),
+    spec("bitcount", -2, &["readonly"], KEY, "bitmap",
+        parse_bitcount),
+    spec("bitfield", -2, &["write", "denyoom"], KEY, "bitmap",
+        parse_bitfield),
+    spec("bitop", -4, &["write", "denyoom"], (2, -1, 1), "bitmap",
+        parse_bitop),
+    spec("bitpos", -3, &["readonly"], KEY, "bitmap",
+        parse_bitpos),
+    spec("blmove", 6, &["write", "denyoom", "blocking"], TWO_KEYS, "list",
+        parse_blmove),
+    spec("blpop", -3, &["write", "blocking"], (1, -2, 1), "list",
+        |args| parse_bpop(args, End::Left)),
+    spec("brpop", -3, &["write", "blocking"], (1, -2, 1), "list",
+        |args| parse_bpop(args, End::Right)),
+    spec("client", -2, &["noscript", "loading", "stale"], NONE, "connection",
+        parse_client),
+    spec("command", -1, &["loading", "stale"], NONE, "server",
+        parse_command),
+    spec("config", -2, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        parse_config),
+    spec("debug", -2, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        parse_debug),
+    spec("decr", 2, &["write", "denyoom", "fast"], KEY, "string",
+        |args| Ok(Command::IncrBy(next_bytes(args)?, -1))),
+    spec("decrby", 3, &["write", "denyoom", "fast"], KEY, "string",
+        parse_decrby),
+    spec("del", -2, &["write"], KEYS, "generic",
+        |args| Ok(Command::Del(rest_bytes(args)?))),
+    spec("discard", 1, &["noscript", "loading", "stale", "fast"], NONE, "transactions",
+        |_| Ok(Command::Discard)),
+    spec("echo", 2, &["fast"], NONE, "connection",
+        |args| Ok(Command::Echo(next_bytes(args)?))),
+    spec("exec", 1, &["noscript", "loading", "stale", "skip_slowlog"], NONE, "transactions",
+        |_| Ok(Command::Exec)),
+    spec("exists", -2, &["readonly", "fast"], KEYS, "generic",
+        |args| Ok(Command::Exists(rest_bytes(args)?))),
+    spec("expire", -3, &["write", "fast"], KEY, "generic",
+        |args| parse_expire(args, "expire", false, false)),
+    spec("expireat", -3, &["write", "fast"], KEY, "generic",
+        |args| parse_expire(args, "expireat", false, true)),
+    spec("expiretime", 2, &["readonly", "fast"], KEY, "generic",
+        |args| Ok(Command::ExpireTime(next_bytes(args)?, false))),
+    spec("geoadd", -5, &["write", "denyoom"], KEY, "geo",
+        parse_geoadd),
+    spec("geodist", -4, &["readonly"], KEY, "geo",
+        parse_geodist),
+    spec("geopos", -2, &["readonly"], KEY, "geo",
+        |args| Ok(Command::GeoPos(next_bytes(args)?, rest_bytes(args)?))),
+    spec("geosearch", -7, &["readonly"], KEY, "geo",
+        parse_geosearch),
+    spec("get", 2, &["readonly", "fast"], KEY, "string",
+        |args| Ok(Command::Get(next_bytes(args)?))),
+    spec("getbit", 3, &["readonly", "fast"], KEY, "bitmap",
+        |args| Ok(Command::GetBit(next_bytes(args)?, next_bit_offset(args)?))),
+    spec("getdel", 2, &["write", "fast"], KEY, "string",
+        |args| Ok(Command::GetDel(next_bytes(args)?))),
+    spec("getex", -2, &["write", "fast"], KEY, "string",
+        parse_getex),
+    spec("getrange", 4, &["readonly"], KEY, "string",
+        |args| Ok(Command::GetRange(next_bytes(args)?, next_integer(args)?, next_integer(args)?))),
+    spec("hdel", -3, &["write", "fast"], KEY, "hash",
+        |args| Ok(Command::HDel(next_bytes(args)?, rest_bytes(args)?))),
+    spec("hello", -1, &["noscript", "loading", "stale", "fast", "no_auth"], NONE, "connection",
+        parse_hello),
+    spec("hexists", 3, &["readonly", "fast"], KEY, "hash",
+        |args| Ok(Command::HExists(next_bytes(args)?, next_bytes(args)?))),
+    spec("hget", 3, &["readonly", "fast"], KEY, "hash",
+        |args| Ok(Command::HGet(next_bytes(args)?, next_bytes(args)?))),
+    spec("hgetall", 2, &["readonly"], KEY, "hash",
+        |args| Ok(Command::HGetAll(next_bytes(args)?))),
+    spec("hincrby", 4, &["write", "denyoom", "fast"], KEY, "hash",
+        |args| Ok(Command::HIncrBy(next_bytes(args)?, next_bytes(args)?, next_integer(args)?))),
+    spec("hlen", 2, &["readonly", "fast"], KEY, "hash",
+        |args| Ok(Command::HLen(next_bytes(args)?))),
+    spec("hscan", -3, &["readonly"], KEY, "hash",
+        |args| parse_key_scan(args, Command::HScan)),
+    spec("hset", -4, &["write", "denyoom", "fast"], KEY, "hash",
+        |args| match args.len() % 2 {
+            1 => Ok(Command::HSet(next_bytes(args)?, rest_pairs(args)?)),
+            _ => Err(Error::WrongArity("hset")),
+        }),
+    spec("incr", 2, &["write", "denyoom", "fast"], KEY, "string",
+        |args| Ok(Command::IncrBy(next_bytes(args)?, 1))),
+    spec("incrby", 3, &["write", "denyoom", "fast"], KEY, "string",
+        |args| Ok(Command::IncrBy(next_bytes(args)?, next_integer(args)?))),
+    spec("info", -1, &["loading", "stale"], NONE, "server",
+        |args| Ok(Command::Info(rest_bytes(args)?))),
+    spec("keys", 2, &["readonly"], NONE, "generic",
+        |args| Ok(Command::Keys(next_bytes(args)?))),
+    spec("latency", -2, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        parse_latency),
+    spec("lcs", -3, &["readonly"], TWO_KEYS, "string",
+        parse_lcs),
+    spec("linsert", 5, &["write", "denyoom"], KEY, "list",
+        parse_linsert),
+    spec("llen", 2, &["readonly", "fast"], KEY, "list",
+        |args| Ok(Command::LLen(next_bytes(args)?))),
+    spec("lmove", 5, &["write", "denyoom"], TWO_KEYS, "list",
+        parse_lmove),
+    spec("lpop", -2, &["write", "fast"], KEY, "list",
+        |args| parse_pop(args, End::Left)),
+    spec("lpos", -3, &["readonly"], KEY, "list",
+        parse_lpos),
+    spec("lpush", -3, &["write", "denyoom", "fast"], KEY, "list",
+        |args| Ok(Command::Push(next_bytes(args)?, End::Left, rest_bytes(args)?))),
+    spec("lrange", 4, &["readonly"], KEY, "list",
+        |args| Ok(Command::LRange(next_bytes(args)?, next_integer(args)?, next_integer(args)?))),
+    spec("lrem", 4, &["write"], KEY, "list",
+        |args| Ok(Command::LRem(next_bytes(args)?, next_integer(args)?, next_bytes(args)?))),
+    spec("lset", 4, &["write", "denyoom"], KEY, "list",
+        |args| Ok(Command::LSet(next_bytes(args)?, next_integer(args)?, next_bytes(args)?))),
+    spec("ltrim", 4, &["write"], KEY, "list",
+        |args| Ok(Command::LTrim(next_bytes(args)?, next_integer(args)?, next_integer(args)?))),
+    spec("memory", -2, &["readonly"], (2, 2, 1), "server",
+        parse_memory_command),
+    spec("mget", -2, &["readonly", "fast"], KEYS, "string",
+        |args| Ok(Command::MGet(rest_bytes(args)?))),
+    spec("monitor", 1, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        |_| Ok(Command::Monitor)),
+    spec("mset", -3, &["write", "denyoom"], (1, -1, 2), "string",
+        |args| Ok(Command::MSet(parse_mset(args, "mset")?))),
+    spec("msetnx", -3, &["write", "denyoom"], (1, -1, 2), "string",
+        |args| Ok(Command::MSetNx(parse_mset(args, "msetnx")?))),
+    spec("multi", 1, &["noscript", "loading", "stale", "fast"], NONE, "transactions",
+        |_| Ok(Command::Multi)),
+    spec("object", -2, &["readonly"], (2, 2, 1), "generic",
+        parse_object),
+    spec("persist", 2, &["write", "fast"], KEY, "generic",
+        |args| Ok(Command::Persist(next_bytes(args)?))),
+    spec("pexpire", -3, &["write", "fast"], KEY, "generic",
+        |args| parse_expire(args, "pexpire", true, false)),
+    spec("pexpireat", -3, &["write", "fast"], KEY, "generic",
+        |args| parse_expire(args, "pexpireat", true, true)),
+    spec("pexpiretime", 2, &["readonly", "fast"], KEY, "generic",
+        |args| Ok(Command::ExpireTime(next_bytes(args)?, true))),
+    spec("ping", 1, &["fast"], NONE, "connection",
+        |_| Ok(Command::Ping)),
+    spec("psetex", 4, &["write", "denyoom"], KEY, "string",
+        |args| parse_setex(args, true)),
+    spec("psubscribe", -2, &["pubsub", "noscript", "loading", "stale"], NONE, "pubsub",
+        |args| Ok(Command::Subscribe(rest_bytes(args)?, true))),
+    spec("psync", 3, &["admin", "noscript", "no_multi"], NONE, "server",
+        parse_psync),
+    spec("pttl", 2, &["readonly", "fast"], KEY, "generic",
+        |args| Ok(Command::Pttl(next_bytes(args)?))),
+    spec("publish", 3, &["pubsub", "loading", "stale", "fast"], NONE, "pubsub",
+        |args| Ok(Command::Publish(next_bytes(args)?, next_bytes(args)?))),
+    spec("pubsub", -2, &["pubsub", "loading", "stale"], NONE, "pubsub",
+        parse_pubsub),
+    spec("punsubscribe", -1, &["pubsub", "noscript", "loading", "stale"], NONE, "pubsub",
+        |args| Ok(Command::Unsubscribe(rest_bytes(args)?, true))),
+    // like Redis, any arguments are ignored
+    spec("quit", -1, &["noscript", "loading", "stale", "fast", "no_auth"], NONE, "connection",
+        |args| rest_bytes(args).map(|_| Command::Quit)),
+    spec("randomkey", 1, &["readonly"], NONE, "generic",
+        |_| Ok(Command::RandomKey)),
+    spec("replconf", -3, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        parse_replconf),
+    spec("replicaof", 3, &["admin", "noscript", "stale"], NONE, "server",
+        parse_replicaof),
+    spec("reset", 1, &["noscript", "loading", "stale", "fast", "no_auth"], NONE, "connection",
+        |_| Ok(Command::Reset)),
+    spec("role", 1, &["noscript", "loading", "stale", "fast"], NONE, "server",
+        |_| Ok(Command::Role)),
+    spec("rpop", -2, &["write", "fast"], KEY, "list",
+        |args| parse_pop(args, End::Right)),
+    spec("rpush", -3, &["write", "denyoom", "fast"], KEY, "list",
+        |args| Ok(Command::Push(next_bytes(args)?, End::Right, rest_bytes(args)?))),
+    spec("sadd", -3, &["write", "denyoom", "fast"], KEY, "set",
+        |args| Ok(Command::SAdd(next_bytes(args)?, rest_bytes(args)?))),
+    spec("save", 1, &["admin", "noscript", "no_multi"], NONE, "server",
+        |_| Ok(Command::Save)),
+    spec("scan", -2, &["readonly"], NONE, "generic",
+        |args| parse_scan(args, true).map(|(cursor, options)| Command::Scan(cursor, options))),
+    spec("scard", 2, &["readonly", "fast"], KEY, "set",
+        |args| Ok(Command::SCard(next_bytes(args)?))),
+    spec("sdiff", -2, &["readonly"], KEYS, "set",
+        |args| Ok(Command::SCombine(SetOp::Diff, rest_bytes(args)?))),
+    spec("sdiffstore", -3, &["write", "denyoom"], KEYS, "set",
+        |args| Ok(Command::SCombineStore(next_bytes(args)?, SetOp::Diff, rest_bytes(args)?))),
+    spec("set", -3, &["write", "denyoom"], KEY, "string",
+        parse_set),
+    spec("setbit", 4, &["write", "denyoom"], KEY, "bitmap",
+        parse_setbit),
+    spec("setex", 4, &["write", "denyoom"], KEY, "string",
+        |args| parse_setex(args, false)),
+    spec("setrange", 4, &["write", "denyoom"], KEY, "string",
+        parse_setrange),
+    spec("shutdown", -1, &["admin", "noscript", "loading", "stale"], NONE, "server",
+        parse_shutdown),
+    spec("sinter", -2, &["readonly"], KEYS, "set",
+        |args| Ok(Command::SCombine(SetOp::Inter, rest_bytes(args)?))),
+    spec("sinterstore", -3, &["write", "denyoom"], KEYS, "set",
+        |args| Ok(Command::SCombineStore(next_bytes(args)?, SetOp::Inter, rest_bytes(args)?))),
+    spec("sismember", 3, &["readonly", "fast"], KEY, "set",
+        |args| Ok(Command::SIsMember(next_bytes(args)?, next_bytes(args)?))),
+    spec("slaveof", 3, &["admin", "noscript", "stale"], NONE, "server",
+        parse_replicaof),
+    spec("slowlog", -2, &["admin", "loading", "stale"], NONE, "server",
+        parse_slowlog),
+    spec("smembers", 2, &["readonly"], KEY, "set",
+        |args| Ok(Command::SMembers(next_bytes(args)?))),
+    spec("srem", -3, &["write", "fast"], KEY, "set",
+        |args| Ok(Command::SRem(next_bytes(args)?, rest_bytes(args)?))),
+    spec("sscan", -3, &["readonly"], KEY, "set",
+        |args| parse_key_scan(args, Command::SScan)),
+    spec("strlen", 2, &["readonly", "fast"], KEY, "string",
+        |args| Ok(Command::Strlen(next_bytes(args)?))),
+    spec("subscribe", -2, &["pubsub", "noscript", "loading", "stale"], NONE, "pubsub",
+        |args| Ok(Command::Subscribe(rest_bytes(args)?, false))),
+    spec("sunion", -2, &["readonly"], KEYS, "set",
+        |args| Ok(Command::SCombine(SetOp::Union, rest_bytes(args)?))),
+    spec("sunionstore", -3, &["write", "denyoom"], KEYS, "set",
+        |args| Ok(Command::SCombineStore(next_bytes(args)?, SetOp::Union, rest_bytes(args)?))),
+    spec("touch", -2, &["readonly", "fast"], KEYS, "generic",
+        |args| Ok(Command::Touch(rest_bytes(args)?))),
+    spec("ttl", 2, &["readonly", "fast"], KEY, "generic",
+        |args| Ok(Command::Ttl(next_bytes(args)?))),
+    spec("type", 2, &["readonly", "fast"], KEY, "generic",
+        |args| Ok(Command::Type(next_bytes(args)?))),
+    spec("unlink", -2, &["write", "fast"], KEYS, "generic",
+        |args| Ok(Command::Unlink(rest_bytes(args)?))),
+    spec("unsubscribe", -1, &["pubsub", "noscript", "loading", "stale"], NONE, "pubsub",
+        |args| Ok(Command::Unsubscribe(rest_bytes(args)?, false))),
+    spec("unwatch", 1, &["noscript", "loading", "stale", "fast"], NONE, "transactions",
+        |_| Ok(Command::Unwatch)),
+    spec("wait", 3, &["noscript"], NONE, "generic",
+        parse_wait),
+    spec("watch", -2, &["noscript", "loading", "stale", "fast"], KEYS, "transactions",
+        |args| Ok(Command::Watch(rest_bytes(args)?))),
+    spec("xack", -4, &["write", "fast"], KEY, "stream",
+        parse_xack),
+    spec("xadd", -5, &["write", "denyoom", "fast"], KEY, "stream",
+        parse_xadd),
+    spec("xclaim", -6, &["write", "fast"], KEY, "stream",
+        parse_xclaim),
+    spec("xgroup", -2, &["write"], (2, 2, 1), "stream",
+        parse_xgroup),
+    spec("xlen", 2, &["readonly", "fast"], KEY, "stream",
+        |args| Ok(Command::XLen(next_bytes(args)?))),
+    spec("xpending", -3, &["readonly"], KEY, "stream",
+        parse_xpending),
+    spec("xrange", -4, &["readonly"], KEY, "stream",
+        |args| parse_xrange(args, false)),
+    spec("xread", -4, &["readonly", "blocking", "movablekeys"], NONE, "stream",
+        |args| parse_xread(args, false)),
+    spec("xreadgroup", -7, &["write", "blocking", "movablekeys"], NONE, "stream",
+        |args| parse_xread(args, true)),
+    spec("xrevrange", -4, &["readonly"], KEY, "stream",
+        |args| parse_xrange(args, true)),
+    spec("zadd", -4, &["write", "denyoom", "fast"], KEY, "sorted-set",
+        parse_zadd),
+    spec("zcard", 2, &["readonly", "fast"], KEY, "sorted-set",
+        |args| Ok(Command::ZCard(next_bytes(args)?))),
+    spec("zcount", 4, &["readonly", "fast"], KEY, "sorted-set",
+        |args| parse_zcount(args, By::Score)),
+    spec("zincrby", 4, &["write", "denyoom", "fast"], KEY, "sorted-set",
+        parse_zincrby),
+    spec("zlexcount", 4, &["readonly", "fast"], KEY, "sorted-set",
+        |args| parse_zcount(args, By::Lex)),
+    spec("zrange", -4, &["readonly"], KEY, "sorted-set",
+        |args| parse_zrange(args, By::Index)),
+    spec("zrangebylex", -4, &["readonly"], KEY, "sorted-set",
+        |args| parse_zrange(args, By::Lex)),
+    spec("zrangebyscore", -4, &["readonly"], KEY, "sorted-set",
+        |args| parse_zrange(args, By::Score)),
+    spec("zrank", 3, &["readonly", "fast"], KEY, "sorted-set",
+        |args| Ok(Command::ZRank(next_bytes(args)?, next_bytes(args)?, false))),
+    spec("zrem", -3, &["write", "fast"], KEY, "sorted-set",
+        |args| Ok(Command::ZRem(next_bytes(args)?, rest_bytes(args)?))),
+    spec("zrevrank", 3, &["readonly", "fast"], KEY, "sorted-set",
+        |args| Ok(Command::ZRank(next_bytes(args)?, next_bytes(args)?, true))),
+    spec("zscan", -3, &["readonly"], KEY, "sorted-set",
+        |args| parse_key_scan(args, Command::ZScan)),
+    spec("zscore", 3, &["readonly", "fast"], KEY, "sorted-set",
+        |args| Ok(Command::ZScore(next_bytes(args)?, next_bytes(args)?))),
+];
+
+/// Returns the spec of the command `name`, ignoring case.
+pub fn lookup(name: &[u8]) -> Option<&'static Spec> {
+    let name = name.to_ascii_lowercase();
+    COMMANDS
+        .binary_search_by(|spec| spec.name.as_bytes().cmp(&name))
+        .ok()
+        .map(|i| &COMMANDS[i])
+}
+
+fn string(s: &'static str) -> Frame {
+    Frame::String(Bytes::from_static(s.as_bytes()))
+}
+
+fn bulk(s: &'static str) -> Frame {
+    Frame::Bulk(Some(Bytes::from_static(s.as_bytes())))
+}
+
+impl Spec {
+    /// Returns the keys among `args`, the command's arguments including its name, which are found
+    /// at the positions the spec gives, other than those of `XREAD` and `XREADGROUP`, which take as
+    /// many keys as IDs after their `STREAMS` argument.
+    pub fn keys<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        if matches!(self.name, "xread" | "xreadgroup") {
+            let streams = args.iter().position(|a| a.eq_ignore_ascii_case(b"streams"));
+            let rest = &args[streams.map_or(args.len(), |i| i + 1)..];
+            return rest[..rest.len() / 2].iter().collect();
+        }
+        let (first, last, step) = self.keys;
+        if first == 0 {
+            return Vec::new();
+        }
+        let last = if last < 0 {
+            args.len() as i64 + last
+        } else {
+            last
+        };
+        (first..=last)
+            .step_by(step as usize)
+            .filter_map(|i| args.get(i as usize))
+            .collect()
+    }
+
+    /// Like `keys`, but only those the command may modify: every key but the sources of commands
+    /// storing their result in their first key, which Redis's key specs flag as only read.
+    pub fn written_keys<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        let mut keys = self.keys(args);
+        if matches!(
+            self.name,
+            "bitop" | "sdiffstore" | "sinterstore" | "sunionstore"
+        ) {
+            keys.truncate(1);
+        }
+        keys
+    }
+
+    /// Whether the command accepts `len` arguments, including its name.
+    pub fn accepts(&self, len: usize) -> bool {
+        match self.arity {
+            arity @ 0.. => len as i64 == arity,
+            arity => len as i64 >= -arity,
+        }
+    }
+
+    /// Returns the command's ACL categories. Redis assigns them by hand, so they're approximated
+    /// from the command's group and flags, where admin commands are also dangerous.
+    pub fn categories(&self) -> Vec<&'static str> {
+        let mut categories = Vec::new();
+        match self.group {
+            "generic" => categories.push("keyspace"),
+            "sorted-set" => categories.push("sortedset"),
+            "transactions" => categories.push("transaction"),
+            "server" => {}
+            group => categories.push(group),
+        }
+        for (flag, flag_categories) in [
+            ("write", &["write"][..]),
+            ("readonly", &["read"]),
+            ("admin", &["admin", "dangerous"]),
+            ("blocking", &["blocking"]),
+        ] {
+            if self.flags.contains(&flag) {
+                categories.extend(flag_categories);
+            }
+        }
+        categories.push(if self.flags.contains(&"fast") {
+            "fast"
+        } else {
+            "slow"
+        });
+        categories
+    }
+
+    /// Describes the command like `COMMAND INFO`, i.e. its name, arity, flags, key positions, ACL
+    /// categories, tips, key specs and subcommands, the last three of which are always empty.
+    fn info(&self) -> Frame {
+        let (first, last, step) = self.keys;
+        Frame::Array(Some(vec![
+            bulk(self.name),
+            Frame::Integer(self.arity),
+            Frame::Set(self.flags.iter().map(|f| string(f)).collect()),
+            Frame::Integer(first),
+            Frame::Integer(last),
+            Frame::Integer(step),
+            Frame::Set(
+                self.categories()
+                    .into_iter()
+                    .map(|c| Frame::String(format!("@{}", c).into()))
+                    .collect(),
+            ),
+            Frame::Set(vec![]),
+            Frame::Array(Some(vec![])),
+            Frame::Array(Some(vec![])),
+        ]))
+    }
+
+    /// Documents the command like `COMMAND DOCS`, which only gives its group.
+    fn docs(&self) -> Frame {
+        Frame::Map(vec![bulk("group"), bulk(self.group)])
+    }
+}
+
+/// Applies a subcommand of `COMMAND`, which only depends on the table of commands.
+pub fn introspect(command: CommandCommand) -> Frame {
+    match command {
+        CommandCommand::All => Frame::Array(Some(COMMANDS.iter().map(Spec::info).collect())),
+        CommandCommand::Count => Frame::Integer(COMMANDS.len() as i64),
+        // like Redis, unknown commands are null, and no names describes every command
+        CommandCommand::Info(names) if names.is_empty() => introspect(CommandCommand::All),
+        CommandCommand::Info(names) => Frame::Array(Some(
+            names
+                .iter()
+                .map(|name| lookup(name).map_or(Frame::Null, Spec::info))
+                .collect(),
+        )),
+        // unknown commands are left out
+        CommandCommand::Docs(names) => {
+            let specs: Vec<&Spec> = match names.is_empty() {
+                true => COMMANDS.iter().collect(),
+                false => names.iter().filter_map(|name| lookup(name)).collect(),
+            };
+            Frame::Map(
+                specs
+                    .into_iter()
+                    .flat_map(|spec| [bulk(spec.name), spec.docs()])
+                    .collect(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_sorted_and_described() {
+        assert!(COMMANDS.windows(2).all(|w| w[0].name < w[1].name));
+        assert_eq!(
+            Frame::Integer(COMMANDS.len() as i64),
+            introspect(CommandCommand::Count)
+        );
+
+        let names = vec!["GET".into(), "nosuchcommand".into()];
+        let Frame::Array(Some(info)) = introspect(CommandCommand::Info(names)) else {
+            panic!("expected an array");
+        };
+        let Frame::Array(Some(get)) = &info[0] else {
+            panic!("expected an array, got {:?}", info[0]);
+        };
+        assert_eq!(bulk("get"), get[0]);
+        assert_eq!(Frame::Integer(2), get[1]);
+        assert_eq!(Frame::Set(vec![string("readonly"), string("fast")]), get[2]);
+        assert_eq!(Frame::Integer(1), get[3]);
+        assert_eq!(Frame::Null, info[1]);
+
+        let docs = introspect(CommandCommand::Docs(vec!["zadd".into()]));
+        let group = Frame::Map(vec![bulk("group"), bulk("sorted-set")]);
+        assert_eq!(Frame::Map(vec![bulk("zadd"), group]), docs);
+    }
+}
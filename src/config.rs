@@ -0,0 +1,743 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    command::parse_memory,
+    db::{AutoRewrite, EncodingLimits, EvictionPolicy, Fsync, Notifications, OutputLimits},
+    glob,
+};
+
+/// Every tunable of the server, as set on the command line and changed by `CONFIG SET`.
+#[derive(Clone)]
+pub struct Config {
+    /// The addresses listened on.
+    pub bind: Vec<String>,
+    pub port: u16,
+    /// The directory snapshots and the AOF are saved in.
+    pub dir: String,
+    pub dbfilename: String,
+    pub appendonly: bool,
+    pub appendfilename: String,
+    pub appendfsync: Fsync,
+    pub auto_rewrite: AutoRewrite,
+    /// How many times a second background tasks such as active expiration run.
+    pub hz: u32,
+    pub active_expire_effort: u32,
+    /// The flags of the keyspace events published, which are valid flags for `Notifications`.
+    pub notify_keyspace_events: String,
+    /// Whether every write command is rejected, regardless of replication role.
+    pub read_only: bool,
+    /// The password clients must authenticate with, or empty if they needn't authenticate.
+    pub requirepass: String,
+    /// Whether clients connect through a proxy that sends a PROXY protocol header first.
+    pub proxy_protocol: bool,
+    /// The host and port of the master followed on startup, if any.
+    pub replicaof: Option<(String, u16)>,
+    /// How many bytes may be pending to be sent to each class of client before it is disconnected.
+    pub client_output_buffer_limit: OutputLimits,
+    /// How many clients may be connected at once.
+    pub maxclients: usize,
+    /// How many bytes the dataset may use, or 0 for no limit.
+    pub maxmemory: u64,
+    /// Which keys are evicted once `maxmemory` is reached.
+    pub maxmemory_policy: EvictionPolicy,
+    /// How many keys are sampled to pick each one to evict.
+    pub maxmemory_samples: usize,
+    /// When the dataset is saved in the background, as pairs of how many seconds have passed and
+    /// how many writes were applied since it was last saved.
+    pub save: Vec<(u64, u64)>,
+    /// How large collections may grow while they are encoded compactly.
+    pub encoding_limits: EncodingLimits,
+    /// How many microseconds a command must take to be added to the slow log, where 0 logs every
+    /// command and a negative number none.
+    pub slowlog_log_slower_than: i64,
+    /// How many entries the slow log keeps, dropping the oldest ones past it.
+    pub slowlog_max_len: u64,
+    /// How many milliseconds an event must take to be recorded as a latency spike, or 0 to record
+    /// none.
+    pub latency_monitor_threshold: u64,
+    /// How many seconds a client may stay idle before it is disconnected, or 0 to never disconnect
+    /// idle clients.
+    pub timeout: u64,
+    /// How many seconds apart TCP keepalive probes are sent to clients, or 0 to send none.
+    pub tcp_keepalive: u64,
+    /// The port TLS connections are accepted on, or 0 to accept none.
+    pub tls_port: u16,
+    /// The PEM files of the server's certificate and private key, and of the CA certificates
+    /// clients are authenticated with.
+    pub tls_cert_file: String,
+    pub tls_key_file: String,
+    pub tls_ca_cert_file: String,
+    /// The file the config was loaded from, which `CONFIG REWRITE` writes to.
+    pub file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: vec!["127.0.0.1".into()],
+            port: 6379,
+            dir: ".".into(),
+            dbfilename: "dump.rdb".into(),
+            appendonly: false,
+            appendfilename: "appendonly.aof".into(),
+            appendfsync: Fsync::EverySec,
+            auto_rewrite: AutoRewrite::default(),
+            hz: 10,
+            active_expire_effort: 1,
+            notify_keyspace_events: String::new(),
+            read_only: false,
+            requirepass: String::new(),
+            proxy_protocol: false,
+            replicaof: None,
+            client_output_buffer_limit: OutputLimits::default(),
+            maxclients: 10000,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            maxmemory_samples: 5,
+            save: vec![(3600, 1), (300, 100), (60, 10000)],
+            encoding_limits: EncodingLimits::default(),
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            timeout: 0,
+            tcp_keepalive: 300,
+            tls_port: 0,
+            tls_cert_file: String::new(),
+            tls_key_file: String::new(),
+            tls_ca_cert_file: String::new(),
+            file: None,
+        }
+    }
+}
+
+/// Why a parameter couldn't be set.
+#[derive(Debug, PartialEq)]
+pub enum SetError {
+    Unknown,
+    /// The parameter can only be set on startup.
+    Immutable,
+    Invalid,
+}
+
+/// A parameter of the config, by the name `CONFIG GET` and `CONFIG SET` know it by.
+struct Param {
+    name: &'static str,
+    /// Whether `CONFIG SET` may change it once the server started.
+    mutable: bool,
+    get: fn(&Config) -> String,
+    /// Parses and sets the value, returning `None` if it is invalid.
+    set: fn(&mut Config, &str) -> Option<()>,
+}
+
+const PARAMS: &[Param] = &[
+    Param {
+        name: "active-expire-effort",
+        mutable: true,
+        get: |c| c.active_expire_effort.to_string(),
+        set: |c, v| {
+            c.active_expire_effort = v.parse().ok().filter(|e| (1..=10).contains(e))?;
+            Some(())
+        },
+    },
+    Param {
+        name: "appendfilename",
+        mutable: false,
+        get: |c| c.appendfilename.clone(),
+        set: |c, v| {
+            c.appendfilename = v.into();
+            Some(())
+        },
+    },
+    Param {
+        name: "appendfsync",
+        mutable: true,
+        get: |c| c.appendfsync.name().into(),
+        set: |c, v| {
+            c.appendfsync = Fsync::parse(v)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "appendonly",
+        mutable: false,
+        get: |c| yes_no(c.appendonly),
+        set: |c, v| {
+            c.appendonly = parse_yes_no(v)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "auto-aof-rewrite-min-size",
+        mutable: true,
+        get: |c| c.auto_rewrite.min_size.to_string(),
+        set: |c, v| {
+            c.auto_rewrite.min_size = parse_memory(v.as_bytes())?;
+            Some(())
+        },
+    },
+    Param {
+        name: "auto-aof-rewrite-percentage",
+        mutable: true,
+        get: |c| c.auto_rewrite.percentage.to_string(),
+        set: |c, v| {
+            c.auto_rewrite.percentage = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "bind",
+        mutable: false,
+        get: |c| c.bind.join(" "),
+        set: |c, v| {
+            c.bind = v.split_whitespace().map(str::to_string).collect();
+            (!c.bind.is_empty()).then_some(())
+        },
+    },
+    // e.g. `pubsub 32mb 8mb 60`, where classes not given keep their limits
+    Param {
+        name: "client-output-buffer-limit",
+        mutable: true,
+        get: |c| c.client_output_buffer_limit.to_string(),
+        set: |c, v| c.client_output_buffer_limit.parse(v),
+    },
+    Param {
+        name: "dbfilename",
+        mutable: true,
+        get: |c| c.dbfilename.clone(),
+        // like Redis, the file must be in `dir`
+        set: |c, v| (!v.is_empty() && !v.contains('/')).then(|| c.dbfilename = v.into()),
+    },
+    Param {
+        name: "dir",
+        mutable: true,
+        get: |c| c.dir.clone(),
+        set: |c, v| (!v.is_empty()).then(|| c.dir = v.into()),
+    },
+    Param {
+        name: "hash-max-listpack-entries",
+        mutable: true,
+        get: |c| c.encoding_limits.hash_max_listpack_entries.to_string(),
+        set: |c, v| {
+            c.encoding_limits.hash_max_listpack_entries = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "hash-max-listpack-value",
+        mutable: true,
+        get: |c| c.encoding_limits.hash_max_listpack_value.to_string(),
+        set: |c, v| {
+            c.encoding_limits.hash_max_listpack_value = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "hz",
+        mutable: true,
+        get: |c| c.hz.to_string(),
+        // like Redis, out of range frequencies are clamped rather than rejected
+        set: |c, v| {
+            c.hz = v.parse::<u32>().ok()?.clamp(1, 500);
+            Some(())
+        },
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        mutable: true,
+        get: |c| c.latency_monitor_threshold.to_string(),
+        set: |c, v| {
+            c.latency_monitor_threshold = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "list-max-listpack-size",
+        mutable: true,
+        get: |c| c.encoding_limits.list_max_listpack_size.to_string(),
+        set: |c, v| {
+            c.encoding_limits.list_max_listpack_size = v.parse().ok().filter(|&n| n >= -5)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "maxclients",
+        mutable: true,
+        get: |c| c.maxclients.to_string(),
+        set: |c, v| {
+            c.maxclients = v.parse().ok().filter(|&n| n > 0)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "maxmemory",
+        mutable: true,
+        get: |c| c.maxmemory.to_string(),
+        set: |c, v| {
+            c.maxmemory = parse_memory(v.as_bytes())?;
+            Some(())
+        },
+    },
+    Param {
+        name: "maxmemory-policy",
+        mutable: true,
+        get: |c| c.maxmemory_policy.name().into(),
+        set: |c, v| {
+            c.maxmemory_policy = EvictionPolicy::parse(v)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "maxmemory-samples",
+        mutable: true,
+        get: |c| c.maxmemory_samples.to_string(),
+        // like Redis, between 1 and 64 keys may be sampled
+        set: |c, v| {
+            c.maxmemory_samples = v.parse().ok().filter(|n| (1..=64).contains(n))?;
+            Some(())
+        },
+    },
+    Param {
+        name: "notify-keyspace-events",
+        mutable: true,
+        get: |c| c.notify_keyspace_events.clone(),
+        set: |c, v| {
+            Notifications::parse(v)?;
+            c.notify_keyspace_events = v.into();
+            Some(())
+        },
+    },
+    Param {
+        name: "port",
+        mutable: false,
+        get: |c| c.port.to_string(),
+        set: |c, v| {
+            c.port = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "proxy-protocol",
+        mutable: false,
+        get: |c| yes_no(c.proxy_protocol),
+        set: |c, v| {
+            c.proxy_protocol = parse_yes_no(v)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "read-only",
+        mutable: true,
+        get: |c| yes_no(c.read_only),
+        set: |c, v| {
+            c.read_only = parse_yes_no(v)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "requirepass",
+        mutable: true,
+        get: |c| c.requirepass.clone(),
+        set: |c, v| {
+            c.requirepass = v.into();
+            Some(())
+        },
+    },
+    // e.g. `3600 1 300 100`, or empty to never save automatically
+    Param {
+        name: "save",
+        mutable: true,
+        get: |c| {
+            let points = c
+                .save
+                .iter()
+                .map(|(secs, changes)| format!("{} {}", secs, changes));
+            points.collect::<Vec<_>>().join(" ")
+        },
+        set: |c, v| {
+            let numbers: Option<Vec<u64>> = v.split_whitespace().map(|n| n.parse().ok()).collect();
+            let numbers = numbers.filter(|n| n.len() % 2 == 0)?;
+            c.save = numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect();
+            Some(())
+        },
+    },
+    Param {
+        name: "set-max-intset-entries",
+        mutable: true,
+        get: |c| c.encoding_limits.set_max_intset_entries.to_string(),
+        set: |c, v| {
+            c.encoding_limits.set_max_intset_entries = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "set-max-listpack-entries",
+        mutable: true,
+        get: |c| c.encoding_limits.set_max_listpack_entries.to_string(),
+        set: |c, v| {
+            c.encoding_limits.set_max_listpack_entries = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "set-max-listpack-value",
+        mutable: true,
+        get: |c| c.encoding_limits.set_max_listpack_value.to_string(),
+        set: |c, v| {
+            c.encoding_limits.set_max_listpack_value = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        mutable: true,
+        get: |c| c.slowlog_log_slower_than.to_string(),
+        set: |c, v| {
+            c.slowlog_log_slower_than = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "slowlog-max-len",
+        mutable: true,
+        get: |c| c.slowlog_max_len.to_string(),
+        set: |c, v| {
+            c.slowlog_max_len = v.parse().ok()?;
+            Some(())
+        },
+    },
+    // e.g. `localhost 6379`, or empty when not following a master
+    Param {
+        name: "replicaof",
+        mutable: false,
+        get: |c| match &c.replicaof {
+            Some((host, port)) => format!("{} {}", host, port),
+            None => String::new(),
+        },
+        set: |c, v| {
+            c.replicaof = match v.split_once(' ') {
+                _ if v.eq_ignore_ascii_case("no one") => None,
+                Some((host, port)) => Some((host.into(), port.trim().parse().ok()?)),
+                None => return None,
+            };
+            Some(())
+        },
+    },
+    Param {
+        name: "tcp-keepalive",
+        mutable: true,
+        get: |c| c.tcp_keepalive.to_string(),
+        set: |c, v| {
+            c.tcp_keepalive = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "timeout",
+        mutable: true,
+        get: |c| c.timeout.to_string(),
+        set: |c, v| {
+            c.timeout = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "tls-ca-cert-file",
+        mutable: false,
+        get: |c| c.tls_ca_cert_file.clone(),
+        set: |c, v| {
+            c.tls_ca_cert_file = v.into();
+            Some(())
+        },
+    },
+    Param {
+        name: "tls-cert-file",
+        mutable: false,
+        get: |c| c.tls_cert_file.clone(),
+        set: |c, v| {
+            c.tls_cert_file = v.into();
+            Some(())
+        },
+    },
+    Param {
+        name: "tls-key-file",
+        mutable: false,
+        get: |c| c.tls_key_file.clone(),
+        set: |c, v| {
+            c.tls_key_file = v.into();
+            Some(())
+        },
+    },
+    Param {
+        name: "tls-port",
+        mutable: false,
+        get: |c| c.tls_port.to_string(),
+        set: |c, v| {
+            c.tls_port = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "zset-max-listpack-entries",
+        mutable: true,
+        get: |c| c.encoding_limits.zset_max_listpack_entries.to_string(),
+        set: |c, v| {
+            c.encoding_limits.zset_max_listpack_entries = v.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "zset-max-listpack-value",
+        mutable: true,
+        get: |c| c.encoding_limits.zset_max_listpack_value.to_string(),
+        set: |c, v| {
+            c.encoding_limits.zset_max_listpack_value = v.parse().ok()?;
+            Some(())
+        },
+    },
+];
+
+impl Config {
+    /// Sets the parameter `name` to `value`, as on startup.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SetError> {
+        let param = param(name).ok_or(SetError::Unknown)?;
+        (param.set)(self, value).ok_or(SetError::Invalid)
+    }
+
+    /// Sets the parameters in the redis.conf-style file at `path`, i.e. a parameter and its values
+    /// on each line, which may be quoted, and then CONFIG REWRITE writes to it.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let invalid = |line: usize, msg: String| {
+            let msg = format!("{}:{}: {}", path.display(), line + 1, msg);
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        };
+        let mut save_points = String::new();
+        for (i, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let args = split_args(line).ok_or_else(|| invalid(i, "unbalanced quotes".into()))?;
+            let (name, values) = args.split_first().expect("the line isn't empty");
+            let mut value = values.join(" ");
+            // like Redis, each `save` line adds save points rather than replacing them
+            if name.eq_ignore_ascii_case("save") {
+                save_points = match value.is_empty() {
+                    true => String::new(),
+                    false => format!("{} {}", save_points, value),
+                };
+                value = save_points.clone();
+            }
+            self.set(name, &value)
+                .map_err(|e| invalid(i, format!("can't set {} to '{}': {:?}", name, value, e)))?;
+        }
+        self.file = Some(std::fs::canonicalize(path)?);
+        Ok(())
+    }
+
+    /// Sets the parameters given on the command line, e.g. `--port 6380`, returning why if one
+    /// couldn't be. Like Redis, a parameter takes every argument up to the next one, so that values
+    /// with several words needn't be quoted, e.g. `--replicaof localhost 6379`.
+    pub fn set_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), String> {
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(format!("unexpected argument '{}'", arg));
+            };
+            let mut values = Vec::new();
+            while let Some(value) = args.next_if(|a| !a.starts_with("--")) {
+                values.push(value);
+            }
+            let value = values.join(" ");
+            if let Err(e) = self.set(name, &value) {
+                return Err(format!("can't set {} to '{}': {:?}", name, value, e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the parameter `name` to `value` once the server started, i.e. `CONFIG SET`.
+    pub fn set_at_runtime(&mut self, name: &str, value: &str) -> Result<(), SetError> {
+        match param(name) {
+            Some(param) if !param.mutable => Err(SetError::Immutable),
+            _ => self.set(name, value),
+        }
+    }
+
+    /// Returns the name and value of every parameter matching the glob-style `pattern`, i.e.
+    /// `CONFIG GET`.
+    pub fn get(&self, pattern: &[u8]) -> Vec<(&'static str, String)> {
+        let pattern = pattern.to_ascii_lowercase();
+        PARAMS
+            .iter()
+            .filter(|p| glob::matches(&pattern, p.name.as_bytes()))
+            .map(|p| (p.name, (p.get)(self)))
+            .collect()
+    }
+
+    /// Writes the config to the file at `path`, i.e. `CONFIG REWRITE`.
+    ///
+    /// Like Redis, the file is kept as it is but for the lines setting parameters, which are
+    /// updated, and parameters that differ from their default are appended if it doesn't set them.
+    pub fn rewrite(&self, path: &Path) -> io::Result<()> {
+        let old = match std::fs::read_to_string(path) {
+            Ok(old) => old,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut written = HashSet::new();
+        let mut lines = Vec::new();
+        for line in old.lines() {
+            let name = line.split_whitespace().next().unwrap_or_default();
+            match param(name) {
+                // a parameter set more than once is only written once
+                Some(param) if written.insert(param.name) => lines.push(directive(param, self)),
+                Some(_) => (),
+                None => lines.push(line.to_string()),
+            }
+        }
+        let default = Config::default();
+        for param in PARAMS {
+            if !written.contains(param.name) && (param.get)(self) != (param.get)(&default) {
+                lines.push(directive(param, self));
+            }
+        }
+        lines.push(String::new());
+        std::fs::write(path, lines.join("\n"))
+    }
+}
+
+fn param(name: &str) -> Option<&'static Param> {
+    PARAMS.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// Returns the line of a config file setting `param` to its value in `config`.
+fn directive(param: &Param, config: &Config) -> String {
+    match (param.get)(config) {
+        value if value.is_empty() => format!("{} \"\"", param.name),
+        value => format!("{} {}", param.name, value),
+    }
+}
+
+/// Splits a line of a config file into its arguments, which are separated by whitespace unless
+/// quoted, returning `None` if a quote isn't closed. Like Redis, double quoted arguments may have
+/// escape sequences such as `\n`, while single quoted ones are taken literally.
+fn split_args(line: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Some(args);
+        };
+        let mut arg = String::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    c if c == first => break,
+                    '\\' if first == '"' => arg.push(match chars.next()? {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        c => c,
+                    }),
+                    c => arg.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+fn yes_no(enabled: bool) -> String {
+    if enabled { "yes" } else { "no" }.into()
+}
+
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_and_set_parameters() {
+        let mut config = Config::default();
+        assert_eq!(Ok(()), config.set_at_runtime("HZ", "1000"));
+        assert_eq!(Ok(()), config.set("replicaof", "localhost 6380"));
+        assert_eq!(Err(SetError::Immutable), config.set_at_runtime("port", "1"));
+        assert_eq!(Err(SetError::Invalid), config.set("appendonly", "maybe"));
+        assert_eq!(Err(SetError::Unknown), config.set("no-such-param", "1"));
+        let hz = ("hz", "500".to_string());
+        let replicaof = ("replicaof", "localhost 6380".to_string());
+        assert_eq!(vec![hz], config.get(b"HZ"));
+        assert_eq!(vec![replicaof], config.get(b"repl*"));
+    }
+
+    #[test]
+    fn load_a_file() {
+        let path = std::env::temp_dir().join(format!("redis-load-{}.conf", std::process::id()));
+        let file = "# a comment\n\nport 6380\nbind 127.0.0.1 ::1\nsave 900 1\nsave 300 10\n\
+                    notify-keyspace-events \"\"\ndir '/tmp/a dir'\n";
+        std::fs::write(&path, file).unwrap();
+        let mut config = Config::default();
+        let loaded = config.load(&path);
+        std::fs::write(&path, "port 6380\nhz \"10\n").unwrap();
+        let unbalanced = Config::default().load(&path);
+        std::fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        assert_eq!(6380, config.port);
+        assert_eq!(vec!["127.0.0.1", "::1"], config.bind);
+        assert_eq!(vec![(900, 1), (300, 10)], config.save);
+        assert_eq!("", config.notify_keyspace_events);
+        assert_eq!("/tmp/a dir", config.dir);
+        assert_eq!(Some(path), config.file);
+        assert!(unbalanced.is_err());
+    }
+
+    #[test]
+    fn set_args() {
+        let mut config = Config::default();
+        let args = [
+            "--port",
+            "6380",
+            "--replicaof",
+            "localhost",
+            "6379",
+            "--bind",
+            "::1 127.0.0.1",
+        ];
+        config.set_args(args.map(String::from)).unwrap();
+        assert_eq!(6380, config.port);
+        assert_eq!(Some(("localhost".into(), 6379)), config.replicaof);
+        assert_eq!(vec!["::1", "127.0.0.1"], config.bind);
+        assert!(config.set_args(["6380".to_string()]).is_err());
+        assert!(config.set_args(["--port".to_string()]).is_err());
+    }
+
+    #[test]
+    fn rewrite_keeps_comments_and_updates_parameters() {
+        let path = std::env::temp_dir().join(format!("redis-{}.conf", std::process::id()));
+        std::fs::write(&path, "# a comment\nhz 20\nport 6380\nhz 30\n").unwrap();
+        let mut config = Config::default();
+        config.set("port", "6380").unwrap();
+        config.set("appendonly", "yes").unwrap();
+        config.rewrite(&path).unwrap();
+        let rewritten = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!("# a comment\nhz 10\nport 6380\nappendonly yes\n", rewritten);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::frame::{Bool, Frame, InvalidBool, InvalidPrefix, Prefix};
+use crate::{
+    frame::{Bool, Frame, InvalidBool, InvalidPrefix, Prefix},
+    reply::ErrorReply,
+};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use core::num;
 use std::{
@@ -6,7 +9,7 @@ use std::{
         self,
         ErrorKind::{self, UnexpectedEof},
     },
-    str,
+    slice, str,
 };
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
@@ -19,6 +22,9 @@ use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 /// A wrapper over a stream, used to read and write RESP frames.
 pub struct Connection<'a, RW> {
+    /// The RESP version spoken by the client. Frames are downgraded to their RESP2 equivalents when
+    /// writing to clients that have not negotiated RESP3.
+    pub protover: u8,
     read_buf: BytesMut,
     write_buf: BytesMut,
     stream: &'a mut RW,
@@ -27,6 +33,10 @@ pub struct Connection<'a, RW> {
 const DEFAULT_BUF_SIZE: usize = 4096;
 const LF: u8 = b'\n';
 const CRLF: &[u8] = &[b'\r', LF];
+/// The most elements an aggregate frame may have, like Redis's limit on multibulk lengths.
+const MAX_AGGREGATE_LEN: usize = 1024 * 1024;
+/// The longest blob frame that may be read, matching Redis's default `proto-max-bulk-len`.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
 
 impl<'a, RW: AsyncRead + AsyncWrite + Unpin> Connection<'a, RW> {
     /// Creates a new Connection with a default read/write buffer capacity. The default is currently
@@ -40,6 +50,7 @@ impl<'a, RW: AsyncRead + AsyncWrite + Unpin> Connection<'a, RW> {
     /// Creates a new Connection with the specified buffer capacity.
     pub fn with_capacity(read_capacity: usize, write_capacity: usize, stream: &'a mut RW) -> Self {
         Self {
+            protover: 2,
             read_buf: BytesMut::with_capacity(read_capacity),
             write_buf: BytesMut::with_capacity(write_capacity),
             stream,
@@ -81,113 +92,141 @@ impl<'a, RW: AsyncRead + AsyncWrite + Unpin> Connection<'a, RW> {
     ///    than than other operations, so when a socket is ready to be read, it's generally best to
     ///    read everything it has.
     pub async fn read_frame(&mut self) -> Result<Option<Frame>, ReadError> {
-        let mut array_stack: Vec<(Vec<Frame>, usize)> = vec![];
+        let mut aggregate_stack: Vec<(Prefix, Vec<Frame>, usize)> = vec![];
 
         loop {
-            // fold completed arrays into previous ones or return the last one if it is completed
-            while let Some((complete_array, _)) = array_stack
+            // fold completed aggregates into previous ones or return the last one if it is completed
+            while let Some((prefix, complete_aggregate, _)) = aggregate_stack
                 .last()
-                .is_some_and(|(arr, intended_capacity)| arr.len() == *intended_capacity)
-                .then(|| array_stack.pop().unwrap())
+                .is_some_and(|(_, frames, intended_capacity)| frames.len() == *intended_capacity)
+                .then(|| aggregate_stack.pop().unwrap())
             {
-                let frame = Frame::Array(Some(complete_array));
-                if array_stack.len() == 0 {
+                let frame = aggregate(prefix, complete_aggregate);
+                if aggregate_stack.is_empty() {
                     return Ok(Some(frame));
                 }
-                array_stack.last_mut().unwrap().0.push(frame);
+                aggregate_stack.last_mut().unwrap().1.push(frame);
             }
 
-            let prefix: Prefix = match self.read_u8().await {
-                Err(e) if array_stack.is_empty() && e.kind() == UnexpectedEof => return Ok(None),
-                r => r?.try_into()?,
+            let first = match self.read_u8().await {
+                Err(e) if aggregate_stack.is_empty() && e.kind() == UnexpectedEof => {
+                    return Ok(None)
+                }
+                r => r?,
+            };
+            let prefix = match Prefix::try_from(first) {
+                Ok(prefix) => prefix,
+                // like Redis, a command that isn't RESP is sent inline, e.g. typed over telnet
+                Err(_) if aggregate_stack.is_empty() => match self.read_inline(first).await? {
+                    Some(frame) => return Ok(Some(frame)),
+                    None => continue,
+                },
+                Err(e) => return Err(e.into()),
             };
 
-            let mut payload = self.read_line().await?;
-            if CRLF != payload.split_off(payload.len() - 2) {
-                return Err(ReadError::MissingTerminator);
-            }
-
+            let payload = self.read_crlf_line().await?;
             let frame = match prefix {
                 Prefix::Array if payload.starts_with(b"-") => Frame::Array(None),
-                Prefix::Array => {
-                    let size = str::from_utf8(&payload)?.parse()?;
-                    let array: Vec<Frame> = Vec::with_capacity(size);
-                    if array.capacity() != 0 {
-                        array_stack.push((array, size));
+                Prefix::Array | Prefix::Attribute | Prefix::Map | Prefix::Push | Prefix::Set => {
+                    let mut size: usize = str::from_utf8(&payload)?.parse()?;
+                    if size > MAX_AGGREGATE_LEN {
+                        return Err(ReadError::InvalidAggregateLength);
+                    }
+                    if matches!(prefix, Prefix::Attribute | Prefix::Map) {
+                        size = size
+                            .checked_mul(2)
+                            .ok_or(ReadError::InvalidAggregateLength)?;
+                    }
+                    // the length comes from the client, so only what it has already sent is
+                    // allocated up front
+                    let frames: Vec<Frame> = Vec::with_capacity(size.min(self.read_buf.len()));
+                    if size != 0 {
+                        aggregate_stack.push((prefix, frames, size));
                         continue;
                     }
-                    Frame::Array(Some(array))
+                    aggregate(prefix, frames)
                 }
+                Prefix::BigNumber => Frame::BigNumber(payload),
                 Prefix::Boolean => Frame::Boolean(Bool::try_from(payload.as_ref())?.into()),
                 Prefix::Bulk if payload.starts_with(b"-") => Frame::Bulk(None),
-                Prefix::Bulk => {
-                    let size = str::from_utf8(&payload)?.parse::<usize>()? + 2;
-                    let mut data = self.read_exact(size).await?;
-                    if CRLF != data.split_off(data.len() - 2) {
-                        return Err(ReadError::MissingTerminator);
-                    }
-                    Frame::Bulk(Some(data))
-                }
+                Prefix::Bulk => Frame::Bulk(Some(self.read_blob(&payload).await?)),
+                Prefix::Double => Frame::Double(str::from_utf8(&payload)?.parse()?),
                 Prefix::Error => Frame::Error(payload),
                 Prefix::Integer => Frame::Integer(str::from_utf8(&payload)?.parse()?),
                 Prefix::Null => Frame::Null,
                 Prefix::String => Frame::String(payload),
+                Prefix::Verbatim => {
+                    let mut data = self.read_blob(&payload).await?;
+                    if data.len() < 4 || data[3] != b':' {
+                        return Err(ReadError::InvalidVerbatim);
+                    }
+                    let format = data.split_to(4)[..3].try_into().unwrap();
+                    Frame::Verbatim(format, data)
+                }
             };
 
-            if let Some((current_array, _)) = array_stack.last_mut() {
-                current_array.push(frame);
+            if let Some((_, current_aggregate, _)) = aggregate_stack.last_mut() {
+                current_aggregate.push(frame);
             } else {
                 return Ok(Some(frame));
             }
         }
     }
 
+    /// Reads an RDB snapshot the way masters send them to replicas, i.e. like a bulk string, but
+    /// without the trailing CRLF.
+    pub async fn read_rdb(&mut self) -> Result<Bytes, ReadError> {
+        if self.read_u8().await? != b'$' {
+            return Err(ReadError::InvalidPrefix);
+        }
+        let header = self.read_crlf_line().await?;
+        let size = str::from_utf8(&header)?.parse()?;
+        Ok(self.read_exact(size).await?)
+    }
+
+    /// Returns whether bytes of the next frame have already been read from the stream, i.e. whether
+    /// the client pipelined more than one command.
+    pub fn has_buffered_data(&self) -> bool {
+        !self.read_buf.is_empty()
+    }
+
+    /// Resolves once the client has disconnected, buffering anything it sends in the meantime so
+    /// that it can still be read afterwards. This makes it safe to cancel, e.g. to watch for the
+    /// client going away while its command is blocked.
+    pub async fn closed(&mut self) {
+        while self.must_fill_buf().await.is_ok() {}
+    }
+
+    /// Serializes a frame into the write buffer. Nothing is sent until `flush` is called, so that the
+    /// replies to pipelined commands can be sent together.
     // TODO(cjshearer): if I ever get around to benchmarking this, it would be cool to see if this
     // could be optimized in the case of large, non-array type frames. If mem::size_of(frame)
     // crosses some threshold, then skipping the intermediate buffer and writing each part of the
     // frame directly to the stream could be faster.
-    pub async fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
-        let init_arr = [frame];
-        let mut iter_stack = vec![init_arr.iter()];
-        while let Some((frame, remaining)) = iter_stack
-            .last_mut()
-            .and_then(|iter| iter.next().map(|f| (f, iter.len())))
-        {
-            if 0 == remaining {
-                iter_stack.pop();
-            }
-            self.write_buf.put_u8(frame.prefix());
-            match frame {
-                Frame::Array(Some(array)) => {
-                    self.write_buf
-                        .put_slice(&array.len().to_string().as_bytes());
-                    if array.len() > 0 {
-                        iter_stack.push(array.iter());
-                    }
-                }
-                Frame::Array(None) | Frame::Bulk(None) => self.write_buf.put_slice(b"-1"),
-                Frame::Boolean(bool) => self.write_buf.put_u8(Bool::from(*bool).into()),
-                Frame::Bulk(Some(bulk)) => {
-                    self.write_buf.put_slice(bulk.len().to_string().as_bytes());
-                    self.write_buf.put_slice(CRLF);
-                    self.write_buf.put_slice(bulk.as_ref());
-                }
-                Frame::Error(error) => self.write_buf.put_slice(error.as_ref()),
-                Frame::Integer(i) => self.write_buf.put_slice(&i.to_string().as_bytes()),
-                Frame::Null => (),
-                Frame::String(string) => self.write_buf.put_slice(string.as_ref()),
-            };
-            self.write_buf.put_slice(CRLF);
-        }
+    pub fn write_frame(&mut self, frame: Frame) {
+        put_frame(&mut self.write_buf, &frame, self.protover < 3);
+    }
+
+    /// Serializes an RDB snapshot into the write buffer the way masters send them to replicas, i.e.
+    /// like a bulk string, but without the trailing CRLF.
+    pub fn write_rdb(&mut self, rdb: &[u8]) {
+        self.write_buf.put_u8(b'$');
+        put_blob(&mut self.write_buf, rdb);
+    }
+
+    /// Writes every buffered frame to the underlying stream.
+    pub async fn flush(&mut self) -> io::Result<()> {
         self.stream.write_all_buf(&mut self.write_buf).await
     }
 
-    /// Reads more than 0 bytes into the read_buffer, returning an EoF error if none could be read
-    async fn must_fill_buf(&mut self) -> io::Result<usize> {
-        return match self.stream.read_buf(&mut self.read_buf).await? {
+    /// Reads more than 0 bytes into the read_buffer, returning an EoF error if none could be read.
+    /// Unlike `read_frame`, this is safe to cancel, e.g. to wait for input while also waiting for
+    /// messages to push to the client.
+    pub async fn must_fill_buf(&mut self) -> io::Result<usize> {
+        match self.stream.read_buf(&mut self.read_buf).await? {
             0 => Err(UnexpectedEof.into()),
             s => Ok(s),
-        };
+        }
     }
 
     /// Reads all bytes until a newline (the 0xA byte) is reached, returning them as `Bytes`.
@@ -195,7 +234,7 @@ impl<'a, RW: AsyncRead + AsyncWrite + Unpin> Connection<'a, RW> {
         let mut cursor = 0;
         loop {
             if let Some(terminal) = self.read_buf[cursor..].iter().position(|c| *c == LF) {
-                cursor = terminal;
+                cursor += terminal;
                 break;
             }
             cursor = self.read_buf.len();
@@ -204,6 +243,48 @@ impl<'a, RW: AsyncRead + AsyncWrite + Unpin> Connection<'a, RW> {
         Ok(self.read_buf.split_to(cursor + 1).freeze())
     }
 
+    /// Reads a line terminated by CRLF, returning it without the terminator.
+    async fn read_crlf_line(&mut self) -> Result<Bytes, ReadError> {
+        let mut line = self.read_line().await?;
+        line.truncate(line.len() - 1);
+        if !line.ends_with(b"\r") {
+            return Err(ReadError::MissingTerminator);
+        }
+        line.truncate(line.len() - 1);
+        Ok(line)
+    }
+
+    /// Reads the rest of an inline command starting with `first`, i.e. its arguments separated by
+    /// spaces and terminated by a newline, returning them as an array of bulk strings, or `None` if
+    /// the line is blank.
+    async fn read_inline(&mut self, first: u8) -> Result<Option<Frame>, ReadError> {
+        let mut line = vec![first];
+        if first != LF {
+            line.extend_from_slice(&self.read_line().await?);
+        }
+        let args = split_inline(&line).ok_or(ReadError::UnbalancedQuotes)?;
+        if args.is_empty() {
+            return Ok(None);
+        }
+        let args = args.into_iter().map(|a| Frame::Bulk(Some(a.into())));
+        Ok(Some(Frame::Array(Some(args.collect()))))
+    }
+
+    /// Reads the length-prefixed data of a blob frame (e.g. a bulk string), given the header holding
+    /// its length.
+    async fn read_blob(&mut self, header: &[u8]) -> Result<Bytes, ReadError> {
+        let size = str::from_utf8(header)?.parse::<usize>()?;
+        if size > MAX_BULK_LEN {
+            return Err(ReadError::InvalidBulkLength);
+        }
+        let size = size + 2;
+        let mut data = self.read_exact(size).await?;
+        if CRLF != data.split_off(data.len() - 2) {
+            return Err(ReadError::MissingTerminator);
+        }
+        Ok(data)
+    }
+
     /// Fills the buffer with at least `size` bytes, returning them as `Bytes`.
     async fn read_exact(&mut self, size: usize) -> io::Result<Bytes> {
         while self.read_buf.len() < size {
@@ -217,20 +298,204 @@ impl<'a, RW: AsyncRead + AsyncWrite + Unpin> Connection<'a, RW> {
         if !self.read_buf.has_remaining() {
             self.must_fill_buf().await?;
         }
-        return Ok(self.read_buf.get_u8());
+        Ok(self.read_buf.get_u8())
+    }
+}
+
+/// Returns how many bytes `frame` takes once serialized in RESP2, e.g. to account for the commands
+/// propagated to replicas.
+pub fn encoded_len(frame: &Frame) -> usize {
+    let mut buf = BytesMut::new();
+    put_frame(&mut buf, frame, true);
+    buf.len()
+}
+
+/// Serializes `frame` into `buf`, downgrading it to its closest RESP2 equivalent if `resp2`.
+fn put_frame(buf: &mut BytesMut, frame: &Frame, resp2: bool) {
+    let mut iter_stack = vec![slice::from_ref(frame).iter()];
+    while let Some((frame, remaining)) = iter_stack
+        .last_mut()
+        .and_then(|iter| iter.next().map(|f| (f, iter.len())))
+    {
+        if 0 == remaining {
+            iter_stack.pop();
+        }
+        // RESP2 clients get the closest RESP2 equivalent of each RESP3 frame
+        buf.put_u8(match frame {
+            Frame::Attribute(_) if resp2 => continue, // RESP2 has no notion of attributes
+            Frame::Map(_) | Frame::Push(_) | Frame::Set(_) if resp2 => b'*',
+            Frame::BigNumber(_) | Frame::Double(_) | Frame::Null | Frame::Verbatim(..) if resp2 => {
+                b'$'
+            }
+            Frame::Boolean(_) if resp2 => b':',
+            _ => frame.prefix(),
+        });
+        match frame {
+            Frame::Array(Some(frames))
+            | Frame::Attribute(frames)
+            | Frame::Map(frames)
+            | Frame::Push(frames)
+            | Frame::Set(frames) => {
+                let len = match frame {
+                    Frame::Attribute(_) | Frame::Map(_) if !resp2 => frames.len() / 2,
+                    _ => frames.len(),
+                };
+                buf.put_slice(len.to_string().as_bytes());
+                if !frames.is_empty() {
+                    iter_stack.push(frames.iter());
+                }
+            }
+            Frame::Array(None) | Frame::Bulk(None) => buf.put_slice(b"-1"),
+            Frame::BigNumber(number) if resp2 => put_blob(buf, number),
+            Frame::Boolean(bool) if resp2 => buf.put_u8(if *bool { b'1' } else { b'0' }),
+            Frame::Double(double) if resp2 => put_blob(buf, format_double(*double).as_bytes()),
+            Frame::Null if resp2 => buf.put_slice(b"-1"),
+            Frame::Verbatim(_, string) if resp2 => put_blob(buf, string),
+            Frame::BigNumber(number) => buf.put_slice(number),
+            Frame::Boolean(bool) => buf.put_u8(Bool::from(*bool).into()),
+            Frame::Bulk(Some(bulk)) => put_blob(buf, bulk),
+            Frame::Double(double) => buf.put_slice(format_double(*double).as_bytes()),
+            Frame::Error(error) => buf.put_slice(error),
+            Frame::Integer(i) => buf.put_slice(i.to_string().as_bytes()),
+            Frame::Null => (),
+            Frame::String(string) => buf.put_slice(string),
+            Frame::Verbatim(format, string) => {
+                buf.put_slice((string.len() + 4).to_string().as_bytes());
+                buf.put_slice(CRLF);
+                buf.put_slice(format);
+                buf.put_u8(b':');
+                buf.put_slice(string);
+            }
+        };
+        buf.put_slice(CRLF);
+    }
+}
+
+/// Splits an inline command into its arguments the way Redis does, returning `None` if a quote
+/// isn't closed, or is closed but not followed by a space. Like in C, double quoted arguments may
+/// escape characters, e.g. `"\x00\n"`, whereas single quoted ones may only escape `'`.
+fn split_inline(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut bytes = line.iter().copied().peekable();
+    loop {
+        while bytes.next_if(u8::is_ascii_whitespace).is_some() {}
+        if bytes.peek().is_none() {
+            return Some(args);
+        }
+        let mut arg = Vec::new();
+        let mut quote = None;
+        loop {
+            match (quote, bytes.next()) {
+                (None, None) => break,
+                (None, Some(c)) if c.is_ascii_whitespace() => break,
+                (None, Some(c @ (b'"' | b'\''))) => quote = Some(c),
+                (None, Some(c)) => arg.push(c),
+                // the closing quote must end the argument
+                (Some(q), Some(c)) if c == q => {
+                    if bytes.next().is_some_and(|c| !c.is_ascii_whitespace()) {
+                        return None;
+                    }
+                    break;
+                }
+                (Some(b'"'), Some(b'\\')) => arg.push(match bytes.next()? {
+                    b'x' => match hex_byte(bytes.clone()) {
+                        Some(byte) => {
+                            bytes.nth(1);
+                            byte
+                        }
+                        None => b'x',
+                    },
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => 0x08,
+                    b'a' => 0x07,
+                    c => c,
+                }),
+                (Some(_), Some(b'\\')) if bytes.next_if_eq(&b'\'').is_some() => arg.push(b'\''),
+                (Some(_), Some(c)) => arg.push(c),
+                (Some(_), None) => return None,
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Parses the next two hexadecimal digits of `bytes` as a byte, e.g. `41` as `A`.
+fn hex_byte(mut bytes: impl Iterator<Item = u8>) -> Option<u8> {
+    let digits = [bytes.next()?, bytes.next()?];
+    u8::from_str_radix(str::from_utf8(&digits).ok()?, 16).ok()
+}
+
+/// Builds the aggregate frame identified by `prefix` from its elements.
+fn aggregate(prefix: Prefix, frames: Vec<Frame>) -> Frame {
+    match prefix {
+        Prefix::Attribute => Frame::Attribute(frames),
+        Prefix::Map => Frame::Map(frames),
+        Prefix::Push => Frame::Push(frames),
+        Prefix::Set => Frame::Set(frames),
+        _ => Frame::Array(Some(frames)),
+    }
+}
+
+/// Writes the length and data of a blob frame (e.g. `4\r\nbulk` for a bulk string), leaving the
+/// prefix and trailing CRLF to the caller.
+fn put_blob(buf: &mut BytesMut, data: &[u8]) {
+    buf.put_slice(data.len().to_string().as_bytes());
+    buf.put_slice(CRLF);
+    buf.put_slice(data);
+}
+
+/// Formats a double the way RESP3 spells it, i.e. `inf`, `-inf` and `nan` for non-finite values.
+fn format_double(double: f64) -> String {
+    match double {
+        d if d.is_nan() => "nan".to_string(),
+        d if d.is_infinite() && d > 0.0 => "inf".to_string(),
+        d if d.is_infinite() => "-inf".to_string(),
+        d => d.to_string(),
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ReadError {
+    InvalidAggregateLength,
     InvalidBool,
+    InvalidBulkLength,
     InvalidPrefix,
+    InvalidVerbatim,
     IoError(ErrorKind),
     MissingTerminator,
+    ParseFloatError(num::ParseFloatError),
     ParseIntError(num::ParseIntError),
+    UnbalancedQuotes,
     Utf8Error(std::str::Utf8Error),
 }
 
+impl From<ReadError> for Frame {
+    fn from(value: ReadError) -> Self {
+        let detail = match value {
+            ReadError::InvalidAggregateLength => "invalid multibulk length",
+            ReadError::InvalidBool => "invalid boolean",
+            ReadError::InvalidBulkLength => "invalid bulk length",
+            ReadError::InvalidPrefix => "invalid frame prefix",
+            ReadError::InvalidVerbatim => "invalid verbatim string",
+            ReadError::IoError(_) => "unreadable frame",
+            ReadError::MissingTerminator => "missing CRLF terminator",
+            ReadError::ParseFloatError(_) => "invalid double",
+            ReadError::ParseIntError(_) => "invalid integer or length",
+            ReadError::UnbalancedQuotes => "unbalanced quotes in request",
+            ReadError::Utf8Error(_) => "invalid UTF-8",
+        };
+        ErrorReply::err(format!("Protocol error: {}", detail)).into()
+    }
+}
+
+impl From<num::ParseFloatError> for ReadError {
+    fn from(value: num::ParseFloatError) -> Self {
+        ReadError::ParseFloatError(value)
+    }
+}
+
 impl From<num::ParseIntError> for ReadError {
     fn from(value: num::ParseIntError) -> Self {
         ReadError::ParseIntError(value)
@@ -288,7 +553,10 @@ mod tests {
                 #[tokio::test]
                 async fn $write_test() {
                     let mut cursor = Cursor::new(Vec::new());
-                    let _ = Connection::new(&mut cursor).write_frame($frame).await;
+                    let mut connection = Connection::new(&mut cursor);
+                    connection.protover = 3;
+                    connection.write_frame($frame);
+                    let _ = connection.flush().await;
                     assert_eq!($bytes, cursor.into_inner().as_slice());
                 }
 
@@ -296,12 +564,59 @@ mod tests {
         };
     }
 
+    macro_rules! resp2_write_tests {
+        ($($write_test:ident: $frame:expr => $bytes:expr),*) => {
+            $(
+                #[tokio::test]
+                async fn $write_test() {
+                    let mut cursor = Cursor::new(Vec::new());
+                    let mut connection = Connection::new(&mut cursor);
+                    connection.write_frame($frame);
+                    let _ = connection.flush().await;
+                    assert_eq!($bytes, cursor.into_inner().as_slice());
+                }
+            )*
+        };
+    }
+
     read_tests! {
         invalid_bool: b"#invalid\r\n" => Err(ReadError::InvalidBool),
         missing_terminator: b"+I forgot the trailing CRLF" => Err(ReadError::IoError(UnexpectedEof)),
         read_empty_buffer: b"" => Ok(None),
         read_null_array: b"*-1\r\n" => Ok(Some(Frame::Array(None))),
-        read_null_bulk: b"$-1\r\n" => Ok(Some(Frame::Bulk(None)))
+        read_null_bulk: b"$-1\r\n" => Ok(Some(Frame::Bulk(None))),
+        invalid_verbatim: b"=3\r\ntxt\r\n" => Err(ReadError::InvalidVerbatim),
+        read_inline: b"\r\n\nset  key \"a \\\"b\\x41\" 'c\\'d'\r\n" => Ok(Some(Frame::Array(Some(vec![
+            Frame::Bulk(Some("set".into())),
+            Frame::Bulk(Some("key".into())),
+            Frame::Bulk(Some("a \"bA".into())),
+            Frame::Bulk(Some("c'd".into())),
+        ])))),
+        read_unbalanced_inline: b"set \"key\"value\r\n" => Err(ReadError::UnbalancedQuotes),
+        read_huge_array: b"*100000000000\r\n" => Err(ReadError::InvalidAggregateLength),
+        read_overflowing_map: b"%18446744073709551615\r\n" => Err(ReadError::InvalidAggregateLength),
+        read_huge_bulk: b"$18446744073709551615\r\n" => Err(ReadError::InvalidBulkLength),
+        read_bare_newline: b"+\n" => Err(ReadError::MissingTerminator)
+    }
+
+    #[tokio::test]
+    async fn read_frame_split_across_reads() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            // the header of the bulk string ends in the second read
+            client.write_all(b"*1\r\n$4\r").await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            client.write_all(b"\nPING\r\n").await.unwrap();
+            client
+        });
+        let frame = Connection::new(&mut server).read_frame().await;
+        assert_eq!(
+            Ok(Some(Frame::Array(Some(vec![Frame::Bulk(Some(
+                "PING".into()
+            ))])))),
+            frame
+        );
+        writer.await.unwrap();
     }
 
     test_reading_and_writing_frames! {
@@ -337,7 +652,43 @@ mod tests {
         read_true: b"#t\r\n",
         write_true: Frame::Boolean(true),
         read_false: b"#f\r\n",
-        write_false: Frame::Boolean(false)
+        write_false: Frame::Boolean(false),
+        read_map: b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+        write_map: {
+            Frame::Map(vec![
+                Frame::String("first".into()),
+                Frame::Integer(1),
+                Frame::String("second".into()),
+                Frame::Integer(2),
+            ])
+        },
+        read_set: b"~2\r\n+a\r\n+b\r\n",
+        write_set: Frame::Set(vec![Frame::String("a".into()), Frame::String("b".into())]),
+        read_push: b">2\r\n+message\r\n$5\r\nhello\r\n",
+        write_push: Frame::Push(vec![Frame::String("message".into()), Frame::Bulk(Some("hello".into()))]),
+        read_attribute: b"|1\r\n+ttl\r\n:3\r\n",
+        write_attribute: Frame::Attribute(vec![Frame::String("ttl".into()), Frame::Integer(3)]),
+        read_nested_map: b"%1\r\n+key\r\n~0\r\n",
+        write_nested_map: Frame::Map(vec![Frame::String("key".into()), Frame::Set(vec![])]),
+        read_double: b",-1.5\r\n",
+        write_double: Frame::Double(-1.5),
+        read_infinity: b",inf\r\n",
+        write_infinity: Frame::Double(f64::INFINITY),
+        read_big_number: b"(3492890328409238509324850943850943825024385\r\n",
+        write_big_number: Frame::BigNumber("3492890328409238509324850943850943825024385".into()),
+        read_verbatim: b"=15\r\ntxt:Some string\r\n",
+        write_verbatim: Frame::Verbatim(*b"txt", "Some string".into())
+    }
+
+    resp2_write_tests! {
+        write_resp2_map: {
+            Frame::Map(vec![Frame::String("key".into()), Frame::Integer(1)])
+        } => b"*2\r\n+key\r\n:1\r\n",
+        write_resp2_attribute: Frame::Attribute(vec![Frame::String("ttl".into()), Frame::Integer(3)]) => b"",
+        write_resp2_null: Frame::Null => b"$-1\r\n",
+        write_resp2_boolean: Frame::Boolean(true) => b":1\r\n",
+        write_resp2_double: Frame::Double(1.5) => b"$3\r\n1.5\r\n",
+        write_resp2_verbatim: Frame::Verbatim(*b"txt", "text".into()) => b"$4\r\ntext\r\n"
     }
 
     #[tokio::test]
@@ -353,4 +704,11 @@ mod tests {
             stream.read_frame().await
         );
     }
+
+    #[tokio::test]
+    async fn read_nan() {
+        let mut buf = Cursor::new(b",nan\r\n".to_vec());
+        let frame = Connection::new(&mut buf).read_frame().await;
+        assert!(matches!(frame, Ok(Some(Frame::Double(d))) if d.is_nan()));
+    }
 }
@@ -1,18 +1,158 @@
+mod acl;
+mod aof;
+mod bitmap;
+mod clients;
+mod debug;
+mod evict;
+mod hash;
+mod info;
+mod latency;
+mod lcs;
+mod memory;
+mod monitor;
+mod notify;
+mod object;
+mod output;
+mod pubsub;
+mod rdb;
+mod replication;
+mod sample;
+mod scan;
+mod set;
+mod slowlog;
+mod sorted_set;
+mod stream;
+mod transaction;
+
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    cmp,
+    collections::{hash_map::RandomState, BTreeSet, HashMap, HashSet, VecDeque},
+    hash::{BuildHasher, Hasher},
+    io,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use tokio::sync::{broadcast, watch, Notify};
+
+use crate::{
+    command::{
+        introspect, parse_integer, BitFieldOp, Command, ConfigCommand, End, Error, Expiry, GeoFrom,
+        GeoSearch, GeoShape, LPosOptions, ObjectCommand, PubSubCommand, SetOp, StreamId,
+        XGroupCommand,
+    },
+    config::{Config, SetError},
+    frame::Frame,
+    geo, glob,
+    reply::{ErrorCode, ErrorReply},
+};
+pub use aof::{AutoRewrite, Fsync};
+pub use evict::EvictionPolicy;
+pub use notify::Notifications;
+pub use object::EncodingLimits;
+pub use output::OutputLimits;
 
-use crate::{command::Command, frame::Frame};
+use acl::Acl;
+use aof::Aof;
+use clients::ClientInfo;
+use hash::Hash;
+use info::Stats;
+use latency::Latency;
+use notify::Class;
+use object::Access;
+use pubsub::PubSub;
+use replication::Replication;
+use sample::Sampler;
+use set::Set;
+use slowlog::SlowLog;
+use sorted_set::SortedSet;
+use stream::{Claim, Stream};
+use transaction::{Replay, Transaction, Watched};
 
 pub struct Db {
     state: Arc<Mutex<State>>,
 }
 
 struct State {
-    keystore: HashMap<Bytes, Bytes>,
+    keystore: HashMap<Bytes, Entry>,
+    /// Every key with an expiry, ordered by when it expires.
+    expires: BTreeSet<(i64, Bytes)>,
+    /// Whether expired keys are removed in the background, which `DEBUG SET-ACTIVE-EXPIRE` stops.
+    active_expire: bool,
+    /// Every key, ordered by its position in scans.
+    positions: BTreeSet<(u64, Bytes)>,
+    /// Every key, to pick random ones from.
+    sampler: Sampler,
+    /// Every key with an expiry, to pick random ones from for the `volatile-*` eviction policies.
+    volatile: Sampler,
+    /// The best candidates to evict found so far, each with its rank, as kept by `pick_eviction`.
+    eviction_pool: Vec<(i64, Bytes)>,
+    /// Roughly how many bytes the keys and values of the dataset occupy, i.e. the sum of the size
+    /// of every entry.
+    used_memory: usize,
+    /// The clients blocked on each key, woken whenever data is pushed to it.
+    blocked: HashMap<Bytes, Vec<Arc<Notify>>>,
+    pubsub: PubSub,
+    notifications: Notifications,
+    /// The keys watched by clients, for `EXEC` to tell whether they were modified.
+    watched: HashMap<Bytes, Watched>,
+    next_client_id: u64,
+    /// Every connected client, by ID.
+    clients: HashMap<u64, ClientInfo>,
+    acl: Acl,
+    /// Where every command is sent for clients that sent `MONITOR`.
+    monitors: broadcast::Sender<Bytes>,
+    slowlog: SlowLog,
+    latency: Latency,
+    /// The state of the generator behind `random`.
+    seed: u64,
+    /// Whether a snapshot is being saved in the background, as only one may be at a time.
+    saving: Arc<AtomicBool>,
+    /// How many writes were applied since the dataset was last saved, for the `save` points.
+    dirty: u64,
+    last_save: Instant,
+    /// Where write commands are logged, if the AOF is enabled.
+    aof: Option<Aof>,
+    replication: Replication,
+    stats: Stats,
+    config: Config,
+    /// Set once the server shuts down, for it to stop accepting connections and serving clients.
+    shutdown: watch::Sender<bool>,
+}
+
+/// The largest string value a command may create, matching Redis's default `proto-max-bulk-len`.
+const MAX_STRING_SIZE: usize = 512 * 1024 * 1024;
+const STRING_TOO_LONG: &str = "string exceeds maximum allowed size (proto-max-bulk-len)";
+const SAVE_IN_PROGRESS: &str = "Background save already in progress";
+
+struct Entry {
+    value: Value,
+    /// The unix time in milliseconds at which the entry expires, if any.
+    expires_at: Option<i64>,
+    access: Access,
+    /// Roughly how many bytes the key and value occupy, as last accounted for in `used_memory`,
+    /// which is set when the entry is inserted.
+    size: usize,
+}
+
+enum Value {
+    String(Bytes),
+    /// A list, which is removed once it becomes empty.
+    List(VecDeque<Bytes>),
+    /// A hash, which is removed once it becomes empty.
+    Hash(Hash),
+    /// A set, which is removed once it becomes empty.
+    Set(Set),
+    /// A sorted set, which is removed once it becomes empty.
+    SortedSet(SortedSet),
+    /// A stream, which unlike other collections is kept once it becomes empty.
+    Stream(Stream),
 }
 
 impl Db {
@@ -21,32 +161,2382 @@ impl Db {
         Db {
             state: Arc::new(Mutex::new(State {
                 keystore: HashMap::new(),
+                expires: BTreeSet::new(),
+                active_expire: true,
+                positions: BTreeSet::new(),
+                sampler: Sampler::default(),
+                volatile: Sampler::default(),
+                eviction_pool: Vec::new(),
+                used_memory: 0,
+                blocked: HashMap::new(),
+                pubsub: PubSub::default(),
+                notifications: Notifications::default(),
+                watched: HashMap::new(),
+                next_client_id: 1,
+                clients: HashMap::new(),
+                acl: Acl::default(),
+                monitors: monitor::channel(),
+                slowlog: SlowLog::default(),
+                latency: Latency::default(),
+                // `RandomState` is seeded randomly, so hashing nothing with it gives a random seed
+                seed: RandomState::new().build_hasher().finish() | 1,
+                saving: Arc::new(AtomicBool::new(false)),
+                dirty: 0,
+                last_save: Instant::now(),
+                aof: None,
+                replication: Replication::default(),
+                stats: Stats::default(),
+                config: Config::default(),
+                shutdown: watch::channel(false).0,
             })),
         }
     }
+
+    /// Like `connect`, but never refuses the client.
+    #[cfg(test)]
+    pub fn client(&self) -> (Client, output::Receiver) {
+        let mut state = self.state.lock().unwrap();
+        self.register(&mut state)
+    }
+
+    /// Registers a new client, returning it along with the messages pushed to it, e.g. those
+    /// published to the channels it subscribes to, or why it is refused if `maxclients` clients are
+    /// connected already.
+    pub fn connect(&self) -> Result<(Client, output::Receiver), Frame> {
+        let mut state = self.state.lock().unwrap();
+        if state.clients.len() >= state.config.maxclients {
+            state.stats.rejected += 1;
+            return Err(ErrorReply::err("max number of clients reached").into());
+        }
+        Ok(self.register(&mut state))
+    }
+
+    fn register(&self, state: &mut State) -> (Client, output::Receiver) {
+        let info = ClientInfo::new();
+        let (messages, receiver) = output::channel(info.kill());
+        let id = state.next_client_id;
+        state.next_client_id += 1;
+        state.stats.connections += 1;
+        state.clients.insert(id, info);
+        let client = Client {
+            state: self.state.clone(),
+            id,
+            resp3: false,
+            authenticated: state.authenticated_by_default(),
+            user: "default".into(),
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            transaction: None,
+            watching: Vec::new(),
+            messages,
+            listening_port: None,
+            full_sync: None,
+            monitors: state.monitors.clone(),
+            received: VecDeque::new(),
+            monitoring: None,
+        };
+        (client, receiver)
+    }
+
+    /// Applies `config`, which is then what `CONFIG GET` replies with.
+    pub fn configure(&self, config: Config) {
+        let mut state = self.state.lock().unwrap();
+        state.acl.require_pass(&config.requirepass);
+        state.config = config;
+        state.reconfigure();
+    }
+
+    /// Returns how long a connection may be idle before keepalive probes are sent, if at all.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        match self.state.lock().unwrap().config.tcp_keepalive {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Loads the snapshot saved where `SAVE` and `BGSAVE` save them, if there is one. Like Redis,
+    /// snapshots aren't loaded when the AOF is enabled, as it is more up to date.
+    pub fn load_rdb(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.config.appendonly {
+            return Ok(());
+        }
+        match std::fs::read(state.rdb_path()) {
+            Ok(data) => state.load(&data, now_ms()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replays the AOF at `path`, if there is one, then logs every write command to it, synced to
+    /// disk according to `fsync`, and rewritten according to `auto_rewrite`.
+    pub async fn open_aof(
+        &self,
+        path: PathBuf,
+        fsync: Fsync,
+        auto_rewrite: AutoRewrite,
+    ) -> io::Result<()> {
+        let commands = aof::read(&path).await?;
+        let aof = Aof::open(&path, fsync, auto_rewrite).await?;
+        let mut state = self.state.lock().unwrap();
+        // the log holds writes that were already accepted, so they're applied even if this server
+        // is read-only, a replica, or out of memory
+        let mut replay = Replay::default();
+        for command in commands {
+            for command in replay.feed(command) {
+                state.apply_unchecked(command);
+            }
+        }
+        state.aof = Some(aof);
+        Ok(())
+    }
+
+    /// Sets the port this server listens on, which replicas tell their master about.
+    pub fn set_listening_port(&self, port: u16) {
+        self.state.lock().unwrap().replication.listening_port = port;
+    }
+
+    /// Starts replicating the master at `host` and `port`, i.e. `--replicaof`.
+    pub fn replicate(&self, host: String, port: u16) {
+        let mut state = self.state.lock().unwrap();
+        state.replicate(Arc::downgrade(&self.state), Some((host, port)));
+    }
+
+    /// Spawns a task that deletes expired keys `hz` times a second, so that keys which are never
+    /// accessed again still free their memory. The task stops once every handle to the database has
+    /// been dropped.
+    ///
+    /// Like Redis's active expiration cycle, each run may hold the lock for a slice of the interval
+    /// that grows with `active-expire-effort` (1 to 10), from 25% to 43%. Unlike Redis, keys are not
+    /// sampled at random: they are indexed by deadline, so each run only visits keys that have
+    /// expired.
+    pub fn spawn_active_expiration(&self) {
+        let state = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            loop {
+                // the config is read on every run, as `CONFIG SET` may change it
+                let (interval, effort) = {
+                    let Some(state) = state.upgrade() else {
+                        break;
+                    };
+                    let config = &state.lock().unwrap().config;
+                    let interval = Duration::from_secs(1) / config.hz.clamp(1, 500);
+                    (interval, config.active_expire_effort.clamp(1, 10))
+                };
+                tokio::time::sleep(interval).await;
+                let budget = interval * (25 + 2 * (effort - 1)) / 100;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                let mut state = state.lock().unwrap();
+                if !state.active_expire {
+                    continue;
+                }
+                let started = Instant::now();
+                state.expire_cycle(now_ms(), started + budget);
+                state.record_latency(latency::EXPIRE_CYCLE, started.elapsed());
+            }
+        });
+    }
+
+    /// Returns what is set once the server shuts down, with `SHUTDOWN` or `shutdown`.
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.state.lock().unwrap().shutdown.subscribe()
+    }
+
+    /// Shuts the server down like `SHUTDOWN`, e.g. when it is sent a signal to terminate, unless
+    /// the dataset fails to be saved.
+    pub fn shutdown(&self) -> Result<(), Frame> {
+        self.state.lock().unwrap().shutdown(None)
+    }
+
+    /// Stops appending to the AOF, if it is enabled, returning once it is synced to disk.
+    pub async fn close(&self) {
+        let aof = self.state.lock().unwrap().aof.take();
+        if let Some(aof) = aof {
+            aof.close().await;
+        }
+    }
+
+    /// Spawns a task that saves the dataset in the background whenever one of the `save` points is
+    /// reached, i.e. enough writes were applied for long enough since it was last saved. The task
+    /// stops once every handle to the database has been dropped.
+    pub fn spawn_auto_save(&self) {
+        let state = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                ticker.tick().await;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                let mut state = state.lock().unwrap();
+                let (elapsed, dirty) = (state.last_save.elapsed().as_secs(), state.dirty);
+                let save_points = &state.config.save;
+                if save_points.iter().any(|&(s, c)| elapsed >= s && dirty >= c) {
+                    // a save already in progress is waited for, to check again once it is done
+                    let _ = state.bgsave(now_ms());
+                }
+            }
+        });
+    }
 }
 
 impl Db {
-    pub fn apply(&self, command: Command) -> Frame {
+    /// Applies each command sent by `client` in order, returning their results. The lock is held
+    /// across consecutive commands, and only released while a command is blocked waiting for data.
+    pub async fn apply_batch(&self, client: &mut Client, commands: Vec<Command>) -> Vec<Frame> {
+        let mut results = Vec::with_capacity(commands.len());
+        let mut commands = commands.into_iter();
+        loop {
+            let blocked = {
+                let mut state = self.state.lock().unwrap();
+                let blocked = loop {
+                    let Some(mut command) = commands.next() else {
+                        break None;
+                    };
+                    let args = client.received.pop_front().unwrap_or_default();
+                    // like Redis, commands are refused before being queued in a transaction, which
+                    // then fails
+                    if let Some(reply) = client
+                        .require_auth(&command)
+                        .or_else(|| state.permit(client, &args))
+                    {
+                        client.fail_transaction();
+                        results.push(reply);
+                        continue;
+                    }
+                    // queued commands can't block, as they are applied when the transaction executes
+                    if !client.in_transaction() && client.restrict(&command).is_none() {
+                        state.resolve_last_ids(&mut command, now_ms());
+                        if state.would_block(&command, now_ms()) {
+                            break Some(command);
+                        }
+                    }
+                    let started = Instant::now();
+                    state.apply_for(client, command, &mut results);
+                    let elapsed = started.elapsed();
+                    state.log_if_slow(client, &args, elapsed);
+                    state.record_latency(latency::COMMAND, elapsed);
+                };
+                state.update_client(client);
+                blocked
+            };
+            let Some(command) = blocked else {
+                break;
+            };
+            results.push(self.block(command).await);
+        }
+        // commands that failed to parse end the batch without being applied
+        client.received.clear();
+        results
+    }
+
+    /// Waits until a blocking command can be served or times out, then applies it.
+    async fn block(&self, command: Command) -> Frame {
+        if let Command::Wait(replicas, timeout) = command {
+            return self.wait(replicas, timeout).await;
+        }
+        let Some((keys, timeout)) = command.blocking() else {
+            return self.state.lock().unwrap().apply(command);
+        };
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+        let waiter = Waiter::new(self.state.clone(), keys.to_vec());
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if !state.would_block(&command, now_ms()) {
+                    return state.apply(command);
+                }
+            }
+            match deadline {
+                Some(deadline) => {
+                    let notified = waiter.notify.notified();
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        break;
+                    }
+                }
+                None => waiter.notify.notified().await,
+            }
+        }
+        // with no data to serve, this produces the command's timeout reply. The lock must be released
+        // before the waiter is dropped, as unregistering it takes the lock too.
+        let reply = self.state.lock().unwrap().apply(command);
+        reply
+    }
+}
+
+/// A connected client, which is unsubscribed from every channel and stops watching every key once
+/// dropped.
+pub struct Client {
+    state: Arc<Mutex<State>>,
+    id: u64,
+    /// Whether the client speaks RESP3, which lets it send any command while subscribed.
+    pub resp3: bool,
+    /// Whether the client authenticated, or needn't as the `default` user requires no password.
+    authenticated: bool,
+    /// The user the client is authenticated as, whose permissions apply to its commands.
+    user: Bytes,
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    /// The commands queued since `MULTI`, if a transaction is open.
+    transaction: Option<Transaction>,
+    /// The keys watched by the client, and their version when they were watched.
+    watching: Vec<(Bytes, u64)>,
+    messages: output::Sender,
+    /// The port the client accepts connections on, if it is a replica that told it.
+    listening_port: Option<u16>,
+    /// The snapshot to send the client once it is replied to, if it just started replicating.
+    full_sync: Option<Vec<u8>>,
+    monitors: broadcast::Sender<Bytes>,
+    /// The arguments of each command received but not applied yet.
+    received: VecDeque<Vec<Bytes>>,
+    /// The commands sent by other clients, if the client sent `MONITOR`.
+    monitoring: Option<broadcast::Receiver<Bytes>>,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let mut state = state.lock().unwrap();
+        self.unsubscribe_all(&mut state.pubsub);
+        self.unwatch(&mut state.watched);
+        state.replication.remove_replica(self.id);
+        state.clients.remove(&self.id);
+    }
+}
+
+/// Registers a blocked client on its keys for as long as it is alive.
+struct Waiter {
+    state: Arc<Mutex<State>>,
+    keys: Vec<Bytes>,
+    notify: Arc<Notify>,
+}
+
+impl Waiter {
+    fn new(state: Arc<Mutex<State>>, keys: Vec<Bytes>) -> Self {
+        let notify = Arc::new(Notify::new());
+        let mut guard = state.lock().unwrap();
+        for key in &keys {
+            guard
+                .blocked
+                .entry(key.clone())
+                .or_default()
+                .push(notify.clone());
+        }
+        drop(guard);
+        Waiter {
+            state,
+            keys,
+            notify,
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        for key in &self.keys {
+            if let Some(waiters) = state.blocked.get_mut(key) {
+                waiters.retain(|w| !Arc::ptr_eq(w, &self.notify));
+                if waiters.is_empty() {
+                    state.blocked.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    fn apply(&mut self, command: Command) -> Frame {
+        if command.is_write() {
+            if self.config.read_only {
+                return ErrorReply::new(
+                    ErrorCode::ReadOnly,
+                    "You can't write against a read only server.",
+                )
+                .into();
+            }
+            // like Redis's default `replica-read-only yes`, only the master writes to replicas
+            if self.replication.is_replica() {
+                return ErrorReply::new(
+                    ErrorCode::ReadOnly,
+                    "You can't write against a read only replica.",
+                )
+                .into();
+            }
+        }
+        // like Redis, keys are evicted before any command, but only those that may grow the
+        // dataset are rejected if not enough could be
+        if !self.evict(now_ms()) && command.denies_oom() {
+            return ErrorReply::new(
+                ErrorCode::Oom,
+                "command not allowed when used memory > 'maxmemory'.",
+            )
+            .into();
+        }
+        self.apply_unchecked(command)
+    }
+
+    /// Like `apply`, but applies writes even if this server is read-only, e.g. for those propagated
+    /// by the master.
+    fn apply_unchecked(&mut self, mut command: Command) -> Frame {
+        let now = now_ms();
+        let write = command.is_write();
+        let propagated = if write && (self.aof.is_some() || self.replication.has_replicas()) {
+            Some(self.make_repeatable(&mut command, now))
+        } else {
+            None
+        };
+        // a write touches the keys it may modify, even if it leaves them unchanged
+        let written = command.written_keys();
+        self.stats.counting = !write;
+        let reply = self.execute(command, now).unwrap_or_else(|e| e);
+        self.stats.counting = false;
+        for key in &written {
+            self.account(key);
+        }
+        if !matches!(reply, Frame::Error(_)) {
+            for key in &written {
+                self.touch(key);
+            }
+            if let Some(propagated) = propagated {
+                self.propagate(propagated, now);
+            }
+            if write {
+                self.dirty += 1;
+            }
+        }
+        reply
+    }
+
+    /// Applies a command sent by `client`, appending its replies to `replies`. Unlike `apply`, this
+    /// handles the commands that manage the client's own state, such as its subscriptions.
+    fn apply_for(&mut self, client: &mut Client, command: Command, replies: &mut Vec<Frame>) {
+        if let Some(reply) = client.restrict(&command) {
+            return replies.push(reply);
+        }
+        self.stats.commands += 1;
+        // like Redis, only the commands managing the transaction itself, and QUIT, aren't queued
+        let mut command = match command {
+            Command::Discard
+            | Command::Exec
+            | Command::Multi
+            | Command::Quit
+            | Command::Reset
+            | Command::Watch(_) => command,
+            command => match client.queue(command) {
+                Some(command) => command,
+                None => return replies.push(Frame::String("QUEUED".into())),
+            },
+        };
+        let reply = match command {
+            Command::Acl(command) => self.acl(client, command),
+            Command::Auth(user, pass) => self.auth(client, user, pass),
+            Command::Client(command) => self.client(client, command),
+            Command::Discard => client.discard(&mut self.watched),
+            Command::Exec => match client.exec(&mut self.watched) {
+                Ok(commands) => {
+                    let mut results = Vec::with_capacity(commands.len());
+                    for command in commands {
+                        self.apply_for(client, command, &mut results);
+                    }
+                    Frame::Array(Some(results))
+                }
+                Err(e) => e,
+            },
+            Command::Hello(protover, auth, name) => self.hello(client, protover, auth, name),
+            Command::Monitor => self.monitor(client),
+            Command::Multi => client.multi(),
+            Command::PSync => self.psync(client, now_ms()),
+            // the connection closes itself once the reply is sent
+            Command::Quit => Frame::Bulk(Some("OK".into())),
+            Command::ReplConf(options) => match self.replconf(client, options) {
+                Some(reply) => reply,
+                // like Redis, acknowledgements aren't replied to
+                None => return,
+            },
+            Command::ReplicaOf(master) => self.replicate(Arc::downgrade(&client.state), master),
+            Command::Reset => self.reset(client),
+            Command::Shutdown(save) => match self.shutdown(save) {
+                // like Redis, the client is disconnected rather than replied to
+                Ok(()) => return,
+                Err(e) => e,
+            },
+            Command::Subscribe(names, pattern) => {
+                return replies.extend(client.subscribe(&mut self.pubsub, names, pattern));
+            }
+            Command::Unsubscribe(names, pattern) => {
+                return replies.extend(client.unsubscribe(&mut self.pubsub, names, pattern));
+            }
+            // like Redis, UNWATCH is queued, though EXEC stops watching every key anyway
+            Command::Unwatch => {
+                client.unwatch(&mut self.watched);
+                Frame::Bulk(Some("OK".into()))
+            }
+            Command::Watch(keys) => {
+                // keys that already expired must not be touched by their removal after being watched
+                for key in &keys {
+                    self.peek(key, now_ms());
+                }
+                client.watch(&mut self.watched, keys)
+            }
+            _ => {
+                self.resolve_last_ids(&mut command, now_ms());
+                self.apply(command)
+            }
+        };
+        replies.push(reply);
+    }
+
+    /// Returns whether `command` is a blocking command with no data to serve yet.
+    fn would_block(&mut self, command: &Command, now: i64) -> bool {
+        if let Command::Wait(replicas, _) = command {
+            let replication = &self.replication;
+            return !replication.is_replica()
+                && replication.acknowledged(replication.offset) < *replicas;
+        }
+        let Some((keys, _)) = command.blocking() else {
+            return false;
+        };
+        if command.is_write() && (self.config.read_only || self.replication.is_replica()) {
+            return false;
+        }
+        // keys of the wrong type don't block, so that the command can reply with an error
         match command {
+            Command::XRead(_, ids, ..) => {
+                keys.iter()
+                    .zip(ids)
+                    .all(|(k, id)| match self.stream(k, now) {
+                        Ok(Some(stream)) => !id.is_some_and(|id| stream.last_id() > id),
+                        Ok(None) => true,
+                        Err(_) => false,
+                    })
+            }
+            // only new entries are waited for, as a consumer's history is always available
+            Command::XReadGroup(group, _, _, ids, _) => keys.iter().zip(ids).all(|(k, id)| {
+                id.is_none()
+                    && match self.stream(k, now) {
+                        Ok(Some(stream)) => stream.has_undelivered(group) == Some(false),
+                        _ => false,
+                    }
+            }),
+            _ => keys.iter().all(|k| self.peek(k, now).is_none()),
+        }
+    }
+
+    /// Replaces the `$` IDs of `XREAD` with the last ID of their stream, so that a blocked `XREAD`
+    /// only waits for entries added after it was first applied.
+    fn resolve_last_ids(&mut self, command: &mut Command, now: i64) {
+        let Command::XRead(keys, ids, ..) = command else {
+            return;
+        };
+        for (k, id) in keys.iter().zip(ids) {
+            if id.is_none() {
+                let last_id = self.stream(k, now).ok().flatten().map(|s| s.last_id());
+                *id = Some(last_id.unwrap_or_default());
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command, now: i64) -> Result<Frame, Frame> {
+        Ok(match command {
             Command::Ping => Frame::Bulk(Some("PONG".into())),
-            Command::Echo(s) => Frame::Bulk(Some(s.clone())),
-            Command::Set([k, v]) => {
-                let _ = self.state.lock().unwrap().keystore.insert(k, v);
+            Command::Append(k, v) => {
+                let mut value = BytesMut::new();
+                if let Some(current) = self.string(&k, now)? {
+                    value.extend_from_slice(current);
+                }
+                value.extend_from_slice(&v);
+                let len = value.len() as i64;
+                self.notify(Class::String, "append", &k);
+                self.set_value(k, value.freeze(), now);
+                Frame::Integer(len)
+            }
+            Command::BgRewriteAof => {
+                let Some(aof) = &self.aof else {
+                    return Err(ErrorReply::err("Append only file is disabled").into());
+                };
+                if aof.rewriting() {
+                    let msg = "Background append only file rewriting already in progress";
+                    return Err(ErrorReply::err(msg).into());
+                }
+                aof.rewrite(self.rewrite(now));
+                Frame::String("Background append only file rewriting started".into())
+            }
+            Command::BgSave => {
+                self.bgsave(now)?;
+                Frame::String("Background saving started".into())
+            }
+            Command::BitCount(k, range) => {
+                Frame::Integer(self.string(&k, now)?.map_or(0, |v| bitmap::count(v, range)))
+            }
+            Command::BitField(k, ops) => {
+                let mut value = self.string(&k, now)?.map_or_else(Vec::new, |v| v.to_vec());
+                let mut changed = false;
+                let mut replies = Vec::new();
+                for op in ops {
+                    let reply = bitmap::field(&mut value, op);
+                    changed |= reply.is_some() && !matches!(op, BitFieldOp::Get(..));
+                    replies.push(reply.map_or(Frame::Bulk(None), Frame::Integer));
+                }
+                if changed {
+                    self.notify(Class::String, "setbit", &k);
+                    self.set_value(k, value.into(), now);
+                }
+                Frame::Array(Some(replies))
+            }
+            Command::BitOp(op, destination, keys) => {
+                let mut values = Vec::new();
+                for k in &keys {
+                    values.push(self.string(k, now)?.cloned().unwrap_or_default());
+                }
+                let value = bitmap::combine(op, &values);
+                let len = value.len() as i64;
+                if value.is_empty() {
+                    if self.remove_live(&destination, now).is_some() {
+                        self.notify(Class::Generic, "del", &destination);
+                    }
+                    return Ok(Frame::Integer(0));
+                }
+                self.notify(Class::String, "set", &destination);
+                let entry = Entry {
+                    value: Value::String(value.into()),
+                    expires_at: None,
+                    access: Access::new(now),
+                    size: 0,
+                };
+                self.insert(destination, entry);
+                Frame::Integer(len)
+            }
+            Command::BitPos(k, bit, range) => Frame::Integer(match self.string(&k, now)? {
+                Some(value) => bitmap::position(value, bit, range),
+                // a missing key is an empty string, padded with zeros like any other
+                None if bit => -1,
+                None => 0,
+            }),
+            Command::BLMove(source, destination, from, to, _) => {
+                Frame::Bulk(self.lmove(source, destination, from, to, now)?)
+            }
+            Command::BPop(keys, end, _) => {
+                for k in keys {
+                    if let Some(list) = self.list(&k, now)? {
+                        let value = pop(list, end).expect("lists are never empty");
+                        self.notify(Class::List, pop_event(end), &k);
+                        self.remove_if_empty(&k);
+                        return Ok(Frame::Array(Some(vec![
+                            Frame::Bulk(Some(k)),
+                            Frame::Bulk(Some(value)),
+                        ])));
+                    }
+                }
+                Frame::Array(None)
+            }
+            Command::Config(command) => self.apply_config(command)?,
+            Command::Debug(command) => self.apply_debug(command, now)?,
+            Command::Del(keys) => {
+                let removed = keys.iter().filter(|k| self.remove_live(k, now).is_some());
+                let removed: Vec<&Bytes> = removed.collect();
+                for k in &removed {
+                    self.notify(Class::Generic, "del", k);
+                }
+                Frame::Integer(removed.len() as i64)
+            }
+            Command::Introspect(command) => introspect(command),
+            Command::SlowLog(command) => self.slowlog(command),
+            Command::Latency(command) => self.latency(command),
+            Command::Memory(command) => self.apply_memory(command, now),
+            Command::Echo(s) => Frame::Bulk(Some(s)),
+            Command::Info(sections) => self.info(&sections, now),
+            Command::Exists(keys) => {
+                Frame::Integer(keys.iter().filter(|k| self.peek(k, now).is_some()).count() as i64)
+            }
+            Command::Expire(k, expiry, options) => {
+                let expires_at = expiry.deadline(now);
+                let Some(entry) = self.entry(&k, now) else {
+                    return Ok(Frame::Integer(0));
+                };
+                let current = entry.expires_at;
+                // like Redis, a key without an expiry expires later than any time
+                let met = options
+                    .exists
+                    .is_none_or(|exists| exists == current.is_some())
+                    && match options.compare {
+                        Some(cmp::Ordering::Greater) => current.is_some_and(|t| expires_at > t),
+                        Some(cmp::Ordering::Less) => current.is_none_or(|t| expires_at < t),
+                        _ => true,
+                    };
+                if !met {
+                    Frame::Integer(0)
+                } else if expires_at <= now {
+                    self.remove(&k);
+                    self.notify(Class::Generic, "del", &k);
+                    Frame::Integer(1)
+                } else {
+                    self.expire(&k, Some(expires_at));
+                    self.notify(Class::Generic, "expire", &k);
+                    Frame::Integer(1)
+                }
+            }
+            Command::ExpireTime(k, millis) => Frame::Integer(match self.peek(&k, now) {
+                None => -2,
+                Some(entry) => match entry.expires_at {
+                    None => -1,
+                    Some(t) if millis => t,
+                    Some(t) => t / 1000,
+                },
+            }),
+            Command::GeoDist(k, member1, member2, unit) => {
+                let Some(zset) = self.zset(&k, now)? else {
+                    return Ok(Frame::Bulk(None));
+                };
+                match (zset.score(&member1), zset.score(&member2)) {
+                    (Some(score1), Some(score2)) => {
+                        let (point1, point2) =
+                            (geo::decode(score1 as u64), geo::decode(score2 as u64));
+                        let distance = geo::distance(point1, point2) / unit;
+                        Frame::Bulk(Some(format!("{:.4}", distance).into()))
+                    }
+                    _ => Frame::Bulk(None),
+                }
+            }
+            Command::GeoPos(k, members) => {
+                let zset = self.zset(&k, now)?;
+                let score = |member| zset.as_ref().and_then(|z| z.score(member));
+                Frame::Array(Some(
+                    members
+                        .iter()
+                        .map(|member| match score(member) {
+                            Some(score) => coordinates(geo::decode(score as u64)),
+                            None => Frame::Array(None),
+                        })
+                        .collect(),
+                ))
+            }
+            Command::GeoSearch(k, search) => self.geo_search(&k, search, now)?,
+            Command::Get(k) => Frame::Bulk(self.string(&k, now)?.cloned()),
+            Command::GetDel(k) => {
+                let value = self.string(&k, now)?.cloned();
+                if value.is_some() {
+                    self.remove(&k);
+                    self.notify(Class::Generic, "del", &k);
+                }
+                Frame::Bulk(value)
+            }
+            Command::GetEx(k, expiry) => {
+                let Some(value) = self.string(&k, now)?.cloned() else {
+                    return Ok(Frame::Bulk(None));
+                };
+                match expiry.map(|e| e.map(|e| e.deadline(now))) {
+                    Some(Some(expires_at)) if expires_at <= now => {
+                        self.remove(&k);
+                        self.notify(Class::Generic, "del", &k);
+                    }
+                    Some(Some(expires_at)) => {
+                        self.expire(&k, Some(expires_at));
+                        self.notify(Class::Generic, "expire", &k);
+                    }
+                    Some(None) if self.keystore[&k].expires_at.is_some() => {
+                        self.expire(&k, None);
+                        self.notify(Class::Generic, "persist", &k);
+                    }
+                    Some(None) | None => (),
+                }
+                Frame::Bulk(Some(value))
+            }
+            Command::GetBit(k, offset) => Frame::Integer(
+                self.string(&k, now)?
+                    .is_some_and(|v| bitmap::get(v, offset))
+                    .into(),
+            ),
+            Command::GetRange(k, start, end) => {
+                let value = self.string(&k, now)?.cloned().unwrap_or_default();
+                Frame::Bulk(Some(match range(value.len(), start, end) {
+                    Some((start, end)) => value.slice(start..=end),
+                    None => Bytes::new(),
+                }))
+            }
+            Command::HDel(k, fields) => {
+                let Some(hash) = self.hash(&k, now)? else {
+                    return Ok(Frame::Integer(0));
+                };
+                let removed = fields.iter().filter(|f| hash.remove(f).is_some()).count();
+                if removed > 0 {
+                    self.notify(Class::Hash, "hdel", &k);
+                }
+                self.remove_if_empty(&k);
+                Frame::Integer(removed as i64)
+            }
+            Command::HExists(k, field) => Frame::Integer(
+                self.hash(&k, now)?
+                    .is_some_and(|h| h.contains_key(&field))
+                    .into(),
+            ),
+            Command::HGet(k, field) => {
+                Frame::Bulk(self.hash(&k, now)?.and_then(|h| h.get(&field).cloned()))
+            }
+            Command::HGetAll(k) => Frame::Map(
+                self.hash(&k, now)?
+                    .into_iter()
+                    .flat_map(|h| h.iter())
+                    .flat_map(|(f, v)| [Frame::Bulk(Some(f.clone())), Frame::Bulk(Some(v.clone()))])
+                    .collect(),
+            ),
+            Command::HIncrBy(k, field, delta) => {
+                let limits = self.config.encoding_limits;
+                self.create(&k, now, || Value::Hash(Hash::default()));
+                let hash = self.hash(&k, now)?.expect("the hash exists");
+                let current = match hash.get(&field) {
+                    Some(value) => parse_integer(value).ok_or_else(|| {
+                        Frame::from(ErrorReply::err("hash value is not an integer"))
+                    })?,
+                    None => 0,
+                };
+                let Some(n) = current.checked_add(delta) else {
+                    self.remove_if_empty(&k);
+                    return Err(ErrorReply::err("increment or decrement would overflow").into());
+                };
+                hash.insert(field, n.to_string().into(), &limits);
+                self.notify(Class::Hash, "hincrby", &k);
+                Frame::Integer(n)
+            }
+            Command::HScan(k, cursor, options) => {
+                let hash = self.hash(&k, now)?.into_iter().flat_map(|h| h.iter());
+                let fields = hash.map(|(f, v)| (scan::position(f), (f, v)));
+                let (cursor, fields) = scan::select(fields, cursor, options.count);
+                let fields = fields
+                    .into_iter()
+                    .filter(|(f, _)| scan::matches(&options, f))
+                    .flat_map(|(f, v)| [Frame::Bulk(Some(f.clone())), Frame::Bulk(Some(v.clone()))])
+                    .collect();
+                scan::reply(cursor, fields)
+            }
+            Command::HLen(k) => Frame::Integer(self.hash(&k, now)?.map_or(0, |h| h.len() as i64)),
+            Command::HSet(k, pairs) => {
+                let limits = self.config.encoding_limits;
+                self.create(&k, now, || Value::Hash(Hash::default()));
+                let hash = self.hash(&k, now)?.expect("the hash exists");
+                let len = pairs.len();
+                let updated = pairs
+                    .into_iter()
+                    .filter_map(|(f, v)| hash.insert(f, v, &limits))
+                    .count();
+                self.notify(Class::Hash, "hset", &k);
+                Frame::Integer((len - updated) as i64)
+            }
+            Command::IncrBy(k, delta) => {
+                let current = match self.string(&k, now)? {
+                    Some(value) => parse_integer(value).ok_or(Error::NotAnInteger)?,
+                    None => 0,
+                };
+                let Some(n) = current.checked_add(delta) else {
+                    return Err(ErrorReply::err("increment or decrement would overflow").into());
+                };
+                self.notify(Class::String, "incrby", &k);
+                self.set_value(k, n.to_string().into(), now);
+                Frame::Integer(n)
+            }
+            Command::Keys(pattern) => Frame::Array(Some(
+                self.keystore
+                    .iter()
+                    .filter(|(_, e)| match e.expires_at {
+                        Some(t) => t > now,
+                        None => true,
+                    })
+                    .filter(|(k, _)| glob::matches(&pattern, k))
+                    .map(|(k, _)| Frame::Bulk(Some(k.clone())))
+                    .collect(),
+            )),
+            Command::Lcs(key1, key2, options) => {
+                let mut strings = Vec::new();
+                for k in [&key1, &key2] {
+                    let msg = "The specified keys must contain string values";
+                    let string = self.string(k, now).map_err(|_| ErrorReply::err(msg))?;
+                    strings.push(string.cloned().unwrap_or_default());
+                }
+                let (a, b) = (&strings[0], &strings[1]);
+                if (a.len() + 1).saturating_mul(b.len() + 1) > u32::MAX as usize {
+                    return Err(ErrorReply::err("String too long for LCS").into());
+                }
+                let (common, matches) = lcs::lcs(a, b);
+                if options.len {
+                    return Ok(Frame::Integer(common.len() as i64));
+                }
+                if !options.idx {
+                    return Ok(Frame::Bulk(Some(common.into())));
+                }
+                let range = |(first, last): (usize, usize)| {
+                    Frame::Array(Some(vec![
+                        Frame::Integer(first as i64),
+                        Frame::Integer(last as i64),
+                    ]))
+                };
+                let matches = matches
+                    .into_iter()
+                    .filter(|m| m.len() >= options.min_match_len)
+                    .map(|m| {
+                        let mut reply = vec![range(m.a), range(m.b)];
+                        if options.with_match_len {
+                            reply.push(Frame::Integer(m.len() as i64));
+                        }
+                        Frame::Array(Some(reply))
+                    });
+                Frame::Map(vec![
+                    Frame::Bulk(Some("matches".into())),
+                    Frame::Array(Some(matches.collect())),
+                    Frame::Bulk(Some("len".into())),
+                    Frame::Integer(common.len() as i64),
+                ])
+            }
+            Command::LInsert(k, before, pivot, element) => {
+                let Some(list) = self.list(&k, now)? else {
+                    return Ok(Frame::Integer(0));
+                };
+                let Some(i) = list.iter().position(|v| *v == pivot) else {
+                    return Ok(Frame::Integer(-1));
+                };
+                list.insert(if before { i } else { i + 1 }, element);
+                let len = list.len() as i64;
+                self.notify(Class::List, "linsert", &k);
+                Frame::Integer(len)
+            }
+            Command::LLen(k) => Frame::Integer(self.list(&k, now)?.map_or(0, |l| l.len() as i64)),
+            Command::LMove(source, destination, from, to) => {
+                Frame::Bulk(self.lmove(source, destination, from, to, now)?)
+            }
+            Command::LPos(k, element, options) => {
+                let positions = match self.list(&k, now)? {
+                    Some(list) => positions(list, &element, &options),
+                    None => vec![],
+                };
+                match options.count {
+                    Some(_) => {
+                        Frame::Array(Some(positions.into_iter().map(Frame::Integer).collect()))
+                    }
+                    None => positions
+                        .first()
+                        .map_or(Frame::Bulk(None), |&i| Frame::Integer(i)),
+                }
+            }
+            Command::LRange(k, start, end) => {
+                let Some(list) = self.list(&k, now)? else {
+                    return Ok(Frame::Array(Some(vec![])));
+                };
+                Frame::Array(Some(match range(list.len(), start, end) {
+                    Some((start, end)) => list
+                        .range(start..=end)
+                        .map(|v| Frame::Bulk(Some(v.clone())))
+                        .collect(),
+                    None => vec![],
+                }))
+            }
+            Command::LRem(k, count, element) => {
+                let Some(list) = self.list(&k, now)? else {
+                    return Ok(Frame::Integer(0));
+                };
+                let limit = match count {
+                    0 => usize::MAX,
+                    count => count.unsigned_abs().try_into().unwrap_or(usize::MAX),
+                };
+                let mut matches: Vec<usize> =
+                    (0..list.len()).filter(|&i| list[i] == element).collect();
+                if count < 0 {
+                    matches.drain(..matches.len().saturating_sub(limit));
+                } else {
+                    matches.truncate(limit);
+                }
+                let removed = matches.len() as i64;
+                // remove from the back, so that the remaining indexes stay valid
+                while let Some(i) = matches.pop() {
+                    list.remove(i);
+                }
+                if removed > 0 {
+                    self.notify(Class::List, "lrem", &k);
+                }
+                self.remove_if_empty(&k);
+                Frame::Integer(removed)
+            }
+            Command::LSet(k, index, element) => {
+                let Some(list) = self.list(&k, now)? else {
+                    return Err(ErrorReply::err("no such key").into());
+                };
+                let len = list.len() as i64;
+                let index = if index < 0 { len + index } else { index };
+                if !(0..len).contains(&index) {
+                    return Err(ErrorReply::err("index out of range").into());
+                }
+                list[index as usize] = element;
+                self.notify(Class::List, "lset", &k);
+                Frame::Bulk(Some("OK".into()))
+            }
+            Command::LTrim(k, start, end) => {
+                if let Some(list) = self.list(&k, now)? {
+                    match range(list.len(), start, end) {
+                        Some((start, end)) => {
+                            list.truncate(end + 1);
+                            list.drain(..start);
+                        }
+                        None => list.clear(),
+                    }
+                    self.notify(Class::List, "ltrim", &k);
+                    self.remove_if_empty(&k);
+                }
+                Frame::Bulk(Some("OK".into()))
+            }
+            Command::MGet(keys) => Frame::Array(Some(
+                keys.iter()
+                    .map(|k| Frame::Bulk(self.string(k, now).ok().flatten().cloned()))
+                    .collect(),
+            )),
+            Command::MSet(pairs) => {
+                for (k, v) in pairs {
+                    self.notify(Class::String, "set", &k);
+                    self.insert(
+                        k,
+                        Entry {
+                            value: Value::String(v),
+                            expires_at: None,
+                            access: Access::new(now),
+                            size: 0,
+                        },
+                    );
+                }
+                Frame::Bulk(Some("OK".into()))
+            }
+            Command::MSetNx(pairs) => {
+                if pairs.iter().any(|(k, _)| self.peek(k, now).is_some()) {
+                    return Ok(Frame::Integer(0));
+                }
+                for (k, v) in pairs {
+                    self.notify(Class::String, "set", &k);
+                    self.insert(
+                        k,
+                        Entry {
+                            value: Value::String(v),
+                            expires_at: None,
+                            access: Access::new(now),
+                            size: 0,
+                        },
+                    );
+                }
+                Frame::Integer(1)
+            }
+            Command::Object(subcommand, k) => {
+                let limits = self.config.encoding_limits;
+                match self.peek(&k, now) {
+                    Some(entry) => match subcommand {
+                        ObjectCommand::Encoding => {
+                            Frame::Bulk(Some(entry.value.encoding(&limits).into()))
+                        }
+                        ObjectCommand::Freq => Frame::Integer(entry.access.frequency(now).into()),
+                        ObjectCommand::IdleTime => Frame::Integer(entry.access.idle_time(now)),
+                        ObjectCommand::RefCount => Frame::Integer(entry.value.refcount()),
+                    },
+                    None => Frame::Bulk(None),
+                }
+            }
+            Command::Persist(k) => Frame::Integer(match self.entry(&k, now) {
+                Some(Entry {
+                    expires_at: Some(_),
+                    ..
+                }) => {
+                    self.expire(&k, None);
+                    self.notify(Class::Generic, "persist", &k);
+                    1
+                }
+                _ => 0,
+            }),
+            Command::Pop(k, end, count) => {
+                let Some(list) = self.list(&k, now)? else {
+                    return Ok(match count {
+                        Some(_) => Frame::Array(None),
+                        None => Frame::Bulk(None),
+                    });
+                };
+                let reply = match count {
+                    Some(count) => Frame::Array(Some(
+                        std::iter::from_fn(|| pop(list, end))
+                            .take(count)
+                            .map(|v| Frame::Bulk(Some(v)))
+                            .collect(),
+                    )),
+                    None => Frame::Bulk(pop(list, end)),
+                };
+                self.notify(Class::List, pop_event(end), &k);
+                self.remove_if_empty(&k);
+                reply
+            }
+            Command::Pttl(k) => Frame::Integer(self.ttl(&k, now)),
+            Command::Publish(channel, message) => {
+                let limit = &self.config.client_output_buffer_limit.pubsub;
+                Frame::Integer(self.pubsub.publish(&channel, &message, limit) as i64)
+            }
+            Command::PubSub(PubSubCommand::Channels(pattern)) => Frame::Array(Some(
+                self.pubsub
+                    .channels(pattern.as_ref())
+                    .map(|c| Frame::Bulk(Some(c.clone())))
+                    .collect(),
+            )),
+            Command::PubSub(PubSubCommand::NumPat) => Frame::Integer(self.pubsub.patterns() as i64),
+            Command::PubSub(PubSubCommand::NumSub(channels)) => Frame::Map(
+                channels
+                    .into_iter()
+                    .flat_map(|c| {
+                        let subscribers = self.pubsub.subscribers_to(&c) as i64;
+                        [Frame::Bulk(Some(c)), Frame::Integer(subscribers)]
+                    })
+                    .collect(),
+            ),
+            Command::Push(k, end, values) => {
+                self.create(&k, now, || Value::List(VecDeque::new()));
+                let list = self.list(&k, now)?.expect("the list exists");
+                for v in values {
+                    match end {
+                        End::Left => list.push_front(v),
+                        End::Right => list.push_back(v),
+                    }
+                }
+                let len = list.len() as i64;
+                let event = match end {
+                    End::Left => "lpush",
+                    End::Right => "rpush",
+                };
+                self.notify(Class::List, event, &k);
+                self.wake(&k);
+                Frame::Integer(len)
+            }
+            Command::RandomKey => Frame::Bulk(self.random_key(now)),
+            Command::Role => self.replication.role(),
+            Command::Save => {
+                if self.saving.load(Ordering::SeqCst) {
+                    return Err(ErrorReply::err(SAVE_IN_PROGRESS).into());
+                }
+                let started = Instant::now();
+                rdb::write(&self.rdb_path(), &self.snapshot(now))
+                    .map_err(|e| Frame::from(ErrorReply::err(e.to_string())))?;
+                self.record_latency(latency::SAVE, started.elapsed());
+                self.dirty = 0;
+                self.last_save = Instant::now();
+                Frame::Bulk(Some("OK".into()))
+            }
+            Command::Scan(cursor, options) => {
+                let (cursor, keys) = self.scan(cursor, &options, now);
+                scan::reply(
+                    cursor,
+                    keys.into_iter().map(|k| Frame::Bulk(Some(k))).collect(),
+                )
+            }
+            Command::SAdd(k, members) => {
+                let limits = self.config.encoding_limits;
+                self.create(&k, now, || Value::Set(Set::default()));
+                let set = self.set(&k, now)?.expect("the set exists");
+                let added = members
+                    .into_iter()
+                    .filter(|m| set.insert(m.clone(), &limits))
+                    .count();
+                if added > 0 {
+                    self.notify(Class::Set, "sadd", &k);
+                }
+                Frame::Integer(added as i64)
+            }
+            Command::SCard(k) => Frame::Integer(self.set(&k, now)?.map_or(0, |s| s.len() as i64)),
+            Command::SCombine(op, keys) => Frame::Set(
+                self.combine(op, &keys, now)?
+                    .into_iter()
+                    .map(|m| Frame::Bulk(Some(m)))
+                    .collect(),
+            ),
+            Command::SCombineStore(destination, op, keys) => {
+                let set = self.combine(op, &keys, now)?;
+                let len = set.len() as i64;
+                if set.is_empty() {
+                    if self.remove_live(&destination, now).is_some() {
+                        self.notify(Class::Generic, "del", &destination);
+                    }
+                    return Ok(Frame::Integer(0));
+                }
+                let event = match op {
+                    SetOp::Inter => "sinterstore",
+                    SetOp::Union => "sunionstore",
+                    SetOp::Diff => "sdiffstore",
+                };
+                self.notify(Class::Set, event, &destination);
+                let entry = Entry {
+                    value: Value::Set(Set::collect(set, &self.config.encoding_limits)),
+                    expires_at: None,
+                    access: Access::new(now),
+                    size: 0,
+                };
+                self.insert(destination, entry);
+                Frame::Integer(len)
+            }
+            Command::SIsMember(k, member) => Frame::Integer(
+                self.set(&k, now)?
+                    .is_some_and(|s| s.contains(&member))
+                    .into(),
+            ),
+            Command::SMembers(k) => Frame::Set(
+                self.set(&k, now)?
+                    .into_iter()
+                    .flat_map(|s| s.iter())
+                    .map(|m| Frame::Bulk(Some(m)))
+                    .collect(),
+            ),
+            Command::SScan(k, cursor, options) => {
+                let set = self.set(&k, now)?.into_iter().flat_map(|s| s.iter());
+                let members = set.map(|m| (scan::position(&m), m));
+                let (cursor, members) = scan::select(members, cursor, options.count);
+                let members = members
+                    .into_iter()
+                    .filter(|m| scan::matches(&options, m))
+                    .map(|m| Frame::Bulk(Some(m)))
+                    .collect();
+                scan::reply(cursor, members)
+            }
+            Command::SRem(k, members) => {
+                let Some(set) = self.set(&k, now)? else {
+                    return Ok(Frame::Integer(0));
+                };
+                let removed = members.iter().filter(|m| set.remove(m)).count();
+                if removed > 0 {
+                    self.notify(Class::Set, "srem", &k);
+                }
+                self.remove_if_empty(&k);
+                Frame::Integer(removed as i64)
+            }
+            Command::Set(k, v, options) => {
+                let previous = match self.entry(&k, now) {
+                    Some(Entry {
+                        value: Value::String(value),
+                        expires_at,
+                        ..
+                    }) => Some((Some(value.clone()), *expires_at)),
+                    Some(_) if options.get => return Err(ErrorReply::wrong_type().into()),
+                    Some(e) => Some((None, e.expires_at)),
+                    None => None,
+                };
+                let reply = if options.get {
+                    Frame::Bulk(previous.as_ref().and_then(|(value, _)| value.clone()))
+                } else {
+                    Frame::Bulk(Some("OK".into()))
+                };
+                if options.exists.is_some_and(|e| e != previous.is_some()) {
+                    return Ok(if options.get {
+                        reply
+                    } else {
+                        Frame::Bulk(None)
+                    });
+                }
+                let expires_at = if options.keep_ttl {
+                    previous.and_then(|(_, expires_at)| expires_at)
+                } else {
+                    options.expiry.map(|e| e.deadline(now))
+                };
+                self.notify(Class::String, "set", &k);
+                if options.expiry.is_some() {
+                    self.notify(Class::Generic, "expire", &k);
+                }
+                self.insert(
+                    k,
+                    Entry {
+                        value: Value::String(v),
+                        expires_at,
+                        access: Access::new(now),
+                        size: 0,
+                    },
+                );
+                reply
+            }
+            Command::SetBit(k, offset, bit) => {
+                let mut value = self.string(&k, now)?.map_or_else(Vec::new, |v| v.to_vec());
+                let old = bitmap::set(&mut value, offset, bit);
+                self.notify(Class::String, "setbit", &k);
+                self.set_value(k, value.into(), now);
+                Frame::Integer(old.into())
+            }
+            Command::SetRange(k, offset, v) => {
+                let current = self.string(&k, now)?.cloned();
+                if v.is_empty() {
+                    return Ok(Frame::Integer(current.map_or(0, |c| c.len() as i64)));
+                }
+                if offset + v.len() > MAX_STRING_SIZE {
+                    return Err(ErrorReply::err(STRING_TOO_LONG).into());
+                }
+                let mut value = BytesMut::from(current.unwrap_or_default().as_ref());
+                if value.len() < offset + v.len() {
+                    value.resize(offset + v.len(), 0);
+                }
+                value[offset..offset + v.len()].copy_from_slice(&v);
+                let len = value.len() as i64;
+                self.notify(Class::String, "setrange", &k);
+                self.set_value(k, value.freeze(), now);
+                Frame::Integer(len)
+            }
+            Command::Acl(_)
+            | Command::Auth(..)
+            | Command::Client(_)
+            | Command::Discard
+            | Command::Exec
+            | Command::Hello(..)
+            | Command::Monitor
+            | Command::Multi
+            | Command::PSync
+            | Command::Quit
+            | Command::ReplConf(_)
+            | Command::ReplicaOf(_)
+            | Command::Reset
+            | Command::Shutdown(_)
+            | Command::Subscribe(..)
+            | Command::Unsubscribe(..)
+            | Command::Unwatch
+            // commands managing a client are applied by `apply_for`, so they only get here if they
+            // weren't sent by a client, e.g. if the master propagated them
+            | Command::Watch(_) => {
+                return Err(ErrorReply::err("command not allowed outside of a connection").into());
+            }
+            Command::Strlen(k) => {
+                Frame::Integer(self.string(&k, now)?.map_or(0, |v| v.len() as i64))
+            }
+            Command::Touch(keys) => {
+                Frame::Integer(keys.iter().filter(|k| self.entry(k, now).is_some()).count() as i64)
+            }
+            Command::Ttl(k) => Frame::Integer(match self.ttl(&k, now) {
+                ms if ms < 0 => ms,
+                ms => (ms + 500) / 1000,
+            }),
+            Command::Type(k) => Frame::String(
+                self.peek(&k, now)
+                    .map_or("none", |e| e.value.type_name())
+                    .into(),
+            ),
+            Command::Unlink(keys) => {
+                let mut removed = Vec::new();
+                for k in &keys {
+                    if let Some(entry) = self.remove_live(k, now) {
+                        self.notify(Class::Generic, "del", k);
+                        removed.push(entry);
+                    }
+                }
+                let count = removed.len() as i64;
+                lazy_free(removed);
+                Frame::Integer(count)
+            }
+            // reached once enough replicas acknowledged, or in a transaction, which like Redis doesn't
+            // wait for them
+            Command::Wait(..) => {
+                if self.replication.is_replica() {
+                    let msg =
+                        "WAIT cannot be used with replica instances. Please also note that since \
+                               Redis 4.0 if a replica is configured to be writable (which is not \
+                               the default) writes to replicas are just local and are not \
+                               propagated.";
+                    return Err(ErrorReply::err(msg).into());
+                }
+                let replication = &self.replication;
+                Frame::Integer(replication.acknowledged(replication.offset) as i64)
+            }
+            Command::XAck(k, group, ids) => {
+                let group = self.stream(&k, now)?.and_then(|s| s.group(&group));
+                Frame::Integer(
+                    group.map_or(0, |g| ids.iter().filter(|id| g.ack(id)).count() as i64),
+                )
+            }
+            Command::XAdd(k, id, fields) => {
+                // check the ID first, so that an invalid one doesn't create an empty stream
+                let id = match self.stream(&k, now)? {
+                    Some(stream) => stream.next_id(id, now.max(0) as u64),
+                    None => Stream::default().next_id(id, now.max(0) as u64),
+                }
+                .map_err(|e| Frame::from(ErrorReply::err(e)))?;
+                self.create(&k, now, || Value::Stream(Stream::default()));
+                let stream = self.stream(&k, now)?.expect("the stream exists");
+                stream.insert(id, fields);
+                self.notify(Class::Stream, "xadd", &k);
+                self.wake(&k);
+                Frame::Bulk(Some(id.to_string().into()))
+            }
+            Command::XClaim(k, group, consumer, ids, options) => {
+                let Some(stream) = self.stream(&k, now)? else {
+                    return Err(no_group(&k, &group));
+                };
+                let claim = Claim {
+                    min_idle: options.min_idle,
+                    delivered_at: (options.time)
+                        .or(options.idle.map(|idle| now - idle))
+                        .unwrap_or(now),
+                    deliveries: options.retry_count,
+                    force: options.force,
+                    just_id: options.just_id,
+                };
+                let Some(claimed) = stream.claim(&group, &consumer, &ids, &claim, now) else {
+                    return Err(no_group(&k, &group));
+                };
+                Frame::Array(Some(if options.just_id {
+                    claimed
+                        .iter()
+                        .map(|id| Frame::Bulk(Some(id.to_string().into())))
+                        .collect()
+                } else {
+                    claimed
+                        .iter()
+                        .filter_map(|id| Some(stream_entry((id, stream.get(id)?))))
+                        .collect()
+                }))
+            }
+            Command::XGroup(XGroupCommand::Create(k, group, id, make_stream)) => {
+                if make_stream {
+                    self.create(&k, now, || Value::Stream(Stream::default()));
+                }
+                let stream = self.stream(&k, now)?.ok_or_else(no_stream)?;
+                let id = id.unwrap_or(stream.last_id());
+                if !stream.create_group(group, id) {
+                    return Err(ErrorReply::new(
+                        ErrorCode::BusyGroup,
+                        "Consumer Group name already exists",
+                    )
+                    .into());
+                }
+                self.notify(Class::Stream, "xgroup-create", &k);
                 Frame::Bulk(Some("OK".into()))
             }
-            Command::Get(k) => Frame::Bulk(
-                self.state
-                    .lock()
-                    .unwrap()
-                    .keystore
-                    .get(&k)
-                    .map(|b| b.clone()),
+            Command::XGroup(XGroupCommand::CreateConsumer(k, group, consumer)) => {
+                let stream = self.stream(&k, now)?.ok_or_else(no_stream)?;
+                let Some(group) = stream.group(&group) else {
+                    return Err(no_such_group(&k, &group));
+                };
+                let created = group.consumer(&consumer);
+                if created {
+                    self.notify(Class::Stream, "xgroup-createconsumer", &k);
+                }
+                Frame::Integer(created.into())
+            }
+            Command::XGroup(XGroupCommand::DelConsumer(k, group, consumer)) => {
+                let stream = self.stream(&k, now)?.ok_or_else(no_stream)?;
+                let Some(group) = stream.group(&group) else {
+                    return Err(no_such_group(&k, &group));
+                };
+                let Some(pending) = group.delete_consumer(&consumer) else {
+                    return Ok(Frame::Integer(0));
+                };
+                self.notify(Class::Stream, "xgroup-delconsumer", &k);
+                Frame::Integer(pending as i64)
+            }
+            Command::XGroup(XGroupCommand::Destroy(k, group)) => {
+                let stream = self.stream(&k, now)?.ok_or_else(no_stream)?;
+                let destroyed = stream.destroy_group(&group);
+                if destroyed {
+                    self.notify(Class::Stream, "xgroup-destroy", &k);
+                }
+                Frame::Integer(destroyed.into())
+            }
+            Command::XGroup(XGroupCommand::SetId(k, group, id)) => {
+                let stream = self.stream(&k, now)?.ok_or_else(no_stream)?;
+                let id = id.unwrap_or(stream.last_id());
+                let Some(group) = stream.group(&group) else {
+                    return Err(no_such_group(&k, &group));
+                };
+                group.set_last_delivered(id);
+                self.notify(Class::Stream, "xgroup-setid", &k);
+                Frame::Bulk(Some("OK".into()))
+            }
+            Command::XLen(k) => Frame::Integer(self.stream(&k, now)?.map_or(0, |s| s.len() as i64)),
+            Command::XPending(k, group, range) => {
+                let Some(g) = self.stream(&k, now)?.and_then(|s| s.group(&group)) else {
+                    return Err(no_group(&k, &group));
+                };
+                let id = |id: &StreamId| Frame::Bulk(Some(id.to_string().into()));
+                let Some(range) = range else {
+                    let mut pending = g.pending();
+                    let Some((first, _)) = pending.next() else {
+                        return Ok(Frame::Array(Some(vec![
+                            Frame::Integer(0),
+                            Frame::Bulk(None),
+                            Frame::Bulk(None),
+                            Frame::Array(None),
+                        ])));
+                    };
+                    let last = pending.last().map_or(first, |(id, _)| id);
+                    let consumers = g.pending_per_consumer().into_iter().map(|(name, count)| {
+                        Frame::Array(Some(vec![
+                            Frame::Bulk(Some(name.clone())),
+                            Frame::Bulk(Some(count.to_string().into())),
+                        ]))
+                    });
+                    return Ok(Frame::Array(Some(vec![
+                        Frame::Integer(g.pending().count() as i64),
+                        id(first),
+                        id(last),
+                        Frame::Array(Some(consumers.collect())),
+                    ])));
+                };
+                let entries = g
+                    .pending_range(range.start, range.end)
+                    .filter(|(_, p)| now - p.delivered_at >= range.min_idle)
+                    .filter(|(_, p)| match &range.consumer {
+                        Some(consumer) => *consumer == p.consumer,
+                        None => true,
+                    })
+                    .take(range.count)
+                    .map(|(i, p)| {
+                        Frame::Array(Some(vec![
+                            id(i),
+                            Frame::Bulk(Some(p.consumer.clone())),
+                            Frame::Integer(now - p.delivered_at),
+                            Frame::Integer(p.deliveries as i64),
+                        ]))
+                    });
+                Frame::Array(Some(entries.collect()))
+            }
+            Command::XRange(k, start, end, count, rev) => {
+                let Some(stream) = self.stream(&k, now)? else {
+                    return Ok(Frame::Array(Some(vec![])));
+                };
+                let entries: Box<dyn Iterator<Item = _>> = if rev {
+                    Box::new(stream.range(start, end).rev())
+                } else {
+                    Box::new(stream.range(start, end))
+                };
+                let entries = entries.take(count.unwrap_or(usize::MAX));
+                Frame::Array(Some(entries.map(stream_entry).collect()))
+            }
+            Command::XRead(keys, ids, options) => {
+                let mut streams = vec![];
+                for (k, id) in keys.into_iter().zip(ids) {
+                    let (Some(stream), Some(id)) = (self.stream(&k, now)?, id) else {
+                        continue;
+                    };
+                    let entries: Vec<Frame> = stream
+                        .range(Bound::Excluded(id), Bound::Unbounded)
+                        .take(options.count.unwrap_or(usize::MAX))
+                        .map(stream_entry)
+                        .collect();
+                    if !entries.is_empty() {
+                        streams.push(Frame::Array(Some(vec![
+                            Frame::Bulk(Some(k)),
+                            Frame::Array(Some(entries)),
+                        ])));
+                    }
+                }
+                Frame::Array((!streams.is_empty()).then_some(streams))
+            }
+            Command::XReadGroup(group, consumer, keys, ids, options) => {
+                let mut streams = vec![];
+                for (k, id) in keys.into_iter().zip(ids) {
+                    let count = options.count.unwrap_or(usize::MAX);
+                    let delivered = match self.stream(&k, now)? {
+                        Some(s) => s.read_group(&group, &consumer, id, count, options.no_ack, now),
+                        None => None,
+                    };
+                    let Some(delivered) = delivered else {
+                        let msg = format!(
+                            "No such key '{}' or consumer group '{}' in XREADGROUP with GROUP \
+                             option",
+                            String::from_utf8_lossy(&k),
+                            String::from_utf8_lossy(&group)
+                        );
+                        return Err(ErrorReply::new(ErrorCode::NoGroup, msg).into());
+                    };
+                    // a consumer's history is returned even if it is empty
+                    if id.is_none() && delivered.is_empty() {
+                        continue;
+                    }
+                    let stream = self.stream(&k, now)?.expect("the stream was just read");
+                    let entries = delivered
+                        .iter()
+                        .filter_map(|id| Some(stream_entry((id, stream.get(id)?))))
+                        .collect();
+                    streams.push(Frame::Array(Some(vec![
+                        Frame::Bulk(Some(k)),
+                        Frame::Array(Some(entries)),
+                    ])));
+                }
+                Frame::Array((!streams.is_empty()).then_some(streams))
+            }
+            Command::ZAdd(k, members, options) => {
+                let limits = self.config.encoding_limits;
+                self.create(&k, now, || Value::SortedSet(SortedSet::default()));
+                let zset = self.zset(&k, now)?.expect("the sorted set exists");
+                let (mut added, mut updated) = (0, 0);
+                for (score, member) in members {
+                    let current = zset.score(&member);
+                    if options.exists.is_some_and(|e| e != current.is_some()) {
+                        continue;
+                    }
+                    if let (Some(current), Some(ordering)) = (current, options.comparison) {
+                        if score.partial_cmp(&current) != Some(ordering) {
+                            continue;
+                        }
+                    }
+                    match zset.insert(member, score, &limits) {
+                        None => added += 1,
+                        Some(previous) if previous != score => updated += 1,
+                        Some(_) => (),
+                    }
+                }
+                if added + updated > 0 {
+                    self.notify(Class::SortedSet, "zadd", &k);
+                }
+                self.remove_if_empty(&k);
+                Frame::Integer(if options.changed {
+                    added + updated
+                } else {
+                    added
+                })
+            }
+            Command::ZCard(k) => Frame::Integer(self.zset(&k, now)?.map_or(0, |z| z.len() as i64)),
+            Command::ZCount(k, by) => Frame::Integer(
+                self.zset(&k, now)?
+                    .map_or(0, |z| z.range(&by, false).count() as i64),
             ),
+            Command::ZIncrBy(k, increment, member) => {
+                let limits = self.config.encoding_limits;
+                self.create(&k, now, || Value::SortedSet(SortedSet::default()));
+                let zset = self.zset(&k, now)?.expect("the sorted set exists");
+                let score = zset.score(&member).unwrap_or(0.0) + increment;
+                if score.is_nan() {
+                    self.remove_if_empty(&k);
+                    return Err(ErrorReply::err("resulting score is not a number (NaN)").into());
+                }
+                zset.insert(member, score, &limits);
+                self.notify(Class::SortedSet, "zincr", &k);
+                Frame::Double(score)
+            }
+            Command::ZRange(k, by, options) => {
+                let Some(zset) = self.zset(&k, now)? else {
+                    return Ok(Frame::Array(Some(vec![])));
+                };
+                let (offset, count) = match options.limit {
+                    Some((offset, _)) if offset < 0 => return Ok(Frame::Array(Some(vec![]))),
+                    Some((offset, count)) => (
+                        offset as usize,
+                        usize::try_from(count).unwrap_or(usize::MAX),
+                    ),
+                    None => (0, usize::MAX),
+                };
+                Frame::Array(Some(
+                    zset.range(&by, options.rev)
+                        .skip(offset)
+                        .take(count)
+                        .flat_map(|(member, score)| {
+                            let score = options.with_scores.then_some(Frame::Double(score));
+                            [Some(Frame::Bulk(Some(member.clone()))), score]
+                        })
+                        .flatten()
+                        .collect(),
+                ))
+            }
+            Command::ZRank(k, member, rev) => {
+                let zset = self.zset(&k, now)?;
+                match zset.and_then(|z| Some((z.rank(&member)?, z.len()))) {
+                    Some((rank, len)) => {
+                        Frame::Integer(if rev { len - 1 - rank } else { rank } as i64)
+                    }
+                    None => Frame::Bulk(None),
+                }
+            }
+            Command::ZRem(k, members) => {
+                let Some(zset) = self.zset(&k, now)? else {
+                    return Ok(Frame::Integer(0));
+                };
+                let removed = members.iter().filter(|m| zset.remove(m).is_some()).count();
+                if removed > 0 {
+                    self.notify(Class::SortedSet, "zrem", &k);
+                }
+                self.remove_if_empty(&k);
+                Frame::Integer(removed as i64)
+            }
+            Command::ZScan(k, cursor, options) => {
+                let zset = self.zset(&k, now)?.into_iter().flat_map(|z| z.iter());
+                let members = zset.map(|(m, score)| (scan::position(m), (m, score)));
+                let (cursor, members) = scan::select(members, cursor, options.count);
+                let members = members
+                    .into_iter()
+                    .filter(|(m, _)| scan::matches(&options, m))
+                    .flat_map(|(m, score)| [Frame::Bulk(Some(m.clone())), Frame::Double(score)])
+                    .collect();
+                scan::reply(cursor, members)
+            }
+            Command::ZScore(k, member) => {
+                match self.zset(&k, now)?.and_then(|z| z.score(&member)) {
+                    Some(score) => Frame::Double(score),
+                    None => Frame::Bulk(None),
+                }
+            }
+        })
+    }
+
+    /// Returns the string stored at `key`, or a `WRONGTYPE` error if it holds another type.
+    fn string(&mut self, key: &Bytes, now: i64) -> Result<Option<&Bytes>, Frame> {
+        match self.entry(key, now) {
+            Some(Entry {
+                value: Value::String(value),
+                ..
+            }) => Ok(Some(value)),
+            Some(_) => Err(ErrorReply::wrong_type().into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the list stored at `key`, or a `WRONGTYPE` error if it holds another type.
+    fn list(&mut self, key: &Bytes, now: i64) -> Result<Option<&mut VecDeque<Bytes>>, Frame> {
+        match self.entry(key, now) {
+            Some(Entry {
+                value: Value::List(list),
+                ..
+            }) => Ok(Some(list)),
+            Some(_) => Err(ErrorReply::wrong_type().into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the hash stored at `key`, or a `WRONGTYPE` error if it holds another type.
+    fn hash(&mut self, key: &Bytes, now: i64) -> Result<Option<&mut Hash>, Frame> {
+        match self.entry(key, now) {
+            Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(Some(hash)),
+            Some(_) => Err(ErrorReply::wrong_type().into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the set stored at `key`, or a `WRONGTYPE` error if it holds another type.
+    fn set(&mut self, key: &Bytes, now: i64) -> Result<Option<&mut Set>, Frame> {
+        match self.entry(key, now) {
+            Some(Entry {
+                value: Value::Set(set),
+                ..
+            }) => Ok(Some(set)),
+            Some(_) => Err(ErrorReply::wrong_type().into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the sorted set stored at `key`, or a `WRONGTYPE` error if it holds another type.
+    fn zset(&mut self, key: &Bytes, now: i64) -> Result<Option<&mut SortedSet>, Frame> {
+        match self.entry(key, now) {
+            Some(Entry {
+                value: Value::SortedSet(zset),
+                ..
+            }) => Ok(Some(zset)),
+            Some(_) => Err(ErrorReply::wrong_type().into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the stream stored at `key`, or a `WRONGTYPE` error if it holds another type.
+    fn stream(&mut self, key: &Bytes, now: i64) -> Result<Option<&mut Stream>, Frame> {
+        match self.entry(key, now) {
+            Some(Entry {
+                value: Value::Stream(stream),
+                ..
+            }) => Ok(Some(stream)),
+            Some(_) => Err(ErrorReply::wrong_type().into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the members of the sorted set stored at `key` within the shape of `search`, as
+    /// indexed by `GEOADD`.
+    ///
+    /// Unlike Redis, which only looks at the geohash cells the shape overlaps, every member is
+    /// checked, taking linear time.
+    fn geo_search(&mut self, key: &Bytes, search: GeoSearch, now: i64) -> Result<Frame, Frame> {
+        let Some(zset) = self.zset(key, now)? else {
+            return Ok(Frame::Array(Some(Vec::new())));
+        };
+        let center = match &search.from {
+            GeoFrom::LonLat(lon, lat) => (*lon, *lat),
+            GeoFrom::Member(member) => match zset.score(member) {
+                Some(score) => geo::decode(score as u64),
+                None => {
+                    let msg = "could not decode requested zset member";
+                    return Err(ErrorReply::err(msg).into());
+                }
+            },
+        };
+        let unit = search.unit;
+        let mut found = Vec::new();
+        for (member, score) in zset.iter() {
+            let point = geo::decode(score as u64);
+            let distance = match search.shape {
+                GeoShape::Radius(radius) => {
+                    Some(geo::distance(center, point)).filter(|&d| d <= radius * unit)
+                }
+                GeoShape::Box(width, height) => {
+                    geo::distance_in_box(center, point, width * unit, height * unit)
+                }
+            };
+            let Some(distance) = distance else {
+                continue;
+            };
+            found.push((member.clone(), distance, score, point));
+            if search.count == Some((found.len(), true)) {
+                break;
+            }
+        }
+        // like Redis, a count without ANY replies with the nearest members
+        let nearest = search.count.is_some_and(|(_, any)| !any);
+        if let Some(descending) = search.descending.or(nearest.then_some(false)) {
+            found.sort_by(|a, b| a.1.total_cmp(&b.1));
+            if descending {
+                found.reverse();
+            }
+        }
+        if let Some((count, _)) = search.count {
+            found.truncate(count);
+        }
+        let replies = found.into_iter().map(|(member, distance, score, point)| {
+            if !(search.with_dist || search.with_hash || search.with_coord) {
+                return Frame::Bulk(Some(member));
+            }
+            let mut reply = vec![Frame::Bulk(Some(member))];
+            if search.with_dist {
+                reply.push(Frame::Bulk(Some(format!("{:.4}", distance / unit).into())));
+            }
+            if search.with_hash {
+                reply.push(Frame::Integer(score as i64));
+            }
+            if search.with_coord {
+                reply.push(coordinates(point));
+            }
+            Frame::Array(Some(reply))
+        });
+        Ok(Frame::Array(Some(replies.collect())))
+    }
+
+    /// Combines the sets stored at `keys`, treating missing keys as empty sets.
+    fn combine(&mut self, op: SetOp, keys: &[Bytes], now: i64) -> Result<HashSet<Bytes>, Frame> {
+        // expire and type check every key first, so that the sets can then be borrowed together
+        for k in keys {
+            self.set(k, now)?;
+        }
+        let empty = Set::default();
+        let sets: Vec<&Set> = keys
+            .iter()
+            .map(|k| match self.keystore.get(k) {
+                Some(Entry {
+                    value: Value::Set(set),
+                    ..
+                }) => set,
+                _ => &empty,
+            })
+            .collect();
+        let Some((first, rest)) = sets.split_first() else {
+            return Ok(HashSet::new());
+        };
+        Ok(match op {
+            SetOp::Inter => first
+                .iter()
+                .filter(|m| rest.iter().all(|s| s.contains(m)))
+                .collect(),
+            SetOp::Union => sets.iter().flat_map(|s| s.iter()).collect(),
+            SetOp::Diff => first
+                .iter()
+                .filter(|m| !rest.iter().any(|s| s.contains(m)))
+                .collect(),
+        })
+    }
+
+    /// Stores an empty collection, created by `empty`, at `key` if nothing is stored there yet.
+    fn create(&mut self, key: &Bytes, now: i64, empty: impl FnOnce() -> Value) {
+        if self.entry(key, now).is_none() {
+            let entry = Entry {
+                value: empty(),
+                expires_at: None,
+                access: Access::new(now),
+                size: 0,
+            };
+            self.insert(key.clone(), entry);
+        }
+    }
+
+    /// Removes the collection at `key` if it has become empty.
+    fn remove_if_empty(&mut self, key: &Bytes) {
+        if self.keystore.get(key).is_some_and(|e| e.value.is_empty()) {
+            self.remove(key);
+            self.notify(Class::Generic, "del", key);
+        }
+    }
+
+    /// Atomically pops an element from one end of the list at `source` and pushes it onto one end of
+    /// the list at `destination`, returning it.
+    fn lmove(
+        &mut self,
+        source: Bytes,
+        destination: Bytes,
+        from: End,
+        to: End,
+        now: i64,
+    ) -> Result<Option<Bytes>, Frame> {
+        if self.list(&source, now)?.is_none() {
+            return Ok(None);
+        }
+        // check the destination before popping, so that a type error leaves the source untouched
+        self.list(&destination, now)?;
+        let list = self
+            .list(&source, now)?
+            .expect("the source was just checked");
+        let value = pop(list, from).expect("lists are never empty");
+        self.notify(Class::List, pop_event(from), &source);
+        self.remove_if_empty(&source);
+        self.execute(Command::Push(destination, to, vec![value.clone()]), now)?;
+        Ok(Some(value))
+    }
+
+    /// Returns a key picked uniformly at random among those that haven't expired, if any.
+    fn random_key(&mut self, now: i64) -> Option<Bytes> {
+        // each expired key picked is removed, so this ends once a live key is picked or none remain
+        loop {
+            let random = self.random();
+            let key = self.sampler.pick(random)?.clone();
+            if self.peek(&key, now).is_some() {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Returns a pseudorandom number uniformly distributed in `[0, 1)`, using xorshift64*.
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed >> 12;
+        self.seed ^= self.seed << 25;
+        self.seed ^= self.seed >> 27;
+        let random = self.seed.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (random >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Wakes every client blocked on `key`, so that they can check whether they can now be served.
+    fn wake(&self, key: &Bytes) {
+        for waiter in self.blocked.get(key).into_iter().flatten() {
+            waiter.notify_one();
+        }
+    }
+
+    /// Returns the entry stored at `key`, removing it instead if it has expired, and records that it
+    /// was accessed.
+    ///
+    /// The entry's expiry must only be changed through `expire`, which keeps `expires` in sync.
+    fn entry(&mut self, key: &Bytes, now: i64) -> Option<&mut Entry> {
+        let random = self.random();
+        let hit = self.peek(key, now).is_some();
+        self.stats.lookup(hit);
+        let entry = self.keystore.get_mut(key)?;
+        entry.access.record(now, random);
+        Some(entry)
+    }
+
+    /// Like `entry`, but without recording an access, for commands that only check whether a key
+    /// exists or inspect its metadata.
+    fn peek(&mut self, key: &Bytes, now: i64) -> Option<&mut Entry> {
+        if self
+            .keystore
+            .get(key)
+            .is_some_and(|e| e.expires_at.is_some_and(|t| t <= now))
+        {
+            self.remove(key);
+            self.stats.expired += 1;
+            self.notify(Class::Expired, "expired", key);
+        }
+        self.keystore.get_mut(key)
+    }
+
+    /// Stores `entry` at `key`, replacing (and returning) any previous entry.
+    fn insert(&mut self, key: Bytes, mut entry: Entry) -> Option<Entry> {
+        let expires_at = entry.expires_at;
+        entry.size = memory::size(&key, &entry.value);
+        self.used_memory += entry.size;
+        let previous = self.keystore.insert(key.clone(), entry);
+        if let Some(previous) = &previous {
+            self.used_memory -= previous.size;
+        }
+        if let Some(t) = previous.as_ref().and_then(|e| e.expires_at) {
+            self.expires.remove(&(t, key.clone()));
+            self.volatile.remove(&key);
+        }
+        if previous.is_none() {
+            self.positions.insert((scan::position(&key), key.clone()));
+            self.sampler.insert(key.clone());
+            self.notify(Class::New, "new", &key);
+        }
+        if let Some(t) = expires_at {
+            self.expires.insert((t, key.clone()));
+            self.volatile.insert(key);
+        }
+        previous
+    }
+
+    /// Removes and returns the entry stored at `key`.
+    fn remove(&mut self, key: &Bytes) -> Option<Entry> {
+        let entry = self.keystore.remove(key)?;
+        self.used_memory -= entry.size;
+        self.positions.remove(&(scan::position(key), key.clone()));
+        self.sampler.remove(key);
+        if let Some(t) = entry.expires_at {
+            self.expires.remove(&(t, key.clone()));
+            self.volatile.remove(key);
+        }
+        self.touch(key);
+        Some(entry)
+    }
+
+    /// Replaces the value stored at `key`, keeping its expiry, or creates it without one.
+    fn set_value(&mut self, key: Bytes, value: Bytes, now: i64) {
+        match self.entry(&key, now) {
+            Some(entry) => entry.value = Value::String(value),
+            None => {
+                self.insert(
+                    key,
+                    Entry {
+                        value: Value::String(value),
+                        expires_at: None,
+                        access: Access::new(now),
+                        size: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Removes every key.
+    fn clear(&mut self) {
+        let keys: Vec<Bytes> = self.keystore.keys().cloned().collect();
+        for key in &keys {
+            self.remove(key);
+        }
+    }
+
+    /// Removes and returns the entry stored at `key`, unless it has already expired.
+    fn remove_live(&mut self, key: &Bytes, now: i64) -> Option<Entry> {
+        self.peek(key, now)?;
+        self.remove(key)
+    }
+
+    /// Sets or clears the expiry of the entry at `key`, if there is one.
+    fn expire(&mut self, key: &Bytes, expires_at: Option<i64>) {
+        let Some(entry) = self.keystore.get_mut(key) else {
+            return;
+        };
+        if let Some(t) = std::mem::replace(&mut entry.expires_at, expires_at) {
+            self.expires.remove(&(t, key.clone()));
+            self.volatile.remove(key);
+        }
+        if let Some(t) = expires_at {
+            self.expires.insert((t, key.clone()));
+            self.volatile.insert(key.clone());
+        }
+    }
+
+    /// Removes keys that expired before `now`, soonest first, until none remain or `deadline` has
+    /// passed.
+    fn expire_cycle(&mut self, now: i64, deadline: Instant) {
+        let mut removed = 0_usize;
+        while let Some((t, key)) = self.expires.first() {
+            // checking the clock is relatively slow, so only do so every few keys
+            if *t > now || (removed & 0xF == 0 && Instant::now() >= deadline) {
+                break;
+            }
+            let key = key.clone();
+            self.remove(&key);
+            self.stats.expired += 1;
+            self.notify(Class::Expired, "expired", &key);
+            removed += 1;
+        }
+    }
+
+    /// Returns the milliseconds until `key` expires, `-1` if it has no expiry, or `-2` if it does
+    /// not exist.
+    fn ttl(&mut self, key: &Bytes, now: i64) -> i64 {
+        self.peek(key, now)
+            .map_or(-2, |e| e.expires_at.map_or(-1, |t| t - now))
+    }
+
+    /// Returns where snapshots are saved by `SAVE` and `BGSAVE`, and loaded from on startup.
+    fn rdb_path(&self) -> PathBuf {
+        Path::new(&self.config.dir).join(&self.config.dbfilename)
+    }
+
+    /// Saves the dataset if `save` is set, or by default if any `save` points are configured, then
+    /// signals the server to shut down. The server keeps running if the dataset fails to be saved.
+    fn shutdown(&mut self, save: Option<bool>) -> Result<(), Frame> {
+        if save.unwrap_or(!self.config.save.is_empty()) {
+            let now = now_ms();
+            if let Err(e) = rdb::write(&self.rdb_path(), &self.snapshot(now)) {
+                println!("failed to save {}: {:?}", self.rdb_path().display(), e);
+                return Err(ErrorReply::err("Errors trying to SHUTDOWN. Check logs.").into());
+            }
+        }
+        self.shutdown.send_replace(true);
+        Ok(())
+    }
+
+    /// Saves a snapshot of the dataset in the background, unless one is already being saved.
+    fn bgsave(&mut self, now: i64) -> Result<(), Frame> {
+        if self.saving.swap(true, Ordering::SeqCst) {
+            return Err(ErrorReply::err(SAVE_IN_PROGRESS).into());
+        }
+        // the snapshot is taken now, so that later writes don't end up in it
+        let started = Instant::now();
+        let snapshot = self.snapshot(now);
+        self.record_latency(latency::FORK, started.elapsed());
+        self.dirty = 0;
+        self.last_save = Instant::now();
+        let (path, saving) = (self.rdb_path(), self.saving.clone());
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = rdb::write(&path, &snapshot) {
+                println!("failed to save {}: {:?}", path.display(), e);
+            }
+            saving.store(false, Ordering::SeqCst);
+        });
+        Ok(())
+    }
+
+    /// Applies the parameters of the config that may change while the server runs.
+    fn reconfigure(&mut self) {
+        // candidates ranked by another policy can't be compared with the new policy's
+        self.eviction_pool.clear();
+        let config = &self.config;
+        self.notifications =
+            Notifications::parse(&config.notify_keyspace_events).expect("flags were validated");
+        if let Some(aof) = &mut self.aof {
+            aof.configure(config.appendfsync, config.auto_rewrite);
+        }
+    }
+
+    /// Applies `CONFIG GET`, `CONFIG SET` or `CONFIG REWRITE`.
+    fn apply_config(&mut self, command: ConfigCommand) -> Result<Frame, Frame> {
+        match command {
+            ConfigCommand::Get(patterns) => {
+                let mut params: Vec<(&str, String)> = Vec::new();
+                for pattern in patterns {
+                    for (name, value) in self.config.get(&pattern) {
+                        if !params.iter().any(|(n, _)| *n == name) {
+                            params.push((name, value));
+                        }
+                    }
+                }
+                let params = params.into_iter().flat_map(|(name, value)| {
+                    [
+                        Frame::Bulk(Some(name.into())),
+                        Frame::Bulk(Some(value.into())),
+                    ]
+                });
+                Ok(Frame::Map(params.collect()))
+            }
+            ConfigCommand::Set(pairs) => {
+                // every parameter is set or none are, so they're set on a copy first
+                let mut config = self.config.clone();
+                for (name, value) in &pairs {
+                    let name = String::from_utf8_lossy(name);
+                    let reason = match config.set_at_runtime(&name, &String::from_utf8_lossy(value))
+                    {
+                        Ok(()) => continue,
+                        Err(SetError::Unknown) => {
+                            let msg = format!(
+                                "Unknown option or number of arguments for CONFIG SET - '{}'",
+                                name
+                            );
+                            return Err(ErrorReply::err(msg).into());
+                        }
+                        Err(SetError::Immutable) => "can't set immutable config".to_string(),
+                        Err(SetError::Invalid) => {
+                            format!("invalid value '{}'", String::from_utf8_lossy(value))
+                        }
+                    };
+                    let msg = format!(
+                        "CONFIG SET failed (possibly related to argument '{}') - {}",
+                        name, reason
+                    );
+                    return Err(ErrorReply::err(msg).into());
+                }
+                // like Redis, setting `requirepass` replaces every password of the default user
+                if config.requirepass != self.config.requirepass {
+                    self.acl.require_pass(&config.requirepass);
+                }
+                self.config = config;
+                self.reconfigure();
+                Ok(Frame::Bulk(Some("OK".into())))
+            }
+            ConfigCommand::Rewrite => {
+                let Some(path) = &self.config.file else {
+                    let msg = "The server is running without a config file";
+                    return Err(ErrorReply::err(msg).into());
+                };
+                self.config.rewrite(path).map_err(|e| {
+                    Frame::from(ErrorReply::err(format!("Rewriting config file: {}", e)))
+                })?;
+                Ok(Frame::Bulk(Some("OK".into())))
+            }
         }
     }
 }
 
+impl Expiry {
+    /// Returns the unix time in milliseconds at which the expiry elapses.
+    fn deadline(self, now: i64) -> i64 {
+        match self {
+            Expiry::In(ms) => now.saturating_add(ms),
+            Expiry::At(ms) => ms,
+        }
+    }
+}
+
+impl Value {
+    /// Returns roughly how many bytes the value occupies.
+    fn size(&self) -> usize {
+        match self {
+            Value::String(value) => value.len(),
+            Value::List(list) => list.iter().map(Bytes::len).sum(),
+            Value::Hash(hash) => hash.iter().map(|(f, v)| f.len() + v.len()).sum(),
+            Value::Set(set) => set.iter().map(|m| m.len()).sum(),
+            Value::SortedSet(zset) => zset.size(),
+            Value::Stream(stream) => stream.size(),
+        }
+    }
+
+    /// Whether the value is an empty collection, which must not be stored. Streams are never empty,
+    /// as they are kept once all of their entries are deleted.
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Stream(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+        }
+    }
+
+    /// Returns the name of the value's type, as replied by `TYPE`.
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+}
+
+/// The error replied to `XGROUP` subcommands on a missing key.
+fn no_stream() -> Frame {
+    let msg = "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want \
+               to use the MKSTREAM option to create an empty stream automatically.";
+    ErrorReply::err(msg).into()
+}
+
+/// The error replied to `XGROUP` subcommands on a missing consumer group.
+fn no_such_group(key: &Bytes, group: &Bytes) -> Frame {
+    let msg = format!(
+        "No such consumer group '{}' for key name '{}'",
+        String::from_utf8_lossy(group),
+        String::from_utf8_lossy(key)
+    );
+    ErrorReply::new(ErrorCode::NoGroup, msg).into()
+}
+
+/// The error replied to `XPENDING` and `XCLAIM` on a missing key or consumer group.
+fn no_group(key: &Bytes, group: &Bytes) -> Frame {
+    let msg = format!(
+        "No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    );
+    ErrorReply::new(ErrorCode::NoGroup, msg).into()
+}
+
+/// Returns a stream entry as an array of its ID and an array of its fields and values.
+fn stream_entry((id, fields): (&StreamId, &Vec<(Bytes, Bytes)>)) -> Frame {
+    let fields = fields
+        .iter()
+        .flat_map(|(f, v)| [Frame::Bulk(Some(f.clone())), Frame::Bulk(Some(v.clone()))]);
+    Frame::Array(Some(vec![
+        Frame::Bulk(Some(id.to_string().into())),
+        Frame::Array(Some(fields.collect())),
+    ]))
+}
+
+/// Returns the keyspace event for popping from `end` of a list.
+fn pop_event(end: End) -> &'static str {
+    match end {
+        End::Left => "lpop",
+        End::Right => "rpop",
+    }
+}
+
+/// Removes an element from the given end of `list`.
+fn pop(list: &mut VecDeque<Bytes>, end: End) -> Option<Bytes> {
+    match end {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+}
+
+/// Returns the indexes of `element` in `list` that `LPOS` should reply with, in the order they were
+/// found.
+fn positions(list: &VecDeque<Bytes>, element: &Bytes, options: &LPosOptions) -> Vec<i64> {
+    let max_len = match options.max_len {
+        0 => list.len(),
+        max_len => max_len,
+    };
+    let count = match options.count {
+        Some(0) => usize::MAX,
+        count => count.unwrap_or(1),
+    };
+    let skip = (options.rank.unsigned_abs() - 1)
+        .try_into()
+        .unwrap_or(usize::MAX);
+    let indexes: Box<dyn Iterator<Item = usize>> = if options.rank < 0 {
+        Box::new((0..list.len()).rev().take(max_len))
+    } else {
+        Box::new((0..list.len()).take(max_len))
+    };
+    indexes
+        .filter(|&i| list[i] == element)
+        .skip(skip)
+        .take(count)
+        .map(|i| i as i64)
+        .collect()
+}
+
+/// Resolves the inclusive range from `start` to `end` within a sequence of length `len`, where
+/// negative indexes count back from the end, or returns `None` if it is empty.
+fn range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let (start, end) = (
+        if start < 0 { len + start } else { start }.max(0),
+        if end < 0 { len + end } else { end }.min(len - 1),
+    );
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+/// Returns the reply of a longitude and latitude of the geo commands.
+fn coordinates((lon, lat): (f64, f64)) -> Frame {
+    Frame::Array(Some(vec![Frame::Double(lon), Frame::Double(lat)]))
+}
+
+/// The number of bytes above which freeing values is worth handing off to another thread.
+const LAZY_FREE_THRESHOLD: usize = 64 * 1024;
+
+/// Drops `entries` on a blocking thread if they are large enough that freeing them could stall the
+/// caller, otherwise drops them immediately.
+fn lazy_free(entries: Vec<Entry>) {
+    let size: usize = entries.iter().map(|e| e.value.size()).sum();
+    if size > LAZY_FREE_THRESHOLD {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn_blocking(move || drop(entries));
+        }
+    }
+}
+
+/// Returns the current unix time in milliseconds.
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
 impl Clone for Db {
     fn clone(&self) -> Self {
         Db {
@@ -54,3 +2544,236 @@ impl Clone for Db {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{ExpireOptions, SetOptions};
+
+    #[test]
+    fn expire_cycle_removes_only_expired_keys() {
+        let db = Db::new();
+        let mut state = db.state.lock().unwrap();
+        for (key, expires_at) in [("a", Some(1)), ("b", Some(2)), ("c", Some(5)), ("d", None)] {
+            let value = Value::String("v".into());
+            let access = Access::new(0);
+            state.insert(
+                key.into(),
+                Entry {
+                    value,
+                    expires_at,
+                    access,
+                    size: 0,
+                },
+            );
+        }
+        state.expire(&"b".into(), None);
+
+        state.expire_cycle(3, Instant::now() + Duration::from_secs(1));
+
+        let mut keys: Vec<_> = state.keystore.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(vec!["b", "c", "d"], keys);
+        assert_eq!(1, state.expires.len());
+    }
+
+    #[tokio::test]
+    async fn commands_check_the_type_of_keys() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![
+            Command::Push("list".into(), End::Right, vec!["a".into()]),
+            Command::Type("list".into()),
+            Command::Type("missing".into()),
+            Command::Get("list".into()),
+            Command::IncrBy("list".into(), 1),
+            Command::HLen("list".into()),
+            Command::SAdd("list".into(), vec!["a".into()]),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        let wrong_type = Frame::from(ErrorReply::wrong_type());
+        let expected = vec![
+            Frame::Integer(1),
+            Frame::String("list".into()),
+            Frame::String("none".into()),
+            wrong_type.clone(),
+            wrong_type.clone(),
+            wrong_type.clone(),
+            wrong_type,
+        ];
+        assert_eq!(expected, replies);
+    }
+
+    #[tokio::test]
+    async fn getex_and_getdel_change_the_expiry_or_remove_the_key() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![
+            Command::Set("k".into(), "v".into(), SetOptions::default()),
+            Command::GetEx("k".into(), Some(Some(Expiry::In(60_000)))),
+            Command::Ttl("k".into()),
+            Command::GetEx("k".into(), Some(None)),
+            Command::Ttl("k".into()),
+            Command::GetDel("k".into()),
+            Command::GetDel("k".into()),
+            Command::GetEx("k".into(), None),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        let v = Frame::Bulk(Some("v".into()));
+        let expected = vec![
+            Frame::Bulk(Some("OK".into())),
+            v.clone(),
+            Frame::Integer(60),
+            v.clone(),
+            Frame::Integer(-1),
+            v,
+            Frame::Bulk(None),
+            Frame::Bulk(None),
+        ];
+        assert_eq!(expected, replies);
+    }
+
+    #[tokio::test]
+    async fn expire_options_compare_with_the_current_expiry() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let expire = |ms, exists, compare| {
+            let options = ExpireOptions { exists, compare };
+            Command::Expire("k".into(), Expiry::At(ms), options)
+        };
+        let later = 4_000_000_000_000;
+        let commands = vec![
+            Command::Set("k".into(), "v".into(), SetOptions::default()),
+            expire(later, Some(true), None),
+            expire(later, None, Some(cmp::Ordering::Greater)),
+            expire(later, None, Some(cmp::Ordering::Less)),
+            Command::ExpireTime("k".into(), true),
+            expire(later, Some(false), None),
+            expire(later - 1000, None, Some(cmp::Ordering::Greater)),
+            expire(later + 1000, None, Some(cmp::Ordering::Greater)),
+            Command::ExpireTime("k".into(), false),
+            Command::Persist("k".into()),
+            Command::ExpireTime("k".into(), false),
+            Command::ExpireTime("missing".into(), false),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        let expected = [0, 0, 1, later, 0, 0, 1, later / 1000 + 1, 1, -1, -2].map(Frame::Integer);
+        assert_eq!(
+            [Frame::Bulk(Some("OK".into()))]
+                .into_iter()
+                .chain(expected)
+                .collect::<Vec<_>>(),
+            replies
+        );
+    }
+
+    #[test]
+    fn lpos_positions() {
+        let list: VecDeque<Bytes> = ["a", "b", "a", "c", "a"].map(Bytes::from).into();
+        let a = Bytes::from("a");
+        let lpos = |rank, count, max_len| {
+            let options = LPosOptions {
+                rank,
+                count,
+                max_len,
+            };
+            positions(&list, &a, &options)
+        };
+        assert_eq!(vec![0], lpos(1, None, 0));
+        assert_eq!(vec![2, 4], lpos(2, Some(0), 0));
+        assert_eq!(vec![4, 2], lpos(-1, Some(2), 0));
+        assert_eq!(vec![0, 2], lpos(1, Some(0), 4));
+        assert!(lpos(4, None, 0).is_empty());
+    }
+
+    #[test]
+    fn combine_sets() {
+        let db = Db::new();
+        let mut state = db.state.lock().unwrap();
+        for (key, members) in [("a", ["1", "2", "3"]), ("b", ["2", "3", "4"])] {
+            let set = Set::collect(members.map(Bytes::from), &EncodingLimits::default());
+            state.insert(
+                key.into(),
+                Entry {
+                    value: Value::Set(set),
+                    expires_at: None,
+                    access: Access::new(0),
+                    size: 0,
+                },
+            );
+        }
+        let mut combine = |op, keys: &[&'static str]| {
+            let keys: Vec<Bytes> = keys.iter().map(|k| Bytes::from(*k)).collect();
+            let mut members: Vec<_> = state.combine(op, &keys, 0).unwrap().into_iter().collect();
+            members.sort();
+            members
+        };
+        assert_eq!(vec!["2", "3"], combine(SetOp::Inter, &["a", "b"]));
+        assert_eq!(
+            vec!["1", "2", "3", "4"],
+            combine(SetOp::Union, &["a", "b", "c"])
+        );
+        assert_eq!(vec!["1"], combine(SetOp::Diff, &["a", "b"]));
+        assert!(combine(SetOp::Inter, &["a", "c"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocked_pop_is_woken_by_push() {
+        let db = Db::new();
+        let blocked = tokio::spawn({
+            let db = db.clone();
+            async move {
+                let pop = Command::BPop(vec!["list".into()], End::Left, None);
+                db.apply_batch(&mut db.client().0, vec![pop]).await
+            }
+        });
+        tokio::task::yield_now().await;
+        let push = Command::Push("list".into(), End::Right, vec!["a".into(), "b".into()]);
+        let (mut client, _) = db.client();
+        db.apply_batch(&mut client, vec![push]).await;
+
+        let reply = blocked.await.unwrap();
+        let expected = vec![
+            Frame::Bulk(Some("list".into())),
+            Frame::Bulk(Some("a".into())),
+        ];
+        assert_eq!(vec![Frame::Array(Some(expected))], reply);
+        let remaining = db
+            .apply_batch(&mut client, vec![Command::LLen("list".into())])
+            .await;
+        assert_eq!(vec![Frame::Integer(1)], remaining);
+    }
+
+    #[test]
+    fn clients_past_maxclients_are_refused() {
+        let db = Db::new();
+        db.state.lock().unwrap().config.maxclients = 1;
+        let first = db.connect();
+        assert!(first.is_ok());
+        let refused = Frame::Error("ERR max number of clients reached".into());
+        assert_eq!(Some(refused), db.connect().err());
+        drop(first);
+        assert!(db.connect().is_ok(), "the first client disconnected");
+        assert_eq!(1, db.state.lock().unwrap().stats.rejected);
+    }
+
+    #[tokio::test]
+    async fn shutdown_is_signaled_unless_saving_fails() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let shutdown = db.shutdown_signal();
+        db.state.lock().unwrap().config.dir = "/nonexistent/dir".into();
+        let replies = db
+            .apply_batch(&mut client, vec![Command::Shutdown(None)])
+            .await;
+        let error = "ERR Errors trying to SHUTDOWN. Check logs.";
+        assert_eq!(vec![Frame::Error(error.into())], replies);
+        assert!(!*shutdown.borrow());
+
+        let replies = db
+            .apply_batch(&mut client, vec![Command::Shutdown(Some(false))])
+            .await;
+        assert!(replies.is_empty(), "the client is disconnected instead");
+        assert!(*shutdown.borrow());
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+
+use super::{Client, State};
+use crate::{
+    command::{self, AclCommand, Command, Spec, CATEGORIES, COMMANDS},
+    frame::Frame,
+    glob,
+    reply::{ErrorCode, ErrorReply},
+    sha256,
+};
+
+/// The users clients authenticate as, and what each of them may access.
+pub struct Acl {
+    users: BTreeMap<Bytes, User>,
+}
+
+/// A user, which is created disabled and without any permissions.
+#[derive(Clone, Default)]
+struct User {
+    enabled: bool,
+    /// Whether any password is accepted.
+    nopass: bool,
+    /// The SHA-256 digests of the user's passwords, in hexadecimal.
+    passwords: Vec<String>,
+    /// The names of the commands the user may run.
+    commands: BTreeSet<&'static str>,
+    /// The command rules `commands` was built by, starting with `+@all` or `-@all`.
+    command_rules: String,
+    keys: Patterns,
+    channels: Patterns,
+}
+
+/// The patterns of the keys or channels a user may access.
+#[derive(Clone, Default)]
+struct Patterns {
+    /// Whether every key or channel may be accessed, i.e. the patterns include `*`.
+    all: bool,
+    patterns: Vec<Bytes>,
+}
+
+impl Default for Acl {
+    /// Like Redis, there's only the `default` user at first, which may run any command without a
+    /// password.
+    fn default() -> Self {
+        let mut default = User::default();
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            default.apply(rule.as_bytes()).expect("the rules are valid");
+        }
+        Acl {
+            users: BTreeMap::from([(Bytes::from("default"), default)]),
+        }
+    }
+}
+
+impl Acl {
+    /// Sets the password of the `default` user to `requirepass`, or lets it authenticate without
+    /// one if it is empty.
+    pub(super) fn require_pass(&mut self, requirepass: &str) {
+        let default = self
+            .users
+            .get_mut(&b"default"[..])
+            .expect("users are never removed");
+        let password = format!(">{}", requirepass);
+        let rule = if requirepass.is_empty() {
+            "nopass"
+        } else {
+            &password
+        };
+        for rule in ["resetpass", rule] {
+            default.apply(rule.as_bytes()).expect("the rules are valid");
+        }
+    }
+
+    fn default_user(&self) -> &User {
+        &self.users[&b"default"[..]]
+    }
+}
+
+impl User {
+    /// Applies a rule of `ACL SETUSER`, e.g. `on`, `>password`, `~key:*`, `&channel` or `+@read`,
+    /// returning why it is invalid if it is.
+    fn apply(&mut self, rule: &[u8]) -> Result<(), String> {
+        match rule.to_ascii_lowercase().as_slice() {
+            b"on" => self.enabled = true,
+            b"off" => self.enabled = false,
+            b"nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            b"resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            b"allkeys" => self.keys.add(b"*", "keys")?,
+            b"resetkeys" => self.keys = Patterns::default(),
+            b"allchannels" => self.channels.add(b"*", "channels")?,
+            b"resetchannels" => self.channels = Patterns::default(),
+            b"allcommands" => self.permit(true, "@all")?,
+            b"nocommands" => self.permit(false, "@all")?,
+            b"reset" => {
+                for rule in ["resetpass", "resetkeys", "resetchannels", "off", "-@all"] {
+                    self.apply(rule.as_bytes())?;
+                }
+            }
+            _ => match rule.split_first() {
+                Some((b'>', password)) => self.add_password(sha256::hex_digest(password)),
+                Some((b'<', password)) => self.remove_password(&sha256::hex_digest(password))?,
+                Some((b'#', digest)) => self.add_password(parse_digest(digest)?),
+                Some((b'!', digest)) => self.remove_password(&parse_digest(digest)?)?,
+                Some((b'~', pattern)) => self.keys.add(pattern, "keys")?,
+                Some((b'&', pattern)) => self.channels.add(pattern, "channels")?,
+                Some((&sign @ (b'+' | b'-'), name)) => {
+                    let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+                    self.permit(sign == b'+', &name)?;
+                }
+                _ => return Err("Syntax error".into()),
+            },
+        }
+        Ok(())
+    }
+
+    fn add_password(&mut self, digest: String) {
+        self.nopass = false;
+        if !self.passwords.contains(&digest) {
+            self.passwords.push(digest);
+        }
+    }
+
+    fn remove_password(&mut self, digest: &str) -> Result<(), String> {
+        match self.passwords.iter().position(|d| d == digest) {
+            Some(i) => {
+                self.passwords.remove(i);
+                Ok(())
+            }
+            None => {
+                Err("The password you are trying to remove from the user does not exist".into())
+            }
+        }
+    }
+
+    /// Allows or, unless `allowed` is set, denies running the command `name`, or every command in
+    /// the category `@name`.
+    fn permit(&mut self, allowed: bool, name: &str) -> Result<(), String> {
+        let specs: Vec<&Spec> = match name.strip_prefix('@') {
+            Some("all") => COMMANDS.iter().collect(),
+            Some(category) if CATEGORIES.contains(&category) => COMMANDS
+                .iter()
+                .filter(|spec| spec.categories().contains(&category))
+                .collect(),
+            Some(_) => Vec::new(),
+            None => command::lookup(name.as_bytes()).into_iter().collect(),
+        };
+        // every category has commands, so only unknown names select none
+        if specs.is_empty() {
+            return Err("Unknown command or category name in ACL".into());
+        }
+        // the rules before `@all` make no difference any more
+        if name == "@all" {
+            self.commands.clear();
+            self.command_rules.clear();
+        } else {
+            self.command_rules.push(' ');
+        }
+        for spec in specs {
+            if allowed {
+                self.commands.insert(spec.name);
+            } else {
+                self.commands.remove(spec.name);
+            }
+        }
+        self.command_rules.push(if allowed { '+' } else { '-' });
+        self.command_rules.push_str(name);
+        Ok(())
+    }
+
+    /// Whether `password` is accepted to authenticate as the user.
+    fn accepts(&self, password: &[u8]) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&sha256::hex_digest(password)))
+    }
+
+    /// Returns the flags of the user as `ACL GETUSER` lists them.
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    /// Describes the user by the rules that would recreate it, as `ACL LIST` does.
+    fn describe(&self) -> String {
+        let mut rules: Vec<String> = self.flags().into_iter().map(String::from).collect();
+        rules.extend(self.passwords.iter().map(|digest| format!("#{}", digest)));
+        rules.extend(self.keys.describe('~'));
+        match self.channels.describe('&') {
+            channels if channels.is_empty() => rules.push("resetchannels".into()),
+            channels => rules.extend(channels),
+        }
+        rules.push(self.command_rules.clone());
+        rules.join(" ")
+    }
+}
+
+impl Patterns {
+    /// Adds `pattern`, where `*` allows every key or channel, returning why it is invalid if it is.
+    fn add(&mut self, pattern: &[u8], kind: &str) -> Result<(), String> {
+        if pattern == b"*" {
+            self.all = true;
+            self.patterns.clear();
+        } else if self.all {
+            return Err(format!(
+                "Adding a pattern after the * pattern (or the 'all{kind}' flag) is not valid and \
+                 does not have any effect. Try 'reset{kind}' to start with an empty list of \
+                 patterns"
+            ));
+        } else if !self.patterns.iter().any(|p| p == pattern) {
+            self.patterns.push(Bytes::copy_from_slice(pattern));
+        }
+        Ok(())
+    }
+
+    /// Whether `name` matches any of the patterns.
+    fn matches(&self, name: &[u8]) -> bool {
+        self.all || self.patterns.iter().any(|p| glob::matches(p, name))
+    }
+
+    fn describe(&self, prefix: char) -> Vec<String> {
+        if self.all {
+            return vec![format!("{}*", prefix)];
+        }
+        let patterns = self.patterns.iter();
+        patterns
+            .map(|p| format!("{}{}", prefix, String::from_utf8_lossy(p)))
+            .collect()
+    }
+}
+
+/// Parses the hexadecimal SHA-256 digest of a password, as given to `#` and `!` rules.
+fn parse_digest(digest: &[u8]) -> Result<String, String> {
+    if digest.len() != 64
+        || !digest
+            .iter()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        let msg = "The password hash must be exactly 64 characters and contain only lowercase \
+                   hexadecimal characters";
+        return Err(msg.into());
+    }
+    Ok(String::from_utf8_lossy(digest).into_owned())
+}
+
+impl State {
+    /// Applies `AUTH [username] password`, authenticating `client` as `username`, which defaults
+    /// to `default`.
+    pub(super) fn auth(&mut self, client: &mut Client, user: Option<Bytes>, pass: Bytes) -> Frame {
+        if user.is_none() && self.acl.default_user().nopass {
+            let msg = "AUTH <password> called without any password configured for the default \
+                       user. Are you sure your configuration is correct?";
+            return ErrorReply::err(msg).into();
+        }
+        let user = user.unwrap_or_else(|| "default".into());
+        match self.authenticate(client, user, &pass) {
+            Ok(()) => Frame::Bulk(Some("OK".into())),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Authenticates `client` as `user` if it is enabled and accepts `pass`.
+    pub(super) fn authenticate(
+        &self,
+        client: &mut Client,
+        user: Bytes,
+        pass: &[u8],
+    ) -> Result<(), ErrorReply> {
+        if !self.acl.users.get(&user).is_some_and(|u| u.accepts(pass)) {
+            let msg = "invalid username-password pair or user is disabled.";
+            return Err(ErrorReply::new(ErrorCode::WrongPass, msg));
+        }
+        client.user = user;
+        client.authenticated = true;
+        Ok(())
+    }
+
+    /// Whether clients are authenticated as the `default` user as soon as they connect, as it
+    /// needs no password.
+    pub(super) fn authenticated_by_default(&self) -> bool {
+        let default = self.acl.default_user();
+        default.enabled && default.nopass
+    }
+
+    /// Returns the reply to a command `client` sent as `args` that its user isn't permitted to
+    /// run, or to run on the keys or channels it was given, or `None` if it is permitted.
+    pub(super) fn permit(&self, client: &Client, args: &[Bytes]) -> Option<Frame> {
+        // only commands received from a connection have their arguments recorded
+        let spec = command::lookup(args.first()?)?;
+        let user = &self.acl.users[&client.user];
+        let noperm = |msg: String| Some(ErrorReply::new(ErrorCode::NoPerm, msg).into());
+        if !user.commands.contains(spec.name) {
+            return noperm(format!(
+                "User {} has no permissions to run the '{}' command",
+                String::from_utf8_lossy(&client.user),
+                spec.name
+            ));
+        }
+        if !spec.keys(args).iter().all(|key| user.keys.matches(key)) {
+            return noperm("No permissions to access a key".into());
+        }
+        let rest = &args[1..];
+        let channels = match spec.name {
+            "publish" => rest[..1].iter().all(|c| user.channels.matches(c)),
+            "subscribe" => rest.iter().all(|c| user.channels.matches(c)),
+            // like Redis, patterns must be permitted as they are, not only match a permitted one
+            "psubscribe" => rest
+                .iter()
+                .all(|p| user.channels.all || user.channels.patterns.contains(p)),
+            _ => true,
+        };
+        if !channels {
+            return noperm("No permissions to access a channel".into());
+        }
+        None
+    }
+
+    /// Applies a subcommand of `ACL` sent by `client`.
+    pub(super) fn acl(&mut self, client: &Client, command: AclCommand) -> Frame {
+        let bulk = |s: &str| Frame::Bulk(Some(Bytes::copy_from_slice(s.as_bytes())));
+        match command {
+            AclCommand::Cat(None) => {
+                Frame::Array(Some(CATEGORIES.iter().map(|c| bulk(c)).collect()))
+            }
+            AclCommand::Cat(Some(category)) => {
+                let category = String::from_utf8_lossy(&category).to_ascii_lowercase();
+                if !CATEGORIES.contains(&category.as_str()) {
+                    return ErrorReply::err(format!("Unknown category '{}'", category)).into();
+                }
+                let names = COMMANDS
+                    .iter()
+                    .filter(|spec| spec.categories().contains(&category.as_str()))
+                    .map(|spec| bulk(spec.name));
+                Frame::Array(Some(names.collect()))
+            }
+            AclCommand::GetUser(name) => {
+                let Some(user) = self.acl.users.get(&name) else {
+                    return Frame::Null;
+                };
+                let strings = |s: Vec<String>| bulk(&s.join(" "));
+                Frame::Map(vec![
+                    bulk("flags"),
+                    Frame::Array(Some(user.flags().into_iter().map(bulk).collect())),
+                    bulk("passwords"),
+                    Frame::Array(Some(user.passwords.iter().map(|d| bulk(d)).collect())),
+                    bulk("commands"),
+                    bulk(&user.command_rules),
+                    bulk("keys"),
+                    strings(user.keys.describe('~')),
+                    bulk("channels"),
+                    strings(user.channels.describe('&')),
+                    bulk("selectors"),
+                    Frame::Array(Some(Vec::new())),
+                ])
+            }
+            AclCommand::List => {
+                let users = self.acl.users.iter().map(|(name, user)| {
+                    let name = String::from_utf8_lossy(name);
+                    bulk(&format!("user {} {}", name, user.describe()))
+                });
+                Frame::Array(Some(users.collect()))
+            }
+            AclCommand::SetUser(name, rules) => {
+                if name.iter().any(|&b| b == b' ' || b == 0) {
+                    return ErrorReply::err("Usernames can't contain spaces or null characters")
+                        .into();
+                }
+                // every rule is applied or none are, so they're applied to a copy first
+                let mut user = self.acl.users.get(&name).cloned().unwrap_or_else(|| {
+                    let mut user = User::default();
+                    user.apply(b"-@all").expect("the rule is valid");
+                    user
+                });
+                for rule in &rules {
+                    if let Err(reason) = user.apply(rule) {
+                        let msg = format!(
+                            "Error in ACL SETUSER modifier '{}': {}",
+                            String::from_utf8_lossy(rule),
+                            reason
+                        );
+                        return ErrorReply::err(msg).into();
+                    }
+                }
+                self.acl.users.insert(name, user);
+                Frame::Bulk(Some("OK".into()))
+            }
+            AclCommand::WhoAmI => Frame::Bulk(Some(client.user.clone())),
+        }
+    }
+}
+
+impl Client {
+    /// Returns the reply to a command sent before the client authenticated, or `None` if the
+    /// command is allowed.
+    pub(super) fn require_auth(&self, command: &Command) -> Option<Frame> {
+        match command {
+            _ if self.authenticated => None,
+            Command::Auth(..) | Command::Hello(..) | Command::Quit | Command::Reset => None,
+            _ => Some(ErrorReply::new(ErrorCode::NoAuth, "Authentication required.").into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    /// Parses `args` as a connection would, recording them to be checked against permissions.
+    fn received(client: &mut Client, args: &[&str]) -> Command {
+        let args = args.iter().map(|a| Frame::Bulk(Some(a.to_string().into())));
+        let frame = Frame::Array(Some(args.collect()));
+        client.received(&frame);
+        Command::try_from(frame).unwrap()
+    }
+
+    #[tokio::test]
+    async fn clients_must_authenticate_once_a_password_is_required() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![received(
+            &mut client,
+            &["CONFIG", "SET", "requirepass", "secret"],
+        )];
+        db.apply_batch(&mut client, commands).await;
+        let (mut client, _) = db.client();
+        let commands = [
+            &["PING"][..],
+            &["AUTH", "wrong"],
+            &["AUTH", "default", "secret"],
+            &["PING"],
+            &["RESET"],
+            &["PING"],
+        ];
+        let commands: Vec<Command> = commands
+            .into_iter()
+            .map(|args| received(&mut client, args))
+            .collect();
+        let replies = db.apply_batch(&mut client, commands).await;
+        let noauth = Frame::Error("NOAUTH Authentication required.".into());
+        assert_eq!(noauth, replies[0]);
+        assert!(matches!(&replies[1], Frame::Error(e) if e.starts_with(b"WRONGPASS")));
+        assert_eq!(Frame::Bulk(Some("OK".into())), replies[2]);
+        assert_eq!(Frame::Bulk(Some("PONG".into())), replies[3]);
+        assert_eq!(noauth, replies[5], "RESET deauthenticates the client");
+
+        let commands = [
+            &["AUTH", "secret"][..],
+            &["CONFIG", "SET", "requirepass", ""],
+            &["AUTH", "secret"],
+        ];
+        let commands: Vec<Command> = commands
+            .into_iter()
+            .map(|args| received(&mut client, args))
+            .collect();
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert!(matches!(&replies[2], Frame::Error(e) if e.starts_with(b"ERR AUTH")));
+    }
+
+    #[tokio::test]
+    async fn users_are_only_permitted_their_commands_keys_and_channels() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let rules = [
+            "on",
+            ">pass",
+            "~cached:*",
+            "&news",
+            "+@string",
+            "-append",
+            "+publish",
+        ];
+        let mut setuser = vec!["ACL", "SETUSER", "alice"];
+        setuser.extend(rules);
+        let commands = [
+            &setuser[..],
+            &["ACL", "SETUSER", "alice", "allkeys", "~cached:*"],
+            &["AUTH", "alice", "pass"],
+            &["ACL", "WHOAMI"],
+            &["SET", "cached:a", "1"],
+            &["MSET", "cached:b", "2", "other", "3"],
+            &["APPEND", "cached:a", "2"],
+            &["PUBLISH", "news", "hello"],
+            &["PUBLISH", "sports", "hello"],
+        ];
+        let commands: Vec<Command> = commands
+            .into_iter()
+            .map(|args| received(&mut client, args))
+            .collect();
+        let replies = db.apply_batch(&mut client, commands).await;
+        let ok = Frame::Bulk(Some("OK".into()));
+        assert_eq!(ok, replies[0]);
+        assert!(matches!(&replies[1], Frame::Error(e) if e.starts_with(b"ERR Error in ACL")));
+        assert_eq!(ok, replies[2]);
+        let noperm = |msg: &str| Frame::Error(format!("NOPERM {}", msg).into());
+        let acl = "User alice has no permissions to run the 'acl' command";
+        assert_eq!(noperm(acl), replies[3], "every subcommand of ACL is admin");
+        assert_eq!(ok, replies[4]);
+        assert_eq!(noperm("No permissions to access a key"), replies[5]);
+        let append = "User alice has no permissions to run the 'append' command";
+        assert_eq!(noperm(append), replies[6]);
+        assert_eq!(Frame::Integer(0), replies[7]);
+        assert_eq!(noperm("No permissions to access a channel"), replies[8]);
+
+        let state = db.state.lock().unwrap();
+        let alice = &state.acl.users[&b"alice"[..]];
+        let digest = sha256::hex_digest(b"pass");
+        let expected = format!(
+            "on #{} ~cached:* &news -@all +@string -append +publish",
+            digest
+        );
+        assert_eq!(expected, alice.describe());
+    }
+}
@@ -0,0 +1,766 @@
+use std::{
+    io::{
+        self,
+        ErrorKind::{InvalidData, UnexpectedEof},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+
+use super::{State, Stream, Value};
+use crate::{
+    command::{
+        Command, End, Error, ExpireOptions, Expiry, SetOptions, StreamId, XAddId, XClaimOptions,
+        XGroupCommand, ZAddOptions,
+    },
+    connection::{self, Connection, ReadError},
+    frame::Frame,
+};
+
+/// How many elements each command of a rewritten AOF adds at most, like Redis's
+/// `AOF_REWRITE_ITEMS_PER_CMD`.
+const ITEMS_PER_COMMAND: usize = 64;
+
+/// When writes to the AOF are synced to disk, i.e. Redis's `appendfsync`.
+#[derive(Clone, Copy)]
+pub enum Fsync {
+    /// After every batch of writes, although clients may be replied to before it completes.
+    Always,
+    /// At most once a second, so that at most a second of writes is lost if the host crashes.
+    EverySec,
+    /// Whenever the operating system decides to.
+    No,
+}
+
+impl Fsync {
+    pub fn parse(policy: &str) -> Option<Self> {
+        match policy.to_ascii_lowercase().as_str() {
+            "always" => Some(Fsync::Always),
+            "everysec" => Some(Fsync::EverySec),
+            "no" => Some(Fsync::No),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Fsync::Always => "always",
+            Fsync::EverySec => "everysec",
+            Fsync::No => "no",
+        }
+    }
+}
+
+/// When the AOF is rewritten automatically, i.e. Redis's `auto-aof-rewrite-percentage` and
+/// `auto-aof-rewrite-min-size`.
+#[derive(Clone, Copy)]
+pub struct AutoRewrite {
+    /// How much the file must have grown since it was last rewritten, as a percentage of its size
+    /// then, or 0 to never rewrite it automatically.
+    pub percentage: u64,
+    /// How many bytes the file must at least hold.
+    pub min_size: u64,
+}
+
+impl Default for AutoRewrite {
+    fn default() -> Self {
+        AutoRewrite {
+            percentage: 100,
+            min_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// The append-only file, which logs every write command so that the dataset can be rebuilt by
+/// replaying them.
+pub struct Aof {
+    /// The commands to append, written by a dedicated task so that the lock isn't held while waiting
+    /// for the disk.
+    messages: UnboundedSender<Message>,
+    size: Arc<Size>,
+    auto_rewrite: AutoRewrite,
+    writer: JoinHandle<()>,
+}
+
+enum Message {
+    Append(Frame),
+    /// Replaces the file with one holding these commands instead.
+    Rewrite(Vec<Frame>),
+    Fsync(Fsync),
+}
+
+/// How large the file is, as tracked by its writer.
+struct Size {
+    current: AtomicU64,
+    /// The size of the file when it was last rewritten, or opened.
+    base: AtomicU64,
+    rewriting: AtomicBool,
+}
+
+impl Aof {
+    /// Opens the file at `path` for appending, creating it if needed. The task writing to it stops
+    /// once the `Aof` is dropped.
+    pub async fn open(path: &Path, fsync: Fsync, auto_rewrite: AutoRewrite) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let len = file.metadata().await?.len();
+        let size = Arc::new(Size {
+            current: AtomicU64::new(len),
+            base: AtomicU64::new(len),
+            rewriting: AtomicBool::new(false),
+        });
+        let (messages, receiver) = mpsc::unbounded_channel();
+        let temp = format!("temp-rewriteaof-{}.aof", std::process::id());
+        let writer = Writer {
+            file,
+            path: path.to_owned(),
+            temp: path.with_file_name(temp),
+            fsync,
+            size: size.clone(),
+        };
+        let writer = tokio::spawn(writer.run(receiver));
+        Ok(Aof {
+            messages,
+            size,
+            auto_rewrite,
+            writer,
+        })
+    }
+
+    pub fn append(&self, command: Frame) {
+        // the writer only stops once this sender is dropped
+        let _ = self.messages.send(Message::Append(command));
+    }
+
+    /// Changes when the file is synced to disk and rewritten, e.g. after `CONFIG SET`.
+    pub fn configure(&mut self, fsync: Fsync, auto_rewrite: AutoRewrite) {
+        self.auto_rewrite = auto_rewrite;
+        let _ = self.messages.send(Message::Fsync(fsync));
+    }
+
+    /// Stops appending to the file, returning once every command appended so far is written and
+    /// synced to disk.
+    pub async fn close(self) {
+        drop(self.messages);
+        let _ = self.writer.await;
+    }
+
+    pub fn rewriting(&self) -> bool {
+        self.size.rewriting.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the file has grown enough since it was last rewritten to be rewritten again.
+    pub fn should_rewrite(&self) -> bool {
+        let AutoRewrite {
+            percentage,
+            min_size,
+        } = self.auto_rewrite;
+        let current = self.size.current.load(Ordering::SeqCst);
+        let base = self.size.base.load(Ordering::SeqCst).max(1);
+        let growth = current.saturating_sub(base).saturating_mul(100);
+        percentage > 0
+            && current >= min_size
+            && growth >= base.saturating_mul(percentage)
+            && !self.rewriting()
+    }
+
+    /// Replaces the file in the background with one holding `commands`, which must rebuild the
+    /// dataset as of the last command appended. Commands appended meanwhile are kept aside, to be
+    /// appended to the new file once it is written.
+    pub fn rewrite(&self, commands: Vec<Frame>) {
+        self.size.rewriting.store(true, Ordering::SeqCst);
+        let _ = self.messages.send(Message::Rewrite(commands));
+    }
+}
+
+struct Writer {
+    file: File,
+    path: PathBuf,
+    /// Where the file is rewritten, before replacing it.
+    temp: PathBuf,
+    fsync: Fsync,
+    size: Arc<Size>,
+}
+
+impl Writer {
+    /// Writes each message received, syncing the file according to `fsync`.
+    async fn run(mut self, mut messages: UnboundedReceiver<Message>) {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        let mut unsynced = false;
+        // the new file being written in the background, and the commands to append to it once done
+        let mut rewrite: Option<(JoinHandle<io::Result<File>>, Vec<Frame>)> = None;
+        loop {
+            tokio::select! {
+                message = messages.recv() => {
+                    let Some(message) = message else {
+                        break;
+                    };
+                    let mut connection = Connection::new(&mut self.file);
+                    let mut next = Some(message);
+                    while let Some(message) = next.take().or_else(|| messages.try_recv().ok()) {
+                        match message {
+                            Message::Append(command) => {
+                                if let Some((_, appended)) = &mut rewrite {
+                                    appended.push(command.clone());
+                                }
+                                connection.write_frame(command);
+                            }
+                            Message::Rewrite(commands) => {
+                                let write = write_new(self.temp.clone(), commands);
+                                rewrite = Some((tokio::spawn(write), Vec::new()));
+                            }
+                            Message::Fsync(fsync) => self.fsync = fsync,
+                        }
+                    }
+                    let mut result = connection.flush().await;
+                    if result.is_ok() {
+                        result = self.flush().await;
+                    }
+                    if let Err(e) = result {
+                        println!("failed to write to the AOF: {:?}", e);
+                    }
+                    unsynced = true;
+                }
+                new = async { (&mut rewrite.as_mut().expect("a rewrite is running").0).await },
+                    if rewrite.is_some() =>
+                {
+                    let (_, appended) = rewrite.take().expect("a rewrite is running");
+                    let new = match new {
+                        Ok(new) => new,
+                        Err(e) => Err(e.into()),
+                    };
+                    if let Err(e) = self.replace(new, appended).await {
+                        println!("failed to rewrite the AOF: {:?}", e);
+                        let _ = tokio::fs::remove_file(&self.temp).await;
+                    }
+                    self.size.rewriting.store(false, Ordering::SeqCst);
+                }
+                _ = ticker.tick(), if unsynced && matches!(self.fsync, Fsync::EverySec) => {
+                    if let Err(e) = self.file.sync_data().await {
+                        println!("failed to sync the AOF: {:?}", e);
+                    }
+                    unsynced = false;
+                }
+            }
+        }
+        if let Err(e) = self.file.sync_data().await {
+            println!("failed to sync the AOF: {:?}", e);
+        }
+    }
+
+    /// Flushes what was written to the file, syncing it if `fsync` is `Always`.
+    async fn flush(&mut self) -> io::Result<()> {
+        self.file.flush().await?;
+        if matches!(self.fsync, Fsync::Always) {
+            self.file.sync_data().await?;
+        }
+        let len = self.file.metadata().await?.len();
+        self.size.current.store(len, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Appends the commands appended during a rewrite to the `new` file it wrote, then replaces
+    /// the file with it.
+    async fn replace(&mut self, new: io::Result<File>, appended: Vec<Frame>) -> io::Result<()> {
+        let mut new = new?;
+        let mut connection = Connection::new(&mut new);
+        for command in appended {
+            connection.write_frame(command);
+        }
+        connection.flush().await?;
+        new.sync_data().await?;
+        tokio::fs::rename(&self.temp, &self.path).await?;
+        self.file = new;
+        self.flush().await?;
+        let len = self.size.current.load(Ordering::SeqCst);
+        self.size.base.store(len, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Writes `commands` to a new file at `path`, returning it.
+async fn write_new(path: PathBuf, commands: Vec<Frame>) -> io::Result<File> {
+    let mut file = File::create(&path).await?;
+    let mut connection = Connection::new(&mut file);
+    for (i, command) in commands.into_iter().enumerate() {
+        connection.write_frame(command);
+        // so that the whole file isn't buffered in memory
+        if i % 1024 == 1023 {
+            connection.flush().await?;
+        }
+    }
+    connection.flush().await?;
+    file.flush().await?;
+    file.sync_data().await?;
+    Ok(file)
+}
+
+/// Reads the commands logged in the AOF at `path`, or none if there is no such file.
+///
+/// Like Redis with its default `aof-load-truncated yes`, a file whose last command was only partly
+/// written, e.g. because the host crashed, is loaded without it, and cut short at the end of the
+/// command before, so that the writes appended next follow a complete one. A file holding commands
+/// that only make sense for a connection, e.g. `SUBSCRIBE`, is rejected, although transactions are
+/// kept for the loader to apply at once, and selecting db 0, as Redis does first, is skipped.
+pub async fn read(path: &Path) -> io::Result<Vec<Command>> {
+    let open = OpenOptions::new().read(true).write(true).open(path).await;
+    let mut file = match open {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut connection = Connection::new(&mut file);
+    let mut commands = Vec::new();
+    // how many bytes the commands read so far take, i.e. where the next one starts
+    let mut complete = 0;
+    loop {
+        let frame = match connection.read_frame().await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(ReadError::IoError(UnexpectedEof)) => {
+                println!(
+                    "{} ends with a truncated command, which is dropped, loading the {} bytes \
+                     before it",
+                    path.display(),
+                    complete
+                );
+                file.set_len(complete).await?;
+                file.sync_data().await?;
+                break;
+            }
+            Err(e) => {
+                let msg = format!("invalid AOF: {:?}", e);
+                return Err(io::Error::new(InvalidData, msg));
+            }
+        };
+        complete += connection::encoded_len(&frame) as u64;
+        match Command::try_from(frame) {
+            Ok(command) if manages_client(&command) => {
+                let msg = format!("command not allowed in AOF: {:?}", command);
+                return Err(io::Error::new(InvalidData, msg));
+            }
+            Ok(command) => commands.push(command),
+            Err(Error::UnknownCommand(name, args)) if name.eq_ignore_ascii_case(b"select") => {
+                if args.first().map(|db| &db[..]) != Some(b"0") {
+                    let msg = "AOF selects a database other than db 0, which is unsupported";
+                    return Err(io::Error::new(InvalidData, msg));
+                }
+            }
+            Err(e) => {
+                let msg = format!("invalid command in AOF: {:?}", e);
+                return Err(io::Error::new(InvalidData, msg));
+            }
+        }
+    }
+    Ok(commands)
+}
+
+/// Returns whether `command` manages the state of the client sending it, other than its
+/// transaction.
+fn manages_client(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Acl(_)
+            | Command::Auth(..)
+            | Command::Client(_)
+            | Command::Hello(..)
+            | Command::Monitor
+            | Command::PSync
+            | Command::Quit
+            | Command::ReplConf(_)
+            | Command::ReplicaOf(_)
+            | Command::Reset
+            | Command::Shutdown(_)
+            | Command::Subscribe(..)
+            | Command::Unsubscribe(..)
+            | Command::Unwatch
+            | Command::Watch(_)
+    )
+}
+
+impl State {
+    /// Returns the fewest commands that rebuild the dataset, for rewriting the AOF.
+    pub(super) fn rewrite(&self, now: i64) -> Vec<Frame> {
+        let mut commands = Vec::new();
+        for (key, entry) in &self.keystore {
+            if entry.expires_at.is_some_and(|t| t <= now) {
+                continue;
+            }
+            let k = || key.clone();
+            match &entry.value {
+                Value::String(value) => {
+                    commands.push(Command::Set(k(), value.clone(), SetOptions::default()))
+                }
+                Value::List(list) => commands.extend(
+                    chunks(list.iter().cloned()).map(|items| Command::Push(k(), End::Right, items)),
+                ),
+                Value::Hash(hash) => commands.extend(
+                    chunks(hash.iter().map(|(f, v)| (f.clone(), v.clone())))
+                        .map(|pairs| Command::HSet(k(), pairs)),
+                ),
+                Value::Set(set) => {
+                    commands.extend(chunks(set.iter()).map(|members| Command::SAdd(k(), members)))
+                }
+                Value::SortedSet(zset) => commands.extend(
+                    chunks(zset.iter().map(|(m, score)| (score, m.clone())))
+                        .map(|members| Command::ZAdd(k(), members, ZAddOptions::default())),
+                ),
+                Value::Stream(stream) => {
+                    for (id, fields) in stream.entries() {
+                        let id = XAddId::Explicit(*id);
+                        commands.push(Command::XAdd(k(), id, fields.clone()));
+                    }
+                    // an empty stream can only be created along with a group
+                    let mut make_stream = stream.len() == 0;
+                    if make_stream && stream.groups().next().is_none() {
+                        let group = Bytes::new();
+                        commands.extend(
+                            [
+                                XGroupCommand::Create(
+                                    k(),
+                                    group.clone(),
+                                    Some(StreamId::MIN),
+                                    true,
+                                ),
+                                XGroupCommand::Destroy(k(), group),
+                            ]
+                            .map(Command::XGroup),
+                        );
+                    }
+                    for (name, group) in stream.groups() {
+                        let last = Some(group.last_delivered());
+                        let create = XGroupCommand::Create(k(), name.clone(), last, make_stream);
+                        commands.push(Command::XGroup(create));
+                        make_stream = false;
+                        for consumer in group.consumers() {
+                            let create =
+                                XGroupCommand::CreateConsumer(k(), name.clone(), consumer.clone());
+                            commands.push(Command::XGroup(create));
+                        }
+                        for (id, pending) in group.pending() {
+                            let options = XClaimOptions {
+                                time: Some(pending.delivered_at),
+                                retry_count: Some(pending.deliveries),
+                                force: true,
+                                just_id: true,
+                                ..Default::default()
+                            };
+                            let consumer = pending.consumer.clone();
+                            commands.push(Command::XClaim(
+                                k(),
+                                name.clone(),
+                                consumer,
+                                vec![*id],
+                                options,
+                            ));
+                        }
+                    }
+                }
+            }
+            if let Some(at) = entry.expires_at {
+                let options = ExpireOptions::default();
+                commands.push(Command::Expire(k(), Expiry::At(at), options));
+            }
+        }
+        commands.iter().map(Command::to_frame).collect()
+    }
+
+    /// Rewrites `command` so that applying it again later has the same effect as applying it now,
+    /// making relative expiries absolute, and generated stream IDs explicit. Returns the frame it
+    /// is propagated as, which like Redis is that of its non-blocking form for blocking commands,
+    /// e.g. `LPOP` of the key `BLPOP` is served from.
+    pub(super) fn make_repeatable(&mut self, command: &mut Command, now: i64) -> Frame {
+        match command {
+            Command::Expire(_, expiry, _)
+            | Command::GetEx(_, Some(Some(expiry)))
+            | Command::Set(
+                ..,
+                SetOptions {
+                    expiry: Some(expiry),
+                    ..
+                },
+            ) => {
+                if let Expiry::In(ms) = *expiry {
+                    *expiry = Expiry::At(now.saturating_add(ms));
+                }
+            }
+            Command::XAdd(k, id @ (XAddId::Auto | XAddId::Seq(_)), _) => {
+                let next = match self.peek(k, now).map(|e| &e.value) {
+                    Some(Value::Stream(stream)) => Some(stream.next_id(*id, now.max(0) as u64)),
+                    Some(_) => None,
+                    None => Some(Stream::default().next_id(*id, now.max(0) as u64)),
+                };
+                // a key of the wrong type or an invalid ID is left as is, to fail the same way when
+                // applied
+                if let Some(Ok(next)) = next {
+                    *id = XAddId::Explicit(next);
+                }
+            }
+            // deliveries replayed from the log are all recent, so entries are claimed regardless
+            Command::XClaim(.., options) => {
+                options.min_idle = 0;
+                if let Some(idle) = options.idle.take() {
+                    options.time = Some(now - idle);
+                }
+            }
+            // a blocking command is only applied once it wouldn't block, or has timed out
+            Command::BPop(keys, side, _) => {
+                let key = keys.iter().find(|k| self.peek(k, now).is_some());
+                let key = key.unwrap_or(&keys[0]).clone();
+                return Command::Pop(key, *side, None).to_frame();
+            }
+            Command::BLMove(source, destination, from, to, _) => {
+                let (source, destination) = (source.clone(), destination.clone());
+                return Command::LMove(source, destination, *from, *to).to_frame();
+            }
+            _ => (),
+        }
+        command.to_frame()
+    }
+}
+
+/// Splits `items` into chunks small enough for a single command each.
+fn chunks<T>(items: impl Iterator<Item = T>) -> impl Iterator<Item = Vec<T>> {
+    let mut chunks: Vec<Vec<T>> = Vec::new();
+    for item in items {
+        match chunks.last_mut() {
+            Some(chunk) if chunk.len() < ITEMS_PER_COMMAND => chunk.push(item),
+            _ => chunks.push(vec![item]),
+        }
+    }
+    chunks.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::XReadOptions,
+        db::{now_ms, Db},
+    };
+
+    #[tokio::test]
+    async fn replaying_the_log_rebuilds_the_same_state() {
+        let path = std::env::temp_dir().join(format!("replay-{}.aof", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = Db::new();
+        db.open_aof(path.clone(), Fsync::Always, AutoRewrite::default())
+            .await
+            .unwrap();
+        let (mut client, _) = db.client();
+        let commands = vec![
+            Command::Push("list".into(), End::Right, vec!["a".into(), "b".into()]),
+            Command::Expire(
+                "list".into(),
+                Expiry::In(60 * 1000),
+                ExpireOptions::default(),
+            ),
+            Command::XAdd(
+                "stream".into(),
+                XAddId::Auto,
+                vec![("f".into(), "v".into())],
+            ),
+            // errors aren't logged
+            Command::IncrBy("list".into(), 1),
+            Command::Get("list".into()),
+        ];
+        db.apply_batch(&mut client, commands).await;
+        let logged = loop {
+            let logged = read(&path).await.unwrap();
+            if logged.len() == 3 {
+                break logged;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert!(matches!(logged[1], Command::Expire(_, Expiry::At(_), _)));
+        assert!(matches!(
+            logged[2],
+            Command::XAdd(_, XAddId::Explicit(_), _)
+        ));
+
+        let replayed = Db::new();
+        replayed
+            .open_aof(path.clone(), Fsync::No, AutoRewrite::default())
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rewritten(&db), rewritten(&replayed));
+    }
+
+    #[tokio::test]
+    async fn transactions_are_replayed_even_if_read_only() {
+        let path = std::env::temp_dir().join(format!("multi-{}.aof", std::process::id()));
+        let log = "*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n*1\r\n$5\r\nMULTI\r\n\
+            *3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$4\r\nINCR\r\n$1\r\na\r\n\
+            *1\r\n$4\r\nEXEC\r\n";
+        std::fs::write(&path, log).unwrap();
+        let db = Db::new();
+        db.state.lock().unwrap().config.read_only = true;
+        db.open_aof(path.clone(), Fsync::No, AutoRewrite::default())
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let reply = db
+            .state
+            .lock()
+            .unwrap()
+            .apply_unchecked(Command::Get("a".into()));
+        assert_eq!(Frame::Bulk(Some("2".into())), reply);
+    }
+
+    #[tokio::test]
+    async fn logs_larger_than_a_read_are_replayed() {
+        let path = std::env::temp_dir().join(format!("large-{}.aof", std::process::id()));
+        let commands = (0..2000)
+            .map(|i| Command::Set(format!("key:{i}").into(), "v".into(), SetOptions::default()))
+            .map(|c| c.to_frame());
+        let mut log = std::io::Cursor::new(Vec::new());
+        let mut connection = Connection::new(&mut log);
+        for command in commands {
+            connection.write_frame(command);
+        }
+        connection.flush().await.unwrap();
+        let log = log.into_inner();
+        assert!(log.len() > 16 * 1024, "the log should take many reads");
+        std::fs::write(&path, &log).unwrap();
+        let db = Db::new();
+        db.open_aof(path.clone(), Fsync::No, AutoRewrite::default())
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(2000, db.state.lock().unwrap().keystore.len());
+    }
+
+    #[tokio::test]
+    async fn truncated_logs_are_loaded_without_their_last_command() {
+        let path = std::env::temp_dir().join(format!("truncated-{}.aof", std::process::id()));
+        let set = "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+        std::fs::write(&path, format!("{}*3\r\n$3\r\nSET\r\n$1\r\nb", set)).unwrap();
+        let db = Db::new();
+        db.open_aof(path.clone(), Fsync::Always, AutoRewrite::default())
+            .await
+            .unwrap();
+        assert_eq!(set.as_bytes(), std::fs::read(&path).unwrap());
+        assert_eq!(1, db.state.lock().unwrap().keystore.len());
+
+        // what is written next follows the last complete command
+        let (mut client, _) = db.client();
+        let incr = Command::IncrBy("a".into(), 1);
+        db.apply_batch(&mut client, vec![incr]).await;
+        db.close().await;
+        let replayed = Db::new();
+        replayed
+            .open_aof(path.clone(), Fsync::No, AutoRewrite::default())
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let reply = replayed
+            .state
+            .lock()
+            .unwrap()
+            .apply_unchecked(Command::Get("a".into()));
+        assert_eq!(Frame::Bulk(Some("2".into())), reply);
+    }
+
+    #[tokio::test]
+    async fn commands_managing_a_client_are_rejected() {
+        let path = std::env::temp_dir().join(format!("subscribe-{}.aof", std::process::id()));
+        std::fs::write(&path, "*2\r\n$9\r\nSUBSCRIBE\r\n$1\r\nc\r\n").unwrap();
+        let err = read(&path).await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(InvalidData, err.kind());
+    }
+
+    #[tokio::test]
+    async fn rewriting_the_log_rebuilds_the_same_state() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let (stream, group, consumer) = (Bytes::from("stream"), Bytes::from("g"), Bytes::from("c"));
+        let mut commands = vec![
+            Command::Set("string".into(), "v".into(), SetOptions::default()),
+            Command::Expire(
+                "string".into(),
+                Expiry::In(60 * 1000),
+                ExpireOptions::default(),
+            ),
+            Command::HSet("hash".into(), vec![("f".into(), "v".into())]),
+            // a single member, as the members of a set are rewritten in an arbitrary order
+            Command::SAdd("set".into(), vec!["a".into()]),
+            Command::ZAdd(
+                "zset".into(),
+                vec![(1.5, "a".into())],
+                ZAddOptions::default(),
+            ),
+            Command::XGroup(XGroupCommand::Create(
+                "empty".into(),
+                group.clone(),
+                None,
+                true,
+            )),
+        ];
+        for i in 0..100 {
+            let item = Bytes::from(i.to_string());
+            commands.push(Command::Push("list".into(), End::Right, vec![item.clone()]));
+            commands.push(Command::XAdd(
+                stream.clone(),
+                XAddId::Auto,
+                vec![(item.clone(), item)],
+            ));
+        }
+        commands.extend([
+            Command::XGroup(XGroupCommand::Create(
+                stream.clone(),
+                group.clone(),
+                Some(StreamId::MIN),
+                false,
+            )),
+            Command::XReadGroup(
+                group.clone(),
+                consumer,
+                vec![stream],
+                vec![None],
+                XReadOptions {
+                    count: Some(10),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        db.apply_batch(&mut client, commands).await;
+        let commands = db.state.lock().unwrap().rewrite(now_ms());
+        // lists are split into commands of at most `ITEMS_PER_COMMAND` elements
+        let pushes = commands
+            .iter()
+            .filter(|c| format!("{:?}", c).contains("RPUSH"));
+        assert_eq!(2, pushes.count());
+
+        let replayed = Db::new();
+        let (mut client, _) = replayed.client();
+        let commands = commands.into_iter().map(|c| Command::try_from(c).unwrap());
+        let replies = replayed.apply_batch(&mut client, commands.collect()).await;
+        assert!(replies.iter().all(|r| !matches!(r, Frame::Error(_))));
+        assert_eq!(rewritten(&db), rewritten(&replayed));
+    }
+
+    /// Returns the commands rewriting the dataset, in a consistent order.
+    fn rewritten(db: &Db) -> Vec<String> {
+        let commands = db.state.lock().unwrap().rewrite(now_ms());
+        let mut commands: Vec<_> = commands.iter().map(|c| format!("{:?}", c)).collect();
+        commands.sort();
+        commands
+    }
+}
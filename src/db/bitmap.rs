@@ -0,0 +1,231 @@
+use bytes::Bytes;
+
+use crate::command::{BitFieldOp, BitFieldType, BitOp, BitRange, Overflow};
+
+/// Returns the bit at `offset` of `value`, where bit 0 is the most significant bit of the first
+/// byte, and bits past its end are 0.
+pub fn get(value: &[u8], offset: u64) -> bool {
+    let byte = value.get((offset / 8) as usize).copied().unwrap_or(0);
+    byte & (0x80 >> (offset % 8)) != 0
+}
+
+/// Sets the bit at `offset` of `value`, growing it with zeros to reach it, returning the bit it
+/// replaced.
+pub fn set(value: &mut Vec<u8>, offset: u64, bit: bool) -> bool {
+    let index = (offset / 8) as usize;
+    if value.len() <= index {
+        value.resize(index + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let old = value[index] & mask != 0;
+    match bit {
+        true => value[index] |= mask,
+        false => value[index] &= !mask,
+    }
+    old
+}
+
+/// Returns the positions of the first and last bits of `value` within `range`, or of all of them,
+/// or `None` if no bit is.
+fn bit_range(value: &[u8], range: Option<BitRange>) -> Option<(usize, usize)> {
+    let Some(BitRange { start, end, bits }) = range else {
+        return (!value.is_empty()).then(|| (0, value.len() * 8 - 1));
+    };
+    let end = end.unwrap_or(-1);
+    match bits {
+        true => super::range(value.len() * 8, start, end),
+        false => {
+            super::range(value.len(), start, end).map(|(first, last)| (first * 8, last * 8 + 7))
+        }
+    }
+}
+
+/// Returns each byte of `value` holding bits from `first` to `last`, with its other bits cleared.
+fn masked(value: &[u8], first: usize, last: usize) -> impl Iterator<Item = (usize, u8, u8)> + '_ {
+    let (first_byte, last_byte) = (first / 8, last / 8);
+    (first_byte..=last_byte).map(move |i| {
+        let mut mask = 0xff;
+        if i == first_byte {
+            mask &= 0xff >> (first % 8);
+        }
+        if i == last_byte {
+            mask &= 0xff << (7 - last % 8);
+        }
+        (i, value[i] & mask, mask)
+    })
+}
+
+/// Returns how many bits of `value` are set within `range`, or all of it, i.e. `BITCOUNT`.
+pub fn count(value: &[u8], range: Option<BitRange>) -> i64 {
+    let Some((first, last)) = bit_range(value, range) else {
+        return 0;
+    };
+    masked(value, first, last)
+        .map(|(_, byte, _)| byte.count_ones() as i64)
+        .sum()
+}
+
+/// Returns the position of the first bit of `value` within `range` that is `bit`, i.e. `BITPOS`.
+///
+/// Like Redis, if no bit is clear and the range doesn't end explicitly, the string is considered
+/// padded with zeros, so that the position just after the range is returned rather than -1.
+pub fn position(value: &[u8], bit: bool, range: Option<BitRange>) -> i64 {
+    let Some((first, last)) = bit_range(value, range) else {
+        return -1;
+    };
+    for (i, byte, mask) in masked(value, first, last) {
+        let found = if bit { byte } else { !byte & mask };
+        if found != 0 {
+            return (i * 8) as i64 + found.leading_zeros() as i64;
+        }
+    }
+    match bit || range.is_some_and(|r| r.end.is_some()) {
+        true => -1,
+        false => last as i64 + 1,
+    }
+}
+
+/// Combines `values` with `op`, where shorter values are padded with zeros, i.e. `BITOP`.
+pub fn combine(op: BitOp, values: &[Bytes]) -> Vec<u8> {
+    let len = values.iter().map(|v| v.len()).max().unwrap_or(0);
+    let byte = |value: &Bytes, i| value.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| {
+            let mut bytes = values.iter().map(|v| byte(v, i));
+            let first = bytes.next().unwrap_or(0);
+            match op {
+                BitOp::And => bytes.fold(first, |a, b| a & b),
+                BitOp::Or => bytes.fold(first, |a, b| a | b),
+                BitOp::Xor => bytes.fold(first, |a, b| a ^ b),
+                BitOp::Not => !first,
+            }
+        })
+        .collect()
+}
+
+/// Applies a subcommand of `BITFIELD` to `value`, returning what it replies with, or `None` if it
+/// failed because of an overflow.
+pub fn field(value: &mut Vec<u8>, op: BitFieldOp) -> Option<i64> {
+    match op {
+        BitFieldOp::Get(ty, offset) => Some(read(value, ty, offset)),
+        BitFieldOp::Set(ty, offset, new, overflow) => {
+            let old = read(value, ty, offset);
+            // like Redis, unsigned integers are set to the value's two's complement
+            let new = match ty.signed {
+                true => new as i128,
+                false => new as u64 as i128,
+            };
+            write(value, ty, offset, fit(new, ty, overflow)?);
+            Some(old)
+        }
+        BitFieldOp::IncrBy(ty, offset, increment, overflow) => {
+            let new = fit(
+                read(value, ty, offset) as i128 + increment as i128,
+                ty,
+                overflow,
+            )?;
+            write(value, ty, offset, new);
+            Some(new)
+        }
+    }
+}
+
+/// Reads the integer of type `ty` at `offset` of `value`, most significant bit first.
+fn read(value: &[u8], ty: BitFieldType, offset: u64) -> i64 {
+    let bits = (offset..offset + ty.bits as u64).fold(0u64, |n, i| n << 1 | get(value, i) as u64);
+    match ty.signed && bits >> (ty.bits - 1) & 1 == 1 {
+        // sign extended
+        true => (bits | u64::MAX.checked_shl(ty.bits).unwrap_or(0)) as i64,
+        false => bits as i64,
+    }
+}
+
+/// Writes the least significant bits of `n` as the integer of type `ty` at `offset` of `value`.
+fn write(value: &mut Vec<u8>, ty: BitFieldType, offset: u64, n: i64) {
+    for i in 0..ty.bits {
+        set(value, offset + i as u64, (n >> (ty.bits - 1 - i)) & 1 == 1);
+    }
+}
+
+/// Returns `n` if it fits in `ty`, or what `overflow` makes of it otherwise.
+fn fit(n: i128, ty: BitFieldType, overflow: Overflow) -> Option<i64> {
+    let (min, max) = match ty.signed {
+        true => (-(1i128 << (ty.bits - 1)), (1i128 << (ty.bits - 1)) - 1),
+        false => (0, (1i128 << ty.bits) - 1),
+    };
+    if (min..=max).contains(&n) {
+        return Some(n as i64);
+    }
+    match overflow {
+        Overflow::Wrap => Some(((n - min).rem_euclid(1i128 << ty.bits) + min) as i64),
+        Overflow::Sat => Some(n.clamp(min, max) as i64),
+        Overflow::Fail => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_positions_and_counts() {
+        let value = [0xff, 0xf0, 0x00];
+        let range = |start, end, bits| Some(BitRange { start, end, bits });
+        assert_eq!(12, count(&value, None));
+        assert_eq!(4, count(&value, range(1, Some(1), false)));
+        assert_eq!(5, count(&value, range(5, Some(9), true)));
+        assert_eq!(0, count(&value, range(2, Some(1), false)));
+
+        assert_eq!(12, position(&value, false, None));
+        assert_eq!(8, position(&value, true, range(1, None, false)));
+        assert_eq!(-1, position(&value, true, range(-1, None, false)));
+        assert_eq!(-1, position(&[0xff], false, range(0, Some(0), false)));
+        assert_eq!(8, position(&[0xff], false, None), "padded with zeros");
+
+        let mut value = value.to_vec();
+        assert!(!set(&mut value, 12, true));
+        assert!(set(&mut value, 0, false));
+        assert!(!get(&value, 0) && get(&value, 12) && !get(&value, 1000));
+        set(&mut value, 39, true);
+        assert_eq!(vec![0x7f, 0xf8, 0, 0, 1], value);
+
+        let values = [
+            Bytes::from_static(&[0xf0, 0x0f]),
+            Bytes::from_static(&[0x3c]),
+        ];
+        assert_eq!(vec![0x30, 0x00], combine(BitOp::And, &values));
+        assert_eq!(vec![0xfc, 0x0f], combine(BitOp::Or, &values));
+        assert_eq!(vec![0xcc, 0x0f], combine(BitOp::Xor, &values));
+        assert_eq!(vec![0x0f, 0xf0], combine(BitOp::Not, &values[..1]));
+    }
+
+    #[test]
+    fn bitfield_overflows() {
+        let i8 = BitFieldType {
+            signed: true,
+            bits: 8,
+        };
+        let u2 = BitFieldType {
+            signed: false,
+            bits: 2,
+        };
+        let mut value = Vec::new();
+        let mut apply = |op| field(&mut value, op);
+        assert_eq!(Some(0), apply(BitFieldOp::Set(i8, 4, -1, Overflow::Wrap)));
+        assert_eq!(Some(-1), apply(BitFieldOp::Get(i8, 4)));
+        assert_eq!(Some(3), apply(BitFieldOp::Get(u2, 10)));
+        assert_eq!(
+            Some(127),
+            apply(BitFieldOp::IncrBy(i8, 4, 200, Overflow::Sat))
+        );
+        assert_eq!(
+            Some(-128),
+            apply(BitFieldOp::IncrBy(i8, 4, 1, Overflow::Wrap))
+        );
+        assert_eq!(None, apply(BitFieldOp::IncrBy(i8, 4, -1, Overflow::Fail)));
+        assert_eq!(Some(1), apply(BitFieldOp::IncrBy(u2, 0, 5, Overflow::Wrap)));
+        assert_eq!(Some(1), apply(BitFieldOp::Set(u2, 0, -1, Overflow::Sat)));
+        assert_eq!(Some(3), apply(BitFieldOp::Get(u2, 0)));
+        assert_eq!(vec![0xc8, 0x00], value);
+    }
+}
@@ -0,0 +1,415 @@
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+use super::{info::REDIS_VERSION, Client, State};
+use crate::{
+    command::{ClientCommand, ClientFilter, ClientType},
+    frame::Frame,
+    reply::{ErrorCode, ErrorReply},
+};
+
+/// What is known about a connected client, as listed by `CLIENT LIST`.
+pub struct ClientInfo {
+    /// The address the client connected from, if it connected over the network.
+    addr: Option<SocketAddr>,
+    name: Option<Bytes>,
+    created: Instant,
+    /// When the client last sent a command.
+    active: Instant,
+    /// The name of the last command the client sent, lowercased.
+    command: Bytes,
+    /// How many channels and patterns the client subscribes to.
+    subscriptions: (usize, usize),
+    /// How many commands the client queued, if it has an open transaction.
+    queued: Option<usize>,
+    /// Notified to close the client's connection.
+    kill: Arc<Notify>,
+}
+
+impl ClientInfo {
+    pub fn name(&self) -> Option<Bytes> {
+        self.name.clone()
+    }
+
+    pub fn kill(&self) -> Arc<Notify> {
+        self.kill.clone()
+    }
+
+    pub fn new() -> Self {
+        let now = Instant::now();
+        ClientInfo {
+            addr: None,
+            name: None,
+            created: now,
+            active: now,
+            command: Bytes::from_static(b"NULL"),
+            subscriptions: (0, 0),
+            queued: None,
+            kill: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl State {
+    /// Returns the address `client` connected from, if it connected over the network.
+    pub(super) fn addr(&self, client: &Client) -> Option<SocketAddr> {
+        self.clients.get(&client.id).and_then(|info| info.addr)
+    }
+
+    /// Records the state of `client` after applying its commands, for it to be listed.
+    pub(super) fn update_client(&mut self, client: &Client) {
+        if let Some(info) = self.clients.get_mut(&client.id) {
+            info.subscriptions = (client.channels.len(), client.patterns.len());
+            info.queued = client.queued();
+        }
+    }
+
+    /// Applies a subcommand of `CLIENT` sent by `client`.
+    pub(super) fn client(&mut self, client: &Client, command: ClientCommand) -> Frame {
+        match command {
+            ClientCommand::Id => Frame::Integer(client.id as i64),
+            ClientCommand::GetName => {
+                Frame::Bulk(self.clients.get(&client.id).and_then(|i| i.name.clone()))
+            }
+            ClientCommand::SetName(name) => match self.set_name(client, name) {
+                Ok(()) => Frame::Bulk(Some("OK".into())),
+                Err(e) => e.into(),
+            },
+            ClientCommand::List => {
+                let mut ids: Vec<&u64> = self.clients.keys().collect();
+                ids.sort_unstable();
+                let mut list = String::new();
+                for id in ids {
+                    self.list_client(&mut list, *id);
+                }
+                Frame::Bulk(Some(list.into()))
+            }
+            ClientCommand::KillAddr(addr) => {
+                let filter = ClientFilter {
+                    addr: Some(addr),
+                    ..ClientFilter::default()
+                };
+                match self.kill(client, &filter) {
+                    0 => ErrorReply::err("No such client").into(),
+                    _ => Frame::Bulk(Some("OK".into())),
+                }
+            }
+            ClientCommand::Kill(filter) => Frame::Integer(self.kill(client, &filter) as i64),
+        }
+    }
+
+    /// Names `client`, or clears its name if `name` is empty.
+    fn set_name(&mut self, client: &Client, name: Bytes) -> Result<(), ErrorReply> {
+        if name.iter().any(|&b| !(b'!'..=b'~').contains(&b)) {
+            let msg = "Client names cannot contain spaces, newlines or special characters.";
+            return Err(ErrorReply::err(msg));
+        }
+        if let Some(info) = self.clients.get_mut(&client.id) {
+            info.name = (!name.is_empty()).then_some(name);
+        }
+        Ok(())
+    }
+
+    /// Applies `HELLO` sent by `client`, authenticating it if `auth` is given and switching it to
+    /// `protover` if given, then replying with the server's details.
+    pub(super) fn hello(
+        &mut self,
+        client: &mut Client,
+        protover: Option<i64>,
+        auth: Option<(Bytes, Bytes)>,
+        name: Option<Bytes>,
+    ) -> Frame {
+        if protover.is_some_and(|v| !(2..=3).contains(&v)) {
+            return ErrorReply::new(ErrorCode::NoProto, "unsupported protocol version").into();
+        }
+        match auth {
+            Some((user, pass)) => {
+                if let Err(e) = self.authenticate(client, user, &pass) {
+                    return e.into();
+                }
+            }
+            None if !client.authenticated => {
+                let msg = "HELLO must be called with the client already authenticated, otherwise \
+                           the HELLO <proto> AUTH <user> <pass> option can be used to \
+                           authenticate the client and select the RESP protocol version at the \
+                           same time";
+                return ErrorReply::new(ErrorCode::NoAuth, msg).into();
+            }
+            None => {}
+        }
+        if let Some(name) = name {
+            if let Err(e) = self.set_name(client, name) {
+                return e.into();
+            }
+        }
+        if let Some(protover) = protover {
+            client.resp3 = protover == 3;
+        }
+        let role = if self.replication.is_replica() {
+            "replica"
+        } else {
+            "master"
+        };
+        Frame::Map(vec![
+            Frame::Bulk(Some("server".into())),
+            Frame::Bulk(Some("redis".into())),
+            Frame::Bulk(Some("version".into())),
+            Frame::Bulk(Some(REDIS_VERSION.into())),
+            Frame::Bulk(Some("proto".into())),
+            Frame::Integer(if client.resp3 { 3 } else { 2 }),
+            Frame::Bulk(Some("id".into())),
+            Frame::Integer(client.id as i64),
+            Frame::Bulk(Some("mode".into())),
+            Frame::Bulk(Some("standalone".into())),
+            Frame::Bulk(Some("role".into())),
+            Frame::Bulk(Some(role.into())),
+            Frame::Bulk(Some("modules".into())),
+            Frame::Array(Some(Vec::new())),
+        ])
+    }
+
+    /// Returns `client` to the state it was in when it connected, other than its name: its
+    /// transaction is discarded, it stops watching keys, subscribing to channels, monitoring and
+    /// speaking RESP3, and it must authenticate again if a password is required. Every database is
+    /// db 0, so there's nothing more to reset.
+    pub(super) fn reset(&mut self, client: &mut Client) -> Frame {
+        client.transaction = None;
+        client.unwatch(&mut self.watched);
+        client.unsubscribe_all(&mut self.pubsub);
+        client.monitoring = None;
+        client.resp3 = false;
+        client.authenticated = self.authenticated_by_default();
+        client.user = "default".into();
+        Frame::String("RESET".into())
+    }
+
+    /// Closes the connection of every client matching `filter`, returning how many there were.
+    fn kill(&self, client: &Client, filter: &ClientFilter) -> usize {
+        let mut killed = 0;
+        for (&id, info) in &self.clients {
+            let addr = info.addr.map(|a| a.to_string());
+            let matches = filter.id.is_none_or(|i| i == id)
+                && (filter.addr.as_ref())
+                    .is_none_or(|a| addr.as_deref().map(str::as_bytes) == Some(&a[..]))
+                && filter.kind.is_none_or(|kind| self.kind(id, info) == kind)
+                && !(filter.skip_me && id == client.id);
+            if matches {
+                info.kill.notify_one();
+                killed += 1;
+            }
+        }
+        killed
+    }
+
+    fn kind(&self, id: u64, info: &ClientInfo) -> ClientType {
+        if self.replication.is_replica_client(id) {
+            ClientType::Replica
+        } else if info.subscriptions != (0, 0) {
+            ClientType::PubSub
+        } else {
+            ClientType::Normal
+        }
+    }
+
+    /// Appends the line of `CLIENT LIST` describing the client `id`.
+    fn list_client(&self, list: &mut String, id: u64) {
+        let info = &self.clients[&id];
+        let addr = info.addr.map(|a| a.to_string()).unwrap_or_default();
+        let name = info.name.as_deref().unwrap_or_default();
+        let mut flags = String::new();
+        match self.kind(id, info) {
+            ClientType::Replica => flags.push('S'),
+            ClientType::PubSub => flags.push('P'),
+            _ => (),
+        }
+        if info.queued.is_some() {
+            flags.push('x');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        writeln!(
+            list,
+            "id={} addr={} name={} age={} idle={} flags={} db=0 sub={} psub={} multi={} cmd={}",
+            id,
+            addr,
+            String::from_utf8_lossy(name),
+            info.created.elapsed().as_secs(),
+            info.active.elapsed().as_secs(),
+            flags,
+            info.subscriptions.0,
+            info.subscriptions.1,
+            info.queued.map_or(-1, |n| n as i64),
+            String::from_utf8_lossy(&info.command),
+        )
+        .unwrap();
+    }
+}
+
+impl Client {
+    /// Records that the client sent the command `name`.
+    pub fn record(&self, name: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(info) = state.clients.get_mut(&self.id) {
+            info.active = Instant::now();
+            info.command = Bytes::from(name.to_ascii_lowercase());
+        }
+    }
+
+    /// Records the address the client connected from.
+    pub fn set_addr(&self, addr: SocketAddr) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(info) = state.clients.get_mut(&self.id) {
+            info.addr = Some(addr);
+        }
+    }
+
+    /// Returns what is notified once the client is killed, i.e. its connection must be closed.
+    pub fn killed(&self) -> Arc<Notify> {
+        let state = self.state.lock().unwrap();
+        state.clients[&self.id].kill()
+    }
+
+    /// Returns when the client is disconnected unless it sends a command, if `timeout` is set.
+    /// Like Redis, replicas and subscribers are never disconnected for being idle.
+    pub fn idle_deadline(&self) -> Option<Instant> {
+        let state = self.state.lock().unwrap();
+        let info = state.clients.get(&self.id)?;
+        let exempt = state.replication.is_replica_client(self.id) || info.subscriptions != (0, 0);
+        match state.config.timeout {
+            0 => None,
+            _ if exempt => None,
+            timeout => Some(info.active + Duration::from_secs(timeout)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Command, db::Db};
+
+    #[tokio::test]
+    async fn list_and_kill_clients() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let (mut subscriber, _) = db.client();
+        client.set_addr("127.0.0.1:1234".parse().unwrap());
+        let commands = vec![
+            Command::Client(ClientCommand::SetName("first".into())),
+            Command::Client(ClientCommand::GetName),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert_eq!(Frame::Bulk(Some("first".into())), replies[1]);
+        let subscribe = Command::Subscribe(vec!["channel".into()], false);
+        db.apply_batch(&mut subscriber, vec![subscribe]).await;
+
+        client.record(b"CLIENT");
+        let list = Command::Client(ClientCommand::List);
+        let list = match db.apply_batch(&mut client, vec![list]).await.pop() {
+            Some(Frame::Bulk(Some(list))) => list,
+            reply => panic!("expected a bulk string, got {:?}", reply),
+        };
+        let list = std::str::from_utf8(&list).unwrap();
+        let lines: Vec<&str> = list.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("id=1 addr=127.0.0.1:1234 name=first "));
+        assert!(lines[0].ends_with(" flags=N db=0 sub=0 psub=0 multi=-1 cmd=client"));
+        assert!(lines[1].contains(" flags=P db=0 sub=1 psub=0 "));
+
+        // only the subscriber is killed, and only once its connection notices
+        let killed = subscriber.killed();
+        let filter = ClientFilter {
+            kind: Some(ClientType::PubSub),
+            skip_me: true,
+            ..ClientFilter::default()
+        };
+        let kill = Command::Client(ClientCommand::Kill(filter));
+        let replies = db.apply_batch(&mut client, vec![kill]).await;
+        assert_eq!(Frame::Integer(1), replies[0]);
+        killed.notified().await;
+        let kill = Command::Client(ClientCommand::KillAddr("127.0.0.1:1".into()));
+        let replies = db.apply_batch(&mut client, vec![kill]).await;
+        assert!(matches!(&replies[0], Frame::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn hello_switches_protocols() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![
+            Command::Hello(
+                Some(3),
+                Some(("default".into(), "".into())),
+                Some("app".into()),
+            ),
+            Command::Hello(Some(4), None, None),
+            Command::Hello(Some(2), Some(("someone".into(), "secret".into())), None),
+            Command::Client(ClientCommand::GetName),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        let details = match &replies[0] {
+            Frame::Map(details) => details,
+            reply => panic!("expected a map, got {:?}", reply),
+        };
+        assert_eq!(Frame::Integer(3), details[5]);
+        assert_eq!(Frame::Bulk(Some("master".into())), details[11]);
+        assert!(client.resp3, "failed commands don't change the protocol");
+        assert_eq!(
+            Frame::Error("NOPROTO unsupported protocol version".into()),
+            replies[1]
+        );
+        assert!(matches!(&replies[2], Frame::Error(e) if e.starts_with(b"WRONGPASS")));
+        assert_eq!(Frame::Bulk(Some("app".into())), replies[3]);
+    }
+
+    #[tokio::test]
+    async fn reset_clears_the_connection_state() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![
+            Command::Watch(vec!["key".into()]),
+            Command::Multi,
+            Command::Reset,
+            Command::Subscribe(vec!["channel".into()], false),
+            Command::Reset,
+            Command::Exec,
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert_eq!(Frame::String("RESET".into()), replies[2]);
+        assert_eq!(Frame::String("RESET".into()), replies[4]);
+        assert!(
+            matches!(&replies[5], Frame::Error(_)),
+            "no transaction is open"
+        );
+        assert!(!client.is_subscribed() && client.watching.is_empty());
+        let state = db.state.lock().unwrap();
+        assert!(state.watched.is_empty());
+        assert_eq!(0, state.pubsub.subscribers_to(&"channel".into()));
+    }
+
+    #[tokio::test]
+    async fn only_idle_normal_clients_time_out() {
+        let db = Db::new();
+        let (client, _) = db.client();
+        let (mut subscriber, _) = db.client();
+        let subscribe = Command::Subscribe(vec!["channel".into()], false);
+        db.apply_batch(&mut subscriber, vec![subscribe]).await;
+        assert_eq!(None, client.idle_deadline());
+
+        db.state.lock().unwrap().config.timeout = 10;
+        let active = db.state.lock().unwrap().clients[&client.id].active;
+        assert_eq!(
+            Some(active + Duration::from_secs(10)),
+            client.idle_deadline()
+        );
+        assert_eq!(None, subscriber.idle_deadline());
+    }
+}
@@ -0,0 +1,147 @@
+use std::{thread, time::Instant};
+
+use bytes::Bytes;
+
+use super::{rdb, State};
+use crate::{command::DebugCommand, frame::Frame, reply::ErrorReply};
+
+impl State {
+    /// Applies a subcommand of `DEBUG`.
+    pub(super) fn apply_debug(&mut self, command: DebugCommand, now: i64) -> Result<Frame, Frame> {
+        match command {
+            DebugCommand::Object(key) => {
+                let limits = self.config.encoding_limits;
+                let Some(entry) = self.peek(&key, now) else {
+                    return Err(ErrorReply::err("no such key").into());
+                };
+                let value = &entry.value;
+                let description = format!(
+                    "Value at:{:p} refcount:{} encoding:{} serializedlength:{} lru:{} \
+                     lru_seconds_idle:{}",
+                    value,
+                    value.refcount(),
+                    value.encoding(&limits),
+                    value.serialized_len(),
+                    entry.access.clock(),
+                    entry.access.idle_time(now),
+                );
+                return Ok(Frame::String(description.into()));
+            }
+            // like Redis, every client waits, as the dataset stays locked
+            DebugCommand::Sleep(duration) => thread::sleep(duration),
+            DebugCommand::Reload => {
+                let snapshot = self.snapshot(now);
+                rdb::write(&self.rdb_path(), &snapshot)
+                    .map_err(|e| Frame::from(ErrorReply::err(e.to_string())))?;
+                self.dirty = 0;
+                self.last_save = Instant::now();
+                let keys: Vec<Bytes> = self.keystore.keys().cloned().collect();
+                for key in &keys {
+                    self.remove(key);
+                }
+                self.load(&snapshot, now)
+                    .map_err(|e| Frame::from(ErrorReply::err(e.to_string())))?;
+            }
+            DebugCommand::SetActiveExpire(enabled) => self.active_expire = enabled,
+            DebugCommand::ChangeReplId => self.replication.change_id(),
+        }
+        Ok(Frame::Bulk(Some("OK".into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Bound::Unbounded;
+
+    use crate::{
+        command::{Command, StreamId, XAddId, XGroupCommand, XReadOptions},
+        db::Db,
+    };
+
+    #[tokio::test]
+    async fn reload_keeps_the_dataset_and_its_encodings() {
+        let db = Db::new();
+        {
+            let mut state = db.state.lock().unwrap();
+            state.config.dir = std::env::temp_dir().display().to_string();
+            state.config.dbfilename = format!("reload-{}.rdb", std::process::id());
+        }
+        let (mut client, _) = db.client();
+        let object = |k: &'static str| Command::Debug(DebugCommand::Object(k.into()));
+        let commands = vec![
+            Command::SAdd("set".into(), vec!["1".into(), "2".into()]),
+            Command::XAdd(
+                "stream".into(),
+                XAddId::Auto,
+                vec![("f".into(), "v".into())],
+            ),
+            Command::Debug(DebugCommand::Reload),
+            object("set"),
+            Command::Exists(vec!["set".into(), "stream".into()]),
+            object("missing"),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert_eq!(Frame::Bulk(Some("OK".into())), replies[2]);
+        let description = match &replies[3] {
+            Frame::String(description) => String::from_utf8_lossy(description),
+            reply => panic!("expected a description, got {:?}", reply),
+        };
+        assert!(description.contains(" encoding:intset serializedlength:5 "));
+        assert_eq!(Frame::Integer(2), replies[4]);
+        assert!(matches!(replies[5], Frame::Error(_)));
+
+        let state = db.state.lock().unwrap();
+        let _ = std::fs::remove_file(state.rdb_path());
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_streams_and_their_groups() {
+        let db = Db::new();
+        {
+            let mut state = db.state.lock().unwrap();
+            state.config.dir = std::env::temp_dir().display().to_string();
+            state.config.dbfilename = format!("reload-stream-{}.rdb", std::process::id());
+        }
+        let (mut client, _) = db.client();
+        let (stream, group) = (Bytes::from("stream"), Bytes::from("g"));
+        let inspect = || {
+            vec![
+                Command::XRange(stream.clone(), Unbounded, Unbounded, None, false),
+                Command::XPending(stream.clone(), group.clone(), None),
+            ]
+        };
+        let mut commands = vec![
+            Command::XAdd(stream.clone(), XAddId::Auto, vec![("f".into(), "1".into())]),
+            Command::XAdd(stream.clone(), XAddId::Auto, vec![("g".into(), "2".into())]),
+            Command::XGroup(XGroupCommand::Create(
+                stream.clone(),
+                group.clone(),
+                Some(StreamId::MIN),
+                false,
+            )),
+            Command::XReadGroup(
+                group.clone(),
+                "consumer".into(),
+                vec![stream.clone()],
+                vec![None],
+                XReadOptions {
+                    count: Some(1),
+                    ..Default::default()
+                },
+            ),
+        ];
+        commands.extend(inspect());
+        commands.push(Command::Debug(DebugCommand::Reload));
+        commands.extend(inspect());
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert_eq!(Frame::Bulk(Some("OK".into())), replies[6]);
+        assert_eq!(replies[4..6], replies[7..9]);
+        assert!(
+            matches!(&replies[5], Frame::Array(Some(summary)) if summary[0] == Frame::Integer(1))
+        );
+
+        let state = db.state.lock().unwrap();
+        let _ = std::fs::remove_file(state.rdb_path());
+    }
+}
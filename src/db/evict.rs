@@ -0,0 +1,203 @@
+use bytes::Bytes;
+
+use super::{notify::Class, State};
+use crate::command::Command;
+
+/// How many candidates to evict are kept across evictions, like Redis.
+const POOL_SIZE: usize = 16;
+
+/// Which keys are evicted once `maxmemory` is reached, i.e. Redis's `maxmemory-policy`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvictionPolicy {
+    /// No key is evicted, and commands that may grow the dataset are rejected instead.
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    /// Keys with an expiry are evicted, soonest to expire first.
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn parse(policy: &str) -> Option<Self> {
+        match policy.to_ascii_lowercase().as_str() {
+            "noeviction" => Some(EvictionPolicy::NoEviction),
+            "allkeys-lru" => Some(EvictionPolicy::AllKeysLru),
+            "volatile-lru" => Some(EvictionPolicy::VolatileLru),
+            "allkeys-lfu" => Some(EvictionPolicy::AllKeysLfu),
+            "volatile-lfu" => Some(EvictionPolicy::VolatileLfu),
+            "allkeys-random" => Some(EvictionPolicy::AllKeysRandom),
+            "volatile-random" => Some(EvictionPolicy::VolatileRandom),
+            "volatile-ttl" => Some(EvictionPolicy::VolatileTtl),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether only keys with an expiry may be evicted.
+    fn volatile(self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        )
+    }
+}
+
+impl State {
+    /// Evicts keys following `maxmemory-policy` until the memory used is within `maxmemory`,
+    /// returning whether it is.
+    pub(super) fn evict(&mut self, now: i64) -> bool {
+        let maxmemory = self.config.maxmemory as usize;
+        if maxmemory == 0 {
+            return true;
+        }
+        while self.memory().total() > maxmemory {
+            let Some(key) = self.pick_eviction(now) else {
+                return false;
+            };
+            self.remove(&key);
+            self.stats.evicted += 1;
+            self.notify(Class::Evicted, "evicted", &key);
+            // replicas don't evict keys themselves, so they're sent what was evicted
+            self.propagate(Command::Del(vec![key]).to_frame(), now);
+        }
+        true
+    }
+
+    /// Picks the key to evict next, if any may be.
+    ///
+    /// Like Redis, LRU, LFU and TTL are approximated by sampling `maxmemory-samples` keys at random
+    /// each time, and keeping the best candidates across evictions in a pool.
+    fn pick_eviction(&mut self, now: i64) -> Option<Bytes> {
+        let policy = self.config.maxmemory_policy;
+        let samples = match policy {
+            EvictionPolicy::NoEviction => return None,
+            EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom => 1,
+            _ => self.config.maxmemory_samples.max(1),
+        };
+        let randoms: Vec<f64> = (0..samples).map(|_| self.random()).collect();
+        let keys = match policy.volatile() {
+            true => &self.volatile,
+            false => &self.sampler,
+        };
+        if samples == 1 {
+            return keys.pick(randoms[0]).cloned();
+        }
+        let pool = &mut self.eviction_pool;
+        for random in randoms {
+            let key = keys.pick(random)?;
+            if pool.iter().any(|(_, k)| k == key) {
+                continue;
+            }
+            let entry = &self.keystore[key];
+            // the lower the rank, the better the key is to evict
+            let rank = match policy {
+                EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+                    entry.access.frequency(now).into()
+                }
+                EvictionPolicy::VolatileTtl => entry.expires_at.unwrap_or(i64::MAX),
+                // roughly when the key was last accessed, to compare with keys pooled earlier
+                _ => now - entry.access.idle_millis(now),
+            };
+            // the pool is sorted from the worst to the best candidate
+            let position = pool.partition_point(|(r, _)| *r > rank);
+            pool.insert(position, (rank, key.clone()));
+            if pool.len() > POOL_SIZE {
+                pool.remove(0);
+            }
+        }
+        while let Some((_, key)) = self.eviction_pool.pop() {
+            // pooled keys may have been removed since, or no longer expire
+            let entry = self.keystore.get(&key);
+            if entry.is_some_and(|e| !policy.volatile() || e.expires_at.is_some()) {
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::{ExpireOptions, Expiry, SetOptions},
+        db::{object::Access, Db, Entry, Value},
+        frame::Frame,
+    };
+
+    #[tokio::test]
+    async fn evicts_keys_or_rejects_writes_past_maxmemory() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let set = |k: &str| {
+            Command::Set(
+                k.to_string().into(),
+                "v".repeat(1000).into(),
+                SetOptions::default(),
+            )
+        };
+        db.apply_batch(&mut client, vec![set("a"), set("b")]).await;
+        {
+            let mut state = db.state.lock().unwrap();
+            state.config.maxmemory = state.memory().total() as u64 + 500;
+        }
+
+        let replies = db.apply_batch(&mut client, vec![set("c"), set("d")]).await;
+        let oom = "OOM command not allowed when used memory > 'maxmemory'.";
+        assert_eq!(Frame::Error(oom.into()), replies[1]);
+
+        db.state.lock().unwrap().config.maxmemory_policy = EvictionPolicy::VolatileLru;
+        let replies = db.apply_batch(&mut client, vec![set("d")]).await;
+        assert_eq!(Frame::Error(oom.into()), replies[0], "no key has an expiry");
+
+        let expire = Command::Expire("a".into(), Expiry::In(60_000), ExpireOptions::default());
+        db.apply_batch(&mut client, vec![expire, set("d")]).await;
+        let state = db.state.lock().unwrap();
+        assert!(!state.keystore.contains_key(&Bytes::from("a")));
+        assert!(state.keystore.contains_key(&Bytes::from("d")));
+        assert_eq!(1, state.stats.evicted);
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_accessed_sampled_key() {
+        let db = Db::new();
+        let mut state = db.state.lock().unwrap();
+        state.config.maxmemory_policy = EvictionPolicy::AllKeysLru;
+        state.config.maxmemory_samples = 64;
+        for (key, accessed_at) in [("b", 5000), ("a", 1000), ("c", 9000)] {
+            let entry = Entry {
+                value: Value::String("v".into()),
+                expires_at: None,
+                access: Access::new(accessed_at),
+                size: 0,
+            };
+            state.insert(key.into(), entry);
+        }
+        let now = 10_000;
+        for expected in ["a", "b", "c"] {
+            let key = state.pick_eviction(now);
+            assert_eq!(Some(Bytes::from(expected)), key);
+            state.remove(&key.unwrap());
+        }
+        assert_eq!(None, state.pick_eviction(now));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::EncodingLimits;
+
+/// A hash, which like Redis is encoded as a listpack, i.e. a list of its fields and their values
+/// that is searched linearly, while it is small, and as a hash table once it grows past the limits
+/// of `hash-max-listpack-entries` and `hash-max-listpack-value`.
+pub enum Hash {
+    Listpack(Vec<(Bytes, Bytes)>),
+    Table(HashMap<Bytes, Bytes>),
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Hash::Listpack(Vec::new())
+    }
+}
+
+impl Hash {
+    /// Builds a hash of `pairs`, encoded compactly if they fit.
+    pub fn collect(
+        pairs: impl IntoIterator<Item = (Bytes, Bytes)>,
+        limits: &EncodingLimits,
+    ) -> Self {
+        let mut hash = Hash::default();
+        for (field, value) in pairs {
+            hash.insert(field, value, limits);
+        }
+        hash
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Hash::Listpack(pairs) => pairs.len(),
+            Hash::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value of `field`, if it is in the hash.
+    pub fn get(&self, field: &Bytes) -> Option<&Bytes> {
+        match self {
+            Hash::Listpack(pairs) => pairs.iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            Hash::Table(table) => table.get(field),
+        }
+    }
+
+    pub fn contains_key(&self, field: &Bytes) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets `field` to `value`, converting the hash to a hash table if it no longer fits in a
+    /// listpack, and returning the field's previous value.
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: &EncodingLimits) -> Option<Bytes> {
+        if let Hash::Listpack(pairs) = self {
+            if let Some((_, v)) = pairs.iter_mut().find(|(f, _)| *f == field) {
+                if value.len() <= limits.hash_max_listpack_value {
+                    return Some(std::mem::replace(v, value));
+                }
+            } else if pairs.len() < limits.hash_max_listpack_entries
+                && field.len().max(value.len()) <= limits.hash_max_listpack_value
+            {
+                pairs.push((field, value));
+                return None;
+            }
+            *self = Hash::Table(std::mem::take(pairs).into_iter().collect());
+        }
+        match self {
+            Hash::Listpack(_) => unreachable!("the hash was converted to a hash table"),
+            Hash::Table(table) => table.insert(field, value),
+        }
+    }
+
+    /// Removes `field`, returning its value.
+    pub fn remove(&mut self, field: &Bytes) -> Option<Bytes> {
+        match self {
+            Hash::Listpack(pairs) => {
+                let i = pairs.iter().position(|(f, _)| f == field)?;
+                Some(pairs.remove(i).1)
+            }
+            Hash::Table(table) => table.remove(field),
+        }
+    }
+
+    /// Returns the fields and their values, in no particular order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + '_> {
+        match self {
+            Hash::Listpack(pairs) => Box::new(pairs.iter().map(|(f, v)| (f, v))),
+            Hash::Table(table) => Box::new(table.iter()),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Hash::Listpack(_) => "listpack",
+            Hash::Table(_) => "hashtable",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_a_hash_table_past_the_limits() {
+        let limits = EncodingLimits {
+            hash_max_listpack_entries: 2,
+            hash_max_listpack_value: 4,
+            ..EncodingLimits::default()
+        };
+        let mut hash = Hash::collect(
+            [("a".into(), "1".into()), ("b".into(), "2".into())],
+            &limits,
+        );
+        assert_eq!(
+            Some(Bytes::from("1")),
+            hash.insert("a".into(), "3".into(), &limits)
+        );
+        assert_eq!("listpack", hash.encoding());
+        hash.insert("c".into(), "4".into(), &limits);
+        assert_eq!("hashtable", hash.encoding());
+        assert_eq!(Some(&Bytes::from("3")), hash.get(&"a".into()));
+
+        let mut hash = Hash::collect([("a".into(), "1".into())], &limits);
+        hash.insert("a".into(), "12345".into(), &limits);
+        assert_eq!("hashtable", hash.encoding());
+        assert_eq!(Some(Bytes::from("12345")), hash.remove(&"a".into()));
+        assert!(hash.is_empty());
+    }
+}
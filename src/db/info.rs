@@ -0,0 +1,230 @@
+use std::{fmt::Write, sync::Arc, time::Instant};
+
+use bytes::Bytes;
+
+use super::State;
+use crate::frame::Frame;
+
+/// The version of Redis reported by `INFO`, whose behavior this server follows.
+pub(super) const REDIS_VERSION: &str = "7.2.0";
+/// The sections of `INFO`, in the order they are rendered, all of which are rendered by default.
+const SECTIONS: [&str; 6] = [
+    "server",
+    "clients",
+    "memory",
+    "stats",
+    "replication",
+    "keyspace",
+];
+
+/// The counters reported by `INFO`.
+pub struct Stats {
+    started: Instant,
+    /// How many clients ever connected, i.e. `total_connections_received`.
+    pub connections: u64,
+    pub commands: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// How many clients were refused because `maxclients` were connected already.
+    pub rejected: u64,
+    /// How many keys were removed because they expired.
+    pub expired: u64,
+    /// How many keys were removed to free memory.
+    pub evicted: u64,
+    /// The most memory ever used, as accounted for by `State::memory`.
+    pub peak_memory: usize,
+    /// Whether key lookups count as hits or misses, which like Redis is only while applying a
+    /// command that doesn't write.
+    pub counting: bool,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            started: Instant::now(),
+            connections: 0,
+            rejected: 0,
+            commands: 0,
+            hits: 0,
+            misses: 0,
+            expired: 0,
+            evicted: 0,
+            peak_memory: 0,
+            counting: false,
+        }
+    }
+}
+
+impl Stats {
+    /// Counts a key lookup as a hit or a miss, if lookups are counted.
+    pub fn lookup(&mut self, hit: bool) {
+        match hit {
+            _ if !self.counting => (),
+            true => self.hits += 1,
+            false => self.misses += 1,
+        }
+    }
+}
+
+impl State {
+    /// Renders the given sections of `INFO`, or the default ones if none are given.
+    pub(super) fn info(&mut self, sections: &[Bytes], now: i64) -> Frame {
+        let all = sections.is_empty()
+            || sections.iter().any(|s| {
+                [&b"all"[..], b"default", b"everything"]
+                    .iter()
+                    .any(|name| s.eq_ignore_ascii_case(name))
+            });
+        let mut info = String::new();
+        for section in SECTIONS {
+            if !all
+                && !sections
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(section.as_bytes()))
+            {
+                continue;
+            }
+            if !info.is_empty() {
+                info.push_str("\r\n");
+            }
+            let mut title = section.to_string();
+            title[..1].make_ascii_uppercase();
+            write!(info, "# {}\r\n", title).unwrap();
+            match section {
+                "server" => self.server_info(&mut info),
+                "clients" => self.clients_info(&mut info),
+                "memory" => self.memory_info(&mut info),
+                "stats" => self.stats_info(&mut info),
+                "replication" => self.replication.info(&mut info),
+                _ => self.keyspace_info(&mut info, now),
+            }
+        }
+        Frame::Bulk(Some(info.into()))
+    }
+
+    fn server_info(&self, info: &mut String) {
+        let uptime = self.stats.started.elapsed().as_secs();
+        write!(info, "redis_version:{}\r\n", REDIS_VERSION).unwrap();
+        write!(info, "redis_mode:standalone\r\n").unwrap();
+        write!(info, "process_id:{}\r\n", std::process::id()).unwrap();
+        let id = String::from_utf8_lossy(&self.replication.id);
+        write!(info, "run_id:{}\r\n", id).unwrap();
+        write!(info, "tcp_port:{}\r\n", self.replication.listening_port).unwrap();
+        write!(info, "uptime_in_seconds:{}\r\n", uptime).unwrap();
+        write!(info, "uptime_in_days:{}\r\n", uptime / (24 * 60 * 60)).unwrap();
+    }
+
+    fn clients_info(&self, info: &mut String) {
+        // a client blocked on several keys waits on each of them
+        let mut blocked: Vec<_> = self.blocked.values().flatten().map(Arc::as_ptr).collect();
+        blocked.sort_unstable();
+        blocked.dedup();
+        write!(info, "connected_clients:{}\r\n", self.clients.len()).unwrap();
+        write!(info, "maxclients:{}\r\n", self.config.maxclients).unwrap();
+        write!(info, "blocked_clients:{}\r\n", blocked.len()).unwrap();
+    }
+
+    fn memory_info(&mut self, info: &mut String) {
+        let memory = self.memory();
+        let used = memory.total();
+        write!(info, "used_memory:{}\r\n", used).unwrap();
+        write!(info, "used_memory_human:{}\r\n", human_bytes(used)).unwrap();
+        write!(info, "used_memory_peak:{}\r\n", memory.peak).unwrap();
+        let peak = human_bytes(memory.peak);
+        write!(info, "used_memory_peak_human:{}\r\n", peak).unwrap();
+        write!(info, "used_memory_overhead:{}\r\n", memory.overhead()).unwrap();
+        write!(info, "used_memory_dataset:{}\r\n", memory.dataset).unwrap();
+        let maxmemory = self.config.maxmemory as usize;
+        write!(info, "maxmemory:{}\r\n", maxmemory).unwrap();
+        write!(info, "maxmemory_human:{}\r\n", human_bytes(maxmemory)).unwrap();
+        let policy = self.config.maxmemory_policy.name();
+        write!(info, "maxmemory_policy:{}\r\n", policy).unwrap();
+    }
+
+    fn stats_info(&self, info: &mut String) {
+        let stats = &self.stats;
+        write!(info, "total_connections_received:{}\r\n", stats.connections).unwrap();
+        write!(info, "total_commands_processed:{}\r\n", stats.commands).unwrap();
+        write!(info, "rejected_connections:{}\r\n", stats.rejected).unwrap();
+        write!(info, "expired_keys:{}\r\n", stats.expired).unwrap();
+        write!(info, "evicted_keys:{}\r\n", stats.evicted).unwrap();
+        write!(info, "keyspace_hits:{}\r\n", stats.hits).unwrap();
+        write!(info, "keyspace_misses:{}\r\n", stats.misses).unwrap();
+        let channels = self.pubsub.channels(None).count();
+        write!(info, "pubsub_channels:{}\r\n", channels).unwrap();
+        write!(info, "pubsub_patterns:{}\r\n", self.pubsub.patterns()).unwrap();
+    }
+
+    fn keyspace_info(&self, info: &mut String, now: i64) {
+        // keys that expired but weren't removed yet aren't counted
+        let expired = self.expires.iter().take_while(|(t, _)| *t <= now).count();
+        let keys = self.keystore.len() - expired;
+        if keys > 0 {
+            let expires = self.expires.len() - expired;
+            let ttls = self
+                .expires
+                .iter()
+                .skip(expired)
+                .map(|(t, _)| (t - now) as u128);
+            let avg_ttl = ttls.sum::<u128>().checked_div(expires as u128).unwrap_or(0);
+            let line = format!("keys={},expires={},avg_ttl={}", keys, expires, avg_ttl);
+            write!(info, "db0:{}\r\n", line).unwrap();
+        }
+    }
+}
+
+/// Formats `bytes` like Redis, e.g. `1.50K`.
+fn human_bytes(bytes: usize) -> String {
+    let units = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    match units.into_iter().find(|(_, size)| bytes >= *size) {
+        Some((unit, size)) => format!("{:.2}{}", bytes as f64 / size as f64, unit),
+        None => format!("{}B", bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::{Command, SetOptions},
+        db::Db,
+    };
+
+    /// Returns the value of `field` in the `INFO` reply.
+    fn field(info: &Frame, field: &str) -> Option<String> {
+        let Frame::Bulk(Some(info)) = info else {
+            return None;
+        };
+        let info = std::str::from_utf8(info).unwrap();
+        let prefix = format!("{}:", field);
+        info.lines()
+            .find_map(|l| l.strip_prefix(&prefix).map(str::to_string))
+    }
+
+    #[tokio::test]
+    async fn counts_clients_commands_and_lookups() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let other = db.client();
+        let set = Command::Set("k".into(), "v".into(), SetOptions::default());
+        let get = |k: &'static str| Command::Get(k.into());
+        let commands = vec![set, get("k"), get("missing"), Command::Info(vec![])];
+        let info = db.apply_batch(&mut client, commands).await.pop().unwrap();
+        assert_eq!(Some("2".into()), field(&info, "connected_clients"));
+        assert_eq!(Some("4".into()), field(&info, "total_commands_processed"));
+        assert_eq!(Some("1".into()), field(&info, "keyspace_hits"));
+        assert_eq!(Some("1".into()), field(&info, "keyspace_misses"));
+        assert_eq!(Some("master".into()), field(&info, "role"));
+        assert_eq!(
+            Some("keys=1,expires=0,avg_ttl=0".into()),
+            field(&info, "db0")
+        );
+
+        drop(other);
+        let commands = vec![Command::Info(vec!["Clients".into(), "STATS".into()])];
+        let info = db.apply_batch(&mut client, commands).await.pop().unwrap();
+        assert_eq!(Some("1".into()), field(&info, "connected_clients"));
+        assert_eq!(Some("2".into()), field(&info, "total_connections_received"));
+        assert_eq!(None, field(&info, "role"));
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::State;
+use crate::{command::LatencyCommand, frame::Frame};
+
+/// How many samples are kept for each event, like Redis.
+const HISTORY_LEN: usize = 160;
+
+/// Applying a command.
+pub const COMMAND: &str = "command";
+/// Actively removing expired keys.
+pub const EXPIRE_CYCLE: &str = "expire-cycle";
+/// Taking the snapshot of the dataset a background save writes, which stalls every client like
+/// Redis's fork does.
+pub const FORK: &str = "fork";
+/// Saving the dataset in the foreground with `SAVE`.
+pub const SAVE: &str = "save";
+
+/// The latency spikes of each event that took at least `latency-monitor-threshold`, by event.
+#[derive(Default)]
+pub struct Latency {
+    events: BTreeMap<&'static str, Series>,
+}
+
+/// The spikes of an event, at most one a second.
+#[derive(Default)]
+struct Series {
+    /// When each spike happened, in seconds since the Unix epoch, and how long it took in
+    /// milliseconds, oldest first.
+    samples: VecDeque<(u64, u64)>,
+    /// The longest spike since the series was created or reset.
+    max: u64,
+}
+
+impl State {
+    /// Records that `event` took `duration`, if it was at least `latency-monitor-threshold`. Spikes
+    /// in the same second are merged, keeping the longest.
+    pub(super) fn record_latency(&mut self, event: &'static str, duration: Duration) {
+        let threshold = self.config.latency_monitor_threshold;
+        let latency = duration.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+        let now = unix_time();
+        let series = self.latency.events.entry(event).or_default();
+        series.max = series.max.max(latency);
+        match series.samples.back_mut() {
+            Some((time, last)) if *time == now => *last = (*last).max(latency),
+            _ => {
+                series.samples.push_back((now, latency));
+                if series.samples.len() > HISTORY_LEN {
+                    series.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Applies a subcommand of `LATENCY`.
+    pub(super) fn latency(&mut self, command: LatencyCommand) -> Frame {
+        let events = &mut self.latency.events;
+        match command {
+            LatencyCommand::Latest => {
+                let latest = events.iter().filter_map(|(event, series)| {
+                    let &(time, latency) = series.samples.back()?;
+                    Some(Frame::Array(Some(vec![
+                        Frame::Bulk(Some(Bytes::from_static(event.as_bytes()))),
+                        Frame::Integer(time as i64),
+                        Frame::Integer(latency as i64),
+                        Frame::Integer(series.max as i64),
+                    ])))
+                });
+                Frame::Array(Some(latest.collect()))
+            }
+            LatencyCommand::History(event) => {
+                let samples = std::str::from_utf8(&event)
+                    .ok()
+                    .and_then(|event| events.get(event))
+                    .map(|series| series.samples.iter())
+                    .into_iter()
+                    .flatten();
+                let samples = samples.map(|&(time, latency)| {
+                    Frame::Array(Some(vec![
+                        Frame::Integer(time as i64),
+                        Frame::Integer(latency as i64),
+                    ]))
+                });
+                Frame::Array(Some(samples.collect()))
+            }
+            LatencyCommand::Reset(names) if names.is_empty() => {
+                let reset = events.len();
+                events.clear();
+                Frame::Integer(reset as i64)
+            }
+            LatencyCommand::Reset(names) => {
+                let before = events.len();
+                events.retain(|event, _| {
+                    !names
+                        .iter()
+                        .any(|n| n.eq_ignore_ascii_case(event.as_bytes()))
+                });
+                Frame::Integer((before - events.len()) as i64)
+            }
+            LatencyCommand::Doctor => Frame::Bulk(Some(self.latency_report().into())),
+        }
+    }
+
+    /// Returns a human readable analysis of the latency spikes, for `LATENCY DOCTOR`.
+    fn latency_report(&self) -> String {
+        if self.config.latency_monitor_threshold == 0 {
+            return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this \
+                    Redis instance. You may use \"CONFIG SET latency-monitor-threshold \
+                    <milliseconds>.\" in order to enable it."
+                .into();
+        }
+        let events = &self.latency.events;
+        if events.values().all(|s| s.samples.is_empty()) {
+            return "Dave, no latency spike was observed during the lifetime of this Redis \
+                    instance, not in the slightest bit. I honestly think you ought to sleep \
+                    tonight."
+                .into();
+        }
+        let mut report = "Dave, I have observed latency spikes in this Redis instance. You don't \
+                          mind talking about it, do you Dave?\n\n"
+            .to_string();
+        let now = unix_time();
+        let mut advice = Vec::new();
+        for (i, (event, series)) in events
+            .iter()
+            .filter(|(_, s)| !s.samples.is_empty())
+            .enumerate()
+        {
+            let count = series.samples.len() as u64;
+            let average = series.samples.iter().map(|&(_, l)| l).sum::<u64>() / count;
+            let deviation = series
+                .samples
+                .iter()
+                .map(|&(_, l)| l.abs_diff(average))
+                .sum::<u64>()
+                / count;
+            let oldest = series.samples.front().map_or(now, |&(time, _)| time);
+            let period = now.saturating_sub(oldest) as f64 / count as f64;
+            writeln!(
+                report,
+                "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {:.2} sec). \
+                 Worst all time event {}ms.",
+                i + 1,
+                event,
+                count,
+                average,
+                deviation,
+                period,
+                series.max,
+            )
+            .unwrap();
+            advice.push(match *event {
+                COMMAND => {
+                    "Check your slow log with SLOWLOG GET, and avoid commands that are O(N) on \
+                     large values, such as KEYS or SMEMBERS of a big set."
+                }
+                EXPIRE_CYCLE => {
+                    "Many keys are expiring at the same time. Consider spreading expiries with a \
+                     random component, or lowering active-expire-effort."
+                }
+                FORK | SAVE => {
+                    "Saving the dataset blocks the server while it is copied. Consider a less \
+                     frequent save policy, and avoid SAVE on a server taking traffic."
+                }
+                _ => continue,
+            });
+        }
+        advice.dedup();
+        report.push_str("\nI have a few advices for you:\n\n");
+        for advice in advice {
+            writeln!(report, "- {}", advice).unwrap();
+        }
+        report
+    }
+}
+
+/// Returns the seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    #[test]
+    fn records_spikes_above_the_threshold() {
+        let db = Db::new();
+        let mut state = db.state.lock().unwrap();
+        state.record_latency(COMMAND, Duration::from_millis(500));
+        assert!(state.latency.events.is_empty(), "monitoring is disabled");
+
+        state.config.latency_monitor_threshold = 100;
+        state.record_latency(COMMAND, Duration::from_millis(50));
+        state.record_latency(COMMAND, Duration::from_millis(200));
+        state.record_latency(COMMAND, Duration::from_millis(300));
+        state.record_latency(FORK, Duration::from_millis(150));
+        let latest = match state.latency(LatencyCommand::Latest) {
+            Frame::Array(Some(latest)) => latest,
+            reply => panic!("expected an array, got {:?}", reply),
+        };
+        assert_eq!(2, latest.len());
+        // spikes in the same second are merged
+        let history = state.latency(LatencyCommand::History("command".into()));
+        let samples = match history {
+            Frame::Array(Some(samples)) => samples,
+            reply => panic!("expected an array, got {:?}", reply),
+        };
+        match &samples[..] {
+            [Frame::Array(Some(sample))] => assert_eq!(Frame::Integer(300), sample[1]),
+            samples => panic!("expected one sample, got {:?}", samples),
+        }
+
+        let reset = LatencyCommand::Reset(vec!["FORK".into(), "unknown".into()]);
+        assert_eq!(Frame::Integer(1), state.latency(reset));
+        assert_eq!(
+            Frame::Integer(1),
+            state.latency(LatencyCommand::Reset(vec![]))
+        );
+        assert!(state.latency.events.is_empty());
+    }
+}
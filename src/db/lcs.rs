@@ -0,0 +1,74 @@
+/// A run of bytes common to both strings, as the first and last positions of its bytes in each.
+pub struct Match {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl Match {
+    pub fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// Returns the longest common subsequence of `a` and `b`, along with the runs it is made of, from
+/// the last to the first like Redis, i.e. `LCS`.
+pub fn lcs(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<Match>) {
+    // the length of the LCS of each pair of prefixes, the ith prefix of `a` along the rows
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            lengths[i * width + j] = match a[i - 1] == b[j - 1] {
+                true => lengths[(i - 1) * width + j - 1] + 1,
+                false => lengths[(i - 1) * width + j].max(lengths[i * width + j - 1]),
+            };
+        }
+    }
+
+    // walk back from the end of both strings, collecting the bytes that are common to both
+    let mut common = Vec::with_capacity(lengths[a.len() * width + b.len()] as usize);
+    let mut matches = Vec::new();
+    let mut current: Option<Match> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            common.push(a[i - 1]);
+            match &mut current {
+                Some(run) => (run.a.0, run.b.0) = (i - 1, j - 1),
+                None => {
+                    current = Some(Match {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                    })
+                }
+            }
+            (i, j) = (i - 1, j - 1);
+            continue;
+        }
+        if lengths[(i - 1) * width + j] > lengths[i * width + j - 1] {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+        matches.extend(current.take());
+    }
+    matches.extend(current);
+    common.reverse();
+    (common, matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_common_subsequence() {
+        // from the examples of https://redis.io/commands/lcs
+        let (common, matches) = lcs(b"ohmytext", b"mynewtext");
+        assert_eq!(b"mytext".to_vec(), common);
+        let ranges: Vec<_> = matches.iter().map(|m| (m.a, m.b, m.len())).collect();
+        assert_eq!(vec![((4, 7), (5, 8), 4), ((2, 3), (0, 1), 2)], ranges);
+
+        assert!(lcs(b"", b"text").0.is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+use std::{fmt::Write, mem::size_of};
+
+use bytes::Bytes;
+
+use super::{clients::ClientInfo, Entry, Hash, Set, SortedSet, State, Value};
+use crate::{command::MemoryCommand, frame::Frame};
+
+/// How many elements of a collection are sampled to estimate its size whenever it is modified.
+const SAMPLES: usize = 5;
+/// What a key costs besides its name and value: its slot in the keyspace, including the control
+/// byte of the hash table.
+const KEY_OVERHEAD: usize = size_of::<(Bytes, Entry)>() + 1;
+/// What an expiring key costs besides, to be indexed by deadline.
+const EXPIRES_OVERHEAD: usize = size_of::<(i64, Bytes)>();
+/// What each element of a collection costs besides its bytes, where listpacks only cost their slot
+/// in an array, and hash tables also cost a control byte.
+const LIST_ELEMENT: usize = size_of::<Bytes>();
+const LISTPACK_PAIR: usize = size_of::<(Bytes, Bytes)>();
+const HASH_ELEMENT: usize = size_of::<(Bytes, Bytes)>() + 1;
+const LISTPACK_MEMBER: usize = size_of::<Bytes>();
+const SET_ELEMENT: usize = size_of::<Bytes>() + 1;
+const LISTPACK_ZSET_ELEMENT: usize = size_of::<(Bytes, f64)>();
+/// Sorted sets encoded as a skiplist index each member both by name and by score.
+const ZSET_ELEMENT: usize = size_of::<(Bytes, f64)>() + 1 + size_of::<(f64, Bytes)>();
+/// What a connected client costs, not counting what it sends or is sent.
+const CLIENT_OVERHEAD: usize = size_of::<ClientInfo>() + size_of::<(u64, ClientInfo)>();
+/// Below this much memory in use, `MEMORY DOCTOR` doesn't look for issues.
+const DOCTOR_MIN_MEMORY: usize = 5 << 20;
+
+/// Roughly how much memory the server uses, as reported by `MEMORY STATS` and `INFO`.
+pub struct Memory {
+    pub peak: usize,
+    /// The keys and values of the dataset.
+    pub dataset: usize,
+    /// The slots of the keys in the keyspace.
+    pub keys: usize,
+    /// The index of expiring keys.
+    pub expires: usize,
+    pub clients: usize,
+    pub key_count: usize,
+}
+
+impl Memory {
+    /// Returns the memory used by anything but the dataset.
+    pub fn overhead(&self) -> usize {
+        self.keys + self.expires + self.clients
+    }
+
+    pub fn total(&self) -> usize {
+        self.dataset + self.overhead()
+    }
+}
+
+impl State {
+    /// Returns how much memory is used, recording the peak. Only the dataset and the structures
+    /// around it are accounted for, rather than every allocation.
+    pub(super) fn memory(&mut self) -> Memory {
+        let mut memory = Memory {
+            peak: 0,
+            dataset: self.used_memory,
+            keys: self.keystore.len() * KEY_OVERHEAD,
+            expires: self.expires.len() * EXPIRES_OVERHEAD,
+            clients: self.clients.len() * CLIENT_OVERHEAD,
+            key_count: self.keystore.len(),
+        };
+        self.stats.peak_memory = self.stats.peak_memory.max(memory.total());
+        memory.peak = self.stats.peak_memory;
+        memory
+    }
+
+    /// Estimates the size of the entry at `key` again after it may have been modified, updating
+    /// `used_memory`.
+    pub(super) fn account(&mut self, key: &Bytes) {
+        let Some(entry) = self.keystore.get_mut(key) else {
+            return;
+        };
+        let size = size(key, &entry.value);
+        self.used_memory = self.used_memory - entry.size + size;
+        entry.size = size;
+    }
+
+    /// Applies a subcommand of `MEMORY`.
+    pub(super) fn apply_memory(&mut self, command: MemoryCommand, now: i64) -> Frame {
+        match command {
+            MemoryCommand::Usage(key, samples) => match self.peek(&key, now) {
+                Some(entry) => {
+                    let expires = entry.expires_at.map_or(0, |_| EXPIRES_OVERHEAD);
+                    let usage = key.len() + KEY_OVERHEAD + expires + entry.value.usage(samples);
+                    Frame::Integer(usage as i64)
+                }
+                None => Frame::Bulk(None),
+            },
+            MemoryCommand::Stats => self.memory().to_frame(),
+            MemoryCommand::Doctor => Frame::Bulk(Some(self.memory().report().into())),
+        }
+    }
+}
+
+impl Memory {
+    /// Returns the fields of `MEMORY STATS`, interleaved with their values.
+    fn to_frame(&self) -> Frame {
+        let total = self.total();
+        let field = |name: &'static str| Frame::Bulk(Some(Bytes::from_static(name.as_bytes())));
+        let integer = |n: usize| Frame::Integer(n as i64);
+        let percentage = |n: usize, of: usize| {
+            let percentage = n as f64 * 100.0 / of.max(1) as f64;
+            Frame::Bulk(Some(format!("{:.2}", percentage).into()))
+        };
+        Frame::Array(Some(vec![
+            field("peak.allocated"),
+            integer(self.peak),
+            field("total.allocated"),
+            integer(total),
+            field("clients.normal"),
+            integer(self.clients),
+            field("db.0"),
+            Frame::Array(Some(vec![
+                field("overhead.hashtable.main"),
+                integer(self.keys),
+                field("overhead.hashtable.expires"),
+                integer(self.expires),
+            ])),
+            field("overhead.total"),
+            integer(self.overhead()),
+            field("keys.count"),
+            integer(self.key_count),
+            field("keys.bytes-per-key"),
+            integer(total.checked_div(self.key_count).unwrap_or(0)),
+            field("dataset.bytes"),
+            integer(self.dataset),
+            field("dataset.percentage"),
+            percentage(self.dataset, total),
+            field("peak.percentage"),
+            percentage(total, self.peak),
+        ]))
+    }
+
+    /// Returns a human readable analysis of the memory usage, for `MEMORY DOCTOR`.
+    fn report(&self) -> String {
+        let total = self.total();
+        if total < DOCTOR_MIN_MEMORY {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                    detector can't be used in these conditions. Please, leave for your mission on \
+                    Earth and fill it with some data. The new Sam and I will be back to our \
+                    programming as soon as I finished rebooting."
+                .into();
+        }
+        let mut issues = Vec::new();
+        if self.peak as f64 > total as f64 * 1.5 {
+            issues.push(format!(
+                "Peak memory: In the past this instance used more than 150% the memory that is \
+                 currently using. The allocator is normally not able to release memory after a \
+                 peak, so you can expect to see a big fragmentation ratio. Peak was {} bytes, \
+                 while {} bytes are used now.",
+                self.peak, total
+            ));
+        }
+        if self.clients > self.dataset {
+            issues.push(
+                "High client overhead: connected clients use more memory than the dataset. \
+                 Consider closing idle connections, or lowering the timeout."
+                    .into(),
+            );
+        }
+        if issues.is_empty() {
+            return "Hi Sam, I can't find any memory issue in your instance. I can only account \
+                    for what occurs on this base."
+                .into();
+        }
+        let mut report =
+            "Sam, I detected a few issues in this Redis instance memory implants:\n\n".to_string();
+        for issue in issues {
+            writeln!(report, " * {}\n", issue).unwrap();
+        }
+        report.push_str("I'm here to keep you safe, Sam. I want to help you.\n");
+        report
+    }
+}
+
+impl Value {
+    /// Returns roughly how many bytes the value occupies, including the structures holding its
+    /// elements. Collections are estimated from `samples` of their elements, or every one if none.
+    pub(super) fn usage(&self, samples: Option<usize>) -> usize {
+        match self {
+            Value::String(value) => value.len(),
+            Value::List(list) => {
+                let sizes = list.iter().map(|e| LIST_ELEMENT + e.len());
+                sampled(list.len(), sizes, samples)
+            }
+            Value::Hash(hash) => {
+                let overhead = match hash {
+                    Hash::Listpack(_) => LISTPACK_PAIR,
+                    Hash::Table(_) => HASH_ELEMENT,
+                };
+                let sizes = hash.iter().map(|(f, v)| overhead + f.len() + v.len());
+                sampled(hash.len(), sizes, samples)
+            }
+            // integers take no more than their slot in the array
+            Value::Set(Set::Intset(integers)) => integers.len() * size_of::<i64>(),
+            Value::Set(set) => {
+                let overhead = match set {
+                    Set::Table(_) => SET_ELEMENT,
+                    _ => LISTPACK_MEMBER,
+                };
+                let sizes = set.iter().map(|m| overhead + m.len());
+                sampled(set.len(), sizes, samples)
+            }
+            Value::SortedSet(zset) => {
+                // members of a skiplist are stored twice, although they share their bytes
+                let overhead = match zset {
+                    SortedSet::Listpack(_) => LISTPACK_ZSET_ELEMENT,
+                    SortedSet::Skiplist { .. } => ZSET_ELEMENT,
+                };
+                let sizes = zset.iter().map(|(m, _)| overhead + m.len());
+                sampled(zset.len(), sizes, samples)
+            }
+            Value::Stream(stream) => stream.size(),
+        }
+    }
+}
+
+/// Estimates how many bytes a key and its value occupy, sampling the elements of collections.
+pub(super) fn size(key: &Bytes, value: &Value) -> usize {
+    key.len() + value.usage(Some(SAMPLES))
+}
+
+/// Estimates the total size of `len` elements from the `sizes` of the first `samples` of them.
+fn sampled(len: usize, sizes: impl Iterator<Item = usize>, samples: Option<usize>) -> usize {
+    let (count, total) = sizes
+        .take(samples.unwrap_or(usize::MAX))
+        .fold((0, 0), |(count, total), size| (count + 1, total + size));
+    match count {
+        0 => 0,
+        count => total * len / count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::{Command, End, SetOptions},
+        db::Db,
+    };
+
+    #[tokio::test]
+    async fn usage_counts_the_key_value_and_overhead() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let list = (0..100).map(|i| Bytes::from(format!("{:03}", i))).collect();
+        let commands = vec![
+            Command::Set("k".into(), "value".into(), SetOptions::default()),
+            Command::Push("list".into(), End::Right, list),
+            Command::Memory(MemoryCommand::Usage("k".into(), Some(5))),
+            Command::Memory(MemoryCommand::Usage("list".into(), Some(5))),
+            Command::Memory(MemoryCommand::Usage("list".into(), None)),
+            Command::Memory(MemoryCommand::Usage("missing".into(), Some(5))),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        let string = 1 + KEY_OVERHEAD + 5;
+        assert_eq!(Frame::Integer(string as i64), replies[2]);
+        // every element is the same size, so sampling doesn't change the estimate
+        let list = 4 + KEY_OVERHEAD + 100 * (LIST_ELEMENT + 3);
+        assert_eq!(Frame::Integer(list as i64), replies[3]);
+        assert_eq!(replies[3], replies[4]);
+        assert_eq!(Frame::Bulk(None), replies[5]);
+
+        let mut state = db.state.lock().unwrap();
+        let memory = state.memory();
+        assert_eq!(2, memory.key_count);
+        assert_eq!(1 + 5 + 4 + 100 * (LIST_ELEMENT + 3), memory.dataset);
+        assert_eq!(memory.total(), memory.peak);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fmt::Write,
+    future,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::{Client, State};
+use crate::frame::Frame;
+
+/// How many lines a monitoring client may fall behind by, after which it skips the oldest ones.
+const BACKLOG: usize = 1024;
+
+impl State {
+    /// Applies `MONITOR`, after which `client` is sent every command other clients send.
+    pub(super) fn monitor(&mut self, client: &mut Client) -> Frame {
+        if client.monitoring.is_none() {
+            client.monitoring = Some(self.monitors.subscribe());
+        }
+        Frame::Bulk(Some("OK".into()))
+    }
+}
+
+impl Client {
+    /// Sends the command the client sent as `frame` to monitoring clients, if there are any, e.g.
+    /// `1339518083.107412 [0 127.0.0.1:60866] "keys" "*"`.
+    pub fn feed_monitors(&self, frame: &Frame) {
+        if self.monitors.receiver_count() == 0 || self.monitoring.is_some() {
+            return;
+        }
+        let Frame::Array(Some(args)) = frame else {
+            return;
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let addr = {
+            let state = self.state.lock().unwrap();
+            state.addr(self)
+        };
+        let addr = addr.map_or("?:0".to_string(), |a| a.to_string());
+        let mut line = format!("{}.{:06} [0 {}]", now.as_secs(), now.subsec_micros(), addr);
+        for arg in args {
+            if let Frame::Bulk(Some(arg)) = arg {
+                line.push(' ');
+                quote(&mut line, arg);
+            }
+        }
+        // sending only fails if every monitoring client disconnected since it was checked
+        let _ = self.monitors.send(line.into());
+    }
+
+    /// Waits for the next command sent by another client, if this one is monitoring, or forever.
+    pub async fn monitored(&mut self) -> Frame {
+        loop {
+            let Some(monitoring) = &mut self.monitoring else {
+                return future::pending().await;
+            };
+            match monitoring.recv().await {
+                Ok(line) => return Frame::String(line),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => self.monitoring = None,
+            }
+        }
+    }
+}
+
+/// Returns the channel monitoring clients are sent commands through.
+pub fn channel() -> broadcast::Sender<Bytes> {
+    broadcast::channel(BACKLOG).0
+}
+
+/// Appends `arg` between double quotes, escaping it like Redis's `sdscatrepr`.
+fn quote(line: &mut String, arg: &[u8]) {
+    line.push('"');
+    for &b in arg {
+        match b {
+            b'\\' | b'"' => write!(line, "\\{}", b as char).unwrap(),
+            b'\n' => line.push_str("\\n"),
+            b'\r' => line.push_str("\\r"),
+            b'\t' => line.push_str("\\t"),
+            0x07 => line.push_str("\\a"),
+            0x08 => line.push_str("\\b"),
+            b' '..=b'~' => line.push(b as char),
+            _ => write!(line, "\\x{:02x}", b).unwrap(),
+        }
+    }
+    line.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Command, db::Db};
+
+    #[tokio::test]
+    async fn monitors_are_sent_commands_of_other_clients() {
+        let db = Db::new();
+        let (mut monitor, _) = db.client();
+        let (client, _) = db.client();
+        client.set_addr("127.0.0.1:1234".parse().unwrap());
+        let replies = db.apply_batch(&mut monitor, vec![Command::Monitor]).await;
+        assert_eq!(Frame::Bulk(Some("OK".into())), replies[0]);
+
+        let args = ["SET", "k", "a \"b\"\n\x01"];
+        let args = args.iter().map(|a| Frame::Bulk(Some(a.as_bytes().into())));
+        client.feed_monitors(&Frame::Array(Some(args.collect())));
+        let line = match monitor.monitored().await {
+            Frame::String(line) => line,
+            reply => panic!("expected a simple string, got {:?}", reply),
+        };
+        let line = std::str::from_utf8(&line).unwrap();
+        let (_, line) = line.split_once(' ').unwrap();
+        assert_eq!(r#"[0 127.0.0.1:1234] "SET" "k" "a \"b\"\n\x01""#, line);
+    }
+}
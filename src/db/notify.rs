@@ -0,0 +1,134 @@
+use bytes::Bytes;
+
+use super::State;
+
+/// The keyspace events published over pub/sub, as configured by a `notify-keyspace-events` flag
+/// string such as `KEA`.
+#[derive(Default, Clone, Copy)]
+pub struct Notifications {
+    /// Whether events are published to `__keyspace@0__:<key>`, with the event as the message.
+    keyspace: bool,
+    /// Whether events are published to `__keyevent@0__:<event>`, with the key as the message.
+    keyevent: bool,
+    /// The enabled classes, one bit per `Class`.
+    classes: u16,
+}
+
+/// A class of keyspace events, enabled by its flag.
+#[derive(Clone, Copy)]
+pub enum Class {
+    /// Commands that apply to any type, e.g. `DEL` or `EXPIRE`.
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    SortedSet,
+    Stream,
+    /// Keys removed because they expired.
+    Expired,
+    /// Keys removed to free memory.
+    Evicted,
+    /// Keys added to the keyspace.
+    New,
+}
+
+impl Class {
+    const ALL: [Class; 10] = [
+        Class::Generic,
+        Class::String,
+        Class::List,
+        Class::Set,
+        Class::Hash,
+        Class::SortedSet,
+        Class::Stream,
+        Class::Expired,
+        Class::Evicted,
+        Class::New,
+    ];
+
+    fn flag(self) -> char {
+        match self {
+            Class::Generic => 'g',
+            Class::String => '$',
+            Class::List => 'l',
+            Class::Set => 's',
+            Class::Hash => 'h',
+            Class::SortedSet => 'z',
+            Class::Stream => 't',
+            Class::Expired => 'x',
+            Class::Evicted => 'e',
+            Class::New => 'n',
+        }
+    }
+}
+
+impl Notifications {
+    /// Parses a flag string, returning `None` if it has an unknown flag. Like Redis, `A` enables
+    /// every class but `New`, and nothing is published unless `K` or `E` is also given.
+    pub fn parse(flags: &str) -> Option<Self> {
+        let mut notifications = Notifications::default();
+        for flag in flags.chars() {
+            match flag {
+                'K' => notifications.keyspace = true,
+                'E' => notifications.keyevent = true,
+                'A' => {
+                    for class in Class::ALL {
+                        if !matches!(class, Class::New) {
+                            notifications.classes |= 1 << class as u16;
+                        }
+                    }
+                }
+                // key misses and module events are never published
+                'm' | 'd' => (),
+                flag => {
+                    let class = Class::ALL.into_iter().find(|c| c.flag() == flag)?;
+                    notifications.classes |= 1 << class as u16;
+                }
+            }
+        }
+        Some(notifications)
+    }
+
+    fn enabled(&self, class: Class) -> bool {
+        (self.keyspace || self.keyevent) && self.classes & (1 << class as u16) != 0
+    }
+}
+
+impl State {
+    /// Publishes that `event` happened to `key`, if its class is enabled.
+    pub(super) fn notify(&self, class: Class, event: &'static str, key: &Bytes) {
+        if !self.notifications.enabled(class) {
+            return;
+        }
+        let event = Bytes::from_static(event.as_bytes());
+        let limit = &self.config.client_output_buffer_limit.pubsub;
+        if self.notifications.keyspace {
+            let channel = [b"__keyspace@0__:", key.as_ref()].concat();
+            self.pubsub.publish(&channel.into(), &event, limit);
+        }
+        if self.notifications.keyevent {
+            let channel = [b"__keyevent@0__:", event.as_ref()].concat();
+            self.pubsub.publish(&channel.into(), key, limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flags() {
+        let notifications = Notifications::parse("KA").unwrap();
+        assert!(notifications.enabled(Class::Expired));
+        assert!(!notifications.enabled(Class::New));
+
+        let notifications = Notifications::parse("El$").unwrap();
+        assert!(notifications.enabled(Class::List) && notifications.enabled(Class::String));
+        assert!(!notifications.enabled(Class::Hash));
+
+        assert!(!Notifications::parse("A").unwrap().enabled(Class::Generic));
+        assert!(Notifications::parse("KEQ").is_none());
+    }
+}
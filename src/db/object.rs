@@ -0,0 +1,183 @@
+use bytes::Bytes;
+
+use super::Value;
+use crate::command::parse_integer;
+
+/// The value of a new key's access counter, so that it isn't evicted before it had a chance to be
+/// accessed again.
+const INITIAL_COUNTER: u8 = 5;
+/// How much harder each increment of the access counter gets, i.e. Redis's `lfu-log-factor`.
+const LOG_FACTOR: f64 = 10.0;
+/// How many milliseconds without an access decrement the access counter, i.e. Redis's
+/// `lfu-decay-time` of a minute.
+const DECAY_PERIOD: i64 = 60 * 1000;
+/// How many milliseconds each tick of the access clock lasts, and the largest value of the clock.
+const CLOCK_RESOLUTION: i64 = 1000;
+const CLOCK_MAX: u32 = (1 << 24) - 1;
+
+/// Strings no longer than this are allocated along with their object by Redis.
+const MAX_EMBSTR_LEN: usize = 44;
+/// Integers below this are shared between keys by Redis, which reports them as never freed.
+const SHARED_INTEGERS: i64 = 10000;
+
+/// How large collections may grow while they are encoded compactly, as a listpack or an intset,
+/// before they are converted to the general encoding of their type. Like Redis, hashes, sets and
+/// sorted sets are never converted back once they shrink.
+#[derive(Clone, Copy)]
+pub struct EncodingLimits {
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+    /// How many elements a list may hold or, if negative, how many bytes its elements may take,
+    /// from -1 for 4kb to -5 for 64kb.
+    pub list_max_listpack_size: i64,
+}
+
+impl Default for EncodingLimits {
+    fn default() -> Self {
+        EncodingLimits {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+        }
+    }
+}
+
+impl EncodingLimits {
+    /// Whether a list of `len` elements, taking `bytes` bytes in total, fits in a listpack.
+    fn list_fits(&self, len: usize, bytes: usize) -> bool {
+        match self.list_max_listpack_size {
+            entries @ 0.. => len as i64 <= entries,
+            size => bytes <= 4096 << (-size - 1).min(4),
+        }
+    }
+}
+
+/// How recently and how often a key was accessed, as reported by `OBJECT IDLETIME` and `OBJECT
+/// FREQ`, and used to pick keys to evict.
+///
+/// Like Redis, this takes 32 bits: a 24-bit clock of when the key was last accessed, in seconds,
+/// which wraps around every 194 days, and a logarithmic access counter that saturates at 255 after
+/// about a million accesses, and decays while the key isn't accessed.
+#[derive(Clone, Copy)]
+pub struct Access(u32);
+
+impl Access {
+    pub fn new(now: i64) -> Self {
+        Access(clock(now) << 8 | u32::from(INITIAL_COUNTER))
+    }
+
+    /// Records an access, where `random` is uniformly distributed in `[0, 1)`.
+    pub fn record(&mut self, now: i64, random: f64) {
+        let counter = self.frequency(now);
+        let base = counter.saturating_sub(INITIAL_COUNTER);
+        let increment = counter < u8::MAX && random < 1.0 / (f64::from(base) * LOG_FACTOR + 1.0);
+        let counter = if increment { counter + 1 } else { counter };
+        self.0 = clock(now) << 8 | u32::from(counter);
+    }
+
+    /// Returns the access clock when the key was last accessed, as reported by `DEBUG OBJECT`.
+    pub fn clock(&self) -> u32 {
+        self.0 >> 8
+    }
+
+    /// Returns how many milliseconds have passed since the key was last accessed, to the clock's
+    /// resolution.
+    pub fn idle_millis(&self, now: i64) -> i64 {
+        // wrapping, in case the clock wrapped around since
+        let elapsed = clock(now).wrapping_sub(self.0 >> 8) & CLOCK_MAX;
+        i64::from(elapsed) * CLOCK_RESOLUTION
+    }
+
+    /// Returns how many seconds have passed since the key was last accessed.
+    pub fn idle_time(&self, now: i64) -> i64 {
+        self.idle_millis(now) / 1000
+    }
+
+    /// Returns the access counter, decayed by how long the key has been idle.
+    pub fn frequency(&self, now: i64) -> u8 {
+        let periods = self.idle_millis(now) / DECAY_PERIOD;
+        let counter = self.0 as u8;
+        counter.saturating_sub(periods.try_into().unwrap_or(u8::MAX))
+    }
+}
+
+/// Returns the access clock at the unix time `now` in milliseconds.
+fn clock(now: i64) -> u32 {
+    (now.max(0) / CLOCK_RESOLUTION) as u32 & CLOCK_MAX
+}
+
+impl Value {
+    /// Returns the name of the value's encoding, as replied by `OBJECT ENCODING`.
+    ///
+    /// Strings are stored the same way regardless, so theirs is the one Redis would use. So is that
+    /// of lists, which are always stored contiguously, like a listpack.
+    pub(super) fn encoding(&self, limits: &EncodingLimits) -> &'static str {
+        match self {
+            Value::String(value) if integer(value).is_some() => "int",
+            Value::String(value) if value.len() <= MAX_EMBSTR_LEN => "embstr",
+            Value::String(_) => "raw",
+            Value::List(list)
+                if limits.list_fits(list.len(), list.iter().map(Bytes::len).sum()) =>
+            {
+                "listpack"
+            }
+            Value::List(_) => "quicklist",
+            Value::Hash(hash) => hash.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::SortedSet(zset) => zset.encoding(),
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Returns how many references Redis would hold to the value, as replied by `OBJECT REFCOUNT`.
+    pub(super) fn refcount(&self) -> i64 {
+        match self {
+            Value::String(value)
+                if integer(value).is_some_and(|i| (0..SHARED_INTEGERS).contains(&i)) =>
+            {
+                i32::MAX.into()
+            }
+            _ => 1,
+        }
+    }
+}
+
+/// Parses `value` as an integer, if it is one written in its canonical form, e.g. not `01`.
+pub(super) fn integer(value: &Bytes) -> Option<i64> {
+    parse_integer(value).filter(|i| i.to_string().as_bytes() == value.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency_grows_logarithmically_and_decays() {
+        let mut access = Access::new(0);
+        for _ in 0..100 {
+            access.record(0, 0.5);
+        }
+        // past the initial value, each increment is less likely to happen than the one before
+        assert_eq!(INITIAL_COUNTER + 1, access.frequency(0));
+        access.record(0, 0.0);
+        assert_eq!(INITIAL_COUNTER + 2, access.frequency(0));
+
+        assert_eq!(INITIAL_COUNTER, access.frequency(2 * DECAY_PERIOD));
+        assert_eq!(0, access.frequency(1000 * DECAY_PERIOD));
+        assert_eq!(2, access.idle_time(2999));
+
+        let wraps_at = i64::from(CLOCK_MAX + 1) * CLOCK_RESOLUTION;
+        let access = Access::new(wraps_at - 1000);
+        assert_eq!(3, access.idle_time(wraps_at + 2000));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{
+    mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+
+use crate::{command::parse_memory, connection, frame::Frame};
+
+/// How many bytes of messages may be pending to be sent to a client before it is disconnected,
+/// either as soon as it exceeds `hard`, or once it stayed over `soft` for longer than `soft_for`.
+/// A limit of 0 is no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OutputLimit {
+    pub hard: u64,
+    pub soft: u64,
+    pub soft_for: Duration,
+}
+
+/// The output limits of each class of client, i.e. Redis's `client-output-buffer-limit`.
+///
+/// Only the messages pushed to a client count towards its limit, as replies are sent before its
+/// next commands are read. Normal clients are only pushed messages once they subscribe, which makes
+/// them pub/sub clients, so their limit is accepted but has nothing to apply to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputLimits {
+    pub normal: OutputLimit,
+    pub replica: OutputLimit,
+    pub pubsub: OutputLimit,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        let limit = |hard: u64, soft: u64, secs| OutputLimit {
+            hard: hard << 20,
+            soft: soft << 20,
+            soft_for: Duration::from_secs(secs),
+        };
+        OutputLimits {
+            normal: OutputLimit::default(),
+            replica: limit(256, 64, 60),
+            pubsub: limit(32, 8, 60),
+        }
+    }
+}
+
+impl OutputLimits {
+    /// Sets the limits of the classes given in `value`, e.g. `pubsub 32mb 8mb 60`, returning `None`
+    /// if it is invalid.
+    pub fn parse(&mut self, value: &str) -> Option<()> {
+        let words: Vec<&str> = value.split_whitespace().collect();
+        if !words.len().is_multiple_of(4) {
+            return None;
+        }
+        let mut limits = *self;
+        for class in words.chunks(4) {
+            let limit = match class[0].to_ascii_lowercase().as_str() {
+                "normal" => &mut limits.normal,
+                "replica" | "slave" => &mut limits.replica,
+                "pubsub" => &mut limits.pubsub,
+                _ => return None,
+            };
+            *limit = OutputLimit {
+                hard: parse_memory(class[1].as_bytes())?,
+                soft: parse_memory(class[2].as_bytes())?,
+                soft_for: Duration::from_secs(class[3].parse().ok()?),
+            };
+        }
+        *self = limits;
+        Some(())
+    }
+}
+
+/// Formats the limits the way `CONFIG GET` replies with them, i.e. like Redis, in bytes.
+impl fmt::Display for OutputLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let classes = [
+            ("normal", self.normal),
+            ("slave", self.replica),
+            ("pubsub", self.pubsub),
+        ];
+        for (i, (class, limit)) in classes.into_iter().enumerate() {
+            let soft_for = limit.soft_for.as_secs();
+            let separator = if i == 0 { "" } else { " " };
+            write!(
+                f,
+                "{}{} {} {} {}",
+                separator, class, limit.hard, limit.soft, soft_for
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What the sending and receiving halves of a client's messages share.
+struct Output {
+    /// How many bytes of messages were sent but not yet written to the client's connection.
+    pending: AtomicUsize,
+    /// When the client went over its soft limit, if it still is.
+    over_soft_since: Mutex<Option<Instant>>,
+    /// Notified to close the client's connection.
+    kill: Arc<Notify>,
+}
+
+/// Sends messages to a client, e.g. those published to the channels it subscribes to.
+#[derive(Clone)]
+pub struct Sender {
+    messages: UnboundedSender<(Frame, usize)>,
+    output: Arc<Output>,
+}
+
+/// Receives the messages sent to a client, to push them over its connection.
+pub struct Receiver {
+    messages: UnboundedReceiver<(Frame, usize)>,
+    output: Arc<Output>,
+    /// How many bytes were received since the connection was last flushed.
+    received: usize,
+}
+
+/// Creates the channel of the messages pushed to a client, which is killed with `kill` if it falls
+/// too far behind reading them.
+pub fn channel(kill: Arc<Notify>) -> (Sender, Receiver) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let output = Arc::new(Output {
+        pending: AtomicUsize::new(0),
+        over_soft_since: Mutex::new(None),
+        kill,
+    });
+    let receiver = Receiver {
+        messages: receiver,
+        output: output.clone(),
+        received: 0,
+    };
+    (
+        Sender {
+            messages: sender,
+            output,
+        },
+        receiver,
+    )
+}
+
+impl Sender {
+    /// Sends `message`, returning whether it was, i.e. unless the client disconnected, or the
+    /// message would put it over `limit`, which disconnects it.
+    pub fn send(&self, message: Frame, limit: &OutputLimit) -> bool {
+        let len = connection::encoded_len(&message);
+        let pending = self.output.pending.load(Ordering::Relaxed) + len;
+        if self.exceeds(pending as u64, limit) {
+            println!("closing a client that exceeded its output buffer limit");
+            self.output.kill.notify_one();
+            return false;
+        }
+        if self.messages.send((message, len)).is_err() {
+            return false;
+        }
+        self.output.pending.fetch_add(len, Ordering::Relaxed);
+        true
+    }
+
+    fn exceeds(&self, pending: u64, limit: &OutputLimit) -> bool {
+        if limit.hard != 0 && pending > limit.hard {
+            return true;
+        }
+        let mut over_soft_since = self.output.over_soft_since.lock().unwrap();
+        if limit.soft == 0 || pending <= limit.soft {
+            *over_soft_since = None;
+            return false;
+        }
+        let since = over_soft_since.get_or_insert_with(Instant::now);
+        since.elapsed() > limit.soft_for
+    }
+}
+
+impl Receiver {
+    /// Receives the next message, or `None` once every sender was dropped.
+    pub async fn recv(&mut self) -> Option<Frame> {
+        let (message, len) = self.messages.recv().await?;
+        self.received += len;
+        Some(message)
+    }
+
+    pub fn try_recv(&mut self) -> Result<Frame, TryRecvError> {
+        let (message, len) = self.messages.try_recv()?;
+        self.received += len;
+        Ok(message)
+    }
+
+    /// Records that the messages received so far were written to the client's connection, so
+    /// that they no longer count towards its limit.
+    pub fn flushed(&mut self) {
+        self.output
+            .pending
+            .fetch_sub(self.received, Ordering::Relaxed);
+        self.received = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_over_their_limit_are_killed() {
+        let kill = Arc::new(Notify::new());
+        let (sender, mut receiver) = channel(kill);
+        let message = Frame::Bulk(Some("message".into()));
+        let len = connection::encoded_len(&message) as u64;
+        let limit = OutputLimit {
+            hard: 2 * len,
+            ..OutputLimit::default()
+        };
+        assert!(sender.send(message.clone(), &limit));
+        assert!(sender.send(message.clone(), &limit));
+        assert!(!sender.send(message.clone(), &limit));
+
+        // messages stop counting once written
+        assert_eq!(Ok(message.clone()), receiver.try_recv());
+        receiver.flushed();
+        assert!(sender.send(message.clone(), &limit));
+
+        let limit = OutputLimit {
+            soft: len,
+            soft_for: Duration::from_millis(1),
+            ..OutputLimit::default()
+        };
+        assert!(sender.send(message.clone(), &limit), "just went over");
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!sender.send(message, &limit));
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+use super::{
+    output::{OutputLimit, Sender},
+    Client,
+};
+use crate::{command::Command, frame::Frame, glob, reply::ErrorReply};
+
+/// The clients subscribed to each channel or pattern, keyed by their ID.
+type Subscribers = HashMap<Bytes, HashMap<u64, Sender>>;
+
+#[derive(Default)]
+pub struct PubSub {
+    channels: Subscribers,
+    patterns: Subscribers,
+}
+
+impl PubSub {
+    /// Sends `message` to every client subscribed to `channel` or to a pattern matching it,
+    /// returning how many times it was received. A client subscribed to both receives it twice,
+    /// and one it would put over `limit` doesn't, but is disconnected.
+    pub fn publish(&self, channel: &Bytes, message: &Bytes, limit: &OutputLimit) -> usize {
+        // a client that has disconnected is unsubscribed as soon as its handle is dropped
+        let send = |subscribers: &HashMap<u64, Sender>, message: Frame| {
+            subscribers
+                .values()
+                .filter(|sender| sender.send(message.clone(), limit))
+                .count()
+        };
+        let mut received = 0;
+        if let Some(subscribers) = self.channels.get(channel) {
+            let message = Frame::Push(vec![
+                Frame::Bulk(Some("message".into())),
+                Frame::Bulk(Some(channel.clone())),
+                Frame::Bulk(Some(message.clone())),
+            ]);
+            received += send(subscribers, message);
+        }
+        for (pattern, subscribers) in &self.patterns {
+            if glob::matches(pattern, channel) {
+                let message = Frame::Push(vec![
+                    Frame::Bulk(Some("pmessage".into())),
+                    Frame::Bulk(Some(pattern.clone())),
+                    Frame::Bulk(Some(channel.clone())),
+                    Frame::Bulk(Some(message.clone())),
+                ]);
+                received += send(subscribers, message);
+            }
+        }
+        received
+    }
+
+    /// Returns the channels with at least one subscriber, excluding pattern subscribers, that match
+    /// `pattern`, if given.
+    pub fn channels<'a>(&'a self, pattern: Option<&'a Bytes>) -> impl Iterator<Item = &'a Bytes> {
+        self.channels.keys().filter(move |channel| match pattern {
+            Some(pattern) => glob::matches(pattern, channel),
+            None => true,
+        })
+    }
+
+    /// Returns how many clients subscribe to `channel`, excluding pattern subscribers.
+    pub fn subscribers_to(&self, channel: &Bytes) -> usize {
+        self.channels.get(channel).map_or(0, HashMap::len)
+    }
+
+    /// Returns how many distinct patterns clients subscribe to.
+    pub fn patterns(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Returns the subscribers to patterns if `pattern` is set, or to channels otherwise.
+    fn subscribers(&mut self, pattern: bool) -> &mut Subscribers {
+        if pattern {
+            &mut self.patterns
+        } else {
+            &mut self.channels
+        }
+    }
+
+    pub(super) fn unsubscribe(&mut self, name: &Bytes, id: u64, pattern: bool) {
+        let subscribers = self.subscribers(pattern);
+        if let Some(clients) = subscribers.get_mut(name) {
+            clients.remove(&id);
+            if clients.is_empty() {
+                subscribers.remove(name);
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Whether the client has any subscriptions.
+    pub fn is_subscribed(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty()
+    }
+
+    /// Returns the patterns the client subscribes to if `pattern` is set, or its channels otherwise.
+    fn subscriptions(&mut self, pattern: bool) -> &mut HashSet<Bytes> {
+        if pattern {
+            &mut self.patterns
+        } else {
+            &mut self.channels
+        }
+    }
+
+    /// Subscribes the client to each channel or, if `pattern` is set, each pattern, returning a
+    /// confirmation for each.
+    pub(super) fn subscribe(
+        &mut self,
+        pubsub: &mut PubSub,
+        names: Vec<Bytes>,
+        pattern: bool,
+    ) -> Vec<Frame> {
+        let kind = if pattern { "psubscribe" } else { "subscribe" };
+        let mut replies = Vec::with_capacity(names.len());
+        for name in names {
+            if self.subscriptions(pattern).insert(name.clone()) {
+                pubsub
+                    .subscribers(pattern)
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(self.id, self.messages.clone());
+            }
+            replies.push(self.confirmation(kind, Some(name)));
+        }
+        replies
+    }
+
+    /// Unsubscribes the client from each channel or, if `pattern` is set, each pattern, or from all
+    /// of them if none are given, returning a confirmation for each.
+    pub(super) fn unsubscribe(
+        &mut self,
+        pubsub: &mut PubSub,
+        names: Vec<Bytes>,
+        pattern: bool,
+    ) -> Vec<Frame> {
+        let kind = if pattern {
+            "punsubscribe"
+        } else {
+            "unsubscribe"
+        };
+        let names = if names.is_empty() {
+            self.subscriptions(pattern).iter().cloned().collect()
+        } else {
+            names
+        };
+        if names.is_empty() {
+            return vec![self.confirmation(kind, None)];
+        }
+        let mut replies = Vec::with_capacity(names.len());
+        for name in names {
+            if self.subscriptions(pattern).remove(&name) {
+                pubsub.unsubscribe(&name, self.id, pattern);
+            }
+            replies.push(self.confirmation(kind, Some(name)));
+        }
+        replies
+    }
+
+    /// Unsubscribes the client from every channel and pattern, without confirming it.
+    pub(super) fn unsubscribe_all(&mut self, pubsub: &mut PubSub) {
+        for channel in self.channels.drain() {
+            pubsub.unsubscribe(&channel, self.id, false);
+        }
+        for pattern in self.patterns.drain() {
+            pubsub.unsubscribe(&pattern, self.id, true);
+        }
+    }
+
+    /// Returns the reply to a command that a RESP2 client can't send while subscribed, as its
+    /// connection is then reserved for messages, or `None` if the command is allowed.
+    pub(super) fn restrict(&self, command: &Command) -> Option<Frame> {
+        if self.resp3 || !self.is_subscribed() {
+            return None;
+        }
+        match command {
+            Command::Quit | Command::Reset | Command::Subscribe(..) | Command::Unsubscribe(..) => {
+                None
+            }
+            Command::Ping => Some(Frame::Array(Some(vec![
+                Frame::Bulk(Some("pong".into())),
+                Frame::Bulk(Some(Bytes::new())),
+            ]))),
+            _ => {
+                let msg =
+                    "only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT / RESET are allowed in \
+                           this context";
+                Some(ErrorReply::err(msg).into())
+            }
+        }
+    }
+
+    /// Confirms a change to the client's subscriptions, along with how many it now has.
+    fn confirmation(&self, kind: &'static str, name: Option<Bytes>) -> Frame {
+        let count = self.channels.len() + self.patterns.len();
+        Frame::Push(vec![
+            Frame::Bulk(Some(kind.into())),
+            Frame::Bulk(name),
+            Frame::Integer(count as i64),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::PubSubCommand, db::Db};
+
+    #[tokio::test]
+    async fn subscribers_are_counted_until_they_disconnect() {
+        let db = Db::new();
+        let (mut subscriber, mut messages) = db.client();
+        let subscribe = Command::Subscribe(vec!["news".into()], true);
+        db.apply_batch(&mut subscriber, vec![subscribe]).await;
+        let subscribe = Command::Subscribe(vec!["news".into(), "weather".into()], false);
+        db.apply_batch(&mut subscriber, vec![subscribe]).await;
+
+        let (mut publisher, _) = db.client();
+        let commands = vec![
+            Command::Publish("news".into(), "hello".into()),
+            Command::PubSub(PubSubCommand::NumPat),
+        ];
+        let replies = db.apply_batch(&mut publisher, commands).await;
+        assert_eq!(vec![Frame::Integer(2), Frame::Integer(1)], replies);
+        assert!(matches!(messages.recv().await, Some(Frame::Push(m)) if m.len() == 3));
+        assert!(matches!(messages.recv().await, Some(Frame::Push(m)) if m.len() == 4));
+
+        drop(subscriber);
+        let commands = vec![
+            Command::PubSub(PubSubCommand::NumSub(vec!["news".into()])),
+            Command::PubSub(PubSubCommand::NumPat),
+            Command::Publish("news".into(), "hello".into()),
+        ];
+        let replies = db.apply_batch(&mut publisher, commands).await;
+        let numsub = Frame::Map(vec![Frame::Bulk(Some("news".into())), Frame::Integer(0)]);
+        assert_eq!(vec![numsub, Frame::Integer(0), Frame::Integer(0)], replies);
+    }
+}
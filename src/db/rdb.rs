@@ -0,0 +1,919 @@
+mod listpack;
+mod ziplist;
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs,
+    io::{self, ErrorKind::InvalidData},
+    ops::Bound,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::{Access, EncodingLimits, Entry, Hash, Set, SortedSet, State, Stream, Value};
+use crate::command::StreamId;
+
+// https://rdb.fnordig.de/file_format.html
+
+const MAGIC: &[u8] = b"REDIS";
+/// The version written, that of Redis 7.2. Any earlier version can be loaded.
+const VERSION: u32 = 11;
+
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// How a node of a quicklist holds its elements: a single large one as is, or several in a
+/// listpack.
+const QUICKLIST_NODE_PLAIN: usize = 1;
+const QUICKLIST_NODE_PACKED: usize = 2;
+
+/// How many entries each listpack of a stream holds at most, like Redis's default
+/// `stream-node-max-entries`.
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+/// The special encodings of strings, flagged by the top two bits of their length being set.
+const ENCODING_INT8: u8 = 0;
+const ENCODING_INT16: u8 = 1;
+const ENCODING_INT32: u8 = 2;
+const ENCODING_LZF: u8 = 3;
+
+impl State {
+    /// Serializes every key that hasn't expired by `now` in the RDB format.
+    ///
+    /// Collections are written in their plain encoding rather than as listpacks or intsets, which
+    /// Redis loads all the same, except for streams, which Redis only encodes as listpacks.
+    pub(super) fn snapshot(&self, now: i64) -> Vec<u8> {
+        let entries: Vec<_> = self
+            .keystore
+            .iter()
+            .filter(|(_, e)| match e.expires_at {
+                Some(t) => t > now,
+                None => true,
+            })
+            .collect();
+        let expires = entries.iter().filter(|(_, e)| e.expires_at.is_some());
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(format!("{VERSION:04}").as_bytes());
+        let ctime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        for (field, value) in [
+            ("redis-ver", "7.2.0".to_string()),
+            ("redis-bits", usize::BITS.to_string()),
+            ("ctime", ctime.to_string()),
+        ] {
+            out.push(OPCODE_AUX);
+            write_string(&mut out, field.as_bytes());
+            write_string(&mut out, value.as_bytes());
+        }
+        out.push(OPCODE_SELECTDB);
+        write_length(&mut out, 0);
+        out.push(OPCODE_RESIZEDB);
+        write_length(&mut out, entries.len());
+        write_length(&mut out, expires.count());
+
+        for (key, entry) in entries {
+            if let Some(t) = entry.expires_at {
+                out.push(OPCODE_EXPIRETIME_MS);
+                out.extend_from_slice(&t.to_le_bytes());
+            }
+            let kind = match &entry.value {
+                Value::String(_) => TYPE_STRING,
+                Value::List(_) => TYPE_LIST,
+                Value::Set(_) => TYPE_SET,
+                Value::Hash(_) => TYPE_HASH,
+                Value::SortedSet(_) => TYPE_ZSET_2,
+                Value::Stream(_) => TYPE_STREAM_LISTPACKS_3,
+            };
+            out.push(kind);
+            write_string(&mut out, key);
+            entry.value.write(&mut out);
+        }
+
+        out.push(OPCODE_EOF);
+        let checksum = crc64(0, &out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// Loads the keys of database 0 from an RDB file, skipping those that expired before `now`.
+    ///
+    /// Only the plain encodings of collections, as written by `snapshot`, are supported, so files
+    /// saved by Redis itself can only be loaded if they hold nothing but strings and streams.
+    pub(super) fn load(&mut self, data: &[u8], now: i64) -> io::Result<()> {
+        let mut reader = Reader { data };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("not an RDB file"));
+        }
+        let version = std::str::from_utf8(reader.bytes(4)?)
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(|| invalid("invalid RDB version"))?;
+        if version > VERSION {
+            return Err(invalid("unsupported RDB version"));
+        }
+
+        let mut db = 0;
+        let mut expires_at = None;
+        loop {
+            let kind = reader.byte()?;
+            match kind {
+                OPCODE_IDLE => {
+                    reader.length()?;
+                    continue;
+                }
+                OPCODE_FREQ => {
+                    reader.byte()?;
+                    continue;
+                }
+                OPCODE_AUX => {
+                    reader.string()?;
+                    reader.string()?;
+                    continue;
+                }
+                OPCODE_RESIZEDB => {
+                    reader.length()?;
+                    reader.length()?;
+                    continue;
+                }
+                OPCODE_EXPIRETIME_MS => {
+                    expires_at = Some(i64::from_le_bytes(reader.array()?));
+                    continue;
+                }
+                OPCODE_EXPIRETIME => {
+                    let seconds = u32::from_le_bytes(reader.array()?);
+                    expires_at = Some(i64::from(seconds) * 1000);
+                    continue;
+                }
+                OPCODE_SELECTDB => {
+                    db = reader.length()?;
+                    continue;
+                }
+                OPCODE_EOF => break,
+                _ => (),
+            }
+            let key = reader.string()?;
+            let value = reader.value(kind, &self.config.encoding_limits)?;
+            let expires_at = expires_at.take();
+            let expired = expires_at.is_some_and(|t| t <= now);
+            if db != 0 || expired || value.is_empty() {
+                continue;
+            }
+            let entry = Entry {
+                value,
+                expires_at,
+                access: Access::new(now),
+                size: 0,
+            };
+            self.insert(key, entry);
+        }
+
+        // checksums were added in version 5, and are zero in files written with `rdbchecksum no`
+        if version < 5 {
+            return Ok(());
+        }
+        let checksummed = data.len() - reader.data.len();
+        let checksum = u64::from_le_bytes(reader.array()?);
+        if checksum != 0 && checksum != crc64(0, &data[..checksummed]) {
+            return Err(invalid("wrong RDB checksum"));
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    /// Serializes the value, but not its type, in the RDB format.
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::String(value) => write_string(out, value),
+            Value::List(list) => {
+                write_length(out, list.len());
+                list.iter().for_each(|e| write_string(out, e));
+            }
+            Value::Set(set) => {
+                write_length(out, set.len());
+                set.iter().for_each(|m| write_string(out, &m));
+            }
+            Value::Hash(hash) => {
+                write_length(out, hash.len());
+                for (field, value) in hash.iter() {
+                    write_string(out, field);
+                    write_string(out, value);
+                }
+            }
+            Value::SortedSet(zset) => {
+                write_length(out, zset.len());
+                for (member, score) in zset.iter() {
+                    write_string(out, member);
+                    out.extend_from_slice(&score.to_le_bytes());
+                }
+            }
+            Value::Stream(stream) => write_stream(out, stream),
+        }
+    }
+
+    /// Returns how many bytes the value takes in a snapshot, as reported by `DEBUG OBJECT`.
+    pub(super) fn serialized_len(&self) -> usize {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out.len()
+    }
+}
+
+/// Writes `data` to `path` atomically, through a temporary file that then replaces it.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temp = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path)
+}
+
+/// Writes a length, using 1, 2, 5 or 9 bytes depending on how large it is.
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    match len {
+        len if len < 1 << 6 => out.push(len as u8),
+        len if len < 1 << 14 => out.extend_from_slice(&(len as u16 | 0x4000).to_be_bytes()),
+        len if len <= u32::MAX as usize => {
+            out.push(0x80);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+        len => {
+            out.push(0x81);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, string: &[u8]) {
+    write_length(out, string.len());
+    out.extend_from_slice(string);
+}
+
+/// Writes a stream ID as two lengths.
+fn write_id(out: &mut Vec<u8>, id: StreamId) {
+    write_length(out, id.ms as usize);
+    write_length(out, id.seq as usize);
+}
+
+/// Encodes a stream ID as 16 big-endian bytes, which is how Redis keys the nodes of a stream and
+/// writes the IDs of pending entries.
+fn encode_id(id: StreamId) -> [u8; 16] {
+    let mut encoded = [0; 16];
+    encoded[..8].copy_from_slice(&id.ms.to_be_bytes());
+    encoded[8..].copy_from_slice(&id.seq.to_be_bytes());
+    encoded
+}
+
+fn decode_id(encoded: [u8; 16]) -> StreamId {
+    StreamId {
+        ms: u64::from_be_bytes(encoded[..8].try_into().unwrap()),
+        seq: u64::from_be_bytes(encoded[8..].try_into().unwrap()),
+    }
+}
+
+/// Writes a stream the way Redis 7.2 does: its entries in listpacks, its metadata, then its
+/// consumer groups.
+fn write_stream(out: &mut Vec<u8>, stream: &Stream) {
+    let entries: Vec<_> = stream.entries().collect();
+    write_length(out, entries.len().div_ceil(STREAM_NODE_MAX_ENTRIES));
+    for node in entries.chunks(STREAM_NODE_MAX_ENTRIES) {
+        write_string(out, &encode_id(*node[0].0));
+        write_string(out, &listpack::write_stream_node(node));
+    }
+    write_length(out, stream.len());
+    write_id(out, stream.last_id());
+    write_id(out, entries.first().map_or(StreamId::MIN, |(id, _)| **id));
+    // deleted entries aren't tracked, so the stream is written as if there were none, i.e. with no
+    // largest deleted ID, and as many entries ever added as it has
+    write_id(out, StreamId::MIN);
+    write_length(out, stream.len());
+
+    write_length(out, stream.groups().count());
+    for (name, group) in stream.groups() {
+        write_string(out, name);
+        write_id(out, group.last_delivered());
+        let read = (Bound::Unbounded, Bound::Included(group.last_delivered()));
+        write_length(out, stream.range(read.0, read.1).count());
+
+        let mut pending_per_consumer: HashMap<&Bytes, Vec<(StreamId, i64)>> = HashMap::new();
+        write_length(out, group.pending().count());
+        for (id, pending) in group.pending() {
+            out.extend_from_slice(&encode_id(*id));
+            out.extend_from_slice(&pending.delivered_at.to_le_bytes());
+            write_length(out, pending.deliveries as usize);
+            let consumer = pending_per_consumer.entry(&pending.consumer).or_default();
+            consumer.push((*id, pending.delivered_at));
+        }
+        write_length(out, group.consumers().count());
+        for consumer in group.consumers() {
+            let pending = pending_per_consumer.remove(consumer).unwrap_or_default();
+            write_string(out, consumer);
+            // when consumers were last seen and active isn't tracked, so it's taken to be when
+            // they were last delivered an entry still pending
+            let seen = pending.iter().map(|(_, at)| *at).max().unwrap_or(0);
+            out.extend_from_slice(&seen.to_le_bytes());
+            out.extend_from_slice(&seen.to_le_bytes());
+            write_length(out, pending.len());
+            for (id, _) in pending {
+                out.extend_from_slice(&encode_id(id));
+            }
+        }
+    }
+}
+
+/// Reads the contents of an RDB file.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+/// A length, or the special encoding of a string if the top two bits are set.
+enum Length {
+    Plain(usize),
+    Encoded(u8),
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(invalid("unexpected end of RDB file"));
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("N bytes were read"))
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn length_or_encoding(&mut self) -> io::Result<Length> {
+        let first = self.byte()?;
+        let len = match first >> 6 {
+            0 => u64::from(first & 0x3F),
+            1 => u64::from(u16::from_be_bytes([first & 0x3F, self.byte()?])),
+            2 if first == 0x80 => u64::from(u32::from_be_bytes(self.array()?)),
+            2 if first == 0x81 => u64::from_be_bytes(self.array()?),
+            2 => return Err(invalid("invalid RDB length")),
+            _ => return Ok(Length::Encoded(first & 0x3F)),
+        };
+        let len = len
+            .try_into()
+            .map_err(|_| invalid("RDB length too large"))?;
+        Ok(Length::Plain(len))
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        match self.length_or_encoding()? {
+            Length::Plain(len) => Ok(len),
+            Length::Encoded(_) => Err(invalid("expected a length, not an encoded string")),
+        }
+    }
+
+    fn string(&mut self) -> io::Result<Bytes> {
+        let integer = match self.length_or_encoding()? {
+            Length::Plain(len) => return Ok(Bytes::copy_from_slice(self.bytes(len)?)),
+            Length::Encoded(ENCODING_INT8) => i64::from(self.byte()? as i8),
+            Length::Encoded(ENCODING_INT16) => i64::from(i16::from_le_bytes(self.array()?)),
+            Length::Encoded(ENCODING_INT32) => i64::from(i32::from_le_bytes(self.array()?)),
+            Length::Encoded(ENCODING_LZF) => {
+                let compressed_len = self.length()?;
+                let len = self.length()?;
+                let compressed = self.bytes(compressed_len)?;
+                return lzf_decompress(compressed, len)
+                    .map(Bytes::from)
+                    .ok_or_else(|| invalid("invalid LZF-compressed string"));
+            }
+            Length::Encoded(_) => return Err(invalid("unknown RDB string encoding")),
+        };
+        Ok(integer.to_string().into())
+    }
+
+    /// Reads a zset score, written as text by the original zset type.
+    fn text_score(&mut self) -> io::Result<f64> {
+        match self.byte()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => std::str::from_utf8(self.bytes(len.into())?)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| invalid("invalid zset score")),
+        }
+    }
+
+    /// Reads the elements of a listpack, written as a string.
+    fn listpack(&mut self) -> io::Result<Vec<Bytes>> {
+        listpack::read(&self.string()?).ok_or_else(|| invalid("invalid listpack"))
+    }
+
+    /// Reads the entries of a ziplist, written as a string.
+    fn ziplist(&mut self) -> io::Result<Vec<Bytes>> {
+        ziplist::read(&self.string()?).ok_or_else(|| invalid("invalid ziplist"))
+    }
+
+    /// Reads the members of an intset, written as a string: the size of its integers, how many
+    /// there are, then each of them, all little-endian.
+    fn intset(&mut self) -> io::Result<Vec<Bytes>> {
+        let intset = self.string()?;
+        let mut reader = Reader { data: &intset };
+        let size = u32::from_le_bytes(reader.array()?) as usize;
+        let len = u32::from_le_bytes(reader.array()?) as usize;
+        if !matches!(size, 2 | 4 | 8) || reader.data.len() != size * len {
+            return Err(invalid("invalid intset"));
+        }
+        let members = reader.data.chunks(size).map(|int| {
+            let mut bytes = [0; 8];
+            bytes[..size].copy_from_slice(int);
+            let shift = 64 - 8 * size as u32;
+            (i64::from_le_bytes(bytes) << shift >> shift)
+                .to_string()
+                .into()
+        });
+        Ok(members.collect())
+    }
+
+    /// Reads a list of type `kind`, split in nodes that are each a ziplist, or in the second
+    /// version, a listpack or a single element.
+    fn quicklist(&mut self, kind: u8) -> io::Result<VecDeque<Bytes>> {
+        let mut list = VecDeque::new();
+        for _ in 0..self.length()? {
+            match kind {
+                TYPE_LIST_QUICKLIST => list.extend(self.ziplist()?),
+                _ => match self.length()? {
+                    QUICKLIST_NODE_PLAIN => list.push_back(self.string()?),
+                    QUICKLIST_NODE_PACKED => list.extend(self.listpack()?),
+                    _ => return Err(invalid("invalid quicklist node")),
+                },
+            }
+        }
+        Ok(list)
+    }
+
+    /// Reads a stream ID written as two lengths.
+    fn id(&mut self) -> io::Result<StreamId> {
+        Ok(StreamId {
+            ms: self.length()? as u64,
+            seq: self.length()? as u64,
+        })
+    }
+
+    /// Reads a stream of type `kind`, where versions after the first hold more metadata, none of
+    /// which is kept.
+    fn stream(&mut self, kind: u8) -> io::Result<Stream> {
+        let mut stream = Stream::default();
+        for _ in 0..self.length()? {
+            let master_id = self.string()?[..]
+                .try_into()
+                .map(decode_id)
+                .map_err(|_| invalid("invalid stream node key"))?;
+            let listpack = self.string()?;
+            let entries = listpack::read_stream_node(master_id, &listpack)
+                .ok_or_else(|| invalid("invalid stream listpack"))?;
+            for (id, fields) in entries {
+                if id <= stream.last_id() && stream.len() > 0 {
+                    return Err(invalid("stream entries out of order"));
+                }
+                stream.insert(id, fields);
+            }
+        }
+        self.length()?;
+        let last_id = self.id()?;
+        if last_id < stream.last_id() {
+            return Err(invalid("stream last ID below its entries"));
+        }
+        stream.set_last_id(last_id);
+        if kind != TYPE_STREAM_LISTPACKS {
+            self.id()?; // the first ID
+            self.id()?; // the largest deleted ID
+            self.length()?; // how many entries were ever added
+        }
+
+        for _ in 0..self.length()? {
+            let name = self.string()?;
+            let last_delivered = self.id()?;
+            if kind != TYPE_STREAM_LISTPACKS {
+                self.length()?; // how many entries were read
+            }
+            if !stream.create_group(name.clone(), last_delivered) {
+                return Err(invalid("duplicate stream consumer group"));
+            }
+            let group = stream.group(&name).expect("the group was just created");
+            let mut pending = BTreeMap::new();
+            for _ in 0..self.length()? {
+                let id = decode_id(self.array()?);
+                let delivered_at = i64::from_le_bytes(self.array()?);
+                pending.insert(id, (delivered_at, self.length()? as u64));
+            }
+            for _ in 0..self.length()? {
+                let consumer = self.string()?;
+                self.array::<8>()?; // when it was last seen
+                if kind == TYPE_STREAM_LISTPACKS_3 {
+                    self.array::<8>()?; // when it was last active
+                }
+                group.consumer(&consumer);
+                for _ in 0..self.length()? {
+                    let id = decode_id(self.array()?);
+                    let (delivered_at, deliveries) = pending.remove(&id).ok_or_else(|| {
+                        invalid("stream consumer entry not pending for its group")
+                    })?;
+                    group.deliver(id, consumer.clone(), delivered_at, deliveries);
+                }
+            }
+            if !pending.is_empty() {
+                return Err(invalid("stream entry pending for no consumer"));
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Reads a value of type `kind`, encoding collections compactly if they fit within `limits`.
+    fn value(&mut self, kind: u8, limits: &EncodingLimits) -> io::Result<Value> {
+        Ok(match kind {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST => {
+                let len = self.length()?;
+                let list = (0..len).map(|_| self.string()).collect::<io::Result<_>>()?;
+                Value::List(list)
+            }
+            TYPE_SET => {
+                let len = self.length()?;
+                let set = (0..len)
+                    .map(|_| self.string())
+                    .collect::<io::Result<Vec<_>>>()?;
+                Value::Set(Set::collect(set, limits))
+            }
+            TYPE_HASH => {
+                let len = self.length()?;
+                let hash = (0..len)
+                    .map(|_| Ok((self.string()?, self.string()?)))
+                    .collect::<io::Result<Vec<_>>>()?;
+                Value::Hash(Hash::collect(hash, limits))
+            }
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = SortedSet::default();
+                for _ in 0..self.length()? {
+                    let member = self.string()?;
+                    let score = match kind {
+                        TYPE_ZSET => self.text_score()?,
+                        _ => f64::from_le_bytes(self.array()?),
+                    };
+                    if score.is_nan() {
+                        return Err(invalid("zset score is NaN"));
+                    }
+                    zset.insert(member, score, limits);
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                Value::Stream(self.stream(kind)?)
+            }
+            // the compact encodings Redis writes small collections in, and lists in any case
+            TYPE_LIST_ZIPLIST => Value::List(self.ziplist()?.into()),
+            TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => Value::List(self.quicklist(kind)?),
+            TYPE_SET_INTSET => Value::Set(Set::collect(self.intset()?, limits)),
+            TYPE_SET_LISTPACK => Value::Set(Set::collect(self.listpack()?, limits)),
+            TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+                let fields = match kind {
+                    TYPE_HASH_ZIPLIST => self.ziplist()?,
+                    _ => self.listpack()?,
+                };
+                Value::Hash(Hash::collect(pairs(fields)?, limits))
+            }
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let members = match kind {
+                    TYPE_ZSET_ZIPLIST => self.ziplist()?,
+                    _ => self.listpack()?,
+                };
+                let mut zset = SortedSet::default();
+                for (member, score) in pairs(members)? {
+                    let score = std::str::from_utf8(&score)
+                        .ok()
+                        .and_then(|s| s.parse::<f64>().ok())
+                        .filter(|s| !s.is_nan())
+                        .ok_or_else(|| invalid("invalid zset score"))?;
+                    zset.insert(member, score, limits);
+                }
+                Value::SortedSet(zset)
+            }
+            kind => {
+                let msg = format!("unsupported RDB value type {kind}");
+                return Err(io::Error::new(InvalidData, msg));
+            }
+        })
+    }
+}
+
+/// Pairs up the elements of an encoded hash or sorted set, which alternate between fields and values,
+/// or members and scores.
+fn pairs(elements: Vec<Bytes>) -> io::Result<Vec<(Bytes, Bytes)>> {
+    let mut elements = elements.into_iter();
+    let mut pairs = Vec::with_capacity(elements.len() / 2);
+    while let Some(first) = elements.next() {
+        let second = elements
+            .next()
+            .ok_or_else(|| invalid("odd number of elements in an encoded hash or zset"))?;
+        pairs.push((first, second));
+    }
+    Ok(pairs)
+}
+
+/// Decompresses data compressed with LZF, which Redis uses for long strings, returning `None` if it
+/// is malformed or doesn't decompress to `len` bytes.
+fn lzf_decompress(mut input: &[u8], len: usize) -> Option<Vec<u8>> {
+    // the length comes from the file, so the output is grown as it fills rather than allocated up
+    // front, and given up on as soon as it exceeds the length
+    let mut out = Vec::new();
+    while let Some((&control, rest)) = input.split_first() {
+        if out.len() > len {
+            return None;
+        }
+        input = rest;
+        if control < 1 << 5 {
+            // a run of literal bytes
+            let len = usize::from(control) + 1;
+            if len > input.len() {
+                return None;
+            }
+            out.extend_from_slice(&input[..len]);
+            input = &input[len..];
+            continue;
+        }
+        // a back reference to bytes already decompressed
+        let mut run = usize::from(control >> 5);
+        if run == 7 {
+            let (&extra, rest) = input.split_first()?;
+            run += usize::from(extra);
+            input = rest;
+        }
+        let (&low, rest) = input.split_first()?;
+        input = rest;
+        let offset = (usize::from(control & 0x1F) << 8) + usize::from(low) + 1;
+        let start = out.len().checked_sub(offset)?;
+        // the run may overlap the bytes it copies, so they are copied one at a time
+        for i in start..start + run + 2 {
+            out.push(out[i]);
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+/// Computes the CRC-64 checksum Redis uses, with the Jones polynomial.
+fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    // the polynomial's bits reversed, as the checksum is computed least significant bit first
+    const POLY: u64 = 0x95AC_9329_AC4B_C9B5;
+    for &byte in data {
+        crc ^= u64::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    #[test]
+    fn crc64_matches_redis() {
+        assert_eq!(0xE9C6_D914_C4B8_D9CA, crc64(0, b"123456789"));
+    }
+
+    #[test]
+    fn lzf_decompresses_back_references() {
+        // "abc" then a reference copying 6 bytes from 3 bytes back
+        let compressed = [2, b'a', b'b', b'c', 4 << 5, 2];
+        assert_eq!(Some(b"abcabcabc".to_vec()), lzf_decompress(&compressed, 9));
+        assert_eq!(None, lzf_decompress(&compressed, 10));
+        // lengths come from the file, so they aren't trusted to allocate
+        assert_eq!(None, lzf_decompress(&compressed, usize::MAX));
+        assert_eq!(None, lzf_decompress(&compressed, 4));
+    }
+
+    #[test]
+    fn snapshots_are_loaded_back() {
+        let db = Db::new();
+        let mut state = db.state.lock().unwrap();
+        let limits = EncodingLimits::default();
+        let mut zset = SortedSet::default();
+        zset.insert("member".into(), 1.5, &limits);
+        let values = [
+            ("string", Value::String("value".into()), Some(i64::MAX)),
+            (
+                "list",
+                Value::List(VecDeque::from(["a".into(), "b".into()])),
+                None,
+            ),
+            ("set", Value::Set(Set::collect(["a".into()], &limits)), None),
+            (
+                "hash",
+                Value::Hash(Hash::collect([("f".into(), "v".into())], &limits)),
+                None,
+            ),
+            ("zset", Value::SortedSet(zset), None),
+            ("expired", Value::String("value".into()), Some(1)),
+        ];
+        for (key, value, expires_at) in values {
+            let access = Access::new(0);
+            state.insert(
+                key.into(),
+                Entry {
+                    value,
+                    expires_at,
+                    access,
+                    size: 0,
+                },
+            );
+        }
+        let snapshot = state.snapshot(2);
+
+        let loaded = Db::new();
+        let mut loaded = loaded.state.lock().unwrap();
+        loaded.load(&snapshot, 2).unwrap();
+        let mut keys: Vec<_> = loaded.keystore.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(vec!["hash", "list", "set", "string", "zset"], keys);
+        assert_eq!(
+            Some(i64::MAX),
+            loaded.keystore[&Bytes::from("string")].expires_at
+        );
+        assert!(matches!(
+            &loaded.keystore[&Bytes::from("zset")].value,
+            Value::SortedSet(zset) if zset.score(&"member".into()) == Some(1.5)
+        ));
+
+        let mut corrupted = snapshot.clone();
+        corrupted[20] ^= 1;
+        assert!(Db::new().state.lock().unwrap().load(&corrupted, 2).is_err());
+    }
+
+    #[test]
+    fn encoded_collections_are_loaded() {
+        let limits = EncodingLimits::default();
+        let load = |kind, value: &[u8]| {
+            let mut out = Vec::new();
+            write_string(&mut out, value);
+            Reader { data: &out }.value(kind, &limits).unwrap()
+        };
+        let list = |value| match value {
+            Value::List(list) => list.into_iter().collect::<Vec<_>>(),
+            _ => panic!("not a list"),
+        };
+
+        // 1, -2 and 3 as 16-bit integers
+        let intset = [2, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0xFE, 0xFF, 3, 0];
+        let Value::Set(set) = load(TYPE_SET_INTSET, &intset) else {
+            panic!("not a set");
+        };
+        assert_eq!(3, set.len());
+        assert!(set.contains(&"-2".into()));
+
+        let listpack = [13, 0, 0, 0, 2, 0, 0x81, b'a', 2, 0x81, b'b', 2, 0xFF];
+        let Value::Set(set) = load(TYPE_SET_LISTPACK, &listpack) else {
+            panic!("not a set");
+        };
+        assert!(set.contains(&"a".into()) && set.contains(&"b".into()));
+
+        let ziplist = [17, 0, 0, 0, 13, 0, 0, 0, 2, 0, 0, 1, b'f', 3, 1, b'v', 0xFF];
+        let Value::Hash(hash) = load(TYPE_HASH_ZIPLIST, &ziplist) else {
+            panic!("not a hash");
+        };
+        assert_eq!(Some(&"v".into()), hash.get(&"f".into()));
+
+        let listpack = [
+            15, 0, 0, 0, 2, 0, 0x81, b'm', 2, 0x83, b'1', b'.', b'5', 4, 0xFF,
+        ];
+        let Value::SortedSet(zset) = load(TYPE_ZSET_LISTPACK, &listpack) else {
+            panic!("not a sorted set");
+        };
+        assert_eq!(Some(1.5), zset.score(&"m".into()));
+
+        // "x", then 7 held in its encoding, then 300 as a 16-bit integer
+        let ziplist = [
+            20, 0, 0, 0, 15, 0, 0, 0, 3, 0, 0, 1, b'x', 3, 0xF8, 2, 0xC0, 0x2C, 0x01, 0xFF,
+        ];
+        assert_eq!(
+            vec!["x", "7", "300"],
+            list(load(TYPE_LIST_ZIPLIST, &ziplist))
+        );
+        let mut quicklist = Vec::new();
+        write_length(&mut quicklist, 1);
+        write_string(&mut quicklist, &ziplist);
+        let loaded = Reader { data: &quicklist }.value(TYPE_LIST_QUICKLIST, &limits);
+        assert_eq!(vec!["x", "7", "300"], list(loaded.unwrap()));
+
+        // a node packing "x" and 7, then a plain one
+        let listpack = [12, 0, 0, 0, 2, 0, 0x81, b'x', 2, 0x07, 1, 0xFF];
+        let mut quicklist = Vec::new();
+        write_length(&mut quicklist, 2);
+        write_length(&mut quicklist, QUICKLIST_NODE_PACKED);
+        write_string(&mut quicklist, &listpack);
+        write_length(&mut quicklist, QUICKLIST_NODE_PLAIN);
+        write_string(&mut quicklist, b"plain");
+        let loaded = Reader { data: &quicklist }.value(TYPE_LIST_QUICKLIST_2, &limits);
+        assert_eq!(vec!["x", "7", "plain"], list(loaded.unwrap()));
+
+        let truncated = &listpack[..listpack.len() - 1];
+        let mut out = Vec::new();
+        write_string(&mut out, truncated);
+        let loaded = Reader { data: &out }.value(TYPE_SET_LISTPACK, &limits);
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn streams_are_loaded_back() {
+        let id = |ms, seq| StreamId { ms, seq };
+        let mut stream = Stream::default();
+        // enough entries for more than one listpack, some with the fields of the first, some not,
+        // and some with values long or numeric enough to be encoded differently
+        for i in 0..150u64 {
+            let mut fields = vec![("f".into(), i.to_string().into())];
+            if i % 3 == 0 {
+                fields.push(("long".into(), "x".repeat(i as usize * 40).into()));
+            }
+            stream.insert(id(1 << 40, i * 1000), fields);
+        }
+        stream.insert(
+            id(u64::MAX, 0),
+            vec![("f".into(), (-5000).to_string().into())],
+        );
+        stream.set_last_id(id(u64::MAX, 7));
+        let (group, alice, bob) = (Bytes::from("g"), Bytes::from("alice"), Bytes::from("bob"));
+        stream.create_group(group.clone(), id(1 << 40, 2000));
+        stream.read_group(&group, &alice, None, 2, false, 100);
+        stream.read_group(&group, &bob, None, 0, false, 100);
+
+        let mut out = Vec::new();
+        Value::Stream(stream).write(&mut out);
+        let limits = EncodingLimits::default();
+        let loaded = Reader { data: &out }.value(TYPE_STREAM_LISTPACKS_3, &limits);
+        let Ok(Value::Stream(mut loaded)) = loaded else {
+            panic!("the stream wasn't loaded back");
+        };
+        assert_eq!(151, loaded.len());
+        assert_eq!(id(u64::MAX, 7), loaded.last_id());
+        let (last_id, last_fields) = loaded.entries().last().unwrap();
+        assert_eq!(id(u64::MAX, 0), *last_id);
+        assert_eq!(&vec![("f".into(), "-5000".into())], last_fields);
+        let fields = loaded.get(&id(1 << 40, 99_000)).unwrap();
+        assert_eq!(&Bytes::from("x".repeat(99 * 40)), &fields[1].1);
+
+        let group = loaded.group(&group).unwrap();
+        assert_eq!(id(1 << 40, 4000), group.last_delivered());
+        let mut consumers: Vec<_> = group.consumers().cloned().collect();
+        consumers.sort();
+        assert_eq!(vec![alice.clone(), bob], consumers);
+        let pending: Vec<_> = group
+            .pending()
+            .map(|(id, p)| (*id, p.consumer.clone(), p.delivered_at, p.deliveries))
+            .collect();
+        assert_eq!(
+            vec![
+                (id(1 << 40, 3000), alice.clone(), 100, 1),
+                (id(1 << 40, 4000), alice, 100, 1)
+            ],
+            pending
+        );
+    }
+}
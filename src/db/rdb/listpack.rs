@@ -0,0 +1,291 @@
+use bytes::Bytes;
+
+use crate::command::StreamId;
+
+// https://github.com/antirez/listpack/blob/master/listpack.md
+// https://github.com/redis/redis/blob/7.2/src/t_stream.c, for how streams use listpacks
+
+const EOF: u8 = 0xFF;
+
+/// How the entries of a stream are flagged, relative to the master entry of their listpack.
+const FLAG_DELETED: i64 = 1;
+const FLAG_SAME_FIELDS: i64 = 2;
+
+/// The fields of a stream entry, paired with their values.
+type Fields = Vec<(Bytes, Bytes)>;
+
+/// An element of a listpack, which Redis stores as an integer whenever it can.
+enum Element<'a> {
+    Int(i64),
+    String(&'a [u8]),
+}
+
+impl Element<'_> {
+    fn int(&self) -> Option<i64> {
+        match self {
+            Element::Int(int) => Some(*int),
+            Element::String(s) => std::str::from_utf8(s).ok()?.parse().ok(),
+        }
+    }
+
+    fn bytes(&self) -> Bytes {
+        match self {
+            Element::Int(int) => int.to_string().into(),
+            Element::String(s) => Bytes::copy_from_slice(s),
+        }
+    }
+}
+
+/// Builds a listpack, element by element.
+#[derive(Default)]
+struct Writer {
+    elements: Vec<u8>,
+    len: usize,
+}
+
+impl Writer {
+    fn int(&mut self, int: i64) {
+        let start = self.elements.len();
+        match int {
+            0..=127 => self.elements.push(int as u8),
+            -4096..=4095 => {
+                let int = int as u16 & 0x1FFF;
+                self.elements
+                    .extend_from_slice(&[0xC0 | (int >> 8) as u8, int as u8]);
+            }
+            -32768..=32767 => {
+                self.elements.push(0xF1);
+                self.elements.extend_from_slice(&(int as i16).to_le_bytes());
+            }
+            -8388608..=8388607 => {
+                self.elements.push(0xF2);
+                self.elements
+                    .extend_from_slice(&(int as i32).to_le_bytes()[..3]);
+            }
+            -2147483648..=2147483647 => {
+                self.elements.push(0xF3);
+                self.elements.extend_from_slice(&(int as i32).to_le_bytes());
+            }
+            _ => {
+                self.elements.push(0xF4);
+                self.elements.extend_from_slice(&int.to_le_bytes());
+            }
+        }
+        self.end_element(start);
+    }
+
+    fn string(&mut self, string: &[u8]) {
+        let start = self.elements.len();
+        match string.len() {
+            len if len < 1 << 6 => self.elements.push(0x80 | len as u8),
+            len if len < 1 << 12 => {
+                self.elements
+                    .extend_from_slice(&[0xE0 | (len >> 8) as u8, len as u8]);
+            }
+            len => {
+                self.elements.push(0xF0);
+                self.elements.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+        self.elements.extend_from_slice(string);
+        self.end_element(start);
+    }
+
+    /// Follows the element starting at `start` with its length, which lets listpacks be traversed
+    /// backwards.
+    fn end_element(&mut self, start: usize) {
+        let len = self.elements.len() - start;
+        // 7 bits per byte, the most significant first, where every byte but it is flagged as
+        // continuing the length when read backwards
+        let size = backlen_size(len);
+        for i in (0..size).rev() {
+            let flag = if i == size - 1 { 0 } else { 0x80 };
+            self.elements.push((len >> (7 * i)) as u8 & 0x7F | flag);
+        }
+        self.len += 1;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let total = 4 + 2 + self.elements.len() + 1;
+        let mut out = Vec::with_capacity(total);
+        out.extend_from_slice(&(total as u32).to_le_bytes());
+        // listpacks with too many elements to count in the header must be traversed to count them
+        out.extend_from_slice(&(self.len.min(u16::MAX as usize) as u16).to_le_bytes());
+        out.extend_from_slice(&self.elements);
+        out.push(EOF);
+        out
+    }
+}
+
+/// Returns how many bytes the length of an element of `len` bytes takes, as Redis encodes it.
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        len if len < 16383 => 2,
+        len if len < 2097151 => 3,
+        len if len < 268435455 => 4,
+        _ => 5,
+    }
+}
+
+/// Reads the elements of a listpack.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(listpack: &'a [u8]) -> Option<Self> {
+        let total = u32::from_le_bytes(listpack.get(..4)?.try_into().unwrap());
+        if total as usize != listpack.len() || listpack.last() != Some(&EOF) {
+            return None;
+        }
+        Some(Reader {
+            data: &listpack[6..listpack.len() - 1],
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    /// Reads an integer of `n` little-endian bytes, sign-extending it.
+    fn signed(&mut self, n: usize) -> Option<i64> {
+        let mut bytes = [0; 8];
+        bytes[..n].copy_from_slice(self.bytes(n)?);
+        let shift = 64 - 8 * n as u32;
+        Some(i64::from_le_bytes(bytes) << shift >> shift)
+    }
+
+    fn next(&mut self) -> Option<Element<'a>> {
+        let start = self.data.len();
+        let first = *self.bytes(1)?.first()?;
+        let element = match first {
+            0x00..=0x7F => Element::Int(first.into()),
+            0x80..=0xBF => Element::String(self.bytes(usize::from(first & 0x3F))?),
+            0xC0..=0xDF => {
+                let int = u16::from_be_bytes([first & 0x1F, *self.bytes(1)?.first()?]);
+                // sign-extend the 13 bits
+                Element::Int(i64::from((int << 3) as i16 >> 3))
+            }
+            0xE0..=0xEF => {
+                let len = u16::from_be_bytes([first & 0x0F, *self.bytes(1)?.first()?]);
+                Element::String(self.bytes(len.into())?)
+            }
+            0xF0 => {
+                let len = u32::from_le_bytes(self.bytes(4)?.try_into().unwrap());
+                Element::String(self.bytes(len.try_into().ok()?)?)
+            }
+            0xF1 => Element::Int(self.signed(2)?),
+            0xF2 => Element::Int(self.signed(3)?),
+            0xF3 => Element::Int(self.signed(4)?),
+            0xF4 => Element::Int(self.signed(8)?),
+            _ => return None,
+        };
+        self.bytes(backlen_size(start - self.data.len()))?;
+        Some(element)
+    }
+
+    fn int(&mut self) -> Option<i64> {
+        self.next()?.int()
+    }
+}
+
+/// Decodes every element of a listpack, as Redis encodes small lists, sets, hashes and sorted sets.
+/// Returns `None` if it is malformed.
+pub fn read(listpack: &[u8]) -> Option<Vec<Bytes>> {
+    let mut reader = Reader::new(listpack)?;
+    let mut elements = Vec::new();
+    while !reader.is_empty() {
+        elements.push(reader.next()?.bytes());
+    }
+    Some(elements)
+}
+
+/// Encodes entries of a stream into a listpack, the way Redis stores each node of a stream: a
+/// master entry holding the fields of the first, then each entry relative to it.
+pub fn write_stream_node(entries: &[(&StreamId, &Fields)]) -> Vec<u8> {
+    let mut writer = Writer::default();
+    let (master_id, master_fields) = entries[0];
+    writer.int(entries.len() as i64);
+    writer.int(0); // deleted entries
+    writer.int(master_fields.len() as i64);
+    for (field, _) in master_fields {
+        writer.string(field);
+    }
+    writer.int(0);
+
+    for (id, fields) in entries {
+        let same_fields = fields.len() == master_fields.len()
+            && fields
+                .iter()
+                .zip(master_fields)
+                .all(|((f, _), (m, _))| f == m);
+        writer.int(if same_fields { FLAG_SAME_FIELDS } else { 0 });
+        writer.int(id.ms.wrapping_sub(master_id.ms) as i64);
+        writer.int(id.seq.wrapping_sub(master_id.seq) as i64);
+        if same_fields {
+            fields.iter().for_each(|(_, value)| writer.string(value));
+        } else {
+            writer.int(fields.len() as i64);
+            for (field, value) in fields.iter() {
+                writer.string(field);
+                writer.string(value);
+            }
+        }
+        // how many elements the entry has, for it to be traversed backwards
+        let len = if same_fields {
+            fields.len() + 3
+        } else {
+            2 * fields.len() + 4
+        };
+        writer.int(len as i64);
+    }
+    writer.finish()
+}
+
+/// Decodes the entries of a stream node encoded by `write_stream_node` (or Redis) whose master
+/// entry has `master_id`, skipping those flagged as deleted. Returns `None` if it is malformed.
+pub fn read_stream_node(master_id: StreamId, listpack: &[u8]) -> Option<Vec<(StreamId, Fields)>> {
+    let mut reader = Reader::new(listpack)?;
+    let count = reader.int()?;
+    let deleted = reader.int()?;
+    let master_fields = (0..reader.int()?)
+        .map(|_| Some(reader.next()?.bytes()))
+        .collect::<Option<Vec<_>>>()?;
+    if reader.int()? != 0 {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    while !reader.is_empty() {
+        let flags = reader.int()?;
+        let id = StreamId {
+            ms: master_id.ms.wrapping_add(reader.int()? as u64),
+            seq: master_id.seq.wrapping_add(reader.int()? as u64),
+        };
+        let fields = if flags & FLAG_SAME_FIELDS != 0 {
+            let fields = master_fields.iter();
+            fields
+                .map(|field| Some((field.clone(), reader.next()?.bytes())))
+                .collect::<Option<Vec<_>>>()?
+        } else {
+            (0..reader.int()?)
+                .map(|_| Some((reader.next()?.bytes(), reader.next()?.bytes())))
+                .collect::<Option<Vec<_>>>()?
+        };
+        reader.int()?;
+        if flags & FLAG_DELETED == 0 {
+            entries.push((id, fields));
+        }
+    }
+    (entries.len() as i64 == count && deleted >= 0).then_some(entries)
+}
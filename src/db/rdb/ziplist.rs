@@ -0,0 +1,89 @@
+use bytes::Bytes;
+
+// https://github.com/redis/redis/blob/6.2/src/ziplist.c, which Redis replaced with listpacks in 7.0
+
+const END: u8 = 0xFF;
+/// How the length of the previous entry is flagged as taking the 4 bytes after it.
+const PREVLEN_LONG: u8 = 0xFE;
+
+/// Reads the entries of a ziplist.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    /// Reads an integer of `n` little-endian bytes, sign-extending it.
+    fn signed(&mut self, n: usize) -> Option<i64> {
+        let mut bytes = [0; 8];
+        bytes[..n].copy_from_slice(self.bytes(n)?);
+        let shift = 64 - 8 * n as u32;
+        Some(i64::from_le_bytes(bytes) << shift >> shift)
+    }
+
+    /// Reads the next entry, which Redis stores as an integer whenever it can, or `None` once at
+    /// the end of the ziplist.
+    fn next(&mut self) -> Option<Option<Bytes>> {
+        let prevlen = self.byte()?;
+        if prevlen == END {
+            return Some(None);
+        }
+        if prevlen == PREVLEN_LONG {
+            self.bytes(4)?;
+        }
+        let encoding = self.byte()?;
+        let int = match encoding {
+            0x00..=0x3F => return self.string(usize::from(encoding)),
+            0x40..=0x7F => {
+                let len = u16::from_be_bytes([encoding & 0x3F, self.byte()?]);
+                return self.string(len.into());
+            }
+            0x80 => {
+                let len = u32::from_be_bytes(self.bytes(4)?.try_into().unwrap());
+                return self.string(len.try_into().ok()?);
+            }
+            0xC0 => self.signed(2)?,
+            0xD0 => self.signed(4)?,
+            0xE0 => self.signed(8)?,
+            0xF0 => self.signed(3)?,
+            0xFE => self.signed(1)?,
+            // the value, from 0 to 12, is held in the encoding itself
+            0xF1..=0xFD => i64::from(encoding & 0x0F) - 1,
+            _ => return None,
+        };
+        Some(Some(int.to_string().into()))
+    }
+
+    fn string(&mut self, len: usize) -> Option<Option<Bytes>> {
+        Some(Some(Bytes::copy_from_slice(self.bytes(len)?)))
+    }
+}
+
+/// Decodes every entry of a ziplist, as Redis before 7.0 encodes small lists, hashes and sorted
+/// sets. Returns `None` if it is malformed.
+pub fn read(ziplist: &[u8]) -> Option<Vec<Bytes>> {
+    let total = u32::from_le_bytes(ziplist.get(..4)?.try_into().unwrap());
+    if total as usize != ziplist.len() || ziplist.last() != Some(&END) {
+        return None;
+    }
+    let mut reader = Reader {
+        data: ziplist.get(10..)?,
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = reader.next()? {
+        entries.push(entry);
+    }
+    reader.data.is_empty().then_some(entries)
+}
@@ -0,0 +1,730 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    fmt::{Debug, Write},
+    hash::{BuildHasher, Hasher},
+    io::{self, ErrorKind::InvalidData},
+    net::SocketAddr,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{net::TcpStream, sync::Notify, task::JoinHandle};
+
+use super::{
+    now_ms,
+    output::{OutputLimit, Sender},
+    transaction::Replay,
+    Client, Db, State,
+};
+use crate::{
+    command::{Command, Error, ReplConfOption},
+    connection::{self, Connection},
+    frame::Frame,
+};
+
+/// How long a replica waits before reconnecting to its master after losing it.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How often a replica acknowledges its offset without being asked to, like Redis.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Both sides of replication, i.e. the replicas following this server's writes, and the master it
+/// follows, if any.
+pub struct Replication {
+    /// Identifies the history of writes, so that replicas can tell whether they follow this one.
+    pub id: Bytes,
+    /// The replicas, by client ID.
+    replicas: HashMap<u64, Replica>,
+    /// How many bytes of commands have been sent to replicas, which replicas acknowledge having
+    /// applied up to.
+    pub offset: u64,
+    /// Notified whenever a replica acknowledges its offset, for `WAIT`.
+    acks: Arc<Notify>,
+    /// The port this server listens on, which it tells its master about.
+    pub listening_port: u16,
+    master: Option<Master>,
+}
+
+struct Replica {
+    /// Where the write commands applied are sent.
+    messages: Sender,
+    /// How many bytes of commands the replica last acknowledged having applied.
+    acked: u64,
+    addr: Option<SocketAddr>,
+    listening_port: Option<u16>,
+}
+
+/// The master followed by this server, which stops being followed once dropped.
+struct Master {
+    host: String,
+    port: u16,
+    /// The task connecting to the master and applying the writes it propagates.
+    task: JoinHandle<()>,
+    /// How many bytes of commands have been applied from the master, or `None` until its dataset
+    /// has been loaded.
+    offset: Option<u64>,
+}
+
+impl Drop for Master {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication {
+            id: random_id(),
+            replicas: HashMap::new(),
+            offset: 0,
+            acks: Arc::new(Notify::new()),
+            listening_port: 6379,
+            master: None,
+        }
+    }
+}
+
+/// Returns a new replication ID, which like Redis is 40 random hexadecimal characters.
+fn random_id() -> Bytes {
+    let random = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}{:08x}", random(), random(), random() as u32).into()
+}
+
+impl Replication {
+    /// Starts a new history of writes, i.e. `DEBUG CHANGE-REPL-ID`, so that replicas following the
+    /// previous one must resync fully.
+    pub fn change_id(&mut self) {
+        self.id = random_id();
+    }
+
+    pub fn has_replicas(&self) -> bool {
+        !self.replicas.is_empty()
+    }
+
+    pub fn remove_replica(&mut self, client: u64) {
+        self.replicas.remove(&client);
+    }
+
+    /// Records that the replica `client` applied every command up to `offset`, i.e. `REPLCONF ACK`.
+    pub fn ack(&mut self, client: u64, offset: u64) {
+        if let Some(replica) = self.replicas.get_mut(&client) {
+            replica.acked = offset;
+            self.acks.notify_waiters();
+        }
+    }
+
+    /// Returns how many replicas acknowledged having applied every command up to `offset`.
+    pub fn acknowledged(&self, offset: u64) -> usize {
+        self.replicas.values().filter(|r| r.acked >= offset).count()
+    }
+
+    /// Sends `command` to every replica, dropping those that have disconnected or that it would put
+    /// over `limit`.
+    fn send(&mut self, command: Frame, limit: &OutputLimit) {
+        self.offset += connection::encoded_len(&command) as u64;
+        let replicas = &mut self.replicas;
+        replicas.retain(|_, replica| replica.messages.send(command.clone(), limit));
+    }
+
+    /// Whether the client `id` is a replica following this server.
+    pub fn is_replica_client(&self, id: u64) -> bool {
+        self.replicas.contains_key(&id)
+    }
+
+    /// Whether this server follows a master, and so only accepts writes from it.
+    pub fn is_replica(&self) -> bool {
+        self.master.is_some()
+    }
+
+    fn set_master_offset(&mut self, offset: u64) {
+        if let Some(master) = &mut self.master {
+            master.offset = Some(offset);
+        }
+    }
+
+    /// Renders the replication section of `INFO`.
+    pub fn info(&self, info: &mut String) {
+        match &self.master {
+            Some(master) => {
+                let link = if master.offset.is_some() {
+                    "up"
+                } else {
+                    "down"
+                };
+                let offset = master.offset.unwrap_or(0);
+                write!(info, "role:slave\r\n").unwrap();
+                write!(info, "master_host:{}\r\n", master.host).unwrap();
+                write!(info, "master_port:{}\r\n", master.port).unwrap();
+                write!(info, "master_link_status:{}\r\n", link).unwrap();
+                write!(info, "slave_repl_offset:{}\r\n", offset).unwrap();
+                write!(info, "master_repl_offset:{}\r\n", offset).unwrap();
+            }
+            None => {
+                write!(info, "role:master\r\n").unwrap();
+                write!(info, "connected_slaves:{}\r\n", self.replicas.len()).unwrap();
+                for (i, replica) in self.replicas.values().enumerate() {
+                    let ip = replica.addr.map(|a| a.ip().to_string()).unwrap_or_default();
+                    let port = replica.listening_port.unwrap_or(0);
+                    write!(
+                        info,
+                        "slave{}:ip={},port={},state=online,offset={}\r\n",
+                        i, ip, port, replica.acked
+                    )
+                    .unwrap();
+                }
+                write!(
+                    info,
+                    "master_replid:{}\r\n",
+                    String::from_utf8_lossy(&self.id)
+                )
+                .unwrap();
+                write!(info, "master_repl_offset:{}\r\n", self.offset).unwrap();
+            }
+        }
+    }
+
+    /// Returns the role of this server along with its replication offsets, i.e. `ROLE`.
+    pub fn role(&self) -> Frame {
+        let bulk = |s: String| Frame::Bulk(Some(s.into()));
+        if let Some(master) = &self.master {
+            let state = if master.offset.is_some() {
+                "connected"
+            } else {
+                "connecting"
+            };
+            return Frame::Array(Some(vec![
+                bulk("slave".into()),
+                bulk(master.host.clone()),
+                Frame::Integer(master.port.into()),
+                bulk(state.into()),
+                Frame::Integer(master.offset.map_or(-1, |o| o as i64)),
+            ]));
+        }
+        let replicas = self.replicas.values().map(|replica| {
+            let ip = replica.addr.map(|a| a.ip().to_string()).unwrap_or_default();
+            let port = replica
+                .listening_port
+                .map(|p| p.to_string())
+                .unwrap_or_default();
+            Frame::Array(Some(vec![
+                bulk(ip),
+                bulk(port),
+                bulk(replica.acked.to_string()),
+            ]))
+        });
+        Frame::Array(Some(vec![
+            bulk("master".into()),
+            Frame::Integer(self.offset as i64),
+            Frame::Array(Some(replicas.collect())),
+        ]))
+    }
+}
+
+impl Client {
+    /// Takes the snapshot of the dataset to send the client, if it just started replicating. It
+    /// must be sent right after the reply to `PSYNC`, before any write propagated to it.
+    pub fn take_full_sync(&mut self) -> Option<Vec<u8>> {
+        self.full_sync.take()
+    }
+}
+
+impl State {
+    /// Starts following this server's writes from `client`, replying with the ID of their history.
+    /// The client must then be sent a snapshot of the dataset, which the writes continue from.
+    ///
+    /// Unlike Redis, no backlog of writes is kept for replicas to resume from, so they are always
+    /// sent the whole dataset, streams and their consumer groups included.
+    pub(super) fn psync(&mut self, client: &mut Client, now: i64) -> Frame {
+        client.full_sync = Some(self.snapshot(now));
+        let replica = Replica {
+            messages: client.messages.clone(),
+            acked: self.replication.offset,
+            addr: self.addr(client),
+            listening_port: client.listening_port,
+        };
+        self.replication.replicas.insert(client.id, replica);
+        let id = String::from_utf8_lossy(&self.replication.id);
+        let reply = format!("FULLRESYNC {} {}", id, self.replication.offset);
+        Frame::String(reply.into())
+    }
+
+    /// Applies `REPLCONF` sent by `client`, returning its reply, or `None` if it shouldn't be replied
+    /// to.
+    pub(super) fn replconf(
+        &mut self,
+        client: &mut Client,
+        options: Vec<ReplConfOption>,
+    ) -> Option<Frame> {
+        let mut acked = false;
+        for option in options {
+            match option {
+                ReplConfOption::Ack(offset) => {
+                    self.replication.ack(client.id, offset);
+                    acked = true;
+                }
+                ReplConfOption::ListeningPort(port) => client.listening_port = Some(port),
+                // replicas answer their master's `GETACK` themselves, as its writes aren't replied to
+                ReplConfOption::Capa | ReplConfOption::GetAck => (),
+            }
+        }
+        (!acked).then(|| Frame::Bulk(Some("OK".into())))
+    }
+
+    /// Starts following `master`, or stops following any master if `None`, i.e. `REPLICAOF`. The
+    /// dataset is replaced by the master's once connected to it.
+    pub(super) fn replicate(
+        &mut self,
+        state: Weak<Mutex<State>>,
+        master: Option<(String, u16)>,
+    ) -> Frame {
+        let Some((host, port)) = master else {
+            self.replication.master = None;
+            return Frame::Bulk(Some("OK".into()));
+        };
+        if let Some(master) = &self.replication.master {
+            if master.host == host && master.port == port {
+                return Frame::Bulk(Some("OK Already connected to specified master".into()));
+            }
+        }
+        let listening_port = self.replication.listening_port;
+        let task = tokio::spawn(follow(state, host.clone(), port, listening_port));
+        self.replication.master = Some(Master {
+            host,
+            port,
+            task,
+            offset: None,
+        });
+        Frame::Bulk(Some("OK".into()))
+    }
+
+    /// Sends a write command that was applied to the AOF and replicas, in a form that applies the
+    /// same way later.
+    pub(super) fn propagate(&mut self, command: Frame, now: i64) {
+        if self.replication.has_replicas() {
+            let limit = &self.config.client_output_buffer_limit.replica;
+            self.replication.send(command.clone(), limit);
+        }
+        if let Some(aof) = &self.aof {
+            aof.append(command);
+            if aof.should_rewrite() {
+                aof.rewrite(self.rewrite(now));
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Waits until `replicas` replicas acknowledge having applied every write propagated so far, or
+    /// `timeout` elapses, replying with how many did.
+    pub(super) async fn wait(&self, replicas: usize, timeout: Option<Duration>) -> Frame {
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+        let (offset, acks) = {
+            let mut state = self.state.lock().unwrap();
+            let limit = state.config.client_output_buffer_limit.replica;
+            let replication = &mut state.replication;
+            let offset = replication.offset;
+            // replicas only acknowledge their offset every second unless asked to
+            let getack = ["REPLCONF", "GETACK", "*"].map(|a| Frame::Bulk(Some(a.into())));
+            replication.send(Frame::Array(Some(getack.into())), &limit);
+            (offset, replication.acks.clone())
+        };
+        loop {
+            // registered before checking, so that acknowledgements received meanwhile aren't missed
+            let notified = acks.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let acknowledged = self.state.lock().unwrap().replication.acknowledged(offset);
+            if acknowledged >= replicas {
+                return Frame::Integer(acknowledged as i64);
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        break;
+                    }
+                }
+                None => notified.await,
+            }
+        }
+        let acknowledged = self.state.lock().unwrap().replication.acknowledged(offset);
+        Frame::Integer(acknowledged as i64)
+    }
+}
+
+/// Follows the master at `host` and `port`, reconnecting whenever the connection is lost, until the
+/// task is aborted or the database is dropped.
+async fn follow(state: Weak<Mutex<State>>, host: String, port: u16, listening_port: u16) {
+    while state.strong_count() > 0 {
+        if let Err(e) = sync(&state, &host, port, listening_port).await {
+            println!("lost the master {}:{}: {:?}", host, port, e);
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Connects to the master, loads the snapshot of its dataset, then applies every write it
+/// propagates, without replying to them.
+async fn sync(
+    state: &Weak<Mutex<State>>,
+    host: &str,
+    port: u16,
+    listening_port: u16,
+) -> io::Result<()> {
+    let mut stream = TcpStream::connect((host, port)).await?;
+    let mut connection = Connection::new(&mut stream);
+    let port = listening_port.to_string();
+    let handshake: [&[&str]; 4] = [
+        &["PING"],
+        &["REPLCONF", "listening-port", &port],
+        &["REPLCONF", "capa", "psync2"],
+        // `?` asks for a full resynchronization, as there is no history to continue from
+        &["PSYNC", "?", "-1"],
+    ];
+    let mut reply = None;
+    for command in handshake {
+        connection.write_frame(command_frame(command));
+        connection.flush().await?;
+        reply = match connection.read_frame().await.map_err(invalid)? {
+            Some(Frame::Error(e)) => {
+                let e = String::from_utf8_lossy(&e);
+                return Err(io::Error::other(format!("master replied {}", e)));
+            }
+            Some(reply) => Some(reply),
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+    }
+    // i.e. `FULLRESYNC replicationid offset`, where the offset is that of the snapshot sent next
+    let mut offset = match reply {
+        Some(Frame::String(reply)) => std::str::from_utf8(&reply)
+            .ok()
+            .and_then(|r| r.rsplit(' ').next()?.parse::<u64>().ok()),
+        _ => None,
+    }
+    .ok_or_else(|| invalid("expected FULLRESYNC in reply to PSYNC"))?;
+    let rdb = connection.read_rdb().await.map_err(invalid)?;
+    {
+        let Some(state) = state.upgrade() else {
+            return Ok(());
+        };
+        let mut state = state.lock().unwrap();
+        state.clear();
+        state.load(&rdb, now_ms())?;
+        state.replication.set_master_offset(offset);
+    }
+    let mut ticker = tokio::time::interval(ACK_INTERVAL);
+    let mut replay = Replay::default();
+    loop {
+        // acknowledge the offset periodically while waiting for more commands
+        if !connection.has_buffered_data() {
+            tokio::select! {
+                read = connection.must_fill_buf() => {
+                    read?;
+                }
+                _ = ticker.tick() => {
+                    connection.write_frame(ack(offset));
+                    connection.flush().await?;
+                    continue;
+                }
+            }
+        }
+        let Some(frame) = connection.read_frame().await.map_err(invalid)? else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        let applied = offset;
+        offset += connection::encoded_len(&frame) as u64;
+        let command = Command::try_from(frame);
+        // the offset acknowledged is that before the `GETACK` asking for it
+        let getack = matches!(&command, Ok(Command::ReplConf(o)) if matches!(o[..], [ReplConfOption::GetAck]));
+        {
+            let Some(state) = state.upgrade() else {
+                return Ok(());
+            };
+            let mut state = state.lock().unwrap();
+            if !getack {
+                state.apply_from_master(&mut replay, command);
+            }
+            state.replication.set_master_offset(offset);
+        }
+        if getack {
+            connection.write_frame(ack(applied));
+            connection.flush().await?;
+        }
+    }
+}
+
+impl State {
+    /// Applies a command propagated by the master, where the commands between `MULTI` and `EXEC`
+    /// are applied together once `EXEC` is received. Like Redis's, masters may select a database
+    /// first, which can only be db 0.
+    fn apply_from_master(&mut self, replay: &mut Replay, command: Result<Command, Error>) {
+        match command {
+            Ok(command) => {
+                for command in replay.feed(command) {
+                    self.apply_unchecked(command);
+                }
+            }
+            Err(Error::UnknownCommand(name, args)) if name.eq_ignore_ascii_case(b"select") => {
+                if args.first().map(|db| &db[..]) != Some(b"0") {
+                    println!(
+                        "the master selected a database other than db 0, which is unsupported"
+                    );
+                }
+            }
+            Err(e) => println!("invalid command from the master: {:?}", e),
+        }
+    }
+}
+
+/// Returns `REPLCONF ACK offset`, acknowledging having applied every command up to `offset`.
+fn ack(offset: u64) -> Frame {
+    command_frame(&["REPLCONF", "ACK", &offset.to_string()])
+}
+
+/// Returns the frame sending a command with the given arguments.
+fn command_frame(args: &[&str]) -> Frame {
+    let args = args
+        .iter()
+        .map(|a| Frame::Bulk(Some(Bytes::from(a.to_string()))));
+    Frame::Array(Some(args.collect()))
+}
+
+fn invalid(e: impl Debug) -> io::Error {
+    io::Error::new(InvalidData, format!("{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::{Command, ExpireOptions, Expiry, SetOptions},
+        db::{now_ms, Db},
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    #[test]
+    fn transactions_from_the_master_are_applied_at_once() {
+        let db = Db::new();
+        let mut state = db.state.lock().unwrap();
+        let mut replay = Replay::default();
+        let mut apply = |state: &mut State, args: &[&str]| {
+            state.apply_from_master(&mut replay, Command::try_from(command_frame(args)));
+        };
+        apply(&mut state, &["SELECT", "0"]);
+        apply(&mut state, &["MULTI"]);
+        apply(&mut state, &["SET", "a", "1"]);
+        // commands managing a client are replied to with an error rather than applied
+        apply(&mut state, &["SUBSCRIBE", "channel"]);
+        apply(&mut state, &["SET", "b", "2"]);
+        assert!(state.keystore.is_empty(), "the block isn't executed yet");
+        apply(&mut state, &["EXEC"]);
+        assert_eq!(2, state.keystore.len());
+    }
+
+    #[tokio::test]
+    async fn replicas_are_sent_the_dataset_then_every_write() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let (mut replica, mut messages) = db.client();
+        let set = |k: &'static str| Command::Set(k.into(), "v".into(), SetOptions::default());
+        db.apply_batch(&mut client, vec![set("before")]).await;
+
+        let replies = db.apply_batch(&mut replica, vec![Command::PSync]).await;
+        assert!(matches!(&replies[..], [Frame::String(s)] if s.starts_with(b"FULLRESYNC ")));
+        let snapshot = replica.take_full_sync().expect("a full sync was started");
+        let loaded = Db::new();
+        {
+            let mut state = loaded.state.lock().unwrap();
+            state.load(&snapshot, now_ms()).unwrap();
+            assert!(state.keystore.contains_key(&Bytes::from("before")));
+        }
+
+        let commands = vec![
+            set("after"),
+            Command::Get("after".into()),
+            Command::Expire(
+                "after".into(),
+                Expiry::In(60 * 1000),
+                ExpireOptions::default(),
+            ),
+            // errors aren't propagated
+            Command::IncrBy("after".into(), 1),
+        ];
+        db.apply_batch(&mut client, commands).await;
+        assert_eq!(Ok(set("after").to_frame()), messages.try_recv());
+        let expire = Command::try_from(messages.try_recv().unwrap()).unwrap();
+        assert!(matches!(expire, Command::Expire(_, Expiry::At(_), _)));
+        assert!(messages.try_recv().is_err());
+
+        // replicas stop being sent writes once they disconnect
+        drop((replica, messages));
+        assert!(!db.state.lock().unwrap().replication.has_replicas());
+    }
+
+    #[tokio::test]
+    async fn blocking_commands_are_propagated_without_blocking() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let (mut replica, mut messages) = db.client();
+        db.apply_batch(&mut replica, vec![Command::PSync]).await;
+        replica.take_full_sync().expect("a full sync was started");
+
+        let commands = [
+            &["RPUSH", "b", "x", "y"][..],
+            &["BLPOP", "a", "b", "0"],
+            &["BLMOVE", "b", "c", "LEFT", "RIGHT", "0"],
+        ];
+        let commands = commands.iter().map(|args| command_frame(args));
+        let commands = commands.map(|frame| Command::try_from(frame).unwrap());
+        db.apply_batch(&mut client, commands.collect()).await;
+        assert_eq!(
+            Ok(command_frame(&["RPUSH", "b", "x", "y"])),
+            messages.try_recv()
+        );
+        assert_eq!(Ok(command_frame(&["LPOP", "b"])), messages.try_recv());
+        let lmove = command_frame(&["LMOVE", "b", "c", "LEFT", "RIGHT"]);
+        assert_eq!(Ok(lmove), messages.try_recv());
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn replicas_apply_the_writes_of_their_master() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let master = Db::new();
+        let (mut client, _) = master.client();
+        let set = |k: &'static str| Command::Set(k.into(), "v".into(), SetOptions::default());
+        master.apply_batch(&mut client, vec![set("before")]).await;
+        let snapshot = master.state.lock().unwrap().snapshot(now_ms());
+
+        let replica = Db::new();
+        replica.replicate("127.0.0.1".into(), port);
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut connection = Connection::new(&mut stream);
+        handshake(&mut connection).await;
+        connection.write_rdb(&snapshot);
+        connection.write_frame(set("after").to_frame());
+        connection.flush().await.unwrap();
+
+        let (mut client, _) = replica.client();
+        let get = || vec![Command::Get("before".into()), Command::Get("after".into())];
+        let value = Frame::Bulk(Some("v".into()));
+        while replica.apply_batch(&mut client, get()).await != [value.clone(), value.clone()] {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // replicas acknowledge the bytes of every command they were sent before `GETACK`
+        let getack = command_frame(&["REPLCONF", "GETACK", "*"]);
+        let offset = connection::encoded_len(&set("after").to_frame());
+        let offset = (offset + connection::encoded_len(&getack)) as u64;
+        connection.write_frame(getack.clone());
+        connection.write_frame(getack.clone());
+        connection.flush().await.unwrap();
+        while connection.read_frame().await.unwrap() != Some(ack(offset)) {}
+        let role = || vec![Command::Role];
+        let offset = Frame::Integer((offset as usize + connection::encoded_len(&getack)) as i64);
+        while !matches!(
+            &replica.apply_batch(&mut client, role()).await[..],
+            [Frame::Array(Some(role))] if role[4] == offset
+        ) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // only the master writes to its replicas
+        let replies = replica.apply_batch(&mut client, vec![set("other")]).await;
+        assert!(matches!(&replies[..], [Frame::Error(e)] if e.starts_with(b"READONLY")));
+    }
+
+    #[tokio::test]
+    async fn replicas_apply_writes_split_across_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let replica = Db::new();
+        replica.replicate("127.0.0.1".into(), port);
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut connection = Connection::new(&mut stream);
+        handshake(&mut connection).await;
+        connection.write_rdb(&Db::new().state.lock().unwrap().snapshot(now_ms()));
+        connection.flush().await.unwrap();
+        drop(connection);
+
+        // every line ends in the read after its carriage return
+        let keys = ["a", "b", "c"];
+        let mut writes = Vec::new();
+        for key in keys {
+            let set = Command::Set(key.into(), "v".into(), SetOptions::default());
+            let mut buf = std::io::Cursor::new(Vec::new());
+            let mut encoder = Connection::new(&mut buf);
+            encoder.write_frame(set.to_frame());
+            encoder.flush().await.unwrap();
+            writes.extend(buf.into_inner());
+        }
+        for piece in writes.split_inclusive(|b| *b == b'\r') {
+            stream.write_all(piece).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // the link survives, so the replica acknowledges every write on the same connection
+        let mut connection = Connection::new(&mut stream);
+        let getack = command_frame(&["REPLCONF", "GETACK", "*"]);
+        connection.write_frame(getack);
+        connection.flush().await.unwrap();
+        while connection.read_frame().await.unwrap() != Some(ack(writes.len() as u64)) {}
+        let (mut client, _) = replica.client();
+        let get = keys.iter().map(|k| Command::Get((*k).into())).collect();
+        let replies = replica.apply_batch(&mut client, get).await;
+        assert!(replies.iter().all(|r| r == &Frame::Bulk(Some("v".into()))));
+    }
+
+    /// Replies to the handshake of a replica as its master would, asking for a full resync.
+    async fn handshake(connection: &mut Connection<'_, TcpStream>) {
+        let mut handshake = Vec::new();
+        for reply in ["PONG", "OK", "OK", "FULLRESYNC 0 0"] {
+            let frame = connection.read_frame().await.unwrap().unwrap();
+            handshake.push(Command::try_from(frame).unwrap());
+            connection.write_frame(Frame::String(reply.into()));
+            connection.flush().await.unwrap();
+        }
+        assert!(matches!(
+            &handshake[..],
+            [
+                Command::Ping,
+                Command::ReplConf(_),
+                Command::ReplConf(_),
+                Command::PSync
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn wait_for_replicas_to_acknowledge_writes() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let (mut replica, mut messages) = db.client();
+        db.apply_batch(&mut replica, vec![Command::PSync]).await;
+        // replicas have applied everything there was when they connected
+        let replies = db
+            .apply_batch(&mut client, vec![Command::Wait(1, None)])
+            .await;
+        assert_eq!(vec![Frame::Integer(1)], replies);
+
+        let set = Command::Set("k".into(), "v".into(), SetOptions::default());
+        let wait = Command::Wait(1, Some(Duration::from_millis(10)));
+        let replies = db.apply_batch(&mut client, vec![set, wait]).await;
+        assert_eq!(Frame::Integer(0), replies[1]);
+
+        let waiting = tokio::spawn({
+            let db = db.clone();
+            async move {
+                db.apply_batch(&mut client, vec![Command::Wait(1, None)])
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        let mut offset = 0;
+        while let Ok(command) = messages.try_recv() {
+            offset += connection::encoded_len(&command);
+        }
+        let ack = Command::ReplConf(vec![ReplConfOption::Ack(offset as u64)]);
+        // acknowledgements aren't replied to
+        assert!(db.apply_batch(&mut replica, vec![ack]).await.is_empty());
+        assert_eq!(vec![Frame::Integer(1)], waiting.await.unwrap());
+    }
+}
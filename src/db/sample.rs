@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+/// A set of keys from which one can be picked uniformly at random in constant time, which a
+/// `HashMap` can't do as it doesn't expose its buckets.
+#[derive(Default)]
+pub struct Sampler {
+    keys: Vec<Bytes>,
+    /// The index of each key in `keys`.
+    indices: HashMap<Bytes, usize>,
+}
+
+impl Sampler {
+    pub fn insert(&mut self, key: Bytes) {
+        if !self.indices.contains_key(&key) {
+            self.indices.insert(key.clone(), self.keys.len());
+            self.keys.push(key);
+        }
+    }
+
+    pub fn remove(&mut self, key: &Bytes) {
+        let Some(index) = self.indices.remove(key) else {
+            return;
+        };
+        self.keys.swap_remove(index);
+        // the last key took the place of the removed one
+        if let Some(moved) = self.keys.get(index) {
+            self.indices.insert(moved.clone(), index);
+        }
+    }
+
+    /// Returns a key picked uniformly at random, given a `random` number in `[0, 1)`, or `None` if
+    /// there are none.
+    pub fn pick(&self, random: f64) -> Option<&Bytes> {
+        let index = (random * self.keys.len() as f64) as usize;
+        self.keys.get(index.min(self.keys.len().saturating_sub(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_only_present_keys() {
+        let mut sampler = Sampler::default();
+        assert_eq!(None, sampler.pick(0.5));
+        for key in ["a", "b", "c"] {
+            sampler.insert(key.into());
+        }
+        sampler.remove(&"a".into());
+        sampler.insert("b".into());
+
+        let mut picked: Vec<_> = [0.0, 0.5, 0.99]
+            .into_iter()
+            .filter_map(|r| sampler.pick(r).cloned())
+            .collect();
+        picked.sort();
+        picked.dedup();
+        assert_eq!(vec!["b", "c"], picked);
+    }
+}
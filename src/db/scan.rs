@@ -0,0 +1,158 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use bytes::Bytes;
+
+use super::State;
+use crate::{command::ScanOptions, frame::Frame, glob};
+
+/// Returns the position of `item` in the order scans visit items in, which unlike Redis's reversed
+/// bucket order doesn't depend on the size of the collection. Each scan resumes from the position
+/// its cursor names, so an item that is present for the whole scan is returned at least once, even
+/// if other items are added or removed in between. Position 0 is reserved for the cursor that
+/// starts and ends a scan.
+pub fn position(item: &[u8]) -> u64 {
+    // unlike `RandomState`, a `DefaultHasher` created by `new` hashes items the same way every time
+    let mut hasher = DefaultHasher::new();
+    item.hash(&mut hasher);
+    hasher.finish().max(1)
+}
+
+/// Selects the first `count` items at or after `cursor`, along with any at the same position as
+/// the last of them, returning them and the cursor to continue from, which is 0 once every item has
+/// been visited.
+pub fn select<T>(
+    items: impl Iterator<Item = (u64, T)>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<T>) {
+    let mut items: Vec<_> = items.filter(|(p, _)| *p >= cursor).collect();
+    if items.len() <= count {
+        return (0, items.into_iter().map(|(_, item)| item).collect());
+    }
+    items.select_nth_unstable_by_key(count - 1, |(p, _)| *p);
+    let last = items[count - 1].0;
+    let next = items[count..]
+        .iter()
+        .map(|(p, _)| *p)
+        .filter(|p| *p > last)
+        .min();
+    let selected = items
+        .into_iter()
+        .filter(|(p, _)| *p <= last)
+        .map(|(_, item)| item)
+        .collect();
+    (next.unwrap_or(0), selected)
+}
+
+/// Whether `item` matches the `MATCH` pattern, if one was given.
+pub fn matches(options: &ScanOptions, item: &[u8]) -> bool {
+    match &options.pattern {
+        Some(pattern) => glob::matches(pattern, item),
+        None => true,
+    }
+}
+
+/// Builds the reply to a scan, made of the cursor to continue from and the items found.
+pub fn reply(cursor: u64, items: Vec<Frame>) -> Frame {
+    Frame::Array(Some(vec![
+        Frame::Bulk(Some(cursor.to_string().into())),
+        Frame::Array(Some(items)),
+    ]))
+}
+
+impl State {
+    /// Scans the keyspace from `cursor`, returning the cursor to continue from and the keys found
+    /// that match `options`. Expired keys are removed rather than returned.
+    pub(super) fn scan(
+        &mut self,
+        cursor: u64,
+        options: &ScanOptions,
+        now: i64,
+    ) -> (u64, Vec<Bytes>) {
+        let mut keys = Vec::new();
+        let mut next = 0;
+        for (position, key) in self.positions.range((cursor, Bytes::new())..) {
+            match keys.last() {
+                Some((last, _)) if keys.len() >= options.count && last != position => {
+                    next = *position;
+                    break;
+                }
+                _ => keys.push((*position, key.clone())),
+            }
+        }
+        let keys = keys
+            .into_iter()
+            .map(|(_, key)| key)
+            .filter(|key| match (self.peek(key, now), &options.kind) {
+                (Some(entry), Some(kind)) => entry.value.type_name().as_bytes() == kind,
+                (entry, None) => entry.is_some(),
+                (None, _) => false,
+            })
+            .filter(|key| matches(options, key))
+            .collect();
+        (next, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Command, db::Db};
+
+    async fn scan_all(db: &Db, keys: &mut Vec<Bytes>, during: impl Fn(usize) -> Vec<Command>) {
+        let (mut client, _) = db.client();
+        let mut cursor = 0;
+        for i in 0.. {
+            let options = ScanOptions {
+                count: 3,
+                ..ScanOptions::default()
+            };
+            let mut commands = during(i);
+            commands.push(Command::Scan(cursor, options));
+            let replies = db.apply_batch(&mut client, commands).await;
+            let Some(Frame::Array(Some(reply))) = replies.last() else {
+                panic!("unexpected reply {replies:?}");
+            };
+            let [Frame::Bulk(Some(next)), Frame::Array(Some(found))] = &reply[..] else {
+                panic!("unexpected reply {reply:?}");
+            };
+            keys.extend(found.iter().map(|k| match k {
+                Frame::Bulk(Some(k)) => k.clone(),
+                k => panic!("unexpected key {k:?}"),
+            }));
+            cursor = std::str::from_utf8(next).unwrap().parse().unwrap();
+            if cursor == 0 {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn scan_returns_keys_present_throughout() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = (0..20)
+            .map(|i| Command::IncrBy(format!("key:{i}").into(), 1))
+            .collect();
+        db.apply_batch(&mut client, commands).await;
+
+        // add and remove other keys as the scan goes on
+        let mut keys = Vec::new();
+        scan_all(&db, &mut keys, |i| {
+            vec![
+                Command::IncrBy(format!("new:{i}").into(), 1),
+                Command::Del(vec![format!("new:{}", i.saturating_sub(1)).into()]),
+            ]
+        })
+        .await;
+        for i in 0..20 {
+            assert!(
+                keys.contains(&format!("key:{i}").into()),
+                "key:{i} is missing"
+            );
+        }
+    }
+}
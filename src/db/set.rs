@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use super::{object::integer, EncodingLimits};
+
+/// A set, which like Redis is encoded as an intset, i.e. a sorted array of integers, while it only
+/// holds a few integers, as a listpack, i.e. a list of its members that is searched linearly, while
+/// it is small, and as a hash table once it grows past the limits of `set-max-intset-entries`,
+/// `set-max-listpack-entries` and `set-max-listpack-value`.
+pub enum Set {
+    Intset(Vec<i64>),
+    Listpack(Vec<Bytes>),
+    Table(HashSet<Bytes>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::Intset(Vec::new())
+    }
+}
+
+impl Set {
+    /// Builds a set of `members`, encoded compactly if they fit.
+    pub fn collect(members: impl IntoIterator<Item = Bytes>, limits: &EncodingLimits) -> Self {
+        let mut set = Set::default();
+        for member in members {
+            set.insert(member, limits);
+        }
+        set
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::Intset(integers) => integers.len(),
+            Set::Listpack(members) => members.len(),
+            Set::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &Bytes) -> bool {
+        match self {
+            Set::Intset(integers) => {
+                integer(member).is_some_and(|i| integers.binary_search(&i).is_ok())
+            }
+            Set::Listpack(members) => members.contains(member),
+            Set::Table(table) => table.contains(member),
+        }
+    }
+
+    /// Adds `member`, converting the set to a more general encoding if it no longer fits in its
+    /// own, and returning whether it wasn't in the set already.
+    pub fn insert(&mut self, member: Bytes, limits: &EncodingLimits) -> bool {
+        if self.contains(&member) {
+            return false;
+        }
+        if let Set::Intset(integers) = self {
+            match integer(&member) {
+                Some(i) if integers.len() < limits.set_max_intset_entries => {
+                    let at = integers.binary_search(&i).unwrap_err();
+                    integers.insert(at, i);
+                    return true;
+                }
+                _ => {
+                    let members = integers
+                        .iter()
+                        .map(|i| Bytes::from(i.to_string()))
+                        .collect();
+                    *self = Set::Listpack(members);
+                }
+            }
+        }
+        if let Set::Listpack(members) = self {
+            let fits = |m: &Bytes| m.len() <= limits.set_max_listpack_value;
+            if members.len() < limits.set_max_listpack_entries
+                && fits(&member)
+                && members.iter().all(fits)
+            {
+                members.push(member);
+                return true;
+            }
+            *self = Set::Table(std::mem::take(members).into_iter().collect());
+        }
+        match self {
+            Set::Table(table) => table.insert(member),
+            _ => unreachable!("the set was converted to a hash table"),
+        }
+    }
+
+    /// Removes `member`, returning whether it was in the set.
+    pub fn remove(&mut self, member: &Bytes) -> bool {
+        match self {
+            Set::Intset(integers) => match integer(member).map(|i| integers.binary_search(&i)) {
+                Some(Ok(at)) => {
+                    integers.remove(at);
+                    true
+                }
+                _ => false,
+            },
+            Set::Listpack(members) => match members.iter().position(|m| m == member) {
+                Some(at) => {
+                    members.remove(at);
+                    true
+                }
+                None => false,
+            },
+            Set::Table(table) => table.remove(member),
+        }
+    }
+
+    /// Returns the members, in no particular order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match self {
+            Set::Intset(integers) => Box::new(integers.iter().map(|i| i.to_string().into())),
+            Set::Listpack(members) => Box::new(members.iter().cloned()),
+            Set::Table(table) => Box::new(table.iter().cloned()),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::Intset(_) => "intset",
+            Set::Listpack(_) => "listpack",
+            Set::Table(_) => "hashtable",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_more_general_encodings() {
+        let limits = EncodingLimits {
+            set_max_intset_entries: 3,
+            set_max_listpack_entries: 2,
+            set_max_listpack_value: 4,
+            ..EncodingLimits::default()
+        };
+        let mut set = Set::collect(["3".into(), "-1".into()], &limits);
+        assert!(!set.insert("3".into(), &limits));
+        assert!(set.insert("2".into(), &limits));
+        assert_eq!("intset", set.encoding());
+        assert_eq!(vec!["-1", "2", "3"], set.iter().collect::<Vec<_>>());
+        assert!(
+            !set.contains(&"03".into()),
+            "not an integer in its canonical form"
+        );
+        set.insert("4".into(), &limits);
+        assert_eq!("hashtable", set.encoding());
+
+        let mut set = Set::collect(["1".into(), "a".into()], &limits);
+        assert_eq!("listpack", set.encoding());
+        assert!(set.remove(&"1".into()));
+        set.insert("abcde".into(), &limits);
+        assert_eq!("hashtable", set.encoding());
+        assert!(set.contains(&"a".into()) && !set.contains(&"1".into()));
+    }
+}
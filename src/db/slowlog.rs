@@ -0,0 +1,193 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::{Client, State};
+use crate::{command::SlowLogCommand, frame::Frame};
+
+/// How many arguments of a command are logged, like Redis.
+const MAX_ARGS: usize = 32;
+/// How many bytes of an argument are logged, like Redis.
+const MAX_ARG_LEN: usize = 128;
+
+/// The commands that took longer than `slowlog-log-slower-than`, most recent first.
+#[derive(Default)]
+pub struct SlowLog {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+}
+
+struct Entry {
+    id: u64,
+    /// When the command was applied, in seconds since the Unix epoch.
+    time: u64,
+    duration: Duration,
+    args: Vec<Bytes>,
+    addr: String,
+    name: Bytes,
+}
+
+impl State {
+    /// Adds the command `client` sent as `args` to the slow log if applying it took longer than
+    /// `slowlog-log-slower-than`.
+    pub(super) fn log_if_slow(&mut self, client: &Client, args: &[Bytes], duration: Duration) {
+        let threshold = self.config.slowlog_log_slower_than;
+        if threshold < 0 || duration.as_micros() < threshold as u128 {
+            return;
+        }
+        // like Redis, only the first arguments and their first bytes are kept
+        let mut logged: Vec<Bytes> = args
+            .iter()
+            .take(MAX_ARGS)
+            .map(|arg| match arg.len().checked_sub(MAX_ARG_LEN) {
+                Some(more @ 1..) => {
+                    let mut truncated = arg[..MAX_ARG_LEN].to_vec();
+                    truncated.extend_from_slice(format!("... ({} more bytes)", more).as_bytes());
+                    truncated.into()
+                }
+                _ => arg.clone(),
+            })
+            .collect();
+        if args.len() > MAX_ARGS {
+            let more = args.len() - MAX_ARGS + 1;
+            logged[MAX_ARGS - 1] = format!("... ({} more arguments)", more).into();
+        }
+        let info = self.clients.get(&client.id);
+        let entry = Entry {
+            id: self.slowlog.next_id,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            duration,
+            args: logged,
+            addr: self.addr(client).map(|a| a.to_string()).unwrap_or_default(),
+            name: info.and_then(|i| i.name()).unwrap_or_default(),
+        };
+        self.slowlog.next_id += 1;
+        self.slowlog.entries.push_front(entry);
+        self.slowlog
+            .entries
+            .truncate(self.config.slowlog_max_len as usize);
+    }
+
+    /// Applies a subcommand of `SLOWLOG`.
+    pub(super) fn slowlog(&mut self, command: SlowLogCommand) -> Frame {
+        match command {
+            SlowLogCommand::Get(count) => {
+                let entries = self.slowlog.entries.iter();
+                let entries: Vec<Frame> = match count {
+                    Some(count) => entries.take(count).map(Entry::to_frame).collect(),
+                    None => entries.map(Entry::to_frame).collect(),
+                };
+                Frame::Array(Some(entries))
+            }
+            SlowLogCommand::Len => Frame::Integer(self.slowlog.entries.len() as i64),
+            SlowLogCommand::Reset => {
+                self.slowlog.entries.clear();
+                Frame::Bulk(Some("OK".into()))
+            }
+        }
+    }
+}
+
+impl Entry {
+    /// Returns the entry as `SLOWLOG GET` replies with it, i.e. its ID, time, duration in
+    /// microseconds, arguments, client address and client name.
+    fn to_frame(&self) -> Frame {
+        let args = self.args.iter().map(|a| Frame::Bulk(Some(a.clone())));
+        Frame::Array(Some(vec![
+            Frame::Integer(self.id as i64),
+            Frame::Integer(self.time as i64),
+            Frame::Integer(self.duration.as_micros() as i64),
+            Frame::Array(Some(args.collect())),
+            Frame::Bulk(Some(self.addr.clone().into())),
+            Frame::Bulk(Some(self.name.clone())),
+        ]))
+    }
+}
+
+impl Client {
+    /// Records the arguments of the command the client sent as `frame`, for them to be checked
+    /// against the client's permissions and logged if the command turns out to be slow.
+    pub fn received(&mut self, frame: &Frame) {
+        let Frame::Array(Some(frames)) = frame else {
+            return;
+        };
+        let args = frames.iter().map(|f| match f {
+            Frame::Bulk(Some(arg)) => arg.clone(),
+            _ => Bytes::new(),
+        });
+        self.received.push_back(args.collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::{Command, ConfigCommand},
+        db::Db,
+    };
+
+    #[tokio::test]
+    async fn logs_commands_slower_than_the_threshold() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let config = |name: &str, value: &str| {
+            let pairs = vec![(
+                Bytes::from(name.to_string()),
+                Bytes::from(value.to_string()),
+            )];
+            Command::Config(ConfigCommand::Set(pairs))
+        };
+        let commands = vec![
+            config("slowlog-log-slower-than", "0"),
+            config("slowlog-max-len", "2"),
+        ];
+        db.apply_batch(&mut client, commands).await;
+
+        let get = |k: &str| {
+            let frame = Frame::Array(Some(vec![
+                Frame::Bulk(Some("GET".into())),
+                Frame::Bulk(Some(Bytes::from(k.repeat(200)))),
+            ]));
+            (frame, Command::Get(k.repeat(200).into()))
+        };
+        for key in ["a", "b", "c"] {
+            let (frame, command) = get(key);
+            client.received(&frame);
+            db.apply_batch(&mut client, vec![command]).await;
+        }
+        let log = Command::SlowLog(SlowLogCommand::Get(None));
+        let commands = vec![log, Command::SlowLog(SlowLogCommand::Len)];
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert_eq!(Frame::Integer(2), replies[1]);
+        let entries = match &replies[0] {
+            Frame::Array(Some(entries)) => entries,
+            reply => panic!("expected an array, got {:?}", reply),
+        };
+        // the most recent entry comes first, and arguments are truncated
+        let args = match &entries[0] {
+            Frame::Array(Some(entry)) => entry[3].clone(),
+            entry => panic!("expected an array, got {:?}", entry),
+        };
+        let key = format!("{}... (72 more bytes)", "c".repeat(128));
+        let expected = vec![
+            Frame::Bulk(Some("GET".into())),
+            Frame::Bulk(Some(key.into())),
+        ];
+        assert_eq!(Frame::Array(Some(expected)), args);
+
+        let commands = vec![
+            Command::SlowLog(SlowLogCommand::Reset),
+            Command::SlowLog(SlowLogCommand::Len),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        // the reset itself is logged, being slower than 0 microseconds
+        assert_eq!(Frame::Integer(1), replies[1]);
+    }
+}
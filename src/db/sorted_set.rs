@@ -0,0 +1,284 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    iter,
+    ops::Bound,
+};
+
+use bytes::Bytes;
+
+use super::EncodingLimits;
+use crate::command::{LexBound, ZRangeBy};
+
+/// A set of members ordered by score, with ties broken by comparing the members lexicographically.
+///
+/// Like Redis, small sets are encoded as a listpack, i.e. a list of their members and scores in
+/// order, which is searched linearly, until they grow past the limits of
+/// `zset-max-listpack-entries` and `zset-max-listpack-value`.
+///
+/// Larger sets index their members both by score, to iterate them in order, and by name, to look up
+/// their score in constant time. Unlike Redis's skiplist, the ordered index does not track the
+/// length of its spans, so finding the rank of a member or the member at an index takes linear
+/// time.
+pub enum SortedSet {
+    Listpack(Vec<(Bytes, f64)>),
+    Skiplist {
+        scores: HashMap<Bytes, f64>,
+        order: BTreeSet<(Score, Bytes)>,
+    },
+}
+
+/// A score, which unlike `f64` is totally ordered. Scores are never NaN.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Default for SortedSet {
+    fn default() -> Self {
+        SortedSet::Listpack(Vec::new())
+    }
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        match self {
+            SortedSet::Listpack(members) => members.len(),
+            SortedSet::Skiplist { scores, .. } => scores.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the score of `member`, if it is in the set.
+    pub fn score(&self, member: &Bytes) -> Option<f64> {
+        match self {
+            SortedSet::Listpack(members) => {
+                members.iter().find(|(m, _)| m == member).map(|(_, s)| *s)
+            }
+            SortedSet::Skiplist { scores, .. } => scores.get(member).copied(),
+        }
+    }
+
+    /// Adds `member` with `score`, or updates its score, returning its previous score. The set is
+    /// converted to a skiplist if it no longer fits in a listpack.
+    pub fn insert(&mut self, member: Bytes, score: f64, limits: &EncodingLimits) -> Option<f64> {
+        // adding zero turns -0 into 0, which would otherwise be ordered before it
+        let score = score + 0.0;
+        if let SortedSet::Listpack(members) = self {
+            let previous = members
+                .iter()
+                .position(|(m, _)| *m == member)
+                .map(|i| members.remove(i).1);
+            if previous.is_some()
+                || (members.len() < limits.zset_max_listpack_entries
+                    && member.len() <= limits.zset_max_listpack_value)
+            {
+                let at = members.partition_point(|(m, s)| (Score(*s), m) < (Score(score), &member));
+                members.insert(at, (member, score));
+                return previous;
+            }
+            let scores = members.iter().cloned().collect();
+            let order = members.drain(..).map(|(m, s)| (Score(s), m)).collect();
+            *self = SortedSet::Skiplist { scores, order };
+        }
+        let SortedSet::Skiplist { scores, order } = self else {
+            unreachable!("the set was converted to a skiplist");
+        };
+        let previous = scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            order.remove(&(Score(previous), member.clone()));
+        }
+        order.insert((Score(score), member));
+        previous
+    }
+
+    /// Removes `member`, returning its score.
+    pub fn remove(&mut self, member: &Bytes) -> Option<f64> {
+        match self {
+            SortedSet::Listpack(members) => {
+                let i = members.iter().position(|(m, _)| m == member)?;
+                Some(members.remove(i).1)
+            }
+            SortedSet::Skiplist { scores, order } => {
+                let score = scores.remove(member)?;
+                order.remove(&(Score(score), member.clone()));
+                Some(score)
+            }
+        }
+    }
+
+    /// Returns the index of `member` in score order.
+    pub fn rank(&self, member: &Bytes) -> Option<usize> {
+        match self {
+            SortedSet::Listpack(members) => members.iter().position(|(m, _)| m == member),
+            SortedSet::Skiplist { order, .. } => {
+                let score = self.score(member)?;
+                Some(order.range(..(Score(score), member.clone())).count())
+            }
+        }
+    }
+
+    /// Returns the members and their scores in score order.
+    pub fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&Bytes, f64)> + '_> {
+        match self {
+            SortedSet::Listpack(members) => Box::new(members.iter().map(|(m, s)| (m, *s))),
+            SortedSet::Skiplist { order, .. } => {
+                Box::new(order.iter().map(|(score, member)| (member, score.0)))
+            }
+        }
+    }
+
+    /// Returns the members selected by `by` and their scores, in score order or, if `rev` is set,
+    /// in reverse.
+    pub fn range<'a>(
+        &'a self,
+        by: &'a ZRangeBy,
+        rev: bool,
+    ) -> Box<dyn Iterator<Item = (&'a Bytes, f64)> + 'a> {
+        let members: Box<dyn Iterator<Item = (&Bytes, f64)>> = if rev {
+            Box::new(self.iter().rev())
+        } else {
+            Box::new(self.iter())
+        };
+        if let ZRangeBy::Index(start, end) = *by {
+            return match super::range(self.len(), start, end) {
+                Some((start, end)) => Box::new(members.skip(start).take(end - start + 1)),
+                None => Box::new(iter::empty()),
+            };
+        }
+        let before = if rev {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        };
+        Box::new(
+            members
+                .map(|(member, score)| (by.position(member, score), member, score))
+                .skip_while(move |(position, ..)| *position == before)
+                .take_while(|(position, ..)| *position == Ordering::Equal)
+                .map(|(_, member, score)| (member, score)),
+        )
+    }
+
+    /// Returns roughly how many bytes the set occupies.
+    pub fn size(&self) -> usize {
+        self.iter().map(|(m, _)| m.len() + 8).sum()
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            SortedSet::Listpack(_) => "listpack",
+            SortedSet::Skiplist { .. } => "skiplist",
+        }
+    }
+}
+
+impl ZRangeBy {
+    /// Returns whether a member comes before (`Less`), within (`Equal`) or after (`Greater`) the
+    /// range. Index ranges don't depend on the member, so every member is within them.
+    fn position(&self, member: &Bytes, score: f64) -> Ordering {
+        let (below, above) = match self {
+            ZRangeBy::Index(..) => (false, false),
+            ZRangeBy::Score(min, max) => (
+                match *min {
+                    Bound::Included(min) => score < min,
+                    Bound::Excluded(min) => score <= min,
+                    Bound::Unbounded => false,
+                },
+                match *max {
+                    Bound::Included(max) => score > max,
+                    Bound::Excluded(max) => score >= max,
+                    Bound::Unbounded => false,
+                },
+            ),
+            ZRangeBy::Lex(min, max) => (
+                match min {
+                    LexBound::Min => false,
+                    LexBound::Max => true,
+                    LexBound::Included(min) => member < min,
+                    LexBound::Excluded(min) => member <= min,
+                },
+                match max {
+                    LexBound::Min => true,
+                    LexBound::Max => false,
+                    LexBound::Included(max) => member > max,
+                    LexBound::Excluded(max) => member >= max,
+                },
+            ),
+        };
+        if below {
+            Ordering::Less
+        } else if above {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_by_score_then_member() {
+        // in both encodings, the set being converted to a skiplist along the way with this limit
+        for entries in [128, 2] {
+            let limits = EncodingLimits {
+                zset_max_listpack_entries: entries,
+                ..EncodingLimits::default()
+            };
+            let mut set = SortedSet::default();
+            set.insert("b".into(), 1.0, &limits);
+            set.insert("a".into(), 1.0, &limits);
+            set.insert("c".into(), -0.0, &limits);
+            set.insert("d".into(), f64::NEG_INFINITY, &limits);
+            set.insert("c".into(), 2.0, &limits);
+
+            let members: Vec<_> = set.iter().map(|(m, s)| (m.clone(), s)).collect();
+            let expected = [("d", f64::NEG_INFINITY), ("a", 1.0), ("b", 1.0), ("c", 2.0)];
+            assert_eq!(expected.map(|(m, s)| (Bytes::from(m), s)).to_vec(), members);
+            assert_eq!(Some(2), set.rank(&"b".into()));
+            assert_eq!(Some(1.0), set.remove(&"a".into()));
+            assert_eq!(Some(1), set.rank(&"b".into()));
+            assert_eq!(3, set.len());
+            let encoding = if entries == 2 { "skiplist" } else { "listpack" };
+            assert_eq!(encoding, set.encoding());
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let mut set = SortedSet::default();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 3.0), ("d", 4.0)] {
+            set.insert(member.into(), score, &EncodingLimits::default());
+        }
+        let members =
+            |by, rev| -> Vec<Bytes> { set.range(&by, rev).map(|(m, _)| m.clone()).collect() };
+
+        let by_score = ZRangeBy::Score(Bound::Excluded(1.0), Bound::Included(3.0));
+        assert_eq!(vec!["b", "c"], members(by_score, false));
+        let by_score = ZRangeBy::Score(Bound::Included(2.0), Bound::Excluded(f64::INFINITY));
+        assert_eq!(vec!["d", "c", "b"], members(by_score, true));
+        let by_lex = ZRangeBy::Lex(LexBound::Included("b".into()), LexBound::Max);
+        assert_eq!(vec!["b", "c", "d"], members(by_lex, false));
+        let by_lex = ZRangeBy::Lex(LexBound::Max, LexBound::Min);
+        assert!(members(by_lex, false).is_empty());
+        assert_eq!(vec!["c", "b"], members(ZRangeBy::Index(1, -2), true));
+    }
+}
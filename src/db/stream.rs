@@ -0,0 +1,416 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Bound,
+};
+
+use bytes::Bytes;
+
+use crate::command::{StreamId, XAddId};
+
+/// An append-only log of entries, each holding field-value pairs, ordered by their unique ID.
+#[derive(Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+    /// The largest ID ever added, which new entries must exceed even once it has been deleted.
+    last_id: StreamId,
+    groups: HashMap<Bytes, ConsumerGroup>,
+}
+
+/// A group of consumers sharing the entries of a stream, each entry being delivered to only one of
+/// them and then tracked as pending until it is acknowledged.
+pub struct ConsumerGroup {
+    /// The ID of the last entry delivered to any consumer in the group.
+    last_delivered: StreamId,
+    /// The entries delivered but not yet acknowledged.
+    pending: BTreeMap<StreamId, Pending>,
+    consumers: HashMap<Bytes, Consumer>,
+}
+
+/// An entry delivered to a consumer but not yet acknowledged.
+pub struct Pending {
+    pub consumer: Bytes,
+    /// The unix time in milliseconds at which the entry was last delivered.
+    pub delivered_at: i64,
+    pub deliveries: u64,
+}
+
+struct Consumer {
+    /// The IDs of the entries pending for this consumer.
+    pending: BTreeSet<StreamId>,
+}
+
+/// The options of `XCLAIM` that change how claimed entries are tracked.
+pub struct Claim {
+    pub min_idle: i64,
+    /// When the claimed entries should be considered delivered.
+    pub delivered_at: i64,
+    pub deliveries: Option<u64>,
+    /// Whether to claim entries that aren't pending, as long as they exist.
+    pub force: bool,
+    /// Whether to leave the delivery count alone, as for `JUSTID`.
+    pub just_id: bool,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Returns the ID that `XADD` should give a new entry, generating the parts of `id` left for
+    /// it to choose, or an error message if the ID would not exceed the last one.
+    pub fn next_id(&self, id: XAddId, now: u64) -> Result<StreamId, &'static str> {
+        let last = self.last_id;
+        let id = match id {
+            XAddId::Auto if now > last.ms => StreamId { ms: now, seq: 0 },
+            XAddId::Auto | XAddId::Seq(_) => {
+                let ms = match id {
+                    XAddId::Seq(ms) => ms,
+                    _ => last.ms,
+                };
+                match ms.cmp(&last.ms) {
+                    Ordering::Less => return Err(ID_TOO_SMALL),
+                    Ordering::Equal => StreamId {
+                        ms,
+                        seq: last.seq.checked_add(1).ok_or(ID_TOO_SMALL)?,
+                    },
+                    Ordering::Greater => StreamId { ms, seq: 0 },
+                }
+            }
+            XAddId::Explicit(id) => id,
+        };
+        if id == StreamId::MIN {
+            Err("The ID specified in XADD must be greater than 0-0")
+        } else if id <= last {
+            Err(ID_TOO_SMALL)
+        } else {
+            Ok(id)
+        }
+    }
+
+    /// Appends an entry, whose ID must come from `next_id`.
+    pub fn insert(&mut self, id: StreamId, fields: Vec<(Bytes, Bytes)>) {
+        self.last_id = id;
+        self.entries.insert(id, fields);
+    }
+
+    /// Sets the largest ID ever added, which may exceed that of the last entry if it was deleted,
+    /// e.g. when loading a stream.
+    pub fn set_last_id(&mut self, id: StreamId) {
+        self.last_id = id;
+    }
+
+    /// Returns the entries with IDs between `start` and `end`, in order.
+    pub fn range(
+        &self,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Vec<(Bytes, Bytes)>)> {
+        // ranges with the start after the end are replaced with one that is empty without being
+        // inverted
+        let range = if is_empty(start, end) {
+            (
+                Bound::Excluded(StreamId::MIN),
+                Bound::Included(StreamId::MIN),
+            )
+        } else {
+            (start, end)
+        };
+        self.entries.range(range)
+    }
+
+    /// Returns every entry, in order.
+    pub fn entries(&self) -> impl Iterator<Item = (&StreamId, &Vec<(Bytes, Bytes)>)> {
+        self.entries.iter()
+    }
+
+    /// Returns the fields of the entry with `id`.
+    pub fn get(&self, id: &StreamId) -> Option<&Vec<(Bytes, Bytes)>> {
+        self.entries.get(id)
+    }
+
+    /// Creates a consumer group that will deliver the entries after `last_delivered`, returning
+    /// whether there wasn't a group with the same name already.
+    pub fn create_group(&mut self, name: Bytes, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        let group = ConsumerGroup {
+            last_delivered,
+            pending: BTreeMap::new(),
+            consumers: HashMap::new(),
+        };
+        self.groups.insert(name, group);
+        true
+    }
+
+    /// Deletes a consumer group, returning whether it existed.
+    pub fn destroy_group(&mut self, name: &Bytes) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Bytes, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    pub fn group(&mut self, name: &Bytes) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Returns whether the group has entries that have never been delivered to its consumers, or
+    /// `None` if there is no such group.
+    pub fn has_undelivered(&self, group: &Bytes) -> Option<bool> {
+        let group = self.groups.get(group)?;
+        Some(self.last_id > group.last_delivered)
+    }
+
+    /// Delivers up to `count` entries to `consumer`, returning their IDs, or `None` if there is no
+    /// such group.
+    ///
+    /// Without an `after` ID, the entries are those never delivered to the group, which are then
+    /// tracked as pending unless `no_ack` is set. Otherwise they are the entries after it that are
+    /// already pending for the consumer, which are delivered again without being counted as such.
+    pub fn read_group(
+        &mut self,
+        group: &Bytes,
+        consumer: &Bytes,
+        after: Option<StreamId>,
+        count: usize,
+        no_ack: bool,
+        now: i64,
+    ) -> Option<Vec<StreamId>> {
+        let group = self.groups.get_mut(group)?;
+        group.consumer(consumer);
+        let Some(after) = after else {
+            let ids: Vec<StreamId> = self
+                .entries
+                .range((Bound::Excluded(group.last_delivered), Bound::Unbounded))
+                .take(count)
+                .map(|(id, _)| *id)
+                .collect();
+            if let Some(last) = ids.last() {
+                group.last_delivered = *last;
+            }
+            if !no_ack {
+                for id in &ids {
+                    group.deliver(*id, consumer.clone(), now, 1);
+                }
+            }
+            return Some(ids);
+        };
+        let pending = &group.consumers[consumer].pending;
+        let ids = pending.range((Bound::Excluded(after), Bound::Unbounded));
+        Some(ids.take(count).copied().collect())
+    }
+
+    /// Transfers pending entries to `consumer` if they have been idle for long enough, returning
+    /// the IDs of those claimed, or `None` if there is no such group.
+    pub fn claim(
+        &mut self,
+        group: &Bytes,
+        consumer: &Bytes,
+        ids: &[StreamId],
+        claim: &Claim,
+        now: i64,
+    ) -> Option<Vec<StreamId>> {
+        let group = self.groups.get_mut(group)?;
+        group.consumer(consumer);
+        let mut claimed = vec![];
+        for id in ids {
+            let deliveries = match group.pending.get(id) {
+                Some(pending) if now - pending.delivered_at < claim.min_idle => continue,
+                Some(pending) => pending.deliveries,
+                None if claim.force && self.entries.contains_key(id) => 0,
+                None => continue,
+            };
+            let deliveries = match claim.deliveries {
+                Some(deliveries) => deliveries,
+                None if claim.just_id => deliveries,
+                None => deliveries + 1,
+            };
+            group.deliver(*id, consumer.clone(), claim.delivered_at, deliveries);
+            claimed.push(*id);
+        }
+        Some(claimed)
+    }
+
+    /// Returns roughly how many bytes the stream occupies.
+    pub fn size(&self) -> usize {
+        let fields = self.entries.values().flatten();
+        self.entries.len() * 16 + fields.map(|(f, v)| f.len() + v.len()).sum::<usize>()
+    }
+}
+
+impl ConsumerGroup {
+    pub fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    /// Returns the names of the consumers, in no particular order.
+    pub fn consumers(&self) -> impl Iterator<Item = &Bytes> {
+        self.consumers.keys()
+    }
+
+    /// Sets the ID of the last entry delivered to the group, as for `XGROUP SETID`.
+    pub fn set_last_delivered(&mut self, id: StreamId) {
+        self.last_delivered = id;
+    }
+
+    /// Creates a consumer unless it already exists, returning whether it was created.
+    pub fn consumer(&mut self, name: &Bytes) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            pending: BTreeSet::new(),
+        };
+        self.consumers.insert(name.clone(), consumer);
+        true
+    }
+
+    /// Deletes a consumer along with its pending entries, returning how many it had.
+    pub fn delete_consumer(&mut self, name: &Bytes) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Acknowledges an entry, returning whether it was pending.
+    pub fn ack(&mut self, id: &StreamId) -> bool {
+        let Some(pending) = self.pending.remove(id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&pending.consumer) {
+            consumer.pending.remove(id);
+        }
+        true
+    }
+
+    /// Tracks an entry as pending for `consumer`, taking it from any other consumer.
+    pub fn deliver(&mut self, id: StreamId, consumer: Bytes, delivered_at: i64, deliveries: u64) {
+        let pending = Pending {
+            consumer: consumer.clone(),
+            delivered_at,
+            deliveries,
+        };
+        if let Some(previous) = self.pending.insert(id, pending) {
+            if let Some(previous) = self.consumers.get_mut(&previous.consumer) {
+                previous.pending.remove(&id);
+            }
+        }
+        if let Some(consumer) = self.consumers.get_mut(&consumer) {
+            consumer.pending.insert(id);
+        }
+    }
+
+    /// Returns the pending entries in order of ID.
+    pub fn pending(&self) -> impl Iterator<Item = (&StreamId, &Pending)> {
+        self.pending.iter()
+    }
+
+    /// Returns the pending entries between `start` and `end`.
+    pub fn pending_range(
+        &self,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+    ) -> impl Iterator<Item = (&StreamId, &Pending)> {
+        let range = if is_empty(start, end) {
+            (
+                Bound::Excluded(StreamId::MIN),
+                Bound::Included(StreamId::MIN),
+            )
+        } else {
+            (start, end)
+        };
+        self.pending.range(range)
+    }
+
+    /// Returns how many entries are pending for each consumer that has any.
+    pub fn pending_per_consumer(&self) -> Vec<(&Bytes, usize)> {
+        let consumers = self.consumers.iter();
+        let mut counts: Vec<_> = consumers
+            .filter(|(_, c)| !c.pending.is_empty())
+            .map(|(name, c)| (name, c.pending.len()))
+            .collect();
+        counts.sort();
+        counts
+    }
+}
+
+/// Returns whether a range of IDs is empty, which `BTreeMap::range` would panic on if the start is
+/// after the end.
+fn is_empty(start: Bound<StreamId>, end: Bound<StreamId>) -> bool {
+    match (start, end) {
+        (Bound::Included(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => {
+            s >= e
+        }
+        _ => false,
+    }
+}
+
+const ID_TOO_SMALL: &str =
+    "The ID specified in XADD is equal or smaller than the target stream top item";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_increasing_ids() {
+        let mut stream = Stream::default();
+        let id = |ms, seq| StreamId { ms, seq };
+        assert_eq!(Ok(id(0, 1)), stream.next_id(XAddId::Seq(0), 5));
+        assert!(stream.next_id(XAddId::Explicit(id(0, 0)), 5).is_err());
+
+        stream.insert(id(10, 3), vec![]);
+        assert_eq!(Ok(id(10, 4)), stream.next_id(XAddId::Auto, 5));
+        assert_eq!(Ok(id(20, 0)), stream.next_id(XAddId::Auto, 20));
+        assert_eq!(Ok(id(10, 4)), stream.next_id(XAddId::Seq(10), 20));
+        assert!(stream.next_id(XAddId::Seq(9), 20).is_err());
+        assert!(stream.next_id(XAddId::Explicit(id(10, 3)), 20).is_err());
+    }
+
+    #[test]
+    fn consumer_groups_track_pending_entries() {
+        let mut stream = Stream::default();
+        let id = |ms| StreamId { ms, seq: 0 };
+        for ms in 1..=3 {
+            stream.insert(id(ms), vec![]);
+        }
+        let (group, alice, bob) = (Bytes::from("g"), Bytes::from("alice"), Bytes::from("bob"));
+        assert!(stream.create_group(group.clone(), id(1)));
+        assert!(!stream.create_group(group.clone(), id(0)));
+
+        let read = stream.read_group(&group, &alice, None, 1, false, 0);
+        assert_eq!(Some(vec![id(2)]), read);
+        let read = stream.read_group(&group, &bob, None, 10, false, 0);
+        assert_eq!(Some(vec![id(3)]), read);
+        assert_eq!(Some(false), stream.has_undelivered(&group));
+
+        let claim = Claim {
+            min_idle: 100,
+            delivered_at: 150,
+            deliveries: None,
+            force: false,
+            just_id: false,
+        };
+        let claimed = stream.claim(&group, &alice, &[id(2), id(3)], &claim, 150);
+        assert_eq!(Some(vec![id(2), id(3)]), claimed);
+        let history = stream.read_group(&group, &alice, Some(id(0)), 10, false, 150);
+        assert_eq!(Some(vec![id(2), id(3)]), history);
+
+        let group = stream.group(&group).unwrap();
+        assert!(group.ack(&id(2)));
+        assert!(!group.ack(&id(2)));
+        assert_eq!(vec![(&alice, 1)], group.pending_per_consumer());
+        let (_, pending) = group.pending().next().unwrap();
+        assert_eq!(2, pending.deliveries);
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::{Client, State};
+use crate::{
+    command::Command,
+    frame::Frame,
+    reply::{ErrorCode, ErrorReply},
+};
+
+/// The commands a client queued since `MULTI`, to be applied together by `EXEC`.
+#[derive(Default)]
+pub struct Transaction {
+    commands: Vec<Command>,
+    /// Whether a command failed to be queued, in which case the transaction is discarded by `EXEC`.
+    failed: bool,
+}
+
+/// Unwraps the `MULTI` ... `EXEC` blocks among commands that weren't sent by a client, i.e. those
+/// propagated by the master or logged in the AOF, so that each block is applied at once.
+#[derive(Default)]
+pub(super) struct Replay {
+    /// The commands since `MULTI`, if a block is open.
+    queued: Option<Vec<Command>>,
+}
+
+impl Replay {
+    /// Returns the commands to apply after `command`: none while a block is open, every command of
+    /// the block once it is executed, or `command` itself outside of blocks.
+    pub(super) fn feed(&mut self, command: Command) -> Vec<Command> {
+        match (command, &mut self.queued) {
+            (Command::Multi, _) => {
+                self.queued = Some(Vec::new());
+                Vec::new()
+            }
+            (Command::Exec, _) => self.queued.take().unwrap_or_default(),
+            (Command::Discard, _) => {
+                self.queued = None;
+                Vec::new()
+            }
+            (command, Some(queued)) => {
+                queued.push(command);
+                Vec::new()
+            }
+            (command, None) => vec![command],
+        }
+    }
+}
+
+/// A key watched by at least one client.
+pub struct Watched {
+    clients: usize,
+    /// How many times the key may have been modified since it was first watched.
+    version: u64,
+}
+
+impl State {
+    /// Marks `key` as modified, so that the transactions of clients watching it fail.
+    pub(super) fn touch(&mut self, key: &Bytes) {
+        if let Some(watched) = self.watched.get_mut(key) {
+            watched.version += 1;
+        }
+    }
+}
+
+impl Client {
+    /// Whether the client has an open transaction.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Returns how many commands the client queued, if it has an open transaction.
+    pub fn queued(&self) -> Option<usize> {
+        self.transaction.as_ref().map(|t| t.commands.len())
+    }
+
+    /// Marks the open transaction, if any, as failed, after a command couldn't be queued because it
+    /// was invalid, e.g. it had the wrong number of arguments.
+    pub fn fail_transaction(&mut self) {
+        if let Some(transaction) = &mut self.transaction {
+            transaction.failed = true;
+        }
+    }
+
+    pub(super) fn multi(&mut self) -> Frame {
+        if self.in_transaction() {
+            return ErrorReply::err("MULTI calls can not be nested").into();
+        }
+        self.transaction = Some(Transaction::default());
+        Frame::Bulk(Some("OK".into()))
+    }
+
+    pub(super) fn discard(&mut self, watched: &mut HashMap<Bytes, Watched>) -> Frame {
+        match self.transaction.take() {
+            Some(_) => {
+                self.unwatch(watched);
+                Frame::Bulk(Some("OK".into()))
+            }
+            None => ErrorReply::err("DISCARD without MULTI").into(),
+        }
+    }
+
+    /// Closes the open transaction, returning its commands to apply, or the reply to `EXEC` if none
+    /// should be, e.g. because a watched key was modified.
+    pub(super) fn exec(
+        &mut self,
+        watched: &mut HashMap<Bytes, Watched>,
+    ) -> Result<Vec<Command>, Frame> {
+        let Some(transaction) = self.transaction.take() else {
+            return Err(ErrorReply::err("EXEC without MULTI").into());
+        };
+        let modified = self
+            .watching
+            .iter()
+            .any(|(key, version)| watched[key].version != *version);
+        self.unwatch(watched);
+        if transaction.failed {
+            let msg = "Transaction discarded because of previous errors.";
+            Err(ErrorReply::new(ErrorCode::ExecAbort, msg).into())
+        } else if modified {
+            Err(Frame::Array(None))
+        } else {
+            Ok(transaction.commands)
+        }
+    }
+
+    /// Watches `keys`, so that the client's next transaction fails if any are modified first.
+    pub(super) fn watch(
+        &mut self,
+        watched: &mut HashMap<Bytes, Watched>,
+        keys: Vec<Bytes>,
+    ) -> Frame {
+        if self.in_transaction() {
+            return ErrorReply::err("WATCH inside MULTI is not allowed").into();
+        }
+        for key in keys {
+            if self.watching.iter().any(|(k, _)| *k == key) {
+                continue;
+            }
+            let key_watch = watched.entry(key.clone()).or_insert(Watched {
+                clients: 0,
+                version: 0,
+            });
+            key_watch.clients += 1;
+            self.watching.push((key, key_watch.version));
+        }
+        Frame::Bulk(Some("OK".into()))
+    }
+
+    /// Stops watching every key.
+    pub(super) fn unwatch(&mut self, watched: &mut HashMap<Bytes, Watched>) {
+        for (key, _) in self.watching.drain(..) {
+            if let Some(key_watch) = watched.get_mut(&key) {
+                key_watch.clients -= 1;
+                if key_watch.clients == 0 {
+                    watched.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Queues `command` if a transaction is open, or otherwise returns it to be applied now.
+    pub(super) fn queue(&mut self, command: Command) -> Option<Command> {
+        match &mut self.transaction {
+            Some(transaction) => {
+                transaction.commands.push(command);
+                None
+            }
+            None => Some(command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    #[tokio::test]
+    async fn exec_applies_queued_commands() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![
+            Command::Multi,
+            Command::IncrBy("counter".into(), 1),
+            Command::IncrBy("counter".into(), 2),
+        ];
+        let replies = db.apply_batch(&mut client, commands).await;
+        let queued = Frame::String("QUEUED".into());
+        assert_eq!(
+            vec![Frame::Bulk(Some("OK".into())), queued.clone(), queued],
+            replies
+        );
+
+        let replies = db.apply_batch(&mut client, vec![Command::Exec]).await;
+        let results = vec![Frame::Integer(1), Frame::Integer(3)];
+        assert_eq!(vec![Frame::Array(Some(results))], replies);
+    }
+
+    #[tokio::test]
+    async fn commands_managing_the_client_are_queued() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let commands = vec![Command::Multi, Command::Subscribe(vec!["ch".into()], false)];
+        let replies = db.apply_batch(&mut client, commands).await;
+        assert_eq!(Frame::String("QUEUED".into()), replies[1]);
+        assert!(!client.is_subscribed());
+
+        let replies = db.apply_batch(&mut client, vec![Command::Exec]).await;
+        assert!(matches!(&replies[..], [Frame::Array(Some(results))] if results.len() == 1));
+        assert!(client.is_subscribed());
+    }
+
+    #[tokio::test]
+    async fn failed_transactions_are_discarded() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let multi = vec![Command::Multi, Command::IncrBy("counter".into(), 1)];
+        db.apply_batch(&mut client, multi).await;
+        client.fail_transaction();
+
+        let replies = db.apply_batch(&mut client, vec![Command::Exec]).await;
+        assert!(matches!(&replies[..], [Frame::Error(e)] if e.starts_with(b"EXECABORT")));
+        let replies = db
+            .apply_batch(&mut client, vec![Command::Get("counter".into())])
+            .await;
+        assert_eq!(vec![Frame::Bulk(None)], replies);
+    }
+
+    #[tokio::test]
+    async fn exec_fails_if_a_watched_key_was_modified() {
+        let db = Db::new();
+        let (mut client, _) = db.client();
+        let (mut other, _) = db.client();
+        let watch = Command::Watch(vec!["counter".into()]);
+        db.apply_batch(&mut client, vec![watch]).await;
+        db.apply_batch(&mut other, vec![Command::IncrBy("counter".into(), 5)])
+            .await;
+
+        let transaction = vec![
+            Command::Multi,
+            Command::IncrBy("counter".into(), 1),
+            Command::Exec,
+        ];
+        let replies = db.apply_batch(&mut client, transaction).await;
+        assert_eq!(Some(&Frame::Array(None)), replies.last());
+
+        // the key is no longer watched once the transaction ends
+        let transaction = vec![
+            Command::Multi,
+            Command::IncrBy("counter".into(), 1),
+            Command::Exec,
+        ];
+        let replies = db.apply_batch(&mut client, transaction).await;
+        let results = Frame::Array(Some(vec![Frame::Integer(6)]));
+        assert_eq!(Some(&results), replies.last());
+    }
+}
@@ -1,7 +1,7 @@
 use bytes::Bytes;
 
 macro_rules! build_matching_prefix_and_frame_enums {
-    ($($name:ident$(($type:ty))? = $value:literal$(,)?)*) => {
+    ($($name:ident$(($($type:ty),+))? = $value:literal$(,)?)*) => {
 
         pub enum Prefix {
             $(
@@ -23,37 +23,45 @@ macro_rules! build_matching_prefix_and_frame_enums {
             }
         }
 
-        #[derive(Debug, PartialEq)]
+        #[derive(Debug, Clone, PartialEq)]
         #[repr(u8)]
         pub enum Frame {
             $(
-                $name $(($type))? = $value,
+                $name $(($($type),+))? = $value,
             )*
         }
     };
 }
 
 // https://redis.io/docs/reference/protocol-spec/#resp-protocol-description
+//
+// Map and Attribute frames hold their keys and values interleaved, i.e. `[k1, v1, k2, v2, ...]`, so
+// that every aggregate can be read and written as a flat list of frames.
 build_matching_prefix_and_frame_enums! {
     Array(Option<Vec<Frame>>) = b'*',
+    Attribute(Vec<Frame>) = b'|',
+    BigNumber(Bytes) = b'(',
     Boolean(bool) = b'#',
     Bulk(Option<Bytes>) = b'$',
+    Double(f64) = b',',
     Error(Bytes) = b'-',
     Integer(i64) = b':',
+    Map(Vec<Frame>) = b'%',
     Null = b'_',
+    Push(Vec<Frame>) = b'>',
+    Set(Vec<Frame>) = b'~',
     String(Bytes) = b'+',
+    Verbatim([u8; 3], Bytes) = b'=',
 }
 
 impl Frame {
-    pub fn get_bytes(&self) -> Option<Bytes> {
-        Some(
-            match self {
-                Frame::String(buf) => buf,
-                Frame::Bulk(Some(buf)) => buf,
-                _ => return None,
-            }
-            .clone(), // shallow clone
-        )
+    /// Consumes the frame, returning its `Bytes` payload without copying it.
+    pub fn into_bytes(self) -> Option<Bytes> {
+        match self {
+            Frame::String(buf) => Some(buf),
+            Frame::Bulk(Some(buf)) => Some(buf),
+            _ => None,
+        }
     }
     pub fn prefix(&self) -> u8 {
         // SAFETY: Because `Self` is marked `repr(u8)`, its layout is a `repr(C)` `union`
@@ -0,0 +1,109 @@
+// Indexes coordinates the way Redis's geo commands do: as 52-bit geohashes, which are stored as the
+// scores of a sorted set, interleaving the bits of the latitude and the longitude so that nearby
+// points tend to have nearby scores.
+
+/// The limits of the coordinates a geohash can encode, which exclude the poles like EPSG:900913.
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+const LAT_MIN: f64 = -85.05112878;
+const LAT_MAX: f64 = 85.05112878;
+/// The number of bits of each coordinate in a geohash.
+const STEP: u32 = 26;
+/// The radius of the earth used by Redis, in meters.
+const EARTH_RADIUS: f64 = 6372797.560856;
+
+/// Returns whether the longitude and latitude can be indexed.
+pub fn is_valid(lon: f64, lat: f64) -> bool {
+    (LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+/// Returns the geohash of a valid longitude and latitude.
+pub fn encode(lon: f64, lat: f64) -> u64 {
+    let cells = (1u64 << STEP) as f64;
+    let lon = ((lon - LON_MIN) / (LON_MAX - LON_MIN) * cells) as u32;
+    let lat = ((lat - LAT_MIN) / (LAT_MAX - LAT_MIN) * cells) as u32;
+    spread(lat) | spread(lon) << 1
+}
+
+/// Returns the longitude and latitude at the center of the area `hash` encodes.
+pub fn decode(hash: u64) -> (f64, f64) {
+    let cells = (1u64 << STEP) as f64;
+    let center = |cell: u32, min: f64, max: f64| {
+        let low = min + (cell as f64 / cells) * (max - min);
+        let high = min + ((cell + 1) as f64 / cells) * (max - min);
+        ((low + high) / 2.0).clamp(min, max)
+    };
+    (
+        center(squash(hash >> 1), LON_MIN, LON_MAX),
+        center(squash(hash), LAT_MIN, LAT_MAX),
+    )
+}
+
+/// Spreads the bits of `n` to the even bits of the result.
+fn spread(n: u32) -> u64 {
+    (0..32).fold(0, |spread, i| spread | ((n as u64 >> i) & 1) << (2 * i))
+}
+
+/// Gathers the even bits of `n`, undoing `spread`.
+fn squash(n: u64) -> u32 {
+    (0..32).fold(0, |squashed, i| {
+        squashed | (((n >> (2 * i)) & 1) as u32) << i
+    })
+}
+
+/// Returns the distance in meters between two points on the earth, using the haversine formula.
+pub fn distance((lon1, lat1): (f64, f64), (lon2, lat2): (f64, f64)) -> f64 {
+    let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+    if v == 0.0 {
+        return latitude_distance(lat1, lat2);
+    }
+    let u = ((lat2 - lat1).to_radians() / 2.0).sin();
+    let a = u * u + lat1.to_radians().cos() * lat2.to_radians().cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Returns the distance in meters between two latitudes.
+fn latitude_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS * (lat2.to_radians() - lat1.to_radians()).abs()
+}
+
+/// Returns the distance in meters between `center` and `point`, if the point is within the box of
+/// `width` and `height` meters centered on it.
+pub fn distance_in_box(
+    center: (f64, f64),
+    point: (f64, f64),
+    width: f64,
+    height: f64,
+) -> Option<f64> {
+    if latitude_distance(point.1, center.1) > height / 2.0 {
+        return None;
+    }
+    if distance(point, (center.0, point.1)) > width / 2.0 {
+        return None;
+    }
+    Some(distance(center, point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_like_redis() {
+        // from the examples of https://redis.io/commands/geoadd
+        let palermo = (13.361389, 38.115556);
+        let catania = (15.087269, 37.502669);
+        let hash = encode(palermo.0, palermo.1);
+        assert_eq!(3479099956230698, hash);
+
+        let (lon, lat) = decode(hash);
+        assert!((lon - 13.361389338970184).abs() < 1e-12);
+        assert!((lat - 38.1155563954963).abs() < 1e-12);
+        let distance = distance(decode(hash), decode(encode(catania.0, catania.1)));
+        assert_eq!("166274.1516", format!("{:.4}", distance));
+
+        assert!(distance_in_box(palermo, catania, 400_000.0, 400_000.0).is_some());
+        assert!(distance_in_box(palermo, catania, 400_000.0, 100_000.0).is_none());
+        assert!(!is_valid(0.0, 86.0));
+    }
+}
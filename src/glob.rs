@@ -0,0 +1,115 @@
+// Matches strings against glob-style patterns the way Redis's `stringmatchlen` does, which is used
+// by pattern subscriptions and commands that filter keys, e.g. `h?llo`, `h*llo` or `h[a-e]llo`.
+
+/// Returns whether `string` matches `pattern`, where:
+/// - `?` matches any byte
+/// - `*` matches any number of bytes, including none
+/// - `[...]` matches any byte in the class, e.g. `[abc]` or `[a-c]`, or not in it, e.g. `[^a]`
+/// - `\` escapes the byte after it, so that it is matched literally
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // where to resume after the last `*`, if the rest of the pattern fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+    while s < string.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, s));
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match class(&pattern[p..], string[s]) {
+                (true, len) => Some(len),
+                (false, _) => None,
+            },
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == string[s]).then_some(2),
+            Some(c) => (*c == string[s]).then_some(1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                s += 1;
+            }
+            // let the last `*` match one more byte, and retry the rest of the pattern after it
+            (None, Some((star_p, star_s))) => {
+                p = star_p;
+                s = star_s + 1;
+                backtrack = Some((star_p, s));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Matches `c` against the character class at the start of `pattern`, returning whether it matched
+/// and the length of the class. Like Redis, an unterminated class ends with the pattern, and a
+/// reversed range such as `[z-a]` is the same as `[a-z]`.
+fn class(pattern: &[u8], c: u8) -> (bool, usize) {
+    let mut i = 1;
+    let negated = pattern.get(i) == Some(&b'^');
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    loop {
+        match pattern.get(i) {
+            None => break,
+            Some(b']') => {
+                i += 1;
+                break;
+            }
+            Some(b'\\') if i + 1 < pattern.len() => {
+                matched |= pattern[i + 1] == c;
+                i += 2;
+            }
+            Some(&start) if pattern.get(i + 1) == Some(&b'-') && i + 2 < pattern.len() => {
+                let end = pattern[i + 2];
+                matched |= (start.min(end)..=start.max(end)).contains(&c);
+                i += 3;
+            }
+            Some(&other) => {
+                matched |= other == c;
+                i += 1;
+            }
+        }
+    }
+    (matched != negated, i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! glob_tests {
+        ($($name:ident: $pattern:literal, $string:literal => $expected:literal),*) => {
+            $(
+                #[test]
+                fn $name() {
+                    assert_eq!($expected, matches($pattern, $string));
+                }
+            )*
+        };
+    }
+
+    glob_tests! {
+        literal: b"hello", b"hello" => true,
+        literal_mismatch: b"hello", b"hell" => false,
+        any_byte: b"h?llo", b"hallo" => true,
+        any_bytes: b"h*llo", b"heeeello" => true,
+        any_bytes_empty: b"h*llo", b"hllo" => true,
+        star_backtracks: b"*a*b", b"xaxxbxb" => true,
+        star_mismatch: b"a*b", b"axxc" => false,
+        only_star: b"*", b"" => true,
+        class: b"h[ae]llo", b"hello" => true,
+        class_mismatch: b"h[ae]llo", b"hillo" => false,
+        negated_class: b"h[^e]llo", b"hallo" => true,
+        negated_class_mismatch: b"h[^e]llo", b"hello" => false,
+        range: b"h[a-e]llo", b"hdllo" => true,
+        reversed_range: b"h[e-a]llo", b"hbllo" => true,
+        escaped: b"h\\*llo", b"h*llo" => true,
+        escaped_mismatch: b"h\\*llo", b"hello" => false,
+        unterminated_class: b"h[ab", b"hb" => true
+    }
+}
@@ -0,0 +1,66 @@
+use std::{io, os::unix::io::AsRawFd, time::Duration};
+
+#[cfg(target_os = "linux")]
+pub use linux::enable;
+
+/// The options are numbered differently on each platform, and only Linux's are known, so the
+/// connection is left with the platform's defaults elsewhere.
+#[cfg(not(target_os = "linux"))]
+pub fn enable(_socket: &impl AsRawFd, _interval: Duration) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use std::{
+        ffi::{c_int, c_void},
+        mem,
+        os::unix::io::RawFd,
+    };
+
+    // https://man7.org/linux/man-pages/man7/tcp.7.html
+    // https://man7.org/linux/man-pages/man7/socket.7.html
+
+    const SOL_SOCKET: c_int = 1;
+    const SO_KEEPALIVE: c_int = 9;
+    const IPPROTO_TCP: c_int = 6;
+    const TCP_KEEPIDLE: c_int = 4;
+    const TCP_KEEPINTVL: c_int = 5;
+    const TCP_KEEPCNT: c_int = 6;
+    /// How many probes go unanswered before the connection is dropped, like Redis.
+    const PROBES: c_int = 3;
+
+    extern "C" {
+        // the standard library links to libc already, which the build doesn't depend on directly
+        fn setsockopt(
+            fd: c_int,
+            level: c_int,
+            name: c_int,
+            value: *const c_void,
+            len: u32,
+        ) -> c_int;
+    }
+
+    /// Enables TCP keepalive on `socket`, probing the peer once the connection has been idle for
+    /// `interval`. Like Redis, the connection is dropped if the peer fails to answer for another
+    /// `interval`, probed 3 times.
+    pub fn enable(socket: &impl AsRawFd, interval: Duration) -> io::Result<()> {
+        let fd = socket.as_raw_fd();
+        let secs = interval.as_secs().clamp(1, c_int::MAX as u64) as c_int;
+        set(fd, SOL_SOCKET, SO_KEEPALIVE, 1)?;
+        set(fd, IPPROTO_TCP, TCP_KEEPIDLE, secs)?;
+        set(fd, IPPROTO_TCP, TCP_KEEPINTVL, (secs / PROBES).max(1))?;
+        set(fd, IPPROTO_TCP, TCP_KEEPCNT, PROBES)
+    }
+
+    fn set(fd: RawFd, level: c_int, name: c_int, value: c_int) -> io::Result<()> {
+        let len = mem::size_of::<c_int>() as u32;
+        // SAFETY: `value` outlives the call, and is as large as `len` says.
+        let result = unsafe { setsockopt(fd, level, name, &value as *const c_int as _, len) };
+        match result {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
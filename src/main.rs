@@ -1,44 +1,375 @@
 mod command;
+mod config;
 mod connection;
 mod db;
 mod frame;
+mod geo;
+mod glob;
+mod keepalive;
+mod proxy;
+mod reply;
+mod sha256;
+mod systemd;
 
 use crate::command::Command;
-use connection::Connection;
+use bytes::Bytes;
+use config::Config;
+use connection::{Connection, ReadError};
 use db::Db;
-use tokio::{self, net::TcpListener};
+use frame::Frame;
+use std::{
+    io,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{
+    self,
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    signal::unix::{signal, Signal, SignalKind},
+    sync::mpsc,
+    task::JoinSet,
+    time,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let listener = TcpListener::bind("127.0.0.1:6379").await?;
+    // e.g. `redis.conf --port 6380`, where options given on the command line override the file
+    let mut config = Config::default();
+    let mut args = std::env::args().skip(1).peekable();
+    if let Some(path) = args.next_if(|a| !a.starts_with("--")) {
+        config.load(Path::new(&path))?;
+    }
+    config.set_args(args)?;
+    // TLS would need a TLS implementation such as rustls, which the build doesn't depend on
+    if config.tls_port != 0 {
+        return Err("tls-port is set, but TLS is not supported by this build".into());
+    }
+
+    let listeners = match systemd::listener()? {
+        Some(listener) => vec![TcpListener::from_std(listener)?],
+        None => {
+            let mut listeners = Vec::new();
+            for addr in &config.bind {
+                // like Redis, `*` and `::*` are every IPv4 and IPv6 address respectively
+                let addr = match addr.as_str() {
+                    "*" => "0.0.0.0",
+                    "::*" => "::",
+                    addr => addr,
+                };
+                listeners.push(TcpListener::bind((addr, config.port)).await?);
+            }
+            listeners
+        }
+    };
+    // like Redis, a server configured to listen nowhere exits rather than serving no one
+    let listener = match listeners.first() {
+        Some(listener) => listener,
+        None => return Err("configured to not listen anywhere, exiting".into()),
+    };
     let db = Db::new();
+    db.set_listening_port(listener.local_addr()?.port());
+    let proxy_protocol = config.proxy_protocol;
+    db.configure(config.clone());
+    db.spawn_active_expiration();
+    db.spawn_auto_save();
+
+    // like Redis, a corrupted snapshot or AOF stops the server rather than losing writes
+    if let Err(e) = db.load_rdb() {
+        println!(
+            "failed to load {}/{}: {:?}",
+            config.dir, config.dbfilename, e
+        );
+        return Err(e.into());
+    }
+    if config.appendonly {
+        let path = Path::new(&config.dir).join(&config.appendfilename);
+        let (fsync, auto_rewrite) = (config.appendfsync, config.auto_rewrite);
+        if let Err(e) = db.open_aof(path, fsync, auto_rewrite).await {
+            println!(
+                "failed to load {}/{}: {:?}",
+                config.dir, config.appendfilename, e
+            );
+            return Err(e.into());
+        }
+    }
+
+    // the dataset loaded above is replaced by the master's once connected to it
+    if let Some((host, port)) = config.replicaof {
+        db.replicate(host, port);
+    }
+
+    // anything that must finish before serving clients (e.g. loading persisted data) goes above
+    if let Err(e) = systemd::notify_ready() {
+        println!("failed to notify systemd: {:?}", e);
+    }
+
+    // every connection task holds a sender, so that once they're all dropped every task ended
+    let (connections, mut ended) = mpsc::channel::<()>(1);
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        let connections = connections.clone();
+        servers.spawn(serve(listener, db.clone(), proxy_protocol, connections));
+    }
+    drop(connections);
+    let mut shutdown = db.shutdown_signal();
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            // accepting connections only stops on an error, which stops the server
+            Some(served) = servers.join_next() => served??,
+            _ = shutdown.changed() => break,
+            _ = signaled(&mut terminate) => {
+                if let Err(e) = db.shutdown() {
+                    println!("failed to shut down: {:?}", e);
+                }
+            }
+        }
+    }
+    // connections are no longer accepted, but clients are replied to what they already sent
+    servers.shutdown().await;
+    ended.recv().await;
+    db.close().await;
+    println!("ready to exit, bye bye...");
+    Ok(())
+}
+
+/// Waits until the process is sent SIGINT, e.g. by Ctrl-C, or `terminate`, i.e. SIGTERM.
+async fn signaled(terminate: &mut Signal) {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+
+/// How long a connection may take to send its PROXY protocol header, so that peers which never do
+/// don't hold on to their task.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accepts connections on `listener`, serving each of them in its own task.
+async fn serve(
+    listener: TcpListener,
+    db: Db,
+    proxy_protocol: bool,
+    connections: mpsc::Sender<()>,
+) -> io::Result<()> {
     loop {
-        let (mut stream, _) = listener.accept().await?;
+        let (mut stream, mut addr) = listener.accept().await?;
+        // peers that went away without closing their connection are noticed, rather than idling
+        if let Some(interval) = db.tcp_keepalive() {
+            if let Err(e) = keepalive::enable(&stream, interval) {
+                println!("{}: failed to enable keepalive: {:?}", addr, e);
+            }
+        }
         let db = db.clone();
+        let connection = connections.clone();
         tokio::spawn(async move {
-            // TODO(cjshearer): pipelining https://redis.io/topics/pipelining
-            let mut connection = Connection::new(&mut stream);
-            loop {
-                let frame = match connection.read_frame().await {
-                    Ok(Some(frame)) => frame,
-                    Ok(None) => break, // disconnect
-                    Err(e) => {
-                        println!("{:?}", e);
-                        continue;
-                        // todo!("send frame parsing error back to client");
-                    }
-                };
-                let command: Command = match frame.try_into() {
-                    Ok(command) => command,
+            let _connection = connection;
+            if proxy_protocol {
+                let read = proxy::read_header(&mut stream);
+                // a peer that never sends its header is disconnected like one sending an invalid one
+                let header = time::timeout(PROXY_HEADER_TIMEOUT, read).await;
+                match header.unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())) {
+                    Ok(Some(client_addr)) => addr = client_addr,
+                    Ok(None) => (),
                     Err(e) => {
-                        println!("{:?}", e);
-                        continue;
-                        // todo!("send command parsing error back to client")
+                        println!("{}: {:?}", addr, e);
+                        return;
                     }
-                };
-                let result = db.apply(command);
-                let _ = connection.write_frame(result).await;
+                }
             }
+            handle(stream, addr, db).await;
         });
     }
 }
+
+/// Serves the client connected from `addr` over `stream` until it disconnects, is killed, or the
+/// server shuts down. The stream is only read and written, so that any transport may carry the
+/// connection.
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, addr: SocketAddr, db: Db) {
+    let mut connection = Connection::new(&mut stream);
+    // like Redis, clients past `maxclients` are told why before being disconnected
+    let (mut client, mut messages) = match db.connect() {
+        Ok(client) => client,
+        Err(e) => {
+            connection.write_frame(e);
+            let _ = connection.flush().await;
+            return;
+        }
+    };
+    client.set_addr(addr);
+    let killed = client.killed();
+    let mut shutdown = db.shutdown_signal();
+    loop {
+        let deadline = client.idle_deadline();
+        // messages are pushed to subscribed clients while waiting for their next command
+        if !connection.has_buffered_data() {
+            tokio::select! {
+                Some(message) = messages.recv() => {
+                    connection.write_frame(message);
+                    while let Ok(message) = messages.try_recv() {
+                        connection.write_frame(message);
+                    }
+                    // a client too slow to read its messages is killed once over its limit
+                    tokio::select! {
+                        flushed = connection.flush() => if flushed.is_err() {
+                            break;
+                        },
+                        _ = killed.notified() => break,
+                    }
+                    messages.flushed();
+                    continue;
+                }
+                line = client.monitored() => {
+                    connection.write_frame(line);
+                    if connection.flush().await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                read = connection.must_fill_buf() => if read.is_err() {
+                    break;
+                },
+                _ = killed.notified() => break,
+                _ = shutdown.changed() => break,
+                _ = idle(deadline) => break,
+            }
+        }
+        let mut commands = Vec::new();
+        let mut error: Option<Frame> = None;
+        let mut disconnected = false;
+        let mut name = None;
+        // drain every frame the client has already pipelined, so they're applied together
+        while !disconnected
+            && error.is_none()
+            && (commands.is_empty() || connection.has_buffered_data())
+        {
+            // a client may go idle halfway through sending a command too
+            let read = tokio::select! {
+                read = connection.read_frame() => read,
+                _ = idle(deadline) => {
+                    disconnected = true;
+                    continue;
+                }
+            };
+            match read {
+                Ok(Some(frame)) => {
+                    name = command_name(&frame).or(name);
+                    client.feed_monitors(&frame);
+                    client.received(&frame);
+                    match Command::try_from(frame) {
+                        Ok(command) => {
+                            // like Redis, nothing sent after QUIT is read, and the client is
+                            // disconnected once replied to
+                            disconnected = matches!(command, Command::Quit);
+                            commands.push(command);
+                        }
+                        Err(e) => error = Some(e.into()),
+                    }
+                }
+                Ok(None) => disconnected = true,
+                Err(e @ ReadError::IoError(_)) => {
+                    println!("{}: {:?}", addr, e);
+                    disconnected = true;
+                }
+                // like Redis, the client is replied to and then disconnected, as whatever it sent
+                // after an invalid frame can't be told apart from the frame's remains
+                Err(e) => {
+                    println!("{}: {:?}", addr, e);
+                    error = Some(e.into());
+                    disconnected = true;
+                }
+            };
+        }
+        if let Some(name) = name {
+            client.record(&name);
+        }
+        // blocked commands are abandoned if the client disconnects while waiting
+        let results = tokio::select! {
+            biased;
+            results = db.apply_batch(&mut client, commands) => results,
+            _ = connection.closed() => break,
+            _ = killed.notified() => break,
+            // blocked commands are abandoned too when the server shuts down
+            _ = shutdown.changed() => break,
+        };
+        // a command that fails to be queued makes the open transaction fail when executed
+        if error.is_some() {
+            client.fail_transaction();
+        }
+        // HELLO and RESET switch the protocol the client is replied to in
+        connection.protover = if client.resp3 { 3 } else { 2 };
+        // errors end a batch, so they are replied to after the commands preceding them
+        for result in results.into_iter().chain(error) {
+            connection.write_frame(result);
+        }
+        // replicas are sent the dataset after the reply to PSYNC, then every write after it
+        if let Some(rdb) = client.take_full_sync() {
+            connection.write_rdb(&rdb);
+        }
+        // https://redis.io/topics/pipelining
+        if connection.flush().await.is_err() || disconnected {
+            break;
+        }
+    }
+}
+
+/// Waits until `deadline`, if any, or forever.
+async fn idle(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns the name of the command sent as `frame`, as listed by `CLIENT LIST`.
+fn command_name(frame: &Frame) -> Option<Bytes> {
+    match frame {
+        Frame::Array(Some(args)) => match args.first() {
+            Some(Frame::Bulk(Some(name))) => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn commands_split_across_reads_are_served() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let addr = SocketAddr::from(([127, 0, 0, 1], 6379));
+        let served = tokio::spawn(handle(server, addr, Db::new()));
+        // the CRLF ending the bulk string's header arrives in two reads
+        client.write_all(b"*1\r\n$4\r").await.unwrap();
+        time::sleep(Duration::from_millis(10)).await;
+        client.write_all(b"\nPING\r\n").await.unwrap();
+        let mut reply = [0; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(b"$4\r\nPONG\r\n", &reply);
+
+        // the client is still connected
+        client.write_all(b"PING\r\n").await.unwrap();
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(b"$4\r\nPONG\r\n", &reply);
+        drop(client);
+        served.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn quit_closes_the_connection_once_replied_to() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let addr = SocketAddr::from(([127, 0, 0, 1], 6379));
+        let served = tokio::spawn(handle(server, addr, Db::new()));
+        // what follows QUIT isn't served
+        client.write_all(b"QUIT\r\nPING\r\n").await.unwrap();
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(b"$2\r\nOK\r\n", &reply[..]);
+        served.await.unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    io::{self, ErrorKind::InvalidData},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str,
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads a PROXY protocol (v1 or v2) header from the start of the stream, returning the address of
+/// the client that connected to the proxy.
+///
+/// Returns `Ok(None)` if the proxy did not forward an address (v1 `UNKNOWN`, v2 `LOCAL` or an
+/// unsupported address family), in which case the peer address of the socket should be used.
+///
+/// The header is read a byte at a time, so that nothing following it is consumed from the stream.
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    match stream.read_u8().await? {
+        b'P' => read_v1(stream).await,
+        b'\r' => read_v2(stream).await,
+        _ => Err(invalid("missing PROXY protocol header")),
+    }
+}
+
+/// Reads the remainder of a human-readable header, e.g. `PROXY TCP4 1.2.3.4 5.6.7.8 1234 6379\r\n`.
+async fn read_v1<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut line = vec![b'P'];
+    while !line.ends_with(b"\r\n") {
+        if line.len() == V1_MAX_LEN {
+            return Err(invalid("PROXY v1 header is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = line
+        .strip_prefix(V1_PREFIX)
+        .and_then(|l| str::from_utf8(&l[..l.len() - 2]).ok())
+        .ok_or_else(|| invalid("malformed PROXY v1 header"))?;
+
+    let mut fields = line.split(' ');
+    match fields.next() {
+        Some("TCP4" | "TCP6") => (),
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unsupported PROXY v1 protocol")),
+    }
+    let (Some(ip), Some(_), Some(port), Some(_), None) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        return Err(invalid("malformed PROXY v1 header"));
+    };
+    let ip: IpAddr = ip
+        .parse()
+        .map_err(|_| invalid("invalid PROXY v1 address"))?;
+    let port: u16 = port.parse().map_err(|_| invalid("invalid PROXY v1 port"))?;
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Reads the remainder of a binary header: the signature, version/command, family, length and
+/// address block.
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0; 16];
+    header[0] = b'\r';
+    stream.read_exact(&mut header[1..]).await?;
+    if &header[..12] != V2_SIGNATURE || header[12] >> 4 != 2 {
+        return Err(invalid("malformed PROXY v2 header"));
+    }
+    let mut addresses = vec![0; u16::from_be_bytes([header[14], header[15]]) as usize];
+    stream.read_exact(&mut addresses).await?;
+
+    // LOCAL connections (e.g. health checks) are made by the proxy on its own behalf.
+    if header[12] & 0xF == 0 {
+        return Ok(None);
+    }
+    let (ip, port) = match header[13] >> 4 {
+        0x1 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[..4].try_into().unwrap();
+            (IpAddr::from(Ipv4Addr::from(ip)), &addresses[8..10])
+        }
+        0x2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().unwrap();
+            (IpAddr::from(Ipv6Addr::from(ip)), &addresses[32..34])
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(SocketAddr::new(
+        ip,
+        u16::from_be_bytes([port[0], port[1]]),
+    )))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn v1_tcp4() {
+        let mut stream = Cursor::new(b"PROXY TCP4 10.0.0.1 10.0.0.2 5555 6379\r\n*1\r\n".to_vec());
+        assert_eq!(
+            Some("10.0.0.1:5555".parse().unwrap()),
+            read_header(&mut stream).await.unwrap()
+        );
+        assert_eq!(b"*1\r\n", &stream.get_ref()[stream.position() as usize..]);
+    }
+
+    #[tokio::test]
+    async fn v1_unknown() {
+        let mut stream = Cursor::new(b"PROXY UNKNOWN\r\n".to_vec());
+        assert_eq!(None, read_header(&mut stream).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn v2_tcp4() {
+        let mut bytes = V2_SIGNATURE.to_vec();
+        bytes.extend([
+            0x21, 0x11, 0, 12, 192, 168, 0, 1, 10, 0, 0, 2, 0x15, 0xB3, 0x18, 0xEB,
+        ]);
+        assert_eq!(
+            Some("192.168.0.1:5555".parse().unwrap()),
+            read_header(&mut Cursor::new(bytes)).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_header() {
+        let mut stream = Cursor::new(b"*1\r\n$4\r\nPING\r\n".to_vec());
+        assert_eq!(
+            InvalidData,
+            read_header(&mut stream).await.unwrap_err().kind()
+        );
+    }
+}
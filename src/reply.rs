@@ -0,0 +1,100 @@
+use std::borrow::Cow;
+
+use crate::frame::Frame;
+
+/// The code starting an error reply, which clients match on to tell kinds of errors apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    /// A generic error.
+    Err,
+    WrongType,
+    /// A write sent to a read-only server or replica.
+    ReadOnly,
+    /// `EXEC` of a transaction that had a command fail to be queued.
+    ExecAbort,
+    /// A consumer group that already exists.
+    BusyGroup,
+    /// A consumer group that doesn't exist.
+    NoGroup,
+    /// A command that may grow the dataset, sent once `maxmemory` is reached.
+    Oom,
+    /// A protocol version, sent with `HELLO`, that isn't supported.
+    NoProto,
+    /// A command sent by a client that must authenticate first.
+    NoAuth,
+    /// A command, key or channel the client's user isn't permitted to access.
+    NoPerm,
+    /// A username and password that don't match any user.
+    WrongPass,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Err => "ERR",
+            ErrorCode::WrongType => "WRONGTYPE",
+            ErrorCode::ReadOnly => "READONLY",
+            ErrorCode::ExecAbort => "EXECABORT",
+            ErrorCode::BusyGroup => "BUSYGROUP",
+            ErrorCode::NoGroup => "NOGROUP",
+            ErrorCode::Oom => "OOM",
+            ErrorCode::NoProto => "NOPROTO",
+            ErrorCode::NoAuth => "NOAUTH",
+            ErrorCode::NoPerm => "NOPERM",
+            ErrorCode::WrongPass => "WRONGPASS",
+        }
+    }
+}
+
+/// An error reply, rendered like Redis as its code followed by a message, e.g. `ERR syntax error`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorReply {
+    code: ErrorCode,
+    message: Cow<'static, str>,
+}
+
+impl ErrorReply {
+    pub fn new(code: ErrorCode, message: impl Into<Cow<'static, str>>) -> Self {
+        ErrorReply {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Returns a generic `ERR` error.
+    pub fn err(message: impl Into<Cow<'static, str>>) -> Self {
+        ErrorReply::new(ErrorCode::Err, message)
+    }
+
+    /// Returns the error replied to commands applied to a key holding another type.
+    pub fn wrong_type() -> Self {
+        ErrorReply::new(
+            ErrorCode::WrongType,
+            "Operation against a key holding the wrong kind of value",
+        )
+    }
+}
+
+impl From<ErrorReply> for Frame {
+    fn from(value: ErrorReply) -> Self {
+        // like Redis, newlines would end the reply early, so they're replaced by spaces
+        let message = value.message.replace(['\r', '\n'], " ");
+        Frame::Error(format!("{} {}", value.code.as_str(), message).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_code_then_the_message() {
+        let frame = Frame::from(ErrorReply::err("syntax error"));
+        assert_eq!(Frame::Error("ERR syntax error".into()), frame);
+        let frame = Frame::from(ErrorReply::wrong_type());
+        let expected = "WRONGTYPE Operation against a key holding the wrong kind of value";
+        assert_eq!(Frame::Error(expected.into()), frame);
+        let frame = Frame::from(ErrorReply::new(ErrorCode::NoGroup, "no\r\ngroup"));
+        assert_eq!(Frame::Error("NOGROUP no  group".into()), frame);
+    }
+}
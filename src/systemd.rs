@@ -0,0 +1,74 @@
+use std::{
+    env,
+    io::{self, ErrorKind::InvalidInput},
+    net::TcpListener,
+    os::unix::{
+        ffi::OsStrExt,
+        io::FromRawFd,
+        net::{SocketAddr, UnixDatagram},
+    },
+    process,
+};
+
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+
+// https://www.freedesktop.org/software/systemd/man/sd_listen_fds.html
+// https://www.freedesktop.org/software/systemd/man/sd_notify.html
+
+/// The first file descriptor passed by systemd, following stdin, stdout and stderr.
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Takes ownership of the listening socket passed by systemd socket activation, returning
+/// `Ok(None)` if the server was not socket activated.
+///
+/// The `LISTEN_*` variables are removed so they are not inherited by child processes.
+pub fn listener() -> io::Result<Option<TcpListener>> {
+    let pid = env::var("LISTEN_PID");
+    let fds = env::var("LISTEN_FDS");
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    if pid.ok().and_then(|p| p.parse().ok()) != Some(process::id()) {
+        return Ok(None);
+    }
+    match fds.ok().and_then(|n| n.parse::<i32>().ok()) {
+        Some(1) => (),
+        Some(0) | None => return Ok(None),
+        Some(_) => return Err(io::Error::new(InvalidInput, "expected a single socket")),
+    }
+
+    // SAFETY: systemd guarantees the descriptor is open and owned by this process, and we have
+    // just removed the variables that would allow it to be claimed a second time.
+    let listener = unsafe { TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+/// Tells the service manager that the server has finished starting up, doing nothing if the server
+/// is not supervised by systemd.
+pub fn notify_ready() -> io::Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let addr = match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => abstract_addr(name)?,
+        None => SocketAddr::from_pathname(path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(b"READY=1", &addr)?;
+    Ok(())
+}
+
+/// Returns the address of the abstract socket named `name`, which systemd spells with a leading `@`.
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &[u8]) -> io::Result<SocketAddr> {
+    SocketAddr::from_abstract_name(name)
+}
+
+/// Abstract sockets only exist on Linux, so they can't be notified elsewhere.
+#[cfg(not(target_os = "linux"))]
+fn abstract_addr(_: &[u8]) -> io::Result<SocketAddr> {
+    let msg = "NOTIFY_SOCKET names an abstract socket, which are only supported on Linux";
+    Err(io::Error::new(io::ErrorKind::Unsupported, msg))
+}